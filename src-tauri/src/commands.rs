@@ -1,7 +1,33 @@
 use crate::database::Database;
+use crate::ics::{self, IcsEvent};
+use crate::fhir::{self, FhirEntry};
+use crate::hl7::{self, Hl7Referral};
+use crate::import;
+use crate::research::{self, Pseudonymizer};
+use crate::xlsx::{self, XlsxCell, XlsxSheet};
 use crate::models::{
-    Procedure, ProcedureFilters, Statistics, Patient, PatientFilters, PatientStatus,
-    PatientStatusCount, PatientWithStatus,
+    AmbulatorioBlockedSlot, AmbulatorioSession, AmbulatorioSlot, AmbulatorioSlotTemplate, Appointment,
+    AppointmentWithPatient, CathLabDurationEstimate, CathLabSession, CathLabSlotSuggestion,
+    AMBULATORIO_DEFAULT_VISIT_MINUTES, APPOINTMENT_STATUSES, APPOINTMENT_TYPES,
+    periprocedural_instructions, risk_factor_vocabulary, ClinicalNoteHit, CtPlanning, CtSizingResult, EcgRecording,
+    FollowUp, Medication, PacemakerRisk, PlanComparison, ProcedureAggregation, ProcedurePlan, OverdueFollowUp, PatientRiskFactor, Procedure, ProcedureFilters, ProcedureOutcome,
+    ProsthesisModel, Statistics, Patient, PatientFilters, PatientSearchResult, PatientStatus,
+    PatientStatusCount, PatientWithStatus, PriorityAlert, RiskFactorDefinition, RiskFactorStatistics,
+    SavedSearch, SmartListCount, SurvivalData, WaitingListAnalytics, ValveModel, ValveSizingChart, VivCompatibilityResult,
+    WorklistEntry,
+};
+use crate::models::{ImportPreview, ImportReport, ImportRequest};
+use crate::models::{
+    Hl7IngestionLogEntry, Hl7IngestionSummary, HL7_INGESTION_OUTCOMES, HL7_OUTCOME_CREATED, HL7_OUTCOME_ERROR,
+    HL7_OUTCOME_IGNORED, HL7_OUTCOME_UPDATED,
+};
+use crate::models::{
+    ResearchExportResult, RESEARCH_EXPORT_FORMATS, RESEARCH_PATIENT_COLUMNS, RESEARCH_PROCEDURE_COLUMNS,
+    RESEARCH_REMOVED_FIELDS, RESEARCH_TRANSFORMATIONS,
+};
+use crate::models::{
+    age_at, export_columns, select_export_columns, XlsxExportColumns, XlsxExportOptions, MAIN_ACCESS_OPTIONS,
+    PATIENT_EXPORT_COLUMNS, PROCEDURE_EXPORT_COLUMNS, XLSX_EXPORT_SHEETS,
 };
 use chrono::Local;
 use regex::Regex;
@@ -36,6 +62,8 @@ pub struct AppSettings {
     pub update_deferred: Option<bool>,
     pub update_last_check: Option<String>,
     pub update_last_error: Option<String>,
+    pub hl7_drop_path: Option<String>,    // cartella di ingresso dei messaggi HL7 v2
+    pub hl7_poll_seconds: Option<u64>,
 }
 
 pub fn settings_file_path() -> PathBuf {
@@ -472,8 +500,13 @@ fn schedule_temp_cleanup(path: PathBuf) {
 }
 
 #[tauri::command]
-pub async fn load_settings() -> Result<AppSettings, String> {
-    read_settings_from_disk()
+pub async fn load_settings(db: State<'_, Database>) -> Result<AppSettings, String> {
+    let mut settings = read_settings_from_disk()?;
+    // Le date di apertura dell'ambulatorio sono gestite dal calendario nel database
+    if let Ok(dates) = db.get_ambulatorio_open_dates() {
+        settings.ambulatorio_open_dates = Some(dates);
+    }
+    Ok(settings)
 }
 
 #[tauri::command]
@@ -579,6 +612,11 @@ pub async fn save_settings(
         db.reconnect(new_db)?;
     }
 
+    // Con un database diverso non si rimuovono le sedute già presenti
+    if let Some(dates) = &settings.ambulatorio_open_dates {
+        db.sync_ambulatorio_sessions(dates, !db_path_changed)?;
+    }
+
     Ok(())
 }
 
@@ -618,12 +656,46 @@ pub async fn calculate_statistics(
     db.calculate_statistics(filters)
 }
 
+/// Statistiche aggregate per grafici e report annuale (vedi PROCEDURE_GROUP_BY)
+#[tauri::command]
+pub async fn get_procedure_aggregation(
+    group_by: String,
+    filters: Option<ProcedureFilters>,
+    db: State<'_, Database>,
+) -> Result<ProcedureAggregation, String> {
+    db.get_procedure_aggregation(&group_by, filters)
+}
+
 #[tauri::command]
 pub async fn get_procedure_count(db: State<'_, Database>) -> Result<i32, String> {
     let procedures = db.get_all_procedures(None)?;
     Ok(procedures.len() as i32)
 }
 
+#[tauri::command]
+pub async fn get_procedure_outcome(
+    procedure_id: i64,
+    db: State<'_, Database>,
+) -> Result<Option<ProcedureOutcome>, String> {
+    db.get_procedure_outcome(procedure_id)
+}
+
+#[tauri::command]
+pub async fn save_procedure_outcome(
+    outcome: ProcedureOutcome,
+    db: State<'_, Database>,
+) -> Result<i64, String> {
+    db.save_procedure_outcome(&outcome)
+}
+
+#[tauri::command]
+pub async fn delete_procedure_outcome(
+    procedure_id: i64,
+    db: State<'_, Database>,
+) -> Result<(), String> {
+    db.delete_procedure_outcome(procedure_id)
+}
+
 // ============================================================================ 
 // PATIENT COMMANDS 
 // ============================================================================
@@ -636,6 +708,121 @@ pub async fn get_all_patients(
     db.get_all_patients_with_status(filters)
 }
 
+#[tauri::command]
+pub async fn search_patients(
+    filters: PatientFilters,
+    db: State<'_, Database>,
+) -> Result<PatientSearchResult, String> {
+    db.search_patients(filters)
+}
+
+#[tauri::command]
+pub async fn get_saved_searches(db: State<'_, Database>) -> Result<Vec<SavedSearch>, String> {
+    db.get_saved_searches()
+}
+
+#[tauri::command]
+pub async fn save_saved_search(
+    search: SavedSearch,
+    db: State<'_, Database>,
+) -> Result<i64, String> {
+    db.save_saved_search(&search)
+}
+
+#[tauri::command]
+pub async fn delete_saved_search(id: i64, db: State<'_, Database>) -> Result<(), String> {
+    db.delete_saved_search(id)
+}
+
+#[tauri::command]
+pub async fn run_saved_search(
+    nome: String,
+    db: State<'_, Database>,
+) -> Result<PatientSearchResult, String> {
+    db.run_saved_search(&nome)
+}
+
+#[tauri::command]
+pub async fn get_smart_list_counts(db: State<'_, Database>) -> Result<Vec<SmartListCount>, String> {
+    db.get_smart_list_counts()
+}
+
+/// Tempi di attesa del percorso TAVI per priorità e provenienza
+#[tauri::command]
+pub async fn get_waiting_list_analytics(
+    db: State<'_, Database>,
+) -> Result<WaitingListAnalytics, String> {
+    db.get_waiting_list_analytics()
+}
+
+// ============================================================================
+// PRIORITY ALERT COMMANDS
+// ============================================================================
+
+pub const PRIORITY_ALERTS_EVENT: &str = "app://priority-alerts";
+const PRIORITY_ALERT_CHECK_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+/// Controlla periodicamente le scadenze di priorità, notificando il frontend. Il controllo
+/// all'avvio lo chiede il frontend con `get_priority_alerts` dopo aver registrato il listener.
+pub fn start_priority_alert_monitor(app_handle: AppHandle) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(PRIORITY_ALERT_CHECK_INTERVAL);
+        emit_priority_alerts(&app_handle);
+    });
+}
+
+fn emit_priority_alerts(app_handle: &AppHandle) {
+    let db = app_handle.state::<Database>();
+    match db.get_priority_alerts(false) {
+        Ok(alerts) => {
+            let _ = app_handle.emit_all(PRIORITY_ALERTS_EVENT, alerts);
+        }
+        Err(e) => eprintln!("Errore controllo scadenze priorità: {}", e),
+    }
+}
+
+#[tauri::command]
+pub async fn get_priority_alerts(
+    include_suppressed: Option<bool>,
+    db: State<'_, Database>,
+) -> Result<Vec<PriorityAlert>, String> {
+    db.get_priority_alerts(include_suppressed.unwrap_or(false))
+}
+
+#[tauri::command]
+pub async fn acknowledge_priority_alert(
+    patient_id: i64,
+    db: State<'_, Database>,
+    app_handle: AppHandle,
+) -> Result<(), String> {
+    db.acknowledge_priority_alert(patient_id)?;
+    emit_priority_alerts(&app_handle);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn snooze_priority_alert(
+    patient_id: i64,
+    until: Option<String>,
+    db: State<'_, Database>,
+    app_handle: AppHandle,
+) -> Result<(), String> {
+    db.snooze_priority_alert(patient_id, until)?;
+    emit_priority_alerts(&app_handle);
+    Ok(())
+}
+
+/// Ricerca full-text nelle note cliniche con snippet evidenziati
+#[tauri::command]
+pub async fn search_clinical_notes(
+    query: String,
+    campo: Option<String>,
+    limit: Option<i64>,
+    db: State<'_, Database>,
+) -> Result<Vec<ClinicalNoteHit>, String> {
+    db.search_clinical_notes(&query, campo.as_deref(), limit)
+}
+
 #[tauri::command]
 pub async fn get_patient_by_id(
     id: i64,
@@ -695,265 +882,1915 @@ pub async fn get_patients_by_status(
 }
 
 // ============================================================================
-// REFERTI
+// FOLLOW-UP COMMANDS
 // ============================================================================
 
-fn format_date_ita(date_iso: &str) -> String {
-    chrono::NaiveDate::parse_from_str(date_iso, "%Y-%m-%d")
-        .map(|d| d.format("%d/%m/%Y").to_string())
-        .unwrap_or_else(|_| date_iso.to_string())
+#[tauri::command]
+pub async fn get_follow_ups(
+    patient_id: i64,
+    db: State<'_, Database>,
+) -> Result<Vec<FollowUp>, String> {
+    db.get_follow_ups(patient_id)
 }
 
-fn format_date_filename(raw_date: &str) -> String {
-    let value = raw_date.trim();
-    if value.is_empty() {
-        return String::new();
-    }
-
-    let parsed = chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
-        .or_else(|_| chrono::NaiveDate::parse_from_str(value, "%d/%m/%Y"))
-        .or_else(|_| chrono::NaiveDate::parse_from_str(value, "%d-%m-%Y"))
-        .or_else(|_| chrono::NaiveDate::parse_from_str(value, "%d.%m.%Y"));
-
-    if let Ok(date) = parsed {
-        return date.format("%d.%m.%Y").to_string();
-    }
+#[tauri::command]
+pub async fn update_follow_up(
+    follow_up: FollowUp,
+    db: State<'_, Database>,
+) -> Result<(), String> {
+    db.update_follow_up(&follow_up)
+}
 
-    value.replace('/', ".").replace('-', ".")
+#[tauri::command]
+pub async fn get_overdue_follow_ups(
+    db: State<'_, Database>,
+) -> Result<Vec<OverdueFollowUp>, String> {
+    db.get_overdue_follow_ups()
 }
 
-fn sanitize_filename(name: &str) -> String {
-    name.chars()
-        .map(|c| if ['\\', '/', ':', '*', '?', '"', '<', '>', '|'].contains(&c) { '_' } else { c })
-        .collect()
+#[tauri::command]
+pub async fn get_survival_data(db: State<'_, Database>) -> Result<SurvivalData, String> {
+    db.get_survival_data()
 }
 
-fn capitalize_first(s: &str) -> String {
-    let mut chars = s.chars();
-    if let Some(first) = chars.next() {
-        first.to_uppercase().collect::<String>() + chars.as_str()
-    } else {
-        String::new()
+/// Esporta i dati di sopravvivenza in CSV (separatore ';') per il report annuale del registro.
+#[tauri::command]
+pub async fn export_survival_data(
+    output_path: String,
+    db: State<'_, Database>,
+) -> Result<String, String> {
+    let data = db.get_survival_data()?;
+
+    let mut csv = String::from(
+        "patient_id;data_tavi;data_ultimo_contatto;giorni;decesso;decesso_cardiovascolare\n",
+    );
+    for r in &data.records {
+        csv.push_str(&format!(
+            "{};{};{};{};{};{}\n",
+            r.patient_id,
+            r.data_tavi,
+            r.data_ultimo_contatto,
+            r.giorni,
+            r.decesso as i32,
+            r.decesso_cardiovascolare as i32
+        ));
+    }
+    csv.push_str("\ngiorni;a_rischio;eventi;censurati;sopravvivenza\n");
+    for point in &data.curva {
+        let sopravvivenza = format!("{:.4}", point.sopravvivenza).replace('.', ",");
+        csv.push_str(&format!(
+            "{};{};{};{};{}\n",
+            point.giorni, point.a_rischio, point.eventi, point.censurati, sopravvivenza
+        ));
     }
-}
 
-fn replace_placeholders(content: &str, map: &HashMap<&str, String>) -> String {
-    // Rimuove marcatori di controllo ortografico e normalizza i placeholder spezzati da tag.
-    let proof_err_re = Regex::new(r"<w:proofErr[^>]*/>").unwrap();
-    let tag_re = Regex::new(r"<[^>]+>").unwrap();
-    let placeholder_clean_re = Regex::new(r"\{([^}]*)\}").unwrap();
+    let out_path = PathBuf::from(&output_path);
+    if let Some(parent) = out_path.parent() {
+        create_dir_all(parent).map_err(|_| "Impossibile creare la cartella di destinazione".to_string())?;
+    }
+    let mut out_file =
+        File::create(&out_path).map_err(|_| "Impossibile creare il file di esportazione".to_string())?;
+    out_file
+        .write_all(csv.as_bytes())
+        .map_err(|_| "Errore salvataggio esportazione".to_string())?;
 
-    let mut out = proof_err_re.replace_all(content, "").to_string();
-    out = placeholder_clean_re
-        .replace_all(&out, |caps: &regex::Captures| {
-            let inner = &caps[1];
-            let cleaned = tag_re.replace_all(inner, "");
-            let cleaned: String = cleaned
-                .chars()
-                .filter(|c| !c.is_whitespace())
-                .collect();
-            format!("{{{}}}", cleaned)
-        })
-        .to_string();
-    for (key, value) in map {
-        // Escapa XML e preserva gli a-capo trasformandoli in <w:br/> per Word.
-        let escaped = value
-            .replace('&', "&amp;")
-            .replace('<', "&lt;")
-            .replace('>', "&gt;");
-        let normalized = escaped
-            .replace("\r\n", "<w:br/>")
-            .replace('\n', "<w:br/>")
-            .replace("&lt;w:br/&gt;", "<w:br/>"); // ripristina i break
+    Ok(out_path.to_string_lossy().to_string())
+}
 
-        let placeholder = format!("{{{}}}", key);
-        out = out.replace(&placeholder, &normalized);
+// ============================================================================
+// MEDICATION COMMANDS
+// ============================================================================
 
-        // Alcuni placeholder possono essere rimasti senza parentesi graffe nel template (es. fdrcv).
-        let tag_placeholder = format!(">{}<", key);
-        if out.contains(&tag_placeholder) {
-            let replacement = format!(">{}<", normalized);
-            out = out.replace(&tag_placeholder, &replacement);
-        }
-    }
-    out
+#[tauri::command]
+pub async fn get_medications(
+    patient_id: i64,
+    db: State<'_, Database>,
+) -> Result<Vec<Medication>, String> {
+    db.get_medications(patient_id)
 }
 
-fn apply_checkbox_flags(content: &str, flags: &[bool]) -> String {
-    let cb_re = Regex::new(r"<w:checkBox>.*?</w:checkBox>").unwrap();
-    let default_re = Regex::new(r#"<w:default w:val="([01])"\s*/>"#).unwrap();
-    let checked_re = Regex::new(r#"<w:checked w:val="([01])"\s*/>"#).unwrap();
-
-    let mut idx = 0usize;
-    cb_re
-        .replace_all(content, |caps: &regex::Captures| {
-            let mut frag = caps[0].to_string();
-            if let Some(flag) = flags.get(idx) {
-                if *flag {
-                    frag = default_re
-                        .replace_all(&frag, r#"<w:default w:val="1"/>"#)
-                        .to_string();
-                    if !frag.contains("w:default") {
-                        frag = frag.replace(
-                            "<w:checkBox>",
-                            "<w:checkBox><w:default w:val=\"1\"/>",
-                        );
-                    }
-                    if checked_re.is_match(&frag) {
-                        frag = checked_re
-                            .replace_all(&frag, r#"<w:checked w:val="1"/>"#)
-                            .to_string();
-                    } else {
-                        frag = frag.replace(
-                            "<w:default w:val=\"1\"/>",
-                            "<w:default w:val=\"1\"/><w:checked w:val=\"1\"/>",
-                        );
-                    }
-                } else {
-                    frag = default_re
-                        .replace_all(&frag, r#"<w:default w:val="0"/>"#)
-                        .to_string();
-                    frag = checked_re
-                        .replace_all(&frag, r#"<w:checked w:val="0"/>"#)
-                        .to_string();
-                }
-            }
-            idx += 1;
-            frag
-        })
-        .to_string()
+#[tauri::command]
+pub async fn save_medication(
+    medication: Medication,
+    db: State<'_, Database>,
+) -> Result<i64, String> {
+    db.save_medication(&medication)
 }
 
-fn title_case(value: &str) -> String {
-    value
-        .split_whitespace()
-        .map(|word| {
-            let mut chars = word.chars();
-            match chars.next() {
-                Some(first) => first.to_uppercase().collect::<String>() + &chars.as_str().to_lowercase(),
-                None => String::new(),
-            }
-        })
-        .collect::<Vec<_>>()
-        .join(" ")
+#[tauri::command]
+pub async fn delete_medication(id: i64, db: State<'_, Database>) -> Result<(), String> {
+    db.delete_medication(id)
 }
 
+/// Indicazioni sulla gestione della terapia antitrombotica attorno alla procedura.
 #[tauri::command]
-pub async fn generate_ambulatorio_referto(
+pub async fn get_periprocedural_instructions(
     patient_id: i64,
     db: State<'_, Database>,
-    app_handle: AppHandle,
-) -> Result<String, String> {
+) -> Result<Vec<String>, String> {
     let patient = db
         .get_patient_by_id(patient_id)?
         .ok_or_else(|| "Paziente non trovato".to_string())?;
+    let today = Local::now().format("%Y-%m-%d").to_string();
+    let medications: Vec<Medication> = db
+        .get_medications(patient_id)?
+        .into_iter()
+        .filter(|m| m.is_active_on(&today))
+        .collect();
+    Ok(periprocedural_instructions(
+        &medications,
+        parse_decimal(patient.patient.procedurale_egfr.as_deref()),
+    ))
+}
 
-    let p = patient.patient;
-    let visit_date = Local::now().format("%d/%m/%Y").to_string();
-    let visit_date_for_filename = p
-        .ambulatorio_data_visita
-        .as_deref()
-        .map(format_date_filename)
-        .filter(|d| !d.trim().is_empty())
-        .unwrap_or_else(|| Local::now().format("%d.%m.%Y").to_string());
+// ============================================================================
+// RISK FACTOR COMMANDS
+// ============================================================================
 
-    let sig_sigra = match p.sesso.as_deref() {
-        Some("F") | Some("f") => "Sig.ra".to_string(),
-        _ => "Sig.".to_string(),
-    };
+#[tauri::command]
+pub async fn get_risk_factor_vocabulary() -> Result<Vec<RiskFactorDefinition>, String> {
+    Ok(risk_factor_vocabulary())
+}
+
+#[tauri::command]
+pub async fn get_patient_risk_factors(
+    patient_id: i64,
+    db: State<'_, Database>,
+) -> Result<Vec<PatientRiskFactor>, String> {
+    db.get_patient_risk_factors(patient_id)
+}
+
+#[tauri::command]
+pub async fn save_patient_risk_factors(
+    patient_id: i64,
+    factors: Vec<PatientRiskFactor>,
+    db: State<'_, Database>,
+) -> Result<(), String> {
+    db.save_patient_risk_factors(patient_id, &factors)
+}
+
+#[tauri::command]
+pub async fn get_risk_factor_statistics(
+    db: State<'_, Database>,
+) -> Result<RiskFactorStatistics, String> {
+    db.get_risk_factor_statistics()
+}
+
+// ============================================================================
+// ECG COMMANDS
+// ============================================================================
+
+#[tauri::command]
+pub async fn get_ecg_recordings(
+    patient_id: i64,
+    db: State<'_, Database>,
+) -> Result<Vec<EcgRecording>, String> {
+    db.get_ecg_recordings(patient_id)
+}
+
+#[tauri::command]
+pub async fn save_ecg_recording(
+    ecg: EcgRecording,
+    db: State<'_, Database>,
+) -> Result<i64, String> {
+    db.save_ecg_recording(&ecg)
+}
+
+#[tauri::command]
+pub async fn delete_ecg_recording(id: i64, db: State<'_, Database>) -> Result<(), String> {
+    db.delete_ecg_recording(id)
+}
+
+#[tauri::command]
+pub async fn get_pacemaker_risk(
+    patient_id: i64,
+    db: State<'_, Database>,
+) -> Result<PacemakerRisk, String> {
+    db.get_pacemaker_risk(patient_id)
+}
+
+// ============================================================================
+// PROCEDURE PLAN COMMANDS
+// ============================================================================
+
+#[tauri::command]
+pub async fn get_procedure_plans(
+    patient_id: i64,
+    db: State<'_, Database>,
+) -> Result<Vec<ProcedurePlan>, String> {
+    db.get_procedure_plans(patient_id)
+}
+
+#[tauri::command]
+pub async fn get_procedure_plan(
+    id: i64,
+    db: State<'_, Database>,
+) -> Result<Option<ProcedurePlan>, String> {
+    db.get_procedure_plan(id)
+}
+
+#[tauri::command]
+pub async fn save_procedure_plan(
+    plan: ProcedurePlan,
+    db: State<'_, Database>,
+) -> Result<i64, String> {
+    db.save_procedure_plan(&plan)
+}
+
+#[tauri::command]
+pub async fn delete_procedure_plan(id: i64, db: State<'_, Database>) -> Result<(), String> {
+    db.delete_procedure_plan(id)
+}
+
+#[tauri::command]
+pub async fn compare_plan_with_procedure(
+    procedure_id: i64,
+    db: State<'_, Database>,
+) -> Result<PlanComparison, String> {
+    db.compare_plan_with_procedure(procedure_id)
+}
+
+// ============================================================================
+// AMBULATORIO CALENDAR COMMANDS
+// ============================================================================
+
+/// Mantiene allineate le date aperte salvate nelle impostazioni con il calendario
+fn mirror_open_dates_to_settings(db: &Database) -> Result<(), String> {
+    let mut settings = read_settings_from_disk().unwrap_or_default();
+    settings.ambulatorio_open_dates = Some(db.get_ambulatorio_open_dates()?);
+    write_settings_to_disk(&settings)
+}
+
+#[tauri::command]
+pub async fn get_ambulatorio_slot_templates(
+    db: State<'_, Database>,
+) -> Result<Vec<AmbulatorioSlotTemplate>, String> {
+    db.get_ambulatorio_slot_templates()
+}
+
+#[tauri::command]
+pub async fn save_ambulatorio_slot_template(
+    template: AmbulatorioSlotTemplate,
+    db: State<'_, Database>,
+) -> Result<i64, String> {
+    db.save_ambulatorio_slot_template(&template)
+}
+
+#[tauri::command]
+pub async fn delete_ambulatorio_slot_template(id: i64, db: State<'_, Database>) -> Result<(), String> {
+    db.delete_ambulatorio_slot_template(id)
+}
+
+#[tauri::command]
+pub async fn get_ambulatorio_sessions(
+    date_from: Option<String>,
+    date_to: Option<String>,
+    db: State<'_, Database>,
+) -> Result<Vec<AmbulatorioSession>, String> {
+    db.get_ambulatorio_sessions(date_from.as_deref(), date_to.as_deref())
+}
+
+#[tauri::command]
+pub async fn open_ambulatorio_session(
+    data: String,
+    note: Option<String>,
+    db: State<'_, Database>,
+) -> Result<i64, String> {
+    let id = db.open_ambulatorio_session(&data, note)?;
+    mirror_open_dates_to_settings(&db)?;
+    Ok(id)
+}
+
+#[tauri::command]
+pub async fn close_ambulatorio_session(data: String, db: State<'_, Database>) -> Result<(), String> {
+    db.close_ambulatorio_session(&data)?;
+    mirror_open_dates_to_settings(&db)
+}
+
+#[tauri::command]
+pub async fn get_ambulatorio_blocked_slots(
+    date_from: Option<String>,
+    date_to: Option<String>,
+    db: State<'_, Database>,
+) -> Result<Vec<AmbulatorioBlockedSlot>, String> {
+    db.get_ambulatorio_blocked_slots(date_from.as_deref(), date_to.as_deref())
+}
+
+#[tauri::command]
+pub async fn block_ambulatorio_slot(
+    data: String,
+    orario: String,
+    motivo: Option<String>,
+    db: State<'_, Database>,
+) -> Result<i64, String> {
+    db.block_ambulatorio_slot(&data, &orario, motivo)
+}
+
+#[tauri::command]
+pub async fn unblock_ambulatorio_slot(id: i64, db: State<'_, Database>) -> Result<(), String> {
+    db.unblock_ambulatorio_slot(id)
+}
+
+#[tauri::command]
+pub async fn get_ambulatorio_free_slots(
+    date_from: String,
+    date_to: String,
+    db: State<'_, Database>,
+) -> Result<Vec<AmbulatorioSlot>, String> {
+    db.get_ambulatorio_free_slots(&date_from, &date_to)
+}
+
+#[tauri::command]
+pub async fn book_ambulatorio_slot(
+    patient_id: i64,
+    data: String,
+    orario: String,
+    db: State<'_, Database>,
+) -> Result<(), String> {
+    db.book_ambulatorio_slot(patient_id, &data, &orario)
+}
+
+#[tauri::command]
+pub async fn move_ambulatorio_booking(
+    patient_id: i64,
+    data: String,
+    orario: String,
+    db: State<'_, Database>,
+) -> Result<(), String> {
+    db.move_ambulatorio_booking(patient_id, &data, &orario)
+}
+
+#[tauri::command]
+pub async fn cancel_ambulatorio_booking(patient_id: i64, db: State<'_, Database>) -> Result<(), String> {
+    db.cancel_ambulatorio_booking(patient_id)
+}
+
+// ============================================================================
+// APPOINTMENT COMMANDS
+// ============================================================================
+
+#[tauri::command]
+pub async fn get_patient_appointments(patient_id: i64, db: State<'_, Database>) -> Result<Vec<Appointment>, String> {
+    db.get_patient_appointments(patient_id)
+}
+
+#[tauri::command]
+pub async fn get_appointments(
+    date_from: Option<String>,
+    date_to: Option<String>,
+    stato: Option<String>,
+    db: State<'_, Database>,
+) -> Result<Vec<AppointmentWithPatient>, String> {
+    db.get_appointments(date_from.as_deref(), date_to.as_deref(), stato.as_deref())
+}
+
+#[tauri::command]
+pub async fn save_appointment(appointment: Appointment, db: State<'_, Database>) -> Result<i64, String> {
+    db.save_appointment(&appointment)
+}
+
+#[tauri::command]
+pub async fn set_appointment_status(id: i64, stato: String, db: State<'_, Database>) -> Result<(), String> {
+    db.set_appointment_status(id, &stato)
+}
+
+#[tauri::command]
+pub async fn delete_appointment(id: i64, db: State<'_, Database>) -> Result<(), String> {
+    db.delete_appointment(id)
+}
+
+// ============================================================================
+// CATH-LAB PLANNER COMMANDS
+// ============================================================================
+
+#[tauri::command]
+pub async fn get_cathlab_sessions(
+    date_from: Option<String>,
+    date_to: Option<String>,
+    db: State<'_, Database>,
+) -> Result<Vec<CathLabSession>, String> {
+    db.get_cathlab_sessions(date_from.as_deref(), date_to.as_deref())
+}
+
+#[tauri::command]
+pub async fn save_cathlab_session(session: CathLabSession, db: State<'_, Database>) -> Result<i64, String> {
+    db.save_cathlab_session(&session)
+}
+
+#[tauri::command]
+pub async fn delete_cathlab_session(id: i64, db: State<'_, Database>) -> Result<(), String> {
+    db.delete_cathlab_session(id)
+}
+
+#[tauri::command]
+pub async fn add_cathlab_case(
+    session_id: i64,
+    patient_id: i64,
+    durata_minuti: Option<i32>,
+    note: Option<String>,
+    db: State<'_, Database>,
+) -> Result<i64, String> {
+    db.add_cathlab_case(session_id, patient_id, durata_minuti, note)
+}
+
+#[tauri::command]
+pub async fn move_cathlab_case(case_id: i64, session_id: i64, db: State<'_, Database>) -> Result<(), String> {
+    db.move_cathlab_case(case_id, session_id)
+}
+
+#[tauri::command]
+pub async fn remove_cathlab_case(case_id: i64, db: State<'_, Database>) -> Result<(), String> {
+    db.remove_cathlab_case(case_id)
+}
+
+#[tauri::command]
+pub async fn reorder_cathlab_cases(
+    session_id: i64,
+    case_ids: Vec<i64>,
+    db: State<'_, Database>,
+) -> Result<(), String> {
+    db.reorder_cathlab_cases(session_id, &case_ids)
+}
+
+#[tauri::command]
+pub async fn update_cathlab_case(
+    case_id: i64,
+    durata_minuti: Option<i32>,
+    note: Option<String>,
+    db: State<'_, Database>,
+) -> Result<(), String> {
+    db.update_cathlab_case(case_id, durata_minuti, note)
+}
+
+#[tauri::command]
+pub async fn get_cathlab_duration_estimates(db: State<'_, Database>) -> Result<Vec<CathLabDurationEstimate>, String> {
+    db.get_cathlab_duration_estimates()
+}
+
+#[tauri::command]
+pub async fn suggest_cathlab_slots(
+    date_from: Option<String>,
+    date_to: Option<String>,
+    db: State<'_, Database>,
+) -> Result<Vec<CathLabSlotSuggestion>, String> {
+    db.suggest_cathlab_slots(date_from.as_deref(), date_to.as_deref())
+}
+
+// ============================================================================
+// ICALENDAR COMMANDS
+// ============================================================================
+
+fn code_label(options: &[(&str, &str)], code: &str) -> String {
+    options
+        .iter()
+        .find(|(c, _)| *c == code)
+        .map(|(_, label)| label.to_string())
+        .unwrap_or_else(|| code.to_string())
+}
+
+/// Durata di una visita: quella del modello di slot del giorno e orario, altrimenti la predefinita
+fn visit_duration_minutes(
+    templates: &[AmbulatorioSlotTemplate],
+    date: chrono::NaiveDate,
+    orario: Option<&str>,
+) -> i32 {
+    use chrono::Datelike;
+
+    templates
+        .iter()
+        .find(|t| t.giorno_settimana == date.weekday().number_from_monday() && orario == Some(t.orario.as_str()))
+        .and_then(|t| t.durata_minuti)
+        .unwrap_or(AMBULATORIO_DEFAULT_VISIT_MINUTES)
+}
+
+/// Esporta in un file .ics le visite ambulatoriali e le date TAVI dell'intervallo.
+/// Con `pseudonimizza` i titoli riportano solo l'identificativo interno del paziente
+/// e le descrizioni non contengono dati clinici.
+#[tauri::command]
+pub async fn export_schedule_ics(
+    output_path: String,
+    date_from: String,
+    date_to: String,
+    include_ambulatorio: Option<bool>,
+    include_tavi: Option<bool>,
+    pseudonimizza: bool,
+    db: State<'_, Database>,
+) -> Result<String, String> {
+    let parse = |value: &str| {
+        chrono::NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d")
+            .map_err(|_| format!("Data non valida: {}", value))
+    };
+    let (from, to) = (parse(&date_from)?, parse(&date_to)?);
+    if to < from {
+        return Err("La data finale precede la data iniziale".to_string());
+    }
+    let (from, to) = (from.format("%Y-%m-%d").to_string(), to.format("%Y-%m-%d").to_string());
+    let patient_label = |patient_id: i64, cognome: &str, nome: &str| {
+        if pseudonimizza {
+            format!("Paziente #{}", patient_id)
+        } else {
+            format!("{} {}", cognome.to_uppercase(), nome)
+        }
+    };
+
+    let mut events = Vec::new();
+
+    if include_ambulatorio.unwrap_or(true) {
+        let templates = db.get_ambulatorio_slot_templates()?;
+        for item in db.get_appointments(Some(&from), Some(&to), None)? {
+            let a = &item.appointment;
+            if a.stato == "annullato" {
+                continue;
+            }
+            let Ok(date) = chrono::NaiveDate::parse_from_str(&a.data, "%Y-%m-%d") else {
+                continue;
+            };
+            let summary = format!(
+                "{}: {}",
+                code_label(&APPOINTMENT_TYPES, &a.tipo),
+                patient_label(a.patient_id, &item.cognome, &item.nome)
+            );
+            let mut description = vec![format!("Stato: {}", code_label(&APPOINTMENT_STATUSES, &a.stato))];
+            if !pseudonimizza {
+                if let Some(medico) = a.medico_nome.as_deref().filter(|m| !m.trim().is_empty()) {
+                    description.push(format!(
+                        "Medico: {} {}",
+                        a.medico_titolo.as_deref().unwrap_or_default(),
+                        medico
+                    ));
+                }
+            }
+            let uid = ics::event_uid("visita", &a.id.unwrap_or_default().to_string());
+
+            let start = a
+                .orario
+                .as_deref()
+                .and_then(|o| chrono::NaiveTime::parse_from_str(o, "%H:%M").ok())
+                .map(|time| date.and_time(time));
+            events.push(match start {
+                Some(start) => {
+                    let minutes = visit_duration_minutes(&templates, date, a.orario.as_deref());
+                    IcsEvent::timed(uid, start, minutes as i64, summary, Some(description.join("\n")))
+                }
+                None => IcsEvent::all_day(uid, date, summary, Some(description.join("\n"))),
+            });
+        }
+    }
+
+    if include_tavi.unwrap_or(true) {
+        // Orario e durata stimati dal planner di sala, quando il caso è in una seduta
+        let mut cases = HashMap::new();
+        for session in db.get_cathlab_sessions(Some(&from), Some(&to))? {
+            let total = session.casi.len();
+            for case in session.casi {
+                cases.insert((case.patient_id, session.data.clone()), (case, total));
+            }
+        }
+
+        let filters = PatientFilters {
+            data_tavi_from: Some(from.clone()),
+            data_tavi_to: Some(to.clone()),
+            ..Default::default()
+        };
+        for item in db.get_all_patients_with_status(Some(filters))? {
+            let p = &item.patient;
+            let (Some(patient_id), Some(data_tavi)) = (p.id, p.data_tavi.as_deref().map(str::trim)) else {
+                continue;
+            };
+            let Ok(date) = chrono::NaiveDate::parse_from_str(data_tavi, "%Y-%m-%d") else {
+                continue;
+            };
+            let summary = format!("TAVI: {}", patient_label(patient_id, &p.cognome, &p.nome));
+            let case = cases.get(&(patient_id, data_tavi.to_string()));
+
+            let mut description = Vec::new();
+            if let Some((case, total)) = case {
+                description.push(format!("Caso {} di {}", case.ordine, total));
+            }
+            if !pseudonimizza {
+                if let Some(modello) = p.procedurale_bioprotesi_modello.as_deref().filter(|m| !m.trim().is_empty()) {
+                    description.push(format!("Valvola pianificata: {}", modello));
+                }
+                if let Some(anestesia) = p.procedurale_anestesia.as_deref().filter(|a| !a.trim().is_empty()) {
+                    description.push(format!("Anestesia: {}", anestesia));
+                }
+            }
+            let description = Some(description.join("\n"));
+            let uid = ics::event_uid("tavi", &format!("{}-{}", patient_id, data_tavi));
+
+            let start = case.and_then(|(case, _)| {
+                chrono::NaiveTime::parse_from_str(&case.orario_stimato, "%H:%M")
+                    .ok()
+                    .map(|time| (date.and_time(time), case.durata_stimata_minuti))
+            });
+            events.push(match start {
+                Some((start, minutes)) => IcsEvent::timed(uid, start, minutes as i64, summary, description),
+                None => IcsEvent::all_day(uid, date, summary, description),
+            });
+        }
+    }
+
+    events.sort_by_key(|e| e.start);
+    let calendar = ics::write_calendar("Registro TAVI", &events);
+
+    let out_path = PathBuf::from(&output_path);
+    if let Some(parent) = out_path.parent() {
+        create_dir_all(parent).map_err(|_| "Impossibile creare la cartella di destinazione".to_string())?;
+    }
+    let mut out_file =
+        File::create(&out_path).map_err(|_| "Impossibile creare il file di esportazione".to_string())?;
+    out_file
+        .write_all(calendar.as_bytes())
+        .map_err(|_| "Errore salvataggio esportazione".to_string())?;
+
+    Ok(out_path.to_string_lossy().to_string())
+}
+
+/// Importa i giorni di apertura dell'ambulatorio da un file .ics: ogni giorno con un evento
+/// diventa una seduta aperta. Restituisce le date importate.
+#[tauri::command]
+pub async fn import_ambulatorio_open_dates_ics(
+    path: String,
+    db: State<'_, Database>,
+) -> Result<Vec<String>, String> {
+    let content = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Impossibile leggere il calendario: {}", e))?;
+    let dates: Vec<String> = ics::parse_event_dates(&content)?
+        .into_iter()
+        .map(|d| d.format("%Y-%m-%d").to_string())
+        .collect();
+    if dates.is_empty() {
+        return Err("Il calendario non contiene eventi con una data".to_string());
+    }
+
+    db.sync_ambulatorio_sessions(&dates, false)?;
+    mirror_open_dates_to_settings(&db)?;
+    Ok(dates)
+}
+
+// ============================================================================
+// EXCEL EXPORT COMMANDS
+// ============================================================================
+
+fn main_access_label(code: &str) -> String {
+    MAIN_ACCESS_OPTIONS
+        .iter()
+        .find(|(c, _)| *c == code)
+        .map(|(_, label)| label.to_string())
+        .unwrap_or_else(|| code.to_string())
+}
+
+fn patient_export_cell(item: &PatientWithStatus, code: &str, today: chrono::NaiveDate) -> XlsxCell {
+    let p = &item.patient;
+    match code {
+        "id" => XlsxCell::number(p.id.map(|v| v as f64)),
+        "cognome" => XlsxCell::text(Some(&p.cognome)),
+        "nome" => XlsxCell::text(Some(&p.nome)),
+        "data_nascita" => XlsxCell::date(Some(&p.data_nascita)),
+        "eta" => XlsxCell::number(age_at(&p.data_nascita, today).map(f64::from)),
+        "sesso" => XlsxCell::text(p.sesso.as_deref()),
+        "luogo_nascita" => XlsxCell::text(p.luogo_nascita.as_deref()),
+        "codice_fiscale" => XlsxCell::text(p.codice_fiscale.as_deref()),
+        "telefono" => XlsxCell::text(p.telefono.as_deref()),
+        "email" => XlsxCell::text(p.email.as_deref()),
+        "provenienza" => XlsxCell::text(p.provenienza.as_deref()),
+        "priority" => XlsxCell::text(p.priority.map(|v| capitalize_first(v.as_str())).as_deref()),
+        "stato" => XlsxCell::text(Some(&item.status)),
+        "stato_dal" => XlsxCell::date(Some(&item.status_created_at)),
+        "altezza" => XlsxCell::number(p.altezza),
+        "peso" => XlsxCell::number(p.peso),
+        "medico" => {
+            let medico = [p.medico_titolo.as_deref(), p.medico_nome.as_deref()]
+                .into_iter()
+                .flatten()
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .collect::<Vec<_>>()
+                .join(" ");
+            XlsxCell::text(Some(&medico))
+        }
+        "ambulatorio_data_visita" => XlsxCell::date(p.ambulatorio_data_visita.as_deref()),
+        "ambulatorio_orario_visita" => XlsxCell::text(p.ambulatorio_orario_visita.as_deref()),
+        "data_tavi" => XlsxCell::date(p.data_tavi.as_deref()),
+        "creatinina" => XlsxCell::number(parse_decimal(p.procedurale_creatinina.as_deref())),
+        "egfr" => XlsxCell::number(parse_decimal(p.procedurale_egfr.as_deref())),
+        "hb" => XlsxCell::number(parse_decimal(p.procedurale_hb.as_deref())),
+        "allergia_mdc" => XlsxCell::text(p.procedurale_allergia_mdc.as_deref()),
+        "anestesia" => XlsxCell::text(p.procedurale_anestesia.as_deref()),
+        "accesso_principale" => match p.procedurale_accesso_principale_fem.as_deref().map(str::trim) {
+            Some("altro") => XlsxCell::text(
+                p.procedurale_accesso_principale_altro
+                    .as_deref()
+                    .filter(|v| !v.trim().is_empty())
+                    .or(Some("Altro")),
+            ),
+            Some(code) if !code.is_empty() => XlsxCell::Text(main_access_label(code)),
+            _ => XlsxCell::Empty,
+        },
+        "bioprotesi_modello" => XlsxCell::text(p.procedurale_bioprotesi_modello.as_deref()),
+        "bioprotesi_dimensione" => match parse_decimal(p.procedurale_bioprotesi_dimensione.as_deref()) {
+            Some(size) => XlsxCell::Number(size),
+            None => XlsxCell::text(p.procedurale_bioprotesi_dimensione.as_deref()),
+        },
+        "note" => XlsxCell::text(p.note.as_deref()),
+        "conclusioni" => XlsxCell::text(p.conclusioni.as_deref()),
+        _ => XlsxCell::Empty,
+    }
+}
+
+fn procedure_export_cell(proc: &Procedure, code: &str) -> XlsxCell {
+    match code {
+        "id" => XlsxCell::number(proc.id.map(|v| v as f64)),
+        "nome" => XlsxCell::text(Some(&proc.nome)),
+        "cognome" => XlsxCell::text(Some(&proc.cognome)),
+        "data_nascita" => XlsxCell::date(Some(&proc.data_nascita)),
+        "eta" => XlsxCell::number(
+            chrono::NaiveDate::parse_from_str(&proc.data_procedura, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| age_at(&proc.data_nascita, date))
+                .map(f64::from),
+        ),
+        "altezza" => XlsxCell::number(proc.altezza),
+        "peso" => XlsxCell::number(proc.peso),
+        "bmi" => XlsxCell::number(match (proc.altezza, proc.peso) {
+            (Some(h), Some(w)) if h > 0.0 && w > 0.0 => Some((w / (h / 100.0).powi(2) * 10.0).round() / 10.0),
+            _ => None,
+        }),
+        "fe" => XlsxCell::number(proc.fe),
+        "vmax" => XlsxCell::number(proc.vmax),
+        "gmax" => XlsxCell::number(proc.gmax),
+        "gmed" => XlsxCell::number(proc.gmed),
+        "ava" => XlsxCell::number(proc.ava),
+        "anulus_aortico" => XlsxCell::number(proc.anulus_aortico),
+        "valvola_protesica" => XlsxCell::Bool(proc.valvola_protesica),
+        "protesica_modello" => XlsxCell::text(proc.protesica_modello.as_deref()),
+        "protesica_dimensione" => XlsxCell::text(proc.protesica_dimensione.as_deref()),
+        "data_procedura" => XlsxCell::date(Some(&proc.data_procedura)),
+        "ora_inizio" => XlsxCell::text(Some(&proc.ora_inizio)),
+        "ora_fine" => XlsxCell::text(Some(&proc.ora_fine)),
+        "durata_minuti" => XlsxCell::number(proc.calculate_duration_minutes().map(f64::from)),
+        "tipo_valvola" => XlsxCell::text(Some(&proc.tipo_valvola)),
+        "modello_valvola" => XlsxCell::text(Some(&proc.modello_valvola)),
+        "dimensione_valvola" => XlsxCell::number(proc.dimensione_valvola),
+        "pre_dilatazione" => XlsxCell::Bool(proc.pre_dilatazione),
+        "post_dilatazione" => XlsxCell::Bool(proc.post_dilatazione),
+        "accesso_principale" => match proc.accesso_principale.as_deref().map(str::trim) {
+            Some(code) if !code.is_empty() => XlsxCell::Text(main_access_label(code)),
+            _ => XlsxCell::Empty,
+        },
+        "operatore" => XlsxCell::text(proc.operatore.as_deref()),
+        "plan_id" => XlsxCell::number(proc.plan_id.map(|v| v as f64)),
+        _ => XlsxCell::Empty,
+    }
+}
+
+fn statistics_sheet(stats: &Statistics) -> XlsxSheet {
+    let round = |value: f64| (value * 100.0).round() / 100.0;
+    let mut rows: Vec<(String, Option<f64>)> = vec![
+        ("Totale procedure".to_string(), Some(stats.total_procedures as f64)),
+        ("Durata media (minuti)".to_string(), Some(stats.average_duration_minutes)),
+        ("Pre-dilatazione (%)".to_string(), Some(stats.pre_dilatazione_percentage)),
+        ("Post-dilatazione (%)".to_string(), Some(stats.post_dilatazione_percentage)),
+        ("Balloon Expandable".to_string(), Some(stats.balloon_expandable_count as f64)),
+        ("Self Expandable".to_string(), Some(stats.self_expandable_count as f64)),
+        ("Procedure su valvola nativa".to_string(), Some(stats.native_procedures as f64)),
+        ("Procedure valve-in-valve".to_string(), Some(stats.viv_procedures as f64)),
+        ("Durata media nativa (minuti)".to_string(), stats.average_duration_native_minutes),
+        ("Durata media ViV (minuti)".to_string(), stats.average_duration_viv_minutes),
+        ("FE media (%)".to_string(), stats.average_fe),
+        ("Vmax media (m/s)".to_string(), stats.average_vmax),
+        ("Gmax medio (mmHg)".to_string(), stats.average_gmax),
+        ("Gmed medio (mmHg)".to_string(), stats.average_gmed),
+        ("AVA media (cm²)".to_string(), stats.average_ava),
+    ];
+    let outcomes = &stats.outcome_rates;
+    rows.extend([
+        ("Esiti registrati".to_string(), Some(outcomes.outcomes_recorded as f64)),
+        ("Mortalità (%)".to_string(), outcomes.mortality_percentage),
+        ("Stroke (%)".to_string(), outcomes.stroke_percentage),
+        ("Complicanze vascolari maggiori (%)".to_string(), outcomes.major_vascular_percentage),
+        ("Sanguinamenti maggiori (%)".to_string(), outcomes.major_bleeding_percentage),
+        ("AKI (%)".to_string(), outcomes.aki_percentage),
+        ("Nuovo pacemaker (%)".to_string(), outcomes.new_pacemaker_percentage),
+        ("PVL moderato-severo (%)".to_string(), outcomes.moderate_severe_pvl_percentage),
+        ("Ostruzione coronarica (%)".to_string(), outcomes.coronary_obstruction_percentage),
+        ("Conversione chirurgica (%)".to_string(), outcomes.conversion_to_surgery_percentage),
+        ("Device success (%)".to_string(), outcomes.device_success_percentage),
+        ("Early safety (%)".to_string(), outcomes.early_safety_percentage),
+    ]);
+    for (model, count) in &stats.top_valve_models {
+        rows.push((format!("Modello: {}", model), Some(*count as f64)));
+    }
+
+    XlsxSheet {
+        name: "Statistiche".to_string(),
+        headers: vec!["Metrica".to_string(), "Valore".to_string()],
+        rows: rows
+            .into_iter()
+            .map(|(label, value)| vec![XlsxCell::Text(label), XlsxCell::number(value.map(round))])
+            .collect(),
+    }
+}
+
+fn sheet_title(code: &str) -> String {
+    XLSX_EXPORT_SHEETS
+        .iter()
+        .find(|(c, _)| *c == code)
+        .map(|(_, name)| name.to_string())
+        .unwrap_or_else(|| code.to_string())
+}
+
+#[tauri::command]
+pub async fn get_xlsx_export_columns() -> Result<XlsxExportColumns, String> {
+    Ok(XlsxExportColumns {
+        pazienti: export_columns(&PATIENT_EXPORT_COLUMNS),
+        procedure: export_columns(&PROCEDURE_EXPORT_COLUMNS),
+    })
+}
+
+/// Esporta in un file .xlsx i pazienti (con gli stessi filtri della lista), le procedure,
+/// lo storico degli stati dei pazienti esportati e le statistiche, con le colonne scelte.
+#[tauri::command]
+pub async fn export_xlsx(
+    output_path: String,
+    options: Option<XlsxExportOptions>,
+    db: State<'_, Database>,
+) -> Result<String, String> {
+    let options = options.unwrap_or_default();
+    if let Some(sheets) = &options.sheets {
+        if let Some(unknown) = sheets.iter().find(|s| !XLSX_EXPORT_SHEETS.iter().any(|(c, _)| c == *s)) {
+            return Err(format!("Foglio di esportazione non valido: {}", unknown));
+        }
+    }
+    let patient_columns = select_export_columns(&PATIENT_EXPORT_COLUMNS, options.patient_columns.as_deref())?;
+    let procedure_columns = select_export_columns(&PROCEDURE_EXPORT_COLUMNS, options.procedure_columns.as_deref())?;
+    let today = Local::now().date_naive();
+
+    // Stessi filtri della lista pazienti, ma senza paginazione
+    let patients = if options.includes_sheet("pazienti") || options.includes_sheet("storico_stati") {
+        let mut filters = options.patient_filters.clone().unwrap_or_default();
+        filters.limit = None;
+        filters.offset = None;
+        db.get_all_patients_with_status(Some(filters))?
+    } else {
+        Vec::new()
+    };
+
+    let mut sheets = Vec::new();
+    if options.includes_sheet("pazienti") {
+        sheets.push(XlsxSheet {
+            name: sheet_title("pazienti"),
+            headers: patient_columns.iter().map(|(_, label)| label.to_string()).collect(),
+            rows: patients
+                .iter()
+                .map(|item| patient_columns.iter().map(|(code, _)| patient_export_cell(item, code, today)).collect())
+                .collect(),
+        });
+    }
+    if options.includes_sheet("procedure") {
+        let procedures = db.get_all_procedures(options.procedure_filters.clone())?;
+        sheets.push(XlsxSheet {
+            name: sheet_title("procedure"),
+            headers: procedure_columns.iter().map(|(_, label)| label.to_string()).collect(),
+            rows: procedures
+                .iter()
+                .map(|proc| procedure_columns.iter().map(|(code, _)| procedure_export_cell(proc, code)).collect())
+                .collect(),
+        });
+    }
+    if options.includes_sheet("storico_stati") {
+        let ids: Vec<i64> = patients.iter().filter_map(|item| item.patient.id).collect();
+        let history = db.get_status_history(Some(&ids))?;
+        sheets.push(XlsxSheet {
+            name: sheet_title("storico_stati"),
+            headers: ["ID paziente", "Cognome", "Nome", "Stato", "Data"]
+                .iter()
+                .map(|h| h.to_string())
+                .collect(),
+            rows: history
+                .iter()
+                .map(|entry| {
+                    vec![
+                        XlsxCell::Number(entry.patient_id as f64),
+                        XlsxCell::text(Some(&entry.cognome)),
+                        XlsxCell::text(Some(&entry.nome)),
+                        XlsxCell::text(Some(&entry.stato)),
+                        XlsxCell::date(Some(&entry.changed_at)),
+                    ]
+                })
+                .collect(),
+        });
+    }
+    if options.includes_sheet("statistiche") {
+        let stats = db.calculate_statistics(options.procedure_filters.clone())?;
+        sheets.push(statistics_sheet(&stats));
+    }
+
+    let bytes = xlsx::write_workbook(&sheets)?;
+    let out_path = PathBuf::from(&output_path);
+    if let Some(parent) = out_path.parent() {
+        create_dir_all(parent).map_err(|_| "Impossibile creare la cartella di destinazione".to_string())?;
+    }
+    let mut out_file =
+        File::create(&out_path).map_err(|_| "Impossibile creare il file di esportazione".to_string())?;
+    out_file
+        .write_all(&bytes)
+        .map_err(|_| "Errore salvataggio esportazione".to_string())?;
+
+    Ok(out_path.to_string_lossy().to_string())
+}
+
+// ============================================================================
+// CT PLANNING COMMANDS
+// ============================================================================
+
+#[tauri::command]
+pub async fn get_ct_plannings(
+    patient_id: i64,
+    db: State<'_, Database>,
+) -> Result<Vec<CtPlanning>, String> {
+    db.get_ct_plannings_by_patient(patient_id)
+}
+
+#[tauri::command]
+pub async fn get_ct_planning_by_id(
+    id: i64,
+    db: State<'_, Database>,
+) -> Result<Option<CtPlanning>, String> {
+    db.get_ct_planning_by_id(id)
+}
+
+#[tauri::command]
+pub async fn create_ct_planning(
+    planning: CtPlanning,
+    db: State<'_, Database>,
+) -> Result<i64, String> {
+    db.insert_ct_planning(&planning)
+}
+
+#[tauri::command]
+pub async fn update_ct_planning(
+    planning: CtPlanning,
+    db: State<'_, Database>,
+) -> Result<(), String> {
+    db.update_ct_planning(&planning)
+}
+
+#[tauri::command]
+pub async fn delete_ct_planning(
+    id: i64,
+    db: State<'_, Database>,
+) -> Result<(), String> {
+    db.delete_ct_planning(id)
+}
+
+#[tauri::command]
+pub async fn get_valve_sizing_charts(
+    modello_valvola: Option<String>,
+    db: State<'_, Database>,
+) -> Result<Vec<ValveSizingChart>, String> {
+    db.get_valve_sizing_charts(modello_valvola.as_deref())
+}
+
+#[tauri::command]
+pub async fn save_valve_sizing_chart(
+    chart: ValveSizingChart,
+    db: State<'_, Database>,
+) -> Result<i64, String> {
+    db.save_valve_sizing_chart(&chart)
+}
+
+#[tauri::command]
+pub async fn delete_valve_sizing_chart(
+    id: i64,
+    db: State<'_, Database>,
+) -> Result<(), String> {
+    db.delete_valve_sizing_chart(id)
+}
+
+#[tauri::command]
+pub async fn suggest_valve_sizes(
+    ct_planning_id: i64,
+    modello_valvola: Option<String>,
+    db: State<'_, Database>,
+) -> Result<CtSizingResult, String> {
+    db.suggest_valve_sizes(ct_planning_id, modello_valvola.as_deref())
+}
+
+// ============================================================================
+// VALVE CATALOGUE COMMANDS
+// ============================================================================
+
+#[tauri::command]
+pub async fn get_valve_models(db: State<'_, Database>) -> Result<Vec<ValveModel>, String> {
+    db.get_valve_models()
+}
+
+#[tauri::command]
+pub async fn create_valve_model(
+    model: ValveModel,
+    db: State<'_, Database>,
+) -> Result<i64, String> {
+    db.insert_valve_model(&model)
+}
+
+#[tauri::command]
+pub async fn update_valve_model(
+    model: ValveModel,
+    db: State<'_, Database>,
+) -> Result<(), String> {
+    db.update_valve_model(&model)
+}
+
+#[tauri::command]
+pub async fn delete_valve_model(
+    id: i64,
+    db: State<'_, Database>,
+) -> Result<(), String> {
+    db.delete_valve_model(id)
+}
+
+#[tauri::command]
+pub async fn validate_procedure(
+    procedure: Procedure,
+    db: State<'_, Database>,
+) -> Result<Vec<String>, String> {
+    db.check_procedure_valve(&procedure)
+}
+
+// ============================================================================
+// VALVE-IN-VALVE COMMANDS
+// ============================================================================
+
+#[tauri::command]
+pub async fn get_prosthesis_models(db: State<'_, Database>) -> Result<Vec<ProsthesisModel>, String> {
+    db.get_prosthesis_models()
+}
+
+#[tauri::command]
+pub async fn save_prosthesis_model(
+    model: ProsthesisModel,
+    db: State<'_, Database>,
+) -> Result<i64, String> {
+    db.save_prosthesis_model(&model)
+}
+
+#[tauri::command]
+pub async fn delete_prosthesis_model(
+    id: i64,
+    db: State<'_, Database>,
+) -> Result<(), String> {
+    db.delete_prosthesis_model(id)
+}
+
+#[tauri::command]
+pub async fn check_viv_compatibility(
+    prosthesis_id: i64,
+    altezza: Option<f64>,
+    peso: Option<f64>,
+    db: State<'_, Database>,
+) -> Result<VivCompatibilityResult, String> {
+    db.check_viv_compatibility(prosthesis_id, altezza, peso)
+}
+
+// ============================================================================
+// REFERTI
+// ============================================================================
+
+fn format_date_ita(date_iso: &str) -> String {
+    chrono::NaiveDate::parse_from_str(date_iso, "%Y-%m-%d")
+        .map(|d| d.format("%d/%m/%Y").to_string())
+        .unwrap_or_else(|_| date_iso.to_string())
+}
+
+fn format_date_filename(raw_date: &str) -> String {
+    let value = raw_date.trim();
+    if value.is_empty() {
+        return String::new();
+    }
+
+    let parsed = chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .or_else(|_| chrono::NaiveDate::parse_from_str(value, "%d/%m/%Y"))
+        .or_else(|_| chrono::NaiveDate::parse_from_str(value, "%d-%m-%Y"))
+        .or_else(|_| chrono::NaiveDate::parse_from_str(value, "%d.%m.%Y"));
+
+    if let Ok(date) = parsed {
+        return date.format("%d.%m.%Y").to_string();
+    }
+
+    value.replace('/', ".").replace('-', ".")
+}
+
+fn sanitize_filename(name: &str) -> String {
+    name.chars()
+        .map(|c| if ['\\', '/', ':', '*', '?', '"', '<', '>', '|'].contains(&c) { '_' } else { c })
+        .collect()
+}
+
+fn capitalize_first(s: &str) -> String {
+    let mut chars = s.chars();
+    if let Some(first) = chars.next() {
+        first.to_uppercase().collect::<String>() + chars.as_str()
+    } else {
+        String::new()
+    }
+}
+
+fn replace_placeholders(content: &str, map: &HashMap<&str, String>) -> String {
+    // Rimuove marcatori di controllo ortografico e normalizza i placeholder spezzati da tag.
+    let proof_err_re = Regex::new(r"<w:proofErr[^>]*/>").unwrap();
+    let tag_re = Regex::new(r"<[^>]+>").unwrap();
+    let placeholder_clean_re = Regex::new(r"\{([^}]*)\}").unwrap();
+
+    let mut out = proof_err_re.replace_all(content, "").to_string();
+    out = placeholder_clean_re
+        .replace_all(&out, |caps: &regex::Captures| {
+            let inner = &caps[1];
+            let cleaned = tag_re.replace_all(inner, "");
+            let cleaned: String = cleaned
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .collect();
+            format!("{{{}}}", cleaned)
+        })
+        .to_string();
+    for (key, value) in map {
+        // Escapa XML e preserva gli a-capo trasformandoli in <w:br/> per Word.
+        let escaped = value
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;");
+        let normalized = escaped
+            .replace("\r\n", "<w:br/>")
+            .replace('\n', "<w:br/>")
+            .replace("&lt;w:br/&gt;", "<w:br/>"); // ripristina i break
+
+        let placeholder = format!("{{{}}}", key);
+        out = out.replace(&placeholder, &normalized);
+
+        // Alcuni placeholder possono essere rimasti senza parentesi graffe nel template (es. fdrcv).
+        let tag_placeholder = format!(">{}<", key);
+        if out.contains(&tag_placeholder) {
+            let replacement = format!(">{}<", normalized);
+            out = out.replace(&tag_placeholder, &replacement);
+        }
+    }
+    out
+}
+
+fn apply_checkbox_flags(content: &str, flags: &[bool]) -> String {
+    let cb_re = Regex::new(r"<w:checkBox>.*?</w:checkBox>").unwrap();
+    let default_re = Regex::new(r#"<w:default w:val="([01])"\s*/>"#).unwrap();
+    let checked_re = Regex::new(r#"<w:checked w:val="([01])"\s*/>"#).unwrap();
+
+    let mut idx = 0usize;
+    cb_re
+        .replace_all(content, |caps: &regex::Captures| {
+            let mut frag = caps[0].to_string();
+            if let Some(flag) = flags.get(idx) {
+                if *flag {
+                    frag = default_re
+                        .replace_all(&frag, r#"<w:default w:val="1"/>"#)
+                        .to_string();
+                    if !frag.contains("w:default") {
+                        frag = frag.replace(
+                            "<w:checkBox>",
+                            "<w:checkBox><w:default w:val=\"1\"/>",
+                        );
+                    }
+                    if checked_re.is_match(&frag) {
+                        frag = checked_re
+                            .replace_all(&frag, r#"<w:checked w:val="1"/>"#)
+                            .to_string();
+                    } else {
+                        frag = frag.replace(
+                            "<w:default w:val=\"1\"/>",
+                            "<w:default w:val=\"1\"/><w:checked w:val=\"1\"/>",
+                        );
+                    }
+                } else {
+                    frag = default_re
+                        .replace_all(&frag, r#"<w:default w:val="0"/>"#)
+                        .to_string();
+                    frag = checked_re
+                        .replace_all(&frag, r#"<w:checked w:val="0"/>"#)
+                        .to_string();
+                }
+            }
+            idx += 1;
+            frag
+        })
+        .to_string()
+}
+
+fn title_case(value: &str) -> String {
+    value
+        .split_whitespace()
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().collect::<String>() + &chars.as_str().to_lowercase(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn parse_decimal(value: Option<&str>) -> Option<f64> {
+    value.and_then(|v| v.trim().replace(',', ".").parse::<f64>().ok())
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn docx_run(text: &str, size: u32, bold: bool) -> String {
+    format!(
+        r#"<w:r><w:rPr><w:rFonts w:ascii="Arial" w:hAnsi="Arial" w:cs="Arial"/>{}<w:sz w:val="{}"/><w:szCs w:val="{}"/></w:rPr><w:t xml:space="preserve">{}</w:t></w:r>"#,
+        if bold { "<w:b/><w:bCs/>" } else { "" },
+        size,
+        size,
+        escape_xml(text)
+    )
+}
+
+fn docx_paragraph(text: &str, bold: bool) -> String {
+    format!("<w:p>{}</w:p>", docx_run(text, 22, bold))
+}
+
+/// Tabella Word semplice con riga di intestazione in grassetto.
+fn build_docx_table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let cell = |text: &str, bold: bool| {
+        format!(
+            r#"<w:tc><w:tcPr><w:tcW w:w="0" w:type="auto"/></w:tcPr><w:p>{}</w:p></w:tc>"#,
+            docx_run(text, 20, bold)
+        )
+    };
+    let border = r#"w:val="single" w:sz="4" w:space="0" w:color="808080""#;
+
+    let mut xml = format!(
+        r#"<w:tbl><w:tblPr><w:tblW w:w="5000" w:type="pct"/><w:tblBorders><w:top {b}/><w:left {b}/><w:bottom {b}/><w:right {b}/><w:insideH {b}/><w:insideV {b}/></w:tblBorders></w:tblPr><w:tblGrid>{}</w:tblGrid>"#,
+        r#"<w:gridCol/>"#.repeat(headers.len()),
+        b = border
+    );
+    xml.push_str("<w:tr>");
+    for header in headers {
+        xml.push_str(&cell(header, true));
+    }
+    xml.push_str("</w:tr>");
+    for row in rows {
+        xml.push_str("<w:tr>");
+        for value in row {
+            xml.push_str(&cell(value, false));
+        }
+        xml.push_str("</w:tr>");
+    }
+    xml.push_str("</w:tbl>");
+    xml
+}
+
+/// Sostituisce l'intero paragrafo che contiene il placeholder con il frammento XML indicato.
+/// Da usare dopo `replace_placeholders`, che ricompone i placeholder spezzati tra più run.
+fn replace_placeholder_paragraph(content: &str, key: &str, replacement: &str) -> String {
+    let placeholder = format!("{{{}}}", key);
+    let Some(pos) = content.find(&placeholder) else {
+        return content.to_string();
+    };
+    let start = [content[..pos].rfind("<w:p "), content[..pos].rfind("<w:p>")]
+        .into_iter()
+        .flatten()
+        .max();
+    let end = content[pos..].find("</w:p>").map(|e| pos + e + "</w:p>".len());
+    match (start, end) {
+        (Some(start), Some(end)) => {
+            format!("{}{}{}", &content[..start], replacement, &content[end..])
+        }
+        _ => content.replace(&placeholder, ""),
+    }
+}
+
+#[tauri::command]
+pub async fn generate_ambulatorio_referto(
+    patient_id: i64,
+    appointment_id: Option<i64>,
+    db: State<'_, Database>,
+    app_handle: AppHandle,
+) -> Result<String, String> {
+    let patient = db
+        .get_patient_by_id(patient_id)?
+        .ok_or_else(|| "Paziente non trovato".to_string())?;
+
+    // Con un appuntamento indicato il referto ne usa data e medico; altrimenti la visita odierna
+    let appointment = match appointment_id {
+        Some(id) => {
+            let appointment = db.get_appointment(id)?;
+            if appointment.patient_id != patient_id {
+                return Err("L'appuntamento non appartiene al paziente".to_string());
+            }
+            Some(appointment)
+        }
+        None => None,
+    };
+
+    let mut p = patient.patient;
+    if let Some(appointment) = &appointment {
+        if appointment.medico_nome.as_deref().is_some_and(|n| !n.trim().is_empty()) {
+            p.medico_titolo = appointment.medico_titolo.clone();
+            p.medico_nome = appointment.medico_nome.clone();
+        }
+    }
+    let visit_date = match &appointment {
+        Some(appointment) => format_date_ita(&appointment.data),
+        None => Local::now().format("%d/%m/%Y").to_string(),
+    };
+    let visit_date_for_filename = appointment
+        .as_ref()
+        .map(|a| a.data.as_str())
+        .or(p.ambulatorio_data_visita.as_deref())
+        .map(format_date_filename)
+        .filter(|d| !d.trim().is_empty())
+        .unwrap_or_else(|| Local::now().format("%d.%m.%Y").to_string());
+
+    let sig_sigra = match p.sesso.as_deref() {
+        Some("F") | Some("f") => "Sig.ra".to_string(),
+        _ => "Sig.".to_string(),
+    };
+
+    let drdrssa = match p.medico_titolo.as_deref() {
+        Some(t) if t.to_lowercase().contains("ssa") => "Dott.ssa".to_string(),
+        _ => "Dott.".to_string(),
+    };
+
+    let drdrssasp = match p.medico_specializzando_titolo.as_deref() {
+        Some(t) if t.to_lowercase().contains("ssa") => "Dott.ssa".to_string(),
+        _ => "Dott.".to_string(),
+    };
+    let specializzando_name = p.medico_specializzando_nome.unwrap_or_default();
+    let has_specializzando = !specializzando_name.trim().is_empty();
+    let specializzando_label = if has_specializzando {
+        "Il Medico in formazione specialistica,".to_string()
+    } else {
+        String::new()
+    };
+
+    // Fattori codificati con i relativi dettagli, seguiti dalle voci libere non codificabili
+    let fattori_raw = db
+        .describe_patient_risk_factors(patient_id)?
+        .into_iter()
+        .chain(
+            p.ambulatorio_fattori
+                .clone()
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|label| PatientRiskFactor::from_free_text(patient_id, label).is_none()),
+        )
+        .collect::<Vec<_>>()
+        .join(", ");
+    let h_fattori = if fattori_raw.is_empty() {
+        String::new()
+    } else {
+        "Fattori di rischio CV".to_string()
+    };
+    let fattori = capitalize_first(&fattori_raw);
+    let anamnesi_cardiologica_raw = p.anamnesi_cardiologica.unwrap_or_default();
+    let anamnesi_cardiologica = anamnesi_cardiologica_raw.clone(); // mantieni formattazione e a-capo
+    let h_anamnesi_cardiologica = if anamnesi_cardiologica_raw.trim().is_empty() {
+        String::new()
+    } else {
+        "Anamnesi Patologica Remota".to_string()
+    };
+    let today = Local::now().format("%Y-%m-%d").to_string();
+    let medications: Vec<Medication> = db
+        .get_medications(patient_id)?
+        .into_iter()
+        .filter(|m| m.is_active_on(&today))
+        .collect();
+    // Con la terapia strutturata il paragrafo {terapia_domiciliare} diventa una tabella
+    // seguita dalle indicazioni peri-procedurali.
+    let medication_xml = if medications.is_empty() {
+        None
+    } else {
+        let rows: Vec<Vec<String>> = medications
+            .iter()
+            .map(|m| {
+                vec![
+                    m.farmaco.clone(),
+                    m.dose.clone().unwrap_or_default(),
+                    m.frequenza.clone().unwrap_or_default(),
+                    m.via.clone().unwrap_or_default(),
+                    m.indicazione.clone().unwrap_or_default(),
+                ]
+            })
+            .collect();
+        let mut xml = build_docx_table(&["Farmaco", "Dose", "Frequenza", "Via", "Indicazione"], &rows);
+        xml.push_str(&docx_paragraph("", false));
+        xml.push_str(&docx_paragraph("Indicazioni terapia peri-procedurale", true));
+        let egfr = parse_decimal(p.procedurale_egfr.as_deref());
+        for instruction in periprocedural_instructions(&medications, egfr) {
+            xml.push_str(&docx_paragraph(&format!("- {}", instruction), false));
+        }
+        Some(xml)
+    };
+    let apr_raw = p.apr.unwrap_or_default();
+    let apr = apr_raw.clone(); // mantieni esattamente il testo inserito
+    let h_apr = if apr_raw.trim().is_empty() && medication_xml.is_none() {
+        String::new()
+    } else {
+        "Terapia domiciliare".to_string()
+    };
+    let visita_raw = p.visita_odierna.unwrap_or_default();
+    let visita_odierna = visita_raw.replace('-', "").trim().to_string();
+    let h_visita_odierna = if visita_odierna.is_empty() {
+        String::new()
+    } else {
+        "Valutazione Odierna".to_string()
+    };
+    let conclusioni_raw = p.conclusioni.unwrap_or_default();
+    let conclusioni = conclusioni_raw.replace('-', "").trim().to_string();
+    let h_conclusioni = if conclusioni.is_empty() {
+        String::new()
+    } else {
+        "Conclusioni".to_string()
+    };
+
+    let mut replacements: HashMap<&str, String> = HashMap::from([
+        ("data_visita", visit_date),
+        ("sig_sigra", sig_sigra),
+        ("nome", p.nome.clone()),
+        ("cognome", p.cognome.clone()),
+        ("dn", format_date_ita(&p.data_nascita)),
+        ("cf", p.codice_fiscale.unwrap_or_default()),
+        ("h_fdrcv", h_fattori),
+        ("fdrcv", fattori),
+        ("h_anamnesi_patologica_remota", h_anamnesi_cardiologica),
+        ("anamnesi_patologica_remota", anamnesi_cardiologica),
+        ("h_terapia_domiciliare", h_apr),
+        ("terapia_domiciliare", apr),
+        ("h_visita_odierna", h_visita_odierna),
+        ("visita_odierna", visita_odierna),
+        ("h_conclusioni", h_conclusioni),
+        ("conclusioni", conclusioni),
+        ("drdrssa", drdrssa),
+        ("cardiologo", p.medico_nome.unwrap_or_default()),
+        ("specializzando", specializzando_label),
+        ("drdrssasp", if has_specializzando { drdrssasp } else { String::new() }),
+        ("nome_specializzando", if has_specializzando { specializzando_name } else { String::new() }),
+    ]);
+    if medication_xml.is_some() {
+        replacements.remove("terapia_domiciliare");
+    }
+
+    let template_path = resolve_template_path(&app_handle, "template_amb_strutturale.docx")?;
+
+    let output_bytes = fill_docx_template(&template_path, |xml| {
+        let mut replaced = replace_placeholders(xml, &replacements);
+        if let Some(medication) = &medication_xml {
+            replaced = replace_placeholder_paragraph(&replaced, "terapia_domiciliare", medication);
+        }
+        replaced
+    })?;
+
+    let settings = read_settings_from_disk().unwrap_or_default();
+    let out_dir = resolve_referti_dir(&settings, "amb", &app_handle);
+    create_dir_all(&out_dir).map_err(|_| "Impossibile creare cartella referti".to_string())?;
+
+    let default_name = "{cognome} {nome} {data_visita}";
+    let naming_pattern = settings
+        .naming_amb
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .unwrap_or(default_name);
+
+    let mut filename = naming_pattern.to_string();
+    let dn_for_filename = format_date_filename(&p.data_nascita);
+    let filename_replacements: [(&str, String); 4] = [
+        ("nome", p.nome.clone()),
+        ("cognome", p.cognome.clone()),
+        ("dn", dn_for_filename),
+        ("data_visita", visit_date_for_filename.clone()),
+    ];
+    for (key, value) in filename_replacements {
+        let placeholder = format!("{{{key}}}");
+        filename = filename.replace(&placeholder, &value);
+    }
 
-    let drdrssa = match p.medico_titolo.as_deref() {
-        Some(t) if t.to_lowercase().contains("ssa") => "Dott.ssa".to_string(),
-        _ => "Dott.".to_string(),
-    };
+    if filename.trim().is_empty() {
+        filename = format!("{} {} {}", p.cognome, p.nome, visit_date_for_filename);
+    }
+    if !filename.to_lowercase().ends_with(".docx") {
+        filename.push_str(".docx");
+    }
 
-    let drdrssasp = match p.medico_specializzando_titolo.as_deref() {
-        Some(t) if t.to_lowercase().contains("ssa") => "Dott.ssa".to_string(),
-        _ => "Dott.".to_string(),
+    let filename = sanitize_filename(&filename);
+    let out_path = out_dir.join(filename);
+
+    let mut out_file =
+        File::create(&out_path).map_err(|_| "Impossibile creare il referto".to_string())?;
+    out_file
+        .write_all(&output_bytes)
+        .map_err(|_| "Errore salvataggio referto".to_string())?;
+
+    let out_path = out_path.to_string_lossy().to_string();
+    if let Some(id) = appointment.and_then(|a| a.id) {
+        db.set_appointment_referto(id, &out_path)?;
+    }
+    Ok(out_path)
+}
+
+#[tauri::command]
+pub async fn generate_scheda_procedurale_referto(
+    patient_id: i64,
+    plan_id: Option<i64>,
+    db: State<'_, Database>,
+    app_handle: AppHandle,
+) -> Result<String, String> {
+    let patient = db
+        .get_patient_by_id(patient_id)?
+        .ok_or_else(|| "Paziente non trovato".to_string())?;
+
+    let p = patient.patient;
+    // Senza piano esplicito si usa la versione corrente; in assenza di versioni i campi del paziente
+    let plan = match plan_id {
+        Some(id) => {
+            let plan = db
+                .get_procedure_plan(id)?
+                .ok_or_else(|| "Piano procedurale non trovato".to_string())?;
+            if plan.patient_id != patient_id {
+                return Err("Il piano procedurale non appartiene al paziente".to_string());
+            }
+            plan
+        }
+        None => db
+            .get_current_procedure_plan(patient_id)?
+            .unwrap_or_else(|| ProcedurePlan::from_patient(&p)),
     };
-    let specializzando_name = p.medico_specializzando_nome.unwrap_or_default();
-    let has_specializzando = !specializzando_name.trim().is_empty();
-    let specializzando_label = if has_specializzando {
-        "Il Medico in formazione specialistica,".to_string()
+    let modello_valvola = match plan.bioprotesi_modello.as_deref() {
+        Some(code) if !code.trim().is_empty() => db
+            .find_valve_model(code)?
+            .map(|m| m.display_name())
+            .unwrap_or_else(|| title_case(&code.replace('_', " "))),
+        _ => String::new(),
+    };
+    // Nota PM: ECG più recente e rischio di pacemaker post-TAVI in coda alle note inserite
+    let pacemaker_risk = db.assess_pacemaker_risk(patient_id, plan.bioprotesi_modello.as_deref())?;
+    let mut note_pm_lines: Vec<String> = plan
+        .pacemaker_note
+        .clone()
+        .filter(|n| !n.trim().is_empty())
+        .into_iter()
+        .collect();
+    if let Some(ecg) = db.get_latest_ecg(patient_id)? {
+        let summary = ecg.summary();
+        if !summary.is_empty() {
+            note_pm_lines.push(format!(
+                "ECG del {}: {}",
+                format_date_ita(&ecg.data_registrazione),
+                summary
+            ));
+        }
+    }
+    if pacemaker_risk.livello != "non_applicabile" {
+        let fattori = if pacemaker_risk.fattori.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", pacemaker_risk.fattori.join(", "))
+        };
+        note_pm_lines.push(format!(
+            "Rischio PM post-TAVI: {}{}",
+            pacemaker_risk.livello, fattori
+        ));
+    }
+    let replacements: HashMap<&str, String> = HashMap::from([
+        ("nome", p.nome.clone()),
+        ("cognome", p.cognome.clone()),
+        ("dn", format_date_ita(&p.data_nascita)),
+        ("peso", p.peso.map(|v| v.to_string()).unwrap_or_default()),
+        ("altezza", p.altezza.map(|v| v.to_string()).unwrap_or_default()),
+        ("creatinina", plan.creatinina.unwrap_or_default()),
+        ("egfr", plan.egfr.unwrap_or_default()),
+        ("hb", plan.hb.unwrap_or_default()),
+        ("altro", plan.altro.unwrap_or_default()),
+        ("modello_valvola", modello_valvola),
+        (
+            "dimensione_valvola",
+            plan.bioprotesi_dimensione
+                .as_ref()
+                .map(|d| format!("{} mm", d))
+                .unwrap_or_default(),
+        ),
+        (
+            "diametro_pallone_femorale",
+            plan.diametro_pallone_femorale.unwrap_or_default(),
+        ),
+        ("guida_safari", plan.guida_safari.unwrap_or_default()),
+        (
+            "note_valvuloplastica",
+            plan.valvuloplastica_note.unwrap_or_default(),
+        ),
+        ("altri_accessi", plan.altri_accessi.unwrap_or_default()),
+        (
+            "altro_accesso_arterioso",
+            if plan.accesso_principale_fem.as_deref() == Some("altro") {
+                plan.accesso_principale_altro.unwrap_or_default()
+            } else {
+                String::new()
+            },
+        ),
+        (
+            "note_accesso_protezione",
+            plan.accesso_protezione_note.unwrap_or_default(),
+        ),
+        (
+            "note_protezione",
+            match plan.protezione_osti.as_deref() {
+                Some("si") | Some("Si") | Some("SI") => "Sì".to_string(),
+                Some("no") | Some("No") | Some("NO") => "No".to_string(),
+                Some(other) => other.to_string(),
+                None => String::new(),
+            },
+        ),
+        ("note_pm_definitivo", note_pm_lines.join("\n")),
+        ("note_cvg", plan.coronarografia_note.unwrap_or_default()),
+    ]);
+    // Mappatura checkbox nel template in ordine:
+    // 0-1 Allergia MdC (sì/no)
+    // 2-8 ECG: ritmo sinusale, FA, BBS, BBD, EAS, BAV I grado, ritmo stimolato
+    // 9-11 Anestesia: Locale, Sedazione, Generale
+    // 12-13 Coronarografia: da eseguire in ricovero, già eseguita
+    // 14-15 Pacemaker definitivo: sì/no
+    // 16-20 Accesso principale: percutaneo dx, percutaneo sn, chirurgico dx, chirurgico sn, altro
+    // 21-22 Accesso protezione: sì/no
+    // 23-24 Protezione osti: sì/no
+    // 25-26 Valvuloplastica: sì/no
+    let accesso = plan.accesso_principale_fem.as_deref();
+    let cb_flags: Vec<bool> = vec![
+        plan.allergia_mdc.as_deref() == Some("si"),
+        plan.allergia_mdc.as_deref() == Some("no"),
+        p.procedurale_ecg_ritmo_sinusale.unwrap_or(false),
+        p.procedurale_ecg_fa.unwrap_or(false),
+        p.procedurale_ecg_bbs.unwrap_or(false),
+        p.procedurale_ecg_bbd.unwrap_or(false),
+        p.procedurale_ecg_eas.unwrap_or(false),
+        p.procedurale_ecg_bav_primo.unwrap_or(false),
+        p.procedurale_ecg_ritmo_stimolato.unwrap_or(false),
+        plan.anestesia.as_deref() == Some("Locale"),
+        plan.anestesia.as_deref() == Some("Sedazione"),
+        plan.anestesia.as_deref() == Some("Generale"),
+        plan.coronarografia.as_deref() == Some("ricovero"),
+        plan.coronarografia.as_deref() == Some("gia_eseguita"),
+        plan.pacemaker.as_deref() == Some("si"),
+        plan.pacemaker.as_deref() == Some("no"),
+        accesso == Some("percutaneo_dx"),
+        accesso == Some("percutaneo_sn"),
+        accesso == Some("chirurgico_dx"),
+        accesso == Some("chirurgico_sn"),
+        accesso == Some("altro"),
+        plan.accesso_protezione.as_deref() == Some("si"),
+        plan.accesso_protezione.as_deref() == Some("no"),
+        matches!(plan.protezione_osti.as_deref(), Some("si") | Some("Si") | Some("SI")),
+        matches!(plan.protezione_osti.as_deref(), Some("no") | Some("No") | Some("NO")),
+        plan.valvuloplastica.as_deref() == Some("si"),
+        plan.valvuloplastica.as_deref() == Some("no"),
+    ];
+
+    let template_path = resolve_template_path(&app_handle, "template_scheda_procedurale.docx")?;
+
+    let output_bytes = fill_docx_template(&template_path, |xml| {
+        apply_checkbox_flags(&replace_placeholders(xml, &replacements), &cb_flags)
+    })?;
+
+    let settings = read_settings_from_disk().unwrap_or_default();
+    let out_dir = resolve_referti_dir(&settings, "proc", &app_handle);
+    create_dir_all(&out_dir).map_err(|_| "Impossibile creare cartella referti".to_string())?;
+
+    let filename = sanitize_filename(&format!("Scheda procedurale - {} {}.docx", p.cognome, p.nome));
+    let out_path = out_dir.join(filename);
+
+    let mut out_file =
+        File::create(&out_path).map_err(|_| "Impossibile creare il referto".to_string())?;
+    out_file
+        .write_all(&output_bytes)
+        .map_err(|_| "Errore salvataggio referto".to_string())?;
+
+    Ok(out_path
+        .to_string_lossy()
+        .to_string())
+}
+
+#[tauri::command]
+pub async fn generate_consenso_informato(
+    patient_id: i64,
+    db: State<'_, Database>,
+    app_handle: AppHandle,
+) -> Result<String, String> {
+    let patient = db
+        .get_patient_by_id(patient_id)?
+        .ok_or_else(|| "Paziente non trovato".to_string())?;
+    let p = patient.patient;
+
+    let template_path = resolve_template_path(&app_handle, "consenso_informato_TAVI.docx")?;
+    let replacements: HashMap<&str, String> = HashMap::from([
+        ("nome", p.nome.clone()),
+        ("cognome", p.cognome.clone()),
+    ]);
+
+    let output_bytes = fill_docx_template(&template_path, |xml| replace_placeholders(xml, &replacements))?;
+
+    let out_dir = resolve_moduli_temp_dir();
+    create_dir_all(&out_dir).map_err(|_| "Impossibile creare cartella moduli".to_string())?;
+
+    let filename = sanitize_filename(&format!(
+        "Consenso informato - {} {}.docx",
+        p.cognome, p.nome
+    ));
+    let out_path = out_dir.join(filename);
+
+    let mut out_file =
+        File::create(&out_path).map_err(|_| "Impossibile creare il referto".to_string())?;
+    out_file
+        .write_all(&output_bytes)
+        .map_err(|_| "Errore salvataggio referto".to_string())?;
+
+    schedule_temp_cleanup(out_path.clone());
+    Ok(out_path.to_string_lossy().to_string())
+}
+
+#[tauri::command]
+pub async fn generate_consenso_informato_html(
+    patient_id: i64,
+    db: State<'_, Database>,
+    app_handle: AppHandle,
+) -> Result<String, String> {
+    let patient = db
+        .get_patient_by_id(patient_id)?
+        .ok_or_else(|| "Paziente non trovato".to_string())?;
+    let p = patient.patient;
+
+    let template_path = resolve_template_path(&app_handle, "consenso_informato_TAVI.docx")?;
+    let replacements: HashMap<&str, String> = HashMap::from([
+        ("nome", p.nome.clone()),
+        ("cognome", p.cognome.clone()),
+    ]);
+
+    let mut template_file =
+        File::open(&template_path).map_err(|_| "Impossibile aprire il template".to_string())?;
+    let mut archive =
+        ZipArchive::new(&mut template_file).map_err(|_| "Template referto non valido".to_string())?;
+
+    let mut document_xml = String::new();
+    let mut styles_xml = String::new();
+    {
+        let mut doc_file = archive
+            .by_name("word/document.xml")
+            .map_err(|_| "Template referto non valido".to_string())?;
+        doc_file
+            .read_to_string(&mut document_xml)
+            .map_err(|_| "Errore lettura template".to_string())?;
+    }
+    if let Ok(mut styles_file) = archive.by_name("word/styles.xml") {
+        let _ = styles_file.read_to_string(&mut styles_xml);
+    }
+
+    let replaced = replace_placeholders(&document_xml, &replacements);
+    let styles_map = if styles_xml.is_empty() {
+        HashMap::new()
     } else {
-        String::new()
+        parse_styles(&styles_xml)
     };
+    let html = docx_xml_to_html(&replaced, Some(&styles_map));
+    if html.trim().is_empty() {
+        return Err("Contenuto consenso vuoto".to_string());
+    }
+    Ok(html)
+}
+
+#[tauri::command]
+pub async fn generate_esami_ematochimici(
+    patient_id: i64,
+    db: State<'_, Database>,
+    app_handle: AppHandle,
+) -> Result<String, String> {
+    let patient = db
+        .get_patient_by_id(patient_id)?
+        .ok_or_else(|| "Paziente non trovato".to_string())?;
+    let p = patient.patient;
+
+    let template_path = resolve_template_path(&app_handle, "ee_tavi.pdf")?;
+    let out_dir = resolve_moduli_temp_dir();
+    create_dir_all(&out_dir).map_err(|_| "Impossibile creare cartella moduli".to_string())?;
+
+    let filename = sanitize_filename(&format!(
+        "Esami ematochimici - {} {}.pdf",
+        p.cognome, p.nome
+    ));
+    let out_path = out_dir.join(filename);
+    std::fs::copy(&template_path, &out_path).map_err(|_| "Impossibile copiare il modulo".to_string())?;
+
+    schedule_temp_cleanup(out_path.clone());
+    Ok(out_path.to_string_lossy().to_string())
+}
+
+// ============================================================================
+// LISTE DI LAVORO GIORNALIERE
+// ============================================================================
 
-    let fattori_raw = p
-        .ambulatorio_fattori
-        .clone()
-        .unwrap_or_default()
-        .join(", ");
-    let h_fattori = if fattori_raw.is_empty() {
-        String::new()
-    } else {
-        "Fattori di rischio CV".to_string()
-    };
-    let fattori = capitalize_first(&fattori_raw);
-    let anamnesi_cardiologica_raw = p.anamnesi_cardiologica.unwrap_or_default();
-    let anamnesi_cardiologica = anamnesi_cardiologica_raw.clone(); // mantieni formattazione e a-capo
-    let h_anamnesi_cardiologica = if anamnesi_cardiologica_raw.trim().is_empty() {
-        String::new()
-    } else {
-        "Anamnesi Patologica Remota".to_string()
-    };
-    let apr_raw = p.apr.unwrap_or_default();
-    let apr = apr_raw.clone(); // mantieni esattamente il testo inserito
-    let h_apr = if apr_raw.trim().is_empty() {
-        String::new()
-    } else {
-        "Terapia domiciliare".to_string()
-    };
-    let visita_raw = p.visita_odierna.unwrap_or_default();
-    let visita_odierna = visita_raw.replace('-', "").trim().to_string();
-    let h_visita_odierna = if visita_odierna.is_empty() {
-        String::new()
-    } else {
-        "Valutazione Odierna".to_string()
-    };
-    let conclusioni_raw = p.conclusioni.unwrap_or_default();
-    let conclusioni = conclusioni_raw.replace('-', "").trim().to_string();
-    let h_conclusioni = if conclusioni.is_empty() {
-        String::new()
-    } else {
-        "Conclusioni".to_string()
-    };
+const WORKLIST_TABLE_MARKER: &str = "[[tabella_lista]]";
+const WEEKDAYS_ITA: [&str; 7] = ["Lunedì", "Martedì", "Mercoledì", "Giovedì", "Venerdì", "Sabato", "Domenica"];
 
-    let replacements: HashMap<&str, String> = HashMap::from([
-        ("data_visita", visit_date),
-        ("sig_sigra", sig_sigra),
-        ("nome", p.nome.clone()),
-        ("cognome", p.cognome.clone()),
-        ("dn", format_date_ita(&p.data_nascita)),
-        ("cf", p.codice_fiscale.unwrap_or_default()),
-        ("h_fdrcv", h_fattori),
-        ("fdrcv", fattori),
-        ("h_anamnesi_patologica_remota", h_anamnesi_cardiologica),
-        ("anamnesi_patologica_remota", anamnesi_cardiologica),
-        ("h_terapia_domiciliare", h_apr),
-        ("terapia_domiciliare", apr),
-        ("h_visita_odierna", h_visita_odierna),
-        ("visita_odierna", visita_odierna),
-        ("h_conclusioni", h_conclusioni),
-        ("conclusioni", conclusioni),
-        ("drdrssa", drdrssa),
-        ("cardiologo", p.medico_nome.unwrap_or_default()),
-        ("specializzando", specializzando_label),
-        ("drdrssasp", if has_specializzando { drdrssasp } else { String::new() }),
-        ("nome_specializzando", if has_specializzando { specializzando_name } else { String::new() }),
-    ]);
+/// Template e destinazione di una lista di lavoro
+struct WorklistTemplate {
+    file: &'static str,
+    kind: &'static str,      // cartella referti di destinazione (vedi resolve_referti_dir)
+    titolo: &'static str,
+    attivita: &'static str,  // intestazione della colonna tipo visita / caso
+}
 
-    let template_path = resolve_template_path(&app_handle, "template_amb_strutturale.docx")?;
+const AMBULATORIO_WORKLIST: WorklistTemplate = WorklistTemplate {
+    file: "worklist_ambulatorio.docx",
+    kind: "amb",
+    titolo: "Lista ambulatorio",
+    attivita: "Visita",
+};
+
+const TAVI_WORKLIST: WorklistTemplate = WorklistTemplate {
+    file: "worklist_tavi.docx",
+    kind: "proc",
+    titolo: "Lista sala TAVI",
+    attivita: "Caso",
+};
+
+fn worklist_headers(attivita: &str) -> Vec<&str> {
+    vec![
+        "Ora", attivita, "Paziente", "Nascita / CF", "Età", "Priorità", "Creatinina", "eGFR", "Hb",
+        "Allergia MDC", "Valvola pianificata", "Accesso", "Note",
+    ]
+}
+
+fn worklist_rows(entries: &[WorklistEntry]) -> Vec<Vec<String>> {
+    entries
+        .iter()
+        .map(|e| {
+            let nascita = match e.codice_fiscale.as_deref() {
+                Some(cf) => format!("{} / {}", format_date_ita(&e.data_nascita), cf),
+                None => format_date_ita(&e.data_nascita),
+            };
+            let note: Vec<String> = e.avvisi.iter().cloned().chain(e.note.clone()).collect();
+            vec![
+                e.orario.clone().unwrap_or_else(|| "-".to_string()),
+                e.attivita.clone().unwrap_or_default(),
+                format!("{} {}", e.cognome, e.nome),
+                nascita,
+                e.eta.map(|v| v.to_string()).unwrap_or_default(),
+                e.priority.map(|p| capitalize_first(p.as_str())).unwrap_or_default(),
+                e.creatinina.clone().unwrap_or_default(),
+                e.egfr.clone().unwrap_or_default(),
+                e.hb.clone().unwrap_or_default(),
+                e.allergia_mdc.clone().unwrap_or_default(),
+                e.valvola_pianificata.clone().unwrap_or_default(),
+                e.accesso.clone().unwrap_or_default(),
+                note.join("; "),
+            ]
+        })
+        .collect()
+}
+
+fn build_html_table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let mut html = String::from("<table class=\"worklist\"><thead><tr>");
+    for header in headers {
+        html.push_str(&format!("<th>{}</th>", escape_html(header)));
+    }
+    html.push_str("</tr></thead><tbody>");
+    for row in rows {
+        html.push_str("<tr>");
+        for value in row {
+            html.push_str(&format!("<td>{}</td>", escape_html(value)));
+        }
+        html.push_str("</tr>");
+    }
+    html.push_str("</tbody></table>");
+    html
+}
 
+/// Compila un template docx applicando `transform` a tutte le parti XML (placeholder,
+/// tabelle, checkbox); le altre parti vengono copiate invariate.
+fn fill_docx_template(
+    template_path: &Path,
+    transform: impl Fn(&str) -> String,
+) -> Result<Vec<u8>, String> {
     let mut template_file =
-        File::open(&template_path).map_err(|_| "Impossibile aprire il template".to_string())?;
+        File::open(template_path).map_err(|_| "Impossibile aprire il template".to_string())?;
     let mut archive =
-        ZipArchive::new(&mut template_file).map_err(|_| "Template referto non valido".to_string())?;
+        ZipArchive::new(&mut template_file).map_err(|_| "Template non valido".to_string())?;
 
     let mut output_bytes: Vec<u8> = Vec::new();
     {
         let mut writer = zip::ZipWriter::new(std::io::Cursor::new(&mut output_bytes));
-
         for i in 0..archive.len() {
             let mut file = archive.by_index(i).map_err(|_| "Errore lettura template".to_string())?;
             let name = file.name().to_string();
@@ -961,7 +2798,7 @@ pub async fn generate_ambulatorio_referto(
             if file.is_dir() {
                 writer
                     .add_directory(name, FileOptions::default())
-                    .map_err(|_| "Errore scrittura referto".to_string())?;
+                    .map_err(|_| "Errore scrittura documento".to_string())?;
                 continue;
             }
 
@@ -970,416 +2807,1068 @@ pub async fn generate_ambulatorio_referto(
                 .map_err(|_| "Errore lettura template".to_string())?;
 
             let options = FileOptions::default().compression_method(file.compression());
-
             if name.ends_with(".xml") {
-                let content = String::from_utf8_lossy(&buffer).to_string();
-                let replaced = replace_placeholders(&content, &replacements);
-                writer
-                    .start_file(name.clone(), options)
-                    .map_err(|_| "Errore scrittura referto".to_string())?;
-                writer
-                    .write_all(replaced.as_bytes())
-                    .map_err(|_| "Errore scrittura referto".to_string())?;
-            } else {
-                writer
-                    .start_file(name.clone(), options)
-                    .map_err(|_| "Errore scrittura referto".to_string())?;
-                writer
-                    .write_all(&buffer)
-                    .map_err(|_| "Errore scrittura referto".to_string())?;
+                buffer = transform(&String::from_utf8_lossy(&buffer)).into_bytes();
             }
+            writer
+                .start_file(name.clone(), options)
+                .map_err(|_| "Errore scrittura documento".to_string())?;
+            writer
+                .write_all(&buffer)
+                .map_err(|_| "Errore scrittura documento".to_string())?;
+        }
+        writer.finish().map_err(|_| "Errore finale documento".to_string())?;
+    }
+    Ok(output_bytes)
+}
+
+/// Genera la lista nel formato richiesto:
+/// - `docx`: file compilato dal template, restituisce il percorso;
+/// - `html`: frammento HTML per l'anteprima;
+/// - `stampa`: pagina HTML completa in A4 orizzontale, da stampare (o salvare in PDF dalla
+///   finestra di stampa) con `print_window`.
+fn render_worklist(
+    app_handle: &AppHandle,
+    template: &WorklistTemplate,
+    data: &str,
+    formato: &str,
+    entries: &[WorklistEntry],
+) -> Result<String, String> {
+    let date = chrono::NaiveDate::parse_from_str(data.trim(), "%Y-%m-%d")
+        .map_err(|_| format!("Data non valida: {}", data))?;
+    let data_lista = format!(
+        "{} {}",
+        WEEKDAYS_ITA[chrono::Datelike::weekday(&date).num_days_from_monday() as usize],
+        date.format("%d/%m/%Y")
+    );
+    let replacements: HashMap<&str, String> = HashMap::from([
+        ("data_lista", data_lista),
+        ("totale_pazienti", entries.len().to_string()),
+        ("generato_il", Local::now().format("%d/%m/%Y %H:%M").to_string()),
+    ]);
+    let headers = worklist_headers(template.attivita);
+    let rows = worklist_rows(entries);
+    let template_path = resolve_template_path(app_handle, template.file)?;
+
+    match formato.trim().to_lowercase().as_str() {
+        "docx" => {
+            let table = if rows.is_empty() {
+                docx_paragraph("Nessun paziente in lista", false)
+            } else {
+                build_docx_table(&headers, &rows)
+            };
+            let output_bytes = fill_docx_template(&template_path, |xml| {
+                replace_placeholder_paragraph(&replace_placeholders(xml, &replacements), "tabella_lista", &table)
+            })?;
+
+            let settings = read_settings_from_disk().unwrap_or_default();
+            let out_dir = resolve_referti_dir(&settings, template.kind, app_handle).join("Liste di lavoro");
+            create_dir_all(&out_dir).map_err(|_| "Impossibile creare cartella liste".to_string())?;
+            let filename = sanitize_filename(&format!("{} {}.docx", template.titolo, date.format("%d.%m.%Y")));
+            let out_path = out_dir.join(filename);
+            let mut out_file =
+                File::create(&out_path).map_err(|_| "Impossibile creare la lista".to_string())?;
+            out_file
+                .write_all(&output_bytes)
+                .map_err(|_| "Errore salvataggio lista".to_string())?;
+            Ok(out_path.to_string_lossy().to_string())
         }
+        "html" | "stampa" => {
+            let mut template_file =
+                File::open(&template_path).map_err(|_| "Impossibile aprire il template".to_string())?;
+            let mut archive =
+                ZipArchive::new(&mut template_file).map_err(|_| "Template non valido".to_string())?;
+            let mut document_xml = String::new();
+            {
+                let mut doc_file = archive
+                    .by_name("word/document.xml")
+                    .map_err(|_| "Template non valido".to_string())?;
+                doc_file
+                    .read_to_string(&mut document_xml)
+                    .map_err(|_| "Errore lettura template".to_string())?;
+            }
+            let mut styles_xml = String::new();
+            if let Ok(mut styles_file) = archive.by_name("word/styles.xml") {
+                let _ = styles_file.read_to_string(&mut styles_xml);
+            }
+            let styles_map = if styles_xml.is_empty() {
+                HashMap::new()
+            } else {
+                parse_styles(&styles_xml)
+            };
+
+            // Il paragrafo della tabella diventa un segnaposto, sostituito dalla tabella HTML
+            let replaced = replace_placeholders(&document_xml, &replacements);
+            let marker = format!("<w:p><w:r><w:t>{}</w:t></w:r></w:p>", WORKLIST_TABLE_MARKER);
+            let replaced = replace_placeholder_paragraph(&replaced, "tabella_lista", &marker);
+            let table = if rows.is_empty() {
+                "<p>Nessun paziente in lista</p>".to_string()
+            } else {
+                build_html_table(&headers, &rows)
+            };
+            let html = docx_xml_to_html(&replaced, Some(&styles_map))
+                .replace(&format!("<p>{}</p>", WORKLIST_TABLE_MARKER), &table);
 
-        writer.finish().map_err(|_| "Errore finale referto".to_string())?;
+            if formato.trim().eq_ignore_ascii_case("html") {
+                return Ok(html);
+            }
+            Ok(format!(
+                "<!DOCTYPE html><html lang=\"it\"><head><meta charset=\"utf-8\"><title>{} {}</title><style>\
+                 @page {{ size: A4 landscape; margin: 12mm; }} \
+                 body {{ font-family: Calibri, Arial, sans-serif; font-size: 10pt; }} \
+                 table.worklist {{ width: 100%; border-collapse: collapse; }} \
+                 table.worklist th, table.worklist td {{ border: 1px solid #808080; padding: 2px 4px; text-align: left; vertical-align: top; }} \
+                 table.worklist thead {{ display: table-header-group; }} \
+                 table.worklist tr {{ page-break-inside: avoid; }}\
+                 </style></head><body>{}</body></html>",
+                escape_html(template.titolo),
+                date.format("%d/%m/%Y"),
+                html
+            ))
+        }
+        other => Err(format!("Formato non supportato: {} (docx, html, stampa)", other)),
     }
+}
 
-    let settings = read_settings_from_disk().unwrap_or_default();
-    let out_dir = resolve_referti_dir(&settings, "amb", &app_handle);
-    create_dir_all(&out_dir).map_err(|_| "Impossibile creare cartella referti".to_string())?;
+/// Lista delle visite ambulatoriali del giorno (template `worklist_ambulatorio.docx`)
+#[tauri::command]
+pub async fn generate_ambulatorio_worklist(
+    data: String,
+    formato: String,
+    db: State<'_, Database>,
+    app_handle: AppHandle,
+) -> Result<String, String> {
+    let entries = db.get_ambulatorio_worklist(&data)?;
+    render_worklist(&app_handle, &AMBULATORIO_WORKLIST, &data, &formato, &entries)
+}
 
-    let default_name = "{cognome} {nome} {data_visita}";
-    let naming_pattern = settings
-        .naming_amb
-        .as_deref()
-        .map(str::trim)
-        .filter(|s| !s.is_empty())
-        .unwrap_or(default_name);
+/// Lista dei casi della giornata di sala TAVI (template `worklist_tavi.docx`)
+#[tauri::command]
+pub async fn generate_tavi_worklist(
+    data: String,
+    formato: String,
+    db: State<'_, Database>,
+    app_handle: AppHandle,
+) -> Result<String, String> {
+    let entries = db.get_tavi_worklist(&data)?;
+    render_worklist(&app_handle, &TAVI_WORKLIST, &data, &formato, &entries)
+}
 
-    let mut filename = naming_pattern.to_string();
-    let dn_for_filename = format_date_filename(&p.data_nascita);
-    let filename_replacements: [(&str, String); 4] = [
-        ("nome", p.nome.clone()),
-        ("cognome", p.cognome.clone()),
-        ("dn", dn_for_filename),
-        ("data_visita", visit_date_for_filename.clone()),
-    ];
-    for (key, value) in filename_replacements {
-        let placeholder = format!("{{{key}}}");
-        filename = filename.replace(&placeholder, &value);
-    }
+#[tauri::command]
+pub fn print_window(window: Window) -> Result<(), String> {
+    window.print().map_err(|e| e.to_string())
+}
 
-    if filename.trim().is_empty() {
-        filename = format!("{} {} {}", p.cognome, p.nome, visit_date_for_filename);
-    }
-    if !filename.to_lowercase().ends_with(".docx") {
-        filename.push_str(".docx");
+// ============================================================================
+// IMPORT COMMANDS
+// ============================================================================
+
+/// Anteprima di un file CSV/XLSX con l'abbinamento colonne proposto per la destinazione
+#[tauri::command]
+pub async fn preview_import_file(
+    path: String,
+    target: String,
+    foglio: Option<String>,
+) -> Result<ImportPreview, String> {
+    import::preview(&path, foglio.as_deref(), &target)
+}
+
+fn run_import_request(db: &Database, request: &ImportRequest, dry_run: bool) -> Result<ImportReport, String> {
+    let candidates = import::build_candidates(request)?;
+    let update_existing = request.aggiorna_esistenti.unwrap_or(true);
+    match request.target.as_str() {
+        "patients" => db.import_patients(candidates, update_existing, dry_run),
+        "procedures" => db.import_procedures(candidates, update_existing, dry_run),
+        other => Err(format!("Destinazione di importazione non valida: {}", other)),
     }
+}
 
-    let filename = sanitize_filename(&filename);
-    let out_path = out_dir.join(filename);
+/// Simula l'importazione: resoconto delle righe da inserire, aggiornare o scartare senza salvare nulla
+#[tauri::command]
+pub async fn dry_run_import(request: ImportRequest, db: State<'_, Database>) -> Result<ImportReport, String> {
+    run_import_request(&db, &request, true)
+}
 
-    let mut out_file =
-        File::create(&out_path).map_err(|_| "Impossibile creare il referto".to_string())?;
-    out_file
-        .write_all(&output_bytes)
-        .map_err(|_| "Errore salvataggio referto".to_string())?;
+/// Importa le righe valide in un'unica transazione; le righe scartate restano nel resoconto
+#[tauri::command]
+pub async fn commit_import(request: ImportRequest, db: State<'_, Database>) -> Result<ImportReport, String> {
+    run_import_request(&db, &request, false)
+}
 
-    Ok(out_path
-        .to_string_lossy()
-        .to_string())
+// ============================================================================
+// FHIR EXPORT COMMANDS
+// ============================================================================
+
+/// Chiave anagrafica per collegare le procedure (che non hanno un riferimento al paziente) ai pazienti
+fn demographic_key(cognome: &str, nome: &str, data_nascita: &str) -> String {
+    format!("{}|{}|{}", cognome.trim().to_uppercase(), nome.trim().to_uppercase(), data_nascita.trim())
 }
 
+/// Esporta un Bundle FHIR R4 (JSON) con pazienti, esami, appuntamenti, visite eseguite,
+/// procedure e valvole impiantate. Con `patient_id` esporta un solo paziente; l'intervallo
+/// di date limita appuntamenti e procedure (obbligatorio senza paziente). Il Bundle viene
+/// validato sulla struttura di base delle risorse prima di essere salvato.
 #[tauri::command]
-pub async fn generate_scheda_procedurale_referto(
-    patient_id: i64,
+pub async fn export_fhir_bundle(
+    output_path: String,
+    patient_id: Option<i64>,
+    date_from: Option<String>,
+    date_to: Option<String>,
     db: State<'_, Database>,
-    app_handle: AppHandle,
 ) -> Result<String, String> {
-    let patient = db
-        .get_patient_by_id(patient_id)?
-        .ok_or_else(|| "Paziente non trovato".to_string())?;
+    let parse = |value: Option<&String>| -> Result<Option<String>, String> {
+        match value.map(|v| v.trim()).filter(|v| !v.is_empty()) {
+            Some(v) => chrono::NaiveDate::parse_from_str(v, "%Y-%m-%d")
+                .map(|d| Some(d.format("%Y-%m-%d").to_string()))
+                .map_err(|_| format!("Data non valida: {}", v)),
+            None => Ok(None),
+        }
+    };
+    let (from, to) = (parse(date_from.as_ref())?, parse(date_to.as_ref())?);
+    if let (Some(from), Some(to)) = (&from, &to) {
+        if to < from {
+            return Err("La data finale precede la data iniziale".to_string());
+        }
+    }
+    if patient_id.is_none() && (from.is_none() || to.is_none()) {
+        return Err("Indicare un paziente oppure un intervallo di date".to_string());
+    }
+    let in_range = |date: &str| {
+        let date = date.trim();
+        from.as_deref().is_none_or(|f| date >= f) && to.as_deref().is_none_or(|t| date <= t)
+    };
 
-    let p = patient.patient;
-    let replacements: HashMap<&str, String> = HashMap::from([
-        ("nome", p.nome.clone()),
-        ("cognome", p.cognome.clone()),
-        ("dn", format_date_ita(&p.data_nascita)),
-        ("peso", p.peso.map(|v| v.to_string()).unwrap_or_default()),
-        ("altezza", p.altezza.map(|v| v.to_string()).unwrap_or_default()),
-        ("creatinina", p.procedurale_creatinina.unwrap_or_default()),
-        ("egfr", p.procedurale_egfr.unwrap_or_default()),
-        ("hb", p.procedurale_hb.unwrap_or_default()),
-        ("altro", p.procedurale_altro.unwrap_or_default()),
-        (
-            "modello_valvola",
-            title_case(
-                &p.procedurale_bioprotesi_modello
-                    .unwrap_or_default()
-                    .replace('_', " "),
-            ),
-        ),
-        (
-            "dimensione_valvola",
-            p.procedurale_bioprotesi_dimensione
-                .as_ref()
-                .map(|d| format!("{} mm", d))
-                .unwrap_or_default(),
-        ),
-        (
-            "diametro_pallone_femorale",
-            p.procedurale_diametro_pallone_femorale.unwrap_or_default(),
-        ),
-        ("guida_safari", p.procedurale_guida_safari.unwrap_or_default()),
-        (
-            "note_valvuloplastica",
-            p.procedurale_valvuloplastica_note.unwrap_or_default(),
-        ),
-        ("altri_accessi", p.procedurale_altri_accessi.unwrap_or_default()),
-        (
-            "altro_accesso_arterioso",
-            if p.procedurale_accesso_principale_fem.as_deref() == Some("altro") {
-                p.procedurale_accesso_principale_altro.unwrap_or_default()
+    let (patients, appointments) = match patient_id {
+        Some(id) => {
+            let patient = db
+                .get_patient_by_id(id)?
+                .ok_or_else(|| "Paziente non trovato".to_string())?
+                .patient;
+            let appointments: Vec<Appointment> = db
+                .get_patient_appointments(id)?
+                .into_iter()
+                .filter(|a| in_range(&a.data))
+                .collect();
+            (vec![patient], appointments)
+        }
+        None => {
+            let appointments: Vec<Appointment> = db
+                .get_appointments(from.as_deref(), to.as_deref(), None)?
+                .into_iter()
+                .map(|item| item.appointment)
+                .collect();
+            (
+                db.get_all_patients_with_status(None)?.into_iter().map(|item| item.patient).collect(),
+                appointments,
+            )
+        }
+    };
+    let procedure_filters = ProcedureFilters {
+        date_from: from.clone(),
+        date_to: to.clone(),
+        ..Default::default()
+    };
+    let mut procedures = db.get_all_procedures(Some(procedure_filters))?;
+    let by_key: HashMap<String, &Patient> = patients
+        .iter()
+        .map(|p| (demographic_key(&p.cognome, &p.nome, &p.data_nascita), p))
+        .collect();
+    if patient_id.is_some() {
+        procedures.retain(|p| by_key.contains_key(&demographic_key(&p.cognome, &p.nome, &p.data_nascita)));
+    }
+    let templates = db.get_ambulatorio_slot_templates()?;
+    let catalogue = db.get_valve_models()?;
+
+    // Pazienti esportati: quelli scelti o con attività nell'intervallo
+    let mut included: Vec<&Patient> = patients
+        .iter()
+        .filter(|p| {
+            patient_id.is_some()
+                || appointments.iter().any(|a| Some(a.patient_id) == p.id)
+                || procedures
+                    .iter()
+                    .any(|proc| demographic_key(&proc.cognome, &proc.nome, &proc.data_nascita)
+                        == demographic_key(&p.cognome, &p.nome, &p.data_nascita))
+        })
+        .collect();
+    included.sort_by(|a, b| (&a.cognome, &a.nome).cmp(&(&b.cognome, &b.nome)));
+
+    let mut entries: Vec<FhirEntry> = Vec::new();
+    let mut subjects: HashMap<String, FhirEntry> = HashMap::new();
+    for patient in &included {
+        let key = patient.id.unwrap_or_default().to_string();
+        let subject = fhir::patient_entry(patient, &key);
+        entries.push(subject.clone());
+        entries.extend(fhir::lab_observation_entries(patient, &key, &subject));
+
+        for appointment in appointments.iter().filter(|a| Some(a.patient_id) == patient.id) {
+            let Ok(date) = chrono::NaiveDate::parse_from_str(&appointment.data, "%Y-%m-%d") else {
+                continue;
+            };
+            let minutes = visit_duration_minutes(&templates, date, appointment.orario.as_deref());
+            let booking = fhir::appointment_entry(appointment, minutes, &subject);
+            if appointment.stato == "eseguito" {
+                let encounter = fhir::encounter_entry(appointment, minutes, &subject, booking.as_ref());
+                entries.extend(booking);
+                entries.push(encounter);
             } else {
-                String::new()
-            },
-        ),
-        (
-            "note_accesso_protezione",
-            p.procedurale_accesso_protezione_note.unwrap_or_default(),
-        ),
-        (
-            "note_protezione",
-            match p.procedurale_protezione_osti.as_deref() {
-                Some("si") | Some("Si") | Some("SI") => "Sì".to_string(),
-                Some("no") | Some("No") | Some("NO") => "No".to_string(),
-                Some(other) => other.to_string(),
-                None => String::new(),
-            },
-        ),
-        (
-            "note_pm_definitivo",
-            p.procedurale_pacemaker_note.unwrap_or_default(),
-        ),
-        ("note_cvg", p.procedurale_coronarografia_note.unwrap_or_default()),
-    ]);
-    // Mappatura checkbox nel template in ordine:
-    // 0-1 Allergia MdC (sì/no)
-    // 2-8 ECG: ritmo sinusale, FA, BBS, BBD, EAS, BAV I grado, ritmo stimolato
-    // 9-11 Anestesia: Locale, Sedazione, Generale
-    // 12-13 Coronarografia: da eseguire in ricovero, già eseguita
-    // 14-15 Pacemaker definitivo: sì/no
-    // 16-20 Accesso principale: percutaneo dx, percutaneo sn, chirurgico dx, chirurgico sn, altro
-    // 21-22 Accesso protezione: sì/no
-    // 23-24 Protezione osti: sì/no
-    // 25-26 Valvuloplastica: sì/no
-    let accesso = p.procedurale_accesso_principale_fem.as_deref();
-    let cb_flags: Vec<bool> = vec![
-        p.procedurale_allergia_mdc.as_deref() == Some("si"),
-        p.procedurale_allergia_mdc.as_deref() == Some("no"),
-        p.procedurale_ecg_ritmo_sinusale.unwrap_or(false),
-        p.procedurale_ecg_fa.unwrap_or(false),
-        p.procedurale_ecg_bbs.unwrap_or(false),
-        p.procedurale_ecg_bbd.unwrap_or(false),
-        p.procedurale_ecg_eas.unwrap_or(false),
-        p.procedurale_ecg_bav_primo.unwrap_or(false),
-        p.procedurale_ecg_ritmo_stimolato.unwrap_or(false),
-        p.procedurale_anestesia.as_deref() == Some("Locale"),
-        p.procedurale_anestesia.as_deref() == Some("Sedazione"),
-        p.procedurale_anestesia.as_deref() == Some("Generale"),
-        p.procedurale_coronarografia.as_deref() == Some("ricovero"),
-        p.procedurale_coronarografia.as_deref() == Some("gia_eseguita"),
-        p.procedurale_pacemaker.as_deref() == Some("si"),
-        p.procedurale_pacemaker.as_deref() == Some("no"),
-        accesso == Some("percutaneo_dx"),
-        accesso == Some("percutaneo_sn"),
-        accesso == Some("chirurgico_dx"),
-        accesso == Some("chirurgico_sn"),
-        accesso == Some("altro"),
-        p.procedurale_accesso_protezione.as_deref() == Some("si"),
-        p.procedurale_accesso_protezione.as_deref() == Some("no"),
-        matches!(p.procedurale_protezione_osti.as_deref(), Some("si") | Some("Si") | Some("SI")),
-        matches!(p.procedurale_protezione_osti.as_deref(), Some("no") | Some("No") | Some("NO")),
-        p.procedurale_valvuloplastica.as_deref() == Some("si"),
-        p.procedurale_valvuloplastica.as_deref() == Some("no"),
-    ];
+                entries.extend(booking);
+            }
+        }
+        subjects.insert(demographic_key(&patient.cognome, &patient.nome, &patient.data_nascita), subject);
+    }
 
-    let template_path = resolve_template_path(&app_handle, "template_scheda_procedurale.docx")?;
+    for procedure in &procedures {
+        if !in_range(&procedure.data_procedura) {
+            continue;
+        }
+        let key = demographic_key(&procedure.cognome, &procedure.nome, &procedure.data_nascita);
+        // Procedura senza paziente in anagrafica: paziente ricavato dai dati della procedura
+        if !subjects.contains_key(&key) {
+            let patient: Patient = serde_json::from_value(serde_json::json!({
+                "nome": procedure.nome,
+                "cognome": procedure.cognome,
+                "data_nascita": procedure.data_nascita,
+            }))
+            .map_err(|e| e.to_string())?;
+            let entry = fhir::patient_entry(&patient, &format!("procedura-{}", key));
+            entries.push(entry.clone());
+            subjects.insert(key.clone(), entry);
+        }
+        let subject = &subjects[&key];
+        let device = fhir::device_entry(procedure, &catalogue, subject);
+        entries.push(fhir::procedure_entry(procedure, subject, &device));
+        entries.push(device);
+    }
 
-    let mut template_file =
-        File::open(&template_path).map_err(|_| "Impossibile aprire il template".to_string())?;
-    let mut archive =
-        ZipArchive::new(&mut template_file).map_err(|_| "Template referto non valido".to_string())?;
+    let bundle = fhir::bundle(&entries);
+    let issues = fhir::validate_bundle(&bundle);
+    if !issues.is_empty() {
+        return Err(format!("Bundle FHIR non valido:\n{}", issues.join("\n")));
+    }
+    let content = serde_json::to_string_pretty(&bundle).map_err(|e| e.to_string())?;
 
-    let mut output_bytes: Vec<u8> = Vec::new();
-    {
-        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(&mut output_bytes));
+    let out_path = PathBuf::from(&output_path);
+    if let Some(parent) = out_path.parent() {
+        create_dir_all(parent).map_err(|_| "Impossibile creare la cartella di destinazione".to_string())?;
+    }
+    let mut out_file =
+        File::create(&out_path).map_err(|_| "Impossibile creare il file di esportazione".to_string())?;
+    out_file
+        .write_all(content.as_bytes())
+        .map_err(|_| "Errore salvataggio esportazione".to_string())?;
 
-        for i in 0..archive.len() {
-            let mut file = archive.by_index(i).map_err(|_| "Errore lettura template".to_string())?;
-            let name = file.name().to_string();
+    Ok(out_path.to_string_lossy().to_string())
+}
 
-            if file.is_dir() {
-                writer
-                    .add_directory(name, FileOptions::default())
-                    .map_err(|_| "Errore scrittura referto".to_string())?;
-                continue;
-            }
+// ============================================================================
+// HL7 INGESTION COMMANDS
+// ============================================================================
 
-            let mut buffer = Vec::new();
-            file.read_to_end(&mut buffer)
-                .map_err(|_| "Errore lettura template".to_string())?;
+pub const HL7_INGESTION_EVENT: &str = "app://hl7-ingestion";
+const HL7_DEFAULT_POLL_SECONDS: u64 = 30;
+const HL7_MIN_POLL_SECONDS: u64 = 5;
+const HL7_FILE_EXTENSIONS: [&str; 4] = ["hl7", "txt", "msg", "er7"];
+const HL7_PROCESSED_DIR: &str = "elaborati";
+const HL7_ERROR_DIR: &str = "errori";
+/// I file modificati più di recente potrebbero essere ancora in scrittura
+const HL7_FILE_SETTLE_TIME: Duration = Duration::from_secs(2);
+
+/// Evita che il controllo periodico e quello manuale elaborino gli stessi file
+static HL7_FOLDER_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+/// Crea o aggiorna il paziente del messaggio: (esito, paziente, dettaglio)
+fn apply_hl7_referral(db: &Database, referral: &Hl7Referral) -> Result<(&'static str, Option<i64>, String), String> {
+    if referral.annullata {
+        return Ok((HL7_OUTCOME_IGNORED, None, "Richiesta annullata dal mittente".to_string()));
+    }
+    let cf = referral
+        .codice_fiscale
+        .as_deref()
+        .filter(|cf| !cf.is_empty())
+        .ok_or_else(|| "Codice fiscale assente (PID-3): impossibile riconoscere il paziente".to_string())?;
 
-            let options = FileOptions::default().compression_method(file.compression());
+    match db.find_patient_ids_by_codice_fiscale(cf)?.as_slice() {
+        [] if referral.is_update_only() => {
+            Ok((HL7_OUTCOME_IGNORED, None, format!("Nessun paziente con codice fiscale {}", cf)))
+        }
+        [] => {
+            let patient = hl7::referral_patient(referral, None)?;
+            let id = db.insert_patient(&patient)?;
+            Ok((HL7_OUTCOME_CREATED, Some(id), "Nuovo paziente in \"Da valutare\"".to_string()))
+        }
+        [id] => {
+            let existing = db
+                .get_patient_by_id(*id)?
+                .ok_or_else(|| "Paziente non trovato".to_string())?
+                .patient;
+            let patient = hl7::referral_patient(referral, Some(existing))?;
+            db.update_patient(&patient)?;
+            Ok((HL7_OUTCOME_UPDATED, Some(*id), "Anagrafica aggiornata".to_string()))
+        }
+        _ => Err(format!("Più pazienti con codice fiscale {}: verificare i duplicati", cf)),
+    }
+}
 
-            if name.ends_with(".xml") {
-                let content = String::from_utf8_lossy(&buffer).to_string();
-                let replaced = replace_placeholders(&content, &replacements);
-                let with_checks = apply_checkbox_flags(&replaced, &cb_flags);
-                writer
-                    .start_file(name.clone(), options)
-                    .map_err(|_| "Errore scrittura referto".to_string())?;
-                writer
-                    .write_all(with_checks.as_bytes())
-                    .map_err(|_| "Errore scrittura referto".to_string())?;
-            } else {
-                writer
-                    .start_file(name.clone(), options)
-                    .map_err(|_| "Errore scrittura referto".to_string())?;
-                writer
-                    .write_all(&buffer)
-                    .map_err(|_| "Errore scrittura referto".to_string())?;
+/// Elabora un messaggio e restituisce la riga di registro corrispondente
+fn ingest_hl7_message(db: &Database, file_name: &str, text: &str) -> Hl7IngestionLogEntry {
+    let mut entry = Hl7IngestionLogEntry {
+        id: None,
+        received_at: None,
+        file_name: file_name.to_string(),
+        message_type: None,
+        control_id: None,
+        sending_facility: None,
+        esito: HL7_OUTCOME_ERROR.to_string(),
+        patient_id: None,
+        dettaglio: None,
+    };
+    let message = match hl7::parse_message(text) {
+        Ok(message) => message,
+        Err(e) => {
+            entry.dettaglio = Some(format!("Messaggio non valido: {}", e));
+            return entry;
+        }
+    };
+    entry.message_type = Some(message.message_type()).filter(|t| !t.is_empty());
+    entry.control_id = Some(message.control_id()).filter(|c| !c.is_empty());
+    entry.sending_facility = Some(message.sending_facility()).filter(|f| !f.is_empty());
+
+    // Un messaggio già applicato (es. file rielaborato dopo un errore) non viene riapplicato
+    if let Some(control_id) = entry.control_id.as_deref() {
+        match db.find_applied_hl7_message(control_id, entry.sending_facility.as_deref()) {
+            Ok(Some(received_at)) => {
+                entry.esito = HL7_OUTCOME_IGNORED.to_string();
+                entry.dettaglio = Some(format!(
+                    "Messaggio {} già acquisito il {}: non rielaborato",
+                    control_id, received_at
+                ));
+                return entry;
+            }
+            Ok(None) => {}
+            Err(e) => {
+                entry.dettaglio = Some(e);
+                return entry;
             }
         }
+    }
 
-        writer.finish().map_err(|_| "Errore finale referto".to_string())?;
+    match hl7::extract_referral(&message).and_then(|referral| apply_hl7_referral(db, &referral)) {
+        Ok((esito, patient_id, dettaglio)) => {
+            entry.esito = esito.to_string();
+            entry.patient_id = patient_id;
+            entry.dettaglio = Some(dettaglio);
+        }
+        Err(e) => entry.dettaglio = Some(e),
     }
+    entry
+}
 
-    let settings = read_settings_from_disk().unwrap_or_default();
-    let out_dir = resolve_referti_dir(&settings, "proc", &app_handle);
-    create_dir_all(&out_dir).map_err(|_| "Impossibile creare cartella referti".to_string())?;
+/// Sposta il file nella sottocartella indicata senza sovrascrivere file omonimi
+fn move_hl7_file(path: &Path, dir_name: &str) -> Result<(), String> {
+    let parent = path.parent().ok_or("Percorso del file non valido")?;
+    let target_dir = parent.join(dir_name);
+    create_dir_all(&target_dir).map_err(|e| format!("Impossibile creare {}: {}", target_dir.display(), e))?;
+    let file_name = path.file_name().ok_or("Percorso del file non valido")?.to_string_lossy().to_string();
+    let mut target = target_dir.join(&file_name);
+    if target.exists() {
+        target = target_dir.join(format!("{}_{}", Local::now().format("%Y%m%d%H%M%S%3f"), file_name));
+    }
+    std::fs::rename(path, &target)
+        .or_else(|_| std::fs::copy(path, &target).and_then(|_| std::fs::remove_file(path)))
+        .map_err(|e| format!("Impossibile spostare {}: {}", file_name, e))
+}
 
-    let filename = sanitize_filename(&format!("Scheda procedurale - {} {}.docx", p.cognome, p.nome));
-    let out_path = out_dir.join(filename);
+/// Elabora un file della cartella di ingresso: ogni messaggio viene registrato; il file va in
+/// "errori" se almeno un messaggio non è valido o non è stato acquisito, altrimenti in "elaborati".
+/// I messaggi acquisiti restano applicati anche quando il file finisce in "errori": il registro
+/// lo segnala e, rielaborando il file, i messaggi con control ID già acquisito (creato o
+/// aggiornato) sono registrati come "ignorato" senza essere riapplicati.
+fn ingest_hl7_file(db: &Database, path: &Path, summary: &mut Hl7IngestionSummary) -> Result<(), String> {
+    let file_name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let bytes = std::fs::read(path).map_err(|e| format!("Impossibile leggere {}: {}", file_name, e))?;
+    // I sistemi ADT inviano spesso in Latin-1
+    let content = String::from_utf8(bytes.clone()).unwrap_or_else(|_| bytes.iter().map(|&b| b as char).collect());
+
+    let mut entries: Vec<Hl7IngestionLogEntry> = hl7::split_messages(&content)
+        .iter()
+        .map(|text| ingest_hl7_message(db, &file_name, text))
+        .collect();
+    if entries.is_empty() {
+        let mut entry = ingest_hl7_message(db, &file_name, "");
+        entry.dettaglio = Some("File vuoto o senza messaggi HL7".to_string());
+        entries.push(entry);
+    }
 
-    let mut out_file =
-        File::create(&out_path).map_err(|_| "Impossibile creare il referto".to_string())?;
-    out_file
-        .write_all(&output_bytes)
-        .map_err(|_| "Errore salvataggio referto".to_string())?;
+    let failed = entries.iter().filter(|e| e.esito == HL7_OUTCOME_ERROR).count();
+    let applied = entries
+        .iter()
+        .filter(|e| e.esito == HL7_OUTCOME_CREATED || e.esito == HL7_OUTCOME_UPDATED)
+        .count();
+    if failed > 0 && applied > 0 {
+        let note = format!(
+            "file spostato in \"{}\", messaggi dello stesso file già acquisiti: {}",
+            HL7_ERROR_DIR, applied
+        );
+        eprintln!("Acquisizione HL7 parziale {}: {} messaggi in errore, {}", file_name, failed, note);
+        for entry in entries.iter_mut().filter(|e| e.esito == HL7_OUTCOME_ERROR) {
+            let dettaglio = entry.dettaglio.take().unwrap_or_default();
+            entry.dettaglio = Some(format!("{} ({})", dettaglio, note));
+        }
+    }
 
-    Ok(out_path
-        .to_string_lossy()
-        .to_string())
+    for entry in &entries {
+        match entry.esito.as_str() {
+            HL7_OUTCOME_CREATED => summary.creati += 1,
+            HL7_OUTCOME_UPDATED => summary.aggiornati += 1,
+            HL7_OUTCOME_IGNORED => summary.ignorati += 1,
+            _ => summary.errori += 1,
+        }
+        db.insert_hl7_ingestion_log(entry)?;
+    }
+    if failed > 0 {
+        summary.file_in_errore += 1;
+        move_hl7_file(path, HL7_ERROR_DIR)
+    } else {
+        summary.file_elaborati += 1;
+        move_hl7_file(path, HL7_PROCESSED_DIR)
+    }
 }
 
-#[tauri::command]
-pub async fn generate_consenso_informato(
-    patient_id: i64,
-    db: State<'_, Database>,
-    app_handle: AppHandle,
-) -> Result<String, String> {
-    let patient = db
-        .get_patient_by_id(patient_id)?
-        .ok_or_else(|| "Paziente non trovato".to_string())?;
-    let p = patient.patient;
+/// Elabora i messaggi presenti nella cartella di ingresso, dal meno recente
+pub fn run_hl7_ingestion(db: &Database, drop_dir: &Path) -> Result<Hl7IngestionSummary, String> {
+    let _guard = HL7_FOLDER_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    if !drop_dir.is_dir() {
+        return Err(format!("Cartella di ingresso HL7 non trovata: {}", drop_dir.display()));
+    }
 
-    let template_path = resolve_template_path(&app_handle, "consenso_informato_TAVI.docx")?;
-    let replacements: HashMap<&str, String> = HashMap::from([
-        ("nome", p.nome.clone()),
-        ("cognome", p.cognome.clone()),
-    ]);
+    let now = std::time::SystemTime::now();
+    let mut files: Vec<(std::time::SystemTime, PathBuf)> = std::fs::read_dir(drop_dir)
+        .map_err(|e| format!("Impossibile leggere la cartella di ingresso: {}", e))?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let path = entry.path();
+            let metadata = entry.metadata().ok()?;
+            let extension = path.extension()?.to_string_lossy().to_lowercase();
+            let hidden = path.file_name()?.to_string_lossy().starts_with('.');
+            if !metadata.is_file() || hidden || !HL7_FILE_EXTENSIONS.contains(&extension.as_str()) {
+                return None;
+            }
+            let modified = metadata.modified().ok()?;
+            let settled = now.duration_since(modified).map_or(true, |age| age >= HL7_FILE_SETTLE_TIME);
+            settled.then_some((modified, path))
+        })
+        .collect();
+    files.sort();
 
-    let mut template_file =
-        File::open(&template_path).map_err(|_| "Impossibile aprire il template".to_string())?;
-    let mut archive =
-        ZipArchive::new(&mut template_file).map_err(|_| "Template referto non valido".to_string())?;
+    let mut summary = Hl7IngestionSummary::default();
+    for (_, path) in files {
+        if let Err(e) = ingest_hl7_file(db, &path, &mut summary) {
+            eprintln!("Errore acquisizione HL7 {}: {}", path.display(), e);
+        }
+    }
+    Ok(summary)
+}
 
-    let mut output_bytes: Vec<u8> = Vec::new();
-    {
-        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(&mut output_bytes));
-        for i in 0..archive.len() {
-            let mut file = archive.by_index(i).map_err(|_| "Errore lettura template".to_string())?;
-            let name = file.name().to_string();
+fn configured_hl7_drop_dir() -> Option<PathBuf> {
+    read_settings_from_disk()
+        .ok()?
+        .hl7_drop_path
+        .map(|p| p.trim().to_string())
+        .filter(|p| !p.is_empty())
+        .map(PathBuf::from)
+}
 
-            if file.is_dir() {
-                writer
-                    .add_directory(name, FileOptions::default())
-                    .map_err(|_| "Errore scrittura referto".to_string())?;
-                continue;
+/// Controlla periodicamente la cartella di ingresso configurata nelle impostazioni
+pub fn start_hl7_folder_watcher(app_handle: AppHandle) {
+    std::thread::spawn(move || loop {
+        let settings = read_settings_from_disk().unwrap_or_default();
+        let interval = settings
+            .hl7_poll_seconds
+            .unwrap_or(HL7_DEFAULT_POLL_SECONDS)
+            .max(HL7_MIN_POLL_SECONDS);
+        if let Some(drop_dir) = configured_hl7_drop_dir().filter(|dir| dir.is_dir()) {
+            let db = app_handle.state::<Database>();
+            match run_hl7_ingestion(&db, &drop_dir) {
+                Ok(summary) if summary.file_elaborati + summary.file_in_errore > 0 => {
+                    let _ = app_handle.emit_all(HL7_INGESTION_EVENT, summary);
+                }
+                Ok(_) => {}
+                Err(e) => eprintln!("Errore acquisizione HL7: {}", e),
             }
+        }
+        std::thread::sleep(Duration::from_secs(interval));
+    });
+}
 
-            let mut buffer = Vec::new();
-            file.read_to_end(&mut buffer)
-                .map_err(|_| "Errore lettura template".to_string())?;
-
-            let options = FileOptions::default().compression_method(file.compression());
+/// Elabora subito la cartella di ingresso HL7
+#[tauri::command]
+pub async fn process_hl7_folder(
+    db: State<'_, Database>,
+    app_handle: AppHandle,
+) -> Result<Hl7IngestionSummary, String> {
+    let drop_dir = configured_hl7_drop_dir()
+        .ok_or_else(|| "Cartella di ingresso HL7 non configurata nelle impostazioni".to_string())?;
+    let summary = run_hl7_ingestion(&db, &drop_dir)?;
+    let _ = app_handle.emit_all(HL7_INGESTION_EVENT, summary.clone());
+    Ok(summary)
+}
 
-            if name.ends_with(".xml") {
-                let content = String::from_utf8_lossy(&buffer).to_string();
-                let replaced = replace_placeholders(&content, &replacements);
-                writer
-                    .start_file(name.clone(), options)
-                    .map_err(|_| "Errore scrittura referto".to_string())?;
-                writer
-                    .write_all(replaced.as_bytes())
-                    .map_err(|_| "Errore scrittura referto".to_string())?;
-            } else {
-                writer
-                    .start_file(name.clone(), options)
-                    .map_err(|_| "Errore scrittura referto".to_string())?;
-                writer
-                    .write_all(&buffer)
-                    .map_err(|_| "Errore scrittura referto".to_string())?;
-            }
+#[tauri::command]
+pub async fn get_hl7_ingestion_log(
+    limit: Option<i64>,
+    esito: Option<String>,
+    db: State<'_, Database>,
+) -> Result<Vec<Hl7IngestionLogEntry>, String> {
+    let esito = esito.as_deref().filter(|e| !e.is_empty());
+    if let Some(esito) = esito {
+        if !HL7_INGESTION_OUTCOMES.iter().any(|(code, _)| *code == esito) {
+            return Err(format!("Esito non valido: {}", esito));
         }
-        writer.finish().map_err(|_| "Errore finale referto".to_string())?;
     }
+    db.get_hl7_ingestion_log(limit.unwrap_or(200), esito)
+}
 
-    let out_dir = resolve_moduli_temp_dir();
-    create_dir_all(&out_dir).map_err(|_| "Impossibile creare cartella moduli".to_string())?;
+// ============================================================================
+// RESEARCH EXPORT COMMANDS
+// ============================================================================
 
-    let filename = sanitize_filename(&format!(
-        "Consenso informato - {} {}.docx",
-        p.cognome, p.nome
-    ));
-    let out_path = out_dir.join(filename);
+const RESEARCH_SECRET_FILE: &str = "research_site_secret.key";
 
-    let mut out_file =
-        File::create(&out_path).map_err(|_| "Impossibile creare il referto".to_string())?;
-    out_file
-        .write_all(&output_bytes)
-        .map_err(|_| "Errore salvataggio referto".to_string())?;
+/// Il segreto di sito sta accanto alle impostazioni, non dentro: non viene mostrato nell'interfaccia
+fn research_site_secret_path() -> PathBuf {
+    settings_file_path().with_file_name(RESEARCH_SECRET_FILE)
+}
 
-    schedule_temp_cleanup(out_path.clone());
-    Ok(out_path.to_string_lossy().to_string())
+/// Dizionario dati del manifest per un gruppo di colonne
+fn research_dictionary(columns: &[(&str, &str, &str)]) -> serde_json::Value {
+    serde_json::Value::Array(
+        columns
+            .iter()
+            .map(|(code, label, transformation)| {
+                serde_json::json!({ "campo": code, "descrizione": label, "trasformazione": transformation })
+            })
+            .collect(),
+    )
 }
 
+/// Esporta un dataset anonimizzato (pazienti e procedure) per registri e pubblicazioni, in CSV
+/// (un file per tabella) o JSON, insieme a `manifest.json` con le trasformazioni applicate.
+/// Gli identificativi sono pseudonimi stabili tra un'esportazione e l'altra; le date di ogni
+/// paziente sono spostate dello stesso offset, così gli intervalli tra eventi restano veri.
+/// L'intervallo di date, se indicato, limita le procedure e i pazienti esportati.
 #[tauri::command]
-pub async fn generate_consenso_informato_html(
-    patient_id: i64,
+pub async fn export_research_dataset(
+    output_dir: String,
+    formato: String,
+    date_from: Option<String>,
+    date_to: Option<String>,
     db: State<'_, Database>,
-    app_handle: AppHandle,
-) -> Result<String, String> {
-    let patient = db
-        .get_patient_by_id(patient_id)?
-        .ok_or_else(|| "Paziente non trovato".to_string())?;
-    let p = patient.patient;
+) -> Result<ResearchExportResult, String> {
+    write_research_dataset(&db, &research_site_secret_path(), &output_dir, &formato, date_from, date_to)
+}
 
-    let template_path = resolve_template_path(&app_handle, "consenso_informato_TAVI.docx")?;
-    let replacements: HashMap<&str, String> = HashMap::from([
-        ("nome", p.nome.clone()),
-        ("cognome", p.cognome.clone()),
-    ]);
+/// Scrive il dataset di ricerca usando il segreto di sito salvato in `secret_path`
+fn write_research_dataset(
+    db: &Database,
+    secret_path: &Path,
+    output_dir: &str,
+    formato: &str,
+    date_from: Option<String>,
+    date_to: Option<String>,
+) -> Result<ResearchExportResult, String> {
+    let formato = formato.trim().to_lowercase();
+    if !RESEARCH_EXPORT_FORMATS.contains(&formato.as_str()) {
+        return Err(format!("Formato di esportazione non valido: {}", formato));
+    }
+    let parse = |value: Option<&String>| -> Result<Option<chrono::NaiveDate>, String> {
+        match value.map(|v| v.trim()).filter(|v| !v.is_empty()) {
+            Some(v) => chrono::NaiveDate::parse_from_str(v, "%Y-%m-%d")
+                .map(Some)
+                .map_err(|_| format!("Data non valida: {}", v)),
+            None => Ok(None),
+        }
+    };
+    let (from, to) = (parse(date_from.as_ref())?, parse(date_to.as_ref())?);
+    if let (Some(from), Some(to)) = (from, to) {
+        if to < from {
+            return Err("La data finale precede la data iniziale".to_string());
+        }
+    }
+    let filtered = from.is_some() || to.is_some();
+    let in_range = |date: &str| match chrono::NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d") {
+        Ok(date) => from.is_none_or(|f| date >= f) && to.is_none_or(|t| date <= t),
+        Err(_) => false,
+    };
 
-    let mut template_file =
-        File::open(&template_path).map_err(|_| "Impossibile aprire il template".to_string())?;
-    let mut archive =
-        ZipArchive::new(&mut template_file).map_err(|_| "Template referto non valido".to_string())?;
+    let pseudonymizer = Pseudonymizer::new(research::load_or_create_site_secret(secret_path)?);
+    let patients = db.get_all_patients_with_status(None)?;
+    let procedures = db.get_all_procedures(None)?;
 
-    let mut document_xml = String::new();
-    let mut styles_xml = String::new();
-    {
-        let mut doc_file = archive
-            .by_name("word/document.xml")
-            .map_err(|_| "Template referto non valido".to_string())?;
-        doc_file
-            .read_to_string(&mut document_xml)
-            .map_err(|_| "Errore lettura template".to_string())?;
+    // Chiave del soggetto: l'id del paziente; le procedure senza paziente corrispondente
+    // usano l'anagrafica, così restano comunque collegate tra loro
+    let patient_ids: HashMap<String, i64> = patients
+        .iter()
+        .filter_map(|item| {
+            let p = &item.patient;
+            Some((demographic_key(&p.cognome, &p.nome, &p.data_nascita), p.id?))
+        })
+        .collect();
+    let subject_key = |cognome: &str, nome: &str, data_nascita: &str| {
+        let key = demographic_key(cognome, nome, data_nascita);
+        match patient_ids.get(&key) {
+            Some(id) => format!("paziente:{}", id),
+            None => format!("anagrafica:{}", key),
+        }
+    };
+
+    let mut first_procedure: HashMap<String, chrono::NaiveDate> = HashMap::new();
+    let mut subjects_in_range: std::collections::HashSet<String> = std::collections::HashSet::new();
+    let mut procedure_rows = Vec::new();
+    for proc in &procedures {
+        let subject = subject_key(&proc.cognome, &proc.nome, &proc.data_nascita);
+        if let Ok(date) = chrono::NaiveDate::parse_from_str(proc.data_procedura.trim(), "%Y-%m-%d") {
+            let first = first_procedure.entry(subject.clone()).or_insert(date);
+            *first = (*first).min(date);
+        }
+        if !in_range(&proc.data_procedura) {
+            continue;
+        }
+        let offset = pseudonymizer.date_offset_days(&subject);
+        let row: Vec<XlsxCell> = RESEARCH_PROCEDURE_COLUMNS
+            .iter()
+            .map(|(code, _, transformation)| match *code {
+                "id_procedura" => XlsxCell::Text(
+                    pseudonymizer.pseudonym("R", &format!("procedura:{}", proc.id.unwrap_or_default())),
+                ),
+                "id_paziente" => XlsxCell::Text(pseudonymizer.pseudonym("P", &subject)),
+                "eta_procedura" => procedure_export_cell(proc, "eta"),
+                _ if *transformation == "data_spostata" => research::shift_cell(procedure_export_cell(proc, code), offset),
+                _ => procedure_export_cell(proc, code),
+            })
+            .collect();
+        subjects_in_range.insert(subject);
+        procedure_rows.push(row);
     }
-    if let Ok(mut styles_file) = archive.by_name("word/styles.xml") {
-        let _ = styles_file.read_to_string(&mut styles_xml);
+
+    let today = Local::now().date_naive();
+    let mut patient_rows = Vec::new();
+    for item in &patients {
+        let p = &item.patient;
+        let Some(id) = p.id else { continue };
+        let subject = format!("paziente:{}", id);
+        if filtered && !subjects_in_range.contains(&subject) && !p.data_tavi.as_deref().is_some_and(in_range) {
+            continue;
+        }
+        let offset = pseudonymizer.date_offset_days(&subject);
+        // Età alla prima procedura registrata, altrimenti alla data TAVI
+        let reference_date = first_procedure.get(&subject).copied().or_else(|| {
+            p.data_tavi
+                .as_deref()
+                .and_then(|d| chrono::NaiveDate::parse_from_str(d.trim(), "%Y-%m-%d").ok())
+        });
+        let row: Vec<XlsxCell> = RESEARCH_PATIENT_COLUMNS
+            .iter()
+            .map(|(code, _, transformation)| match *code {
+                "id_paziente" => XlsxCell::Text(pseudonymizer.pseudonym("P", &subject)),
+                "eta_procedura" => {
+                    XlsxCell::number(reference_date.and_then(|date| age_at(&p.data_nascita, date)).map(f64::from))
+                }
+                "data_inserimento" => research::shift_cell(XlsxCell::date(p.created_at.as_deref()), offset),
+                "accesso_principale" => match patient_export_cell(item, code, today) {
+                    XlsxCell::Text(_) if p.procedurale_accesso_principale_fem.as_deref().map(str::trim) == Some("altro") => {
+                        XlsxCell::Text("Altro".to_string())
+                    }
+                    cell => cell,
+                },
+                _ if *transformation == "data_spostata" => {
+                    research::shift_cell(patient_export_cell(item, code, today), offset)
+                }
+                _ => patient_export_cell(item, code, today),
+            })
+            .collect();
+        patient_rows.push(row);
     }
 
-    let replaced = replace_placeholders(&document_xml, &replacements);
-    let styles_map = if styles_xml.is_empty() {
-        HashMap::new()
+    let patient_headers: Vec<&str> = RESEARCH_PATIENT_COLUMNS.iter().map(|(code, _, _)| *code).collect();
+    let procedure_headers: Vec<&str> = RESEARCH_PROCEDURE_COLUMNS.iter().map(|(code, _, _)| *code).collect();
+    let files: Vec<(String, String, usize, Vec<u8>)> = if formato == "csv" {
+        vec![
+            (
+                "pazienti.csv".to_string(),
+                "pazienti".to_string(),
+                patient_rows.len(),
+                research::csv_table(&patient_headers, &patient_rows).into_bytes(),
+            ),
+            (
+                "procedure.csv".to_string(),
+                "procedure".to_string(),
+                procedure_rows.len(),
+                research::csv_table(&procedure_headers, &procedure_rows).into_bytes(),
+            ),
+        ]
     } else {
-        parse_styles(&styles_xml)
+        let dataset = serde_json::json!({
+            "pazienti": research::json_table(&patient_headers, &patient_rows),
+            "procedure": research::json_table(&procedure_headers, &procedure_rows),
+        });
+        vec![(
+            "dataset.json".to_string(),
+            "pazienti, procedure".to_string(),
+            patient_rows.len() + procedure_rows.len(),
+            serde_json::to_vec_pretty(&dataset).map_err(|e| e.to_string())?,
+        )]
     };
-    let html = docx_xml_to_html(&replaced, Some(&styles_map));
-    if html.trim().is_empty() {
-        return Err("Contenuto consenso vuoto".to_string());
+
+    let manifest = serde_json::json!({
+        "tipo": "dataset_ricerca_anonimizzato",
+        "generato_il": Local::now().format("%Y-%m-%dT%H:%M:%S").to_string(),
+        "versione_applicazione": env!("CARGO_PKG_VERSION"),
+        "formato": formato,
+        "file": files
+            .iter()
+            .map(|(name, content, rows, _)| serde_json::json!({ "nome": name, "contenuto": content, "righe": rows }))
+            .collect::<Vec<_>>(),
+        "filtri": {
+            "data_procedura_da": from.map(|d| d.format("%Y-%m-%d").to_string()),
+            "data_procedura_a": to.map(|d| d.format("%Y-%m-%d").to_string()),
+        },
+        "pseudonimizzazione": {
+            "algoritmo": "HMAC-SHA256",
+            "chiave": "segreto di sito conservato localmente, non incluso nell'esportazione",
+            "impronta_chiave": pseudonymizer.fingerprint(),
+            "prefissi": { "P": "paziente", "R": "procedura" },
+            "stabilita": "lo stesso paziente riceve lo stesso pseudonimo in ogni esportazione con la stessa chiave",
+        },
+        "spostamento_date": {
+            "metodo": "offset in giorni per paziente derivato dal segreto di sito, uguale per tutte le date del paziente",
+            "intervallo_giorni": [-research::DATE_SHIFT_MAX_DAYS, research::DATE_SHIFT_MAX_DAYS],
+            "zero_escluso": true,
+            "nota": "gli intervalli tra date dello stesso paziente sono conservati; i confronti di calendario tra pazienti no",
+        },
+        "eta": "età in anni compiuti alla procedura, calcolata dalla data di nascita reale prima della rimozione",
+        "formato_csv": { "separatore": ";", "decimali": ",", "booleani": "1/0", "date": "AAAA-MM-GG" },
+        "trasformazioni": RESEARCH_TRANSFORMATIONS
+            .iter()
+            .map(|(code, description)| serde_json::json!({ "codice": code, "descrizione": description }))
+            .collect::<Vec<_>>(),
+        "campi_rimossi": RESEARCH_REMOVED_FIELDS
+            .iter()
+            .map(|(field, reason)| serde_json::json!({ "campo": field, "motivo": reason }))
+            .collect::<Vec<_>>(),
+        "dizionario": {
+            "pazienti": research_dictionary(&RESEARCH_PATIENT_COLUMNS),
+            "procedure": research_dictionary(&RESEARCH_PROCEDURE_COLUMNS),
+        },
+    });
+
+    let out_dir = PathBuf::from(output_dir);
+    create_dir_all(&out_dir).map_err(|_| "Impossibile creare la cartella di destinazione".to_string())?;
+    let manifest_bytes = serde_json::to_vec_pretty(&manifest).map_err(|e| e.to_string())?;
+    let mut written = Vec::new();
+    for (name, bytes) in files
+        .iter()
+        .map(|(name, _, _, bytes)| (name.as_str(), bytes.as_slice()))
+        .chain([("manifest.json", manifest_bytes.as_slice())])
+    {
+        let path = out_dir.join(name);
+        let mut out_file =
+            File::create(&path).map_err(|_| "Impossibile creare il file di esportazione".to_string())?;
+        out_file
+            .write_all(bytes)
+            .map_err(|_| "Errore salvataggio esportazione".to_string())?;
+        written.push(path.to_string_lossy().to_string());
     }
-    Ok(html)
+
+    Ok(ResearchExportResult {
+        cartella: out_dir.to_string_lossy().to_string(),
+        file: written,
+        pazienti: patient_rows.len(),
+        procedure: procedure_rows.len(),
+    })
 }
 
-#[tauri::command]
-pub async fn generate_esami_ematochimici(
-    patient_id: i64,
-    db: State<'_, Database>,
-    app_handle: AppHandle,
-) -> Result<String, String> {
-    let patient = db
-        .get_patient_by_id(patient_id)?
-        .ok_or_else(|| "Paziente non trovato".to_string())?;
-    let p = patient.patient;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let template_path = resolve_template_path(&app_handle, "ee_tavi.pdf")?;
-    let out_dir = resolve_moduli_temp_dir();
-    create_dir_all(&out_dir).map_err(|_| "Impossibile creare cartella moduli".to_string())?;
+    const ADT_A04: &str = include_str!("../tests/fixtures/hl7/adt_a04.hl7");
+    const ADT_A08: &str = include_str!("../tests/fixtures/hl7/adt_a08.hl7");
 
-    let filename = sanitize_filename(&format!(
-        "Esami ematochimici - {} {}.pdf",
-        p.cognome, p.nome
-    ));
-    let out_path = out_dir.join(filename);
-    std::fs::copy(&template_path, &out_path).map_err(|_| "Impossibile copiare il modulo".to_string())?;
+    /// Cartella di ingresso temporanea con il database accanto
+    fn hl7_workspace(name: &str) -> (PathBuf, Database) {
+        let root = std::env::temp_dir().join(format!("tavi-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        create_dir_all(root.join("ingresso")).unwrap();
+        let db = Database::new(root.join("tavi.db")).unwrap();
+        (root, db)
+    }
 
-    schedule_temp_cleanup(out_path.clone());
-    Ok(out_path.to_string_lossy().to_string())
-}
+    /// Scrive un file già "stabile": più vecchio del tempo di attesa per i file in scrittura
+    fn drop_file(dir: &Path, name: &str, content: &str) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, content).unwrap();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(std::time::SystemTime::now() - Duration::from_secs(60))
+            .unwrap();
+        path
+    }
 
-#[tauri::command]
-pub fn print_window(window: Window) -> Result<(), String> {
-    window.print().map_err(|e| e.to_string())
+    #[test]
+    fn hl7_ingestion_moves_processed_files_and_skips_others() {
+        let (root, db) = hl7_workspace("hl7-elaborati");
+        let drop_dir = root.join("ingresso");
+        drop_file(&drop_dir, "a04.hl7", ADT_A04);
+        drop_file(&drop_dir, "referto.pdf", "non HL7");
+        std::fs::write(drop_dir.join("in_scrittura.hl7"), ADT_A08).unwrap();
+
+        let summary = run_hl7_ingestion(&db, &drop_dir).unwrap();
+        assert_eq!((summary.file_elaborati, summary.file_in_errore), (1, 0));
+        assert_eq!((summary.creati, summary.errori), (1, 0));
+        assert!(drop_dir.join(HL7_PROCESSED_DIR).join("a04.hl7").is_file());
+        assert!(!drop_dir.join("a04.hl7").exists());
+        assert!(drop_dir.join("referto.pdf").is_file());
+        assert!(drop_dir.join("in_scrittura.hl7").is_file());
+
+        let ids = db.find_patient_ids_by_codice_fiscale("RSSMRA48C07H501X").unwrap();
+        assert_eq!(ids.len(), 1);
+        let log = db.get_hl7_ingestion_log(10, Some(HL7_OUTCOME_CREATED)).unwrap();
+        assert_eq!(log[0].control_id.as_deref(), Some("MSG0001"));
+        assert_eq!(log[0].patient_id, Some(ids[0]));
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn hl7_ingestion_moves_partially_applied_files_to_errors() {
+        let (root, db) = hl7_workspace("hl7-errori");
+        let drop_dir = root.join("ingresso");
+        drop_file(&drop_dir, "a04.hl7", ADT_A04);
+        run_hl7_ingestion(&db, &drop_dir).unwrap();
+
+        let broken = "MSH|^~\\&|ADT|OSPEDALE CIVILE|||20301006|||ADT^A08|MSG0009|P|2.5\rEVN|A08\r";
+        drop_file(&drop_dir, "lotto.hl7", &format!("{}{}", ADT_A08, broken));
+        let summary = run_hl7_ingestion(&db, &drop_dir).unwrap();
+        assert_eq!((summary.file_elaborati, summary.file_in_errore), (0, 1));
+        assert_eq!((summary.aggiornati, summary.errori), (1, 1));
+        assert!(drop_dir.join(HL7_ERROR_DIR).join("lotto.hl7").is_file());
+
+        // L'aggiornamento valido resta applicato e l'errore lo segnala nel registro
+        let id = db.find_patient_ids_by_codice_fiscale("RSSMRA48C07H501X").unwrap()[0];
+        let patient = db.get_patient_by_id(id).unwrap().unwrap().patient;
+        assert_eq!(patient.nome, "MARIO ANTONIO");
+        assert_eq!(patient.telefono, None);
+        let errors = db.get_hl7_ingestion_log(10, Some(HL7_OUTCOME_ERROR)).unwrap();
+        assert_eq!(errors.len(), 1);
+        let dettaglio = errors[0].dettaglio.as_deref().unwrap_or("");
+        assert!(dettaglio.contains("Segmento PID mancante"), "{}", dettaglio);
+        assert!(dettaglio.contains("messaggi dello stesso file già acquisiti: 1"), "{}", dettaglio);
+
+        // Rielaborando il file l'aggiornamento già acquisito non viene riapplicato
+        let mut edited = patient;
+        edited.nome = "MARIO".to_string();
+        db.update_patient(&edited).unwrap();
+        let failed = drop_dir.join(HL7_ERROR_DIR).join("lotto.hl7");
+        drop_file(&drop_dir, "lotto.hl7", &std::fs::read_to_string(&failed).unwrap());
+        std::fs::remove_file(failed).unwrap();
+        let summary = run_hl7_ingestion(&db, &drop_dir).unwrap();
+        assert_eq!((summary.aggiornati, summary.ignorati, summary.errori), (0, 1, 1));
+        assert_eq!(db.get_patient_by_id(id).unwrap().unwrap().patient.nome, "MARIO");
+        let ignored = db.get_hl7_ingestion_log(10, Some(HL7_OUTCOME_IGNORED)).unwrap();
+        assert_eq!(ignored[0].control_id.as_deref(), Some("MSG0002"));
+        assert!(ignored[0].dettaglio.as_deref().unwrap_or("").contains("già acquisito"));
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn research_export_removes_identifiers_and_shifts_dates_per_patient() {
+        let root = std::env::temp_dir().join(format!("tavi-ricerca-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        create_dir_all(&root).unwrap();
+        let db = Database::new(root.join("tavi.db")).unwrap();
+        let patient: Patient = serde_json::from_value(serde_json::json!({
+            "cognome": "Rossi",
+            "nome": "Mario",
+            "data_nascita": "1940-01-01",
+            "codice_fiscale": "RSSMRA40A01H501X",
+            "telefono": "0612345678",
+            "ambulatorio_data_visita": "2024-02-01",
+            "data_tavi": "2024-03-10",
+        }))
+        .unwrap();
+        db.insert_patient(&patient).unwrap();
+        let procedure: Procedure = serde_json::from_value(serde_json::json!({
+            "cognome": "Rossi",
+            "nome": "Mario",
+            "data_nascita": "1940-01-01",
+            "valvola_protesica": false,
+            "data_procedura": "2024-03-10",
+            "ora_inizio": "09:00",
+            "ora_fine": "10:15",
+            "tipo_valvola": "Balloon Expandable",
+            "modello_valvola": "SAPIEN 3",
+            "pre_dilatazione": false,
+            "post_dilatazione": true,
+        }))
+        .unwrap();
+        db.insert_procedure(&procedure).unwrap();
+
+        let secret_path = root.join("segreto.key");
+        let out_dir = root.join("export");
+        let result =
+            write_research_dataset(&db, &secret_path, &out_dir.to_string_lossy(), "json", None, None).unwrap();
+        assert_eq!((result.pazienti, result.procedure), (1, 1));
+        assert!(secret_path.is_file());
+
+        let text = std::fs::read_to_string(out_dir.join("dataset.json")).unwrap();
+        for identifier in ["Rossi", "Mario", "RSSMRA", "0612345678", "1940-01-01"] {
+            assert!(!text.contains(identifier), "{} presente nel dataset", identifier);
+        }
+        let dataset: serde_json::Value = serde_json::from_str(&text).unwrap();
+        let paziente = &dataset["pazienti"][0];
+        let procedura = &dataset["procedure"][0];
+        let columns: Vec<&str> = paziente.as_object().unwrap().keys().map(String::as_str).collect();
+        for removed in ["id", "cognome", "nome", "data_nascita", "codice_fiscale", "telefono", "note"] {
+            assert!(!columns.contains(&removed), "colonna {} esportata", removed);
+        }
+        assert_eq!(paziente["eta_procedura"], 84);
+
+        // Stesso offset per tutte le date del paziente, anche nella tabella procedure
+        let date = |value: &serde_json::Value| {
+            chrono::NaiveDate::parse_from_str(value.as_str().unwrap(), "%Y-%m-%d").unwrap()
+        };
+        assert_eq!(paziente["id_paziente"], procedura["id_paziente"]);
+        assert_ne!(paziente["data_tavi"], "2024-03-10");
+        assert_eq!((date(&paziente["data_tavi"]) - date(&paziente["ambulatorio_data_visita"])).num_days(), 38);
+        assert_eq!(procedura["data_procedura"], paziente["data_tavi"]);
+
+        // Il manifest descrive esattamente le colonne esportate
+        let manifest: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(out_dir.join("manifest.json")).unwrap()).unwrap();
+        let campi = |table: &str| -> Vec<String> {
+            manifest["dizionario"][table]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|field| field["campo"].as_str().unwrap().to_string())
+                .collect()
+        };
+        let mut exported: Vec<String> = columns.iter().map(|c| c.to_string()).collect();
+        let mut listed = campi("pazienti");
+        exported.sort();
+        listed.sort();
+        assert_eq!(exported, listed);
+        let mut exported: Vec<String> = procedura.as_object().unwrap().keys().cloned().collect();
+        let mut listed = campi("procedure");
+        exported.sort();
+        listed.sort();
+        assert_eq!(exported, listed);
+        assert_eq!(manifest["file"][0]["nome"], "dataset.json");
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
 }
@@ -2,16 +2,70 @@ use rusqlite::{Connection, params, Result as SqlResult};
 use std::path::PathBuf;
 use std::sync::Mutex;
 use crate::models::{Procedure, ProcedureFilters, Statistics, Patient, PatientStatus, PatientWithStatus, PatientFilters, PatientStatusCount};
+use crate::models::{PatientSearchResult, SavedSearch, SmartListCount, PATIENT_SORT_FIELDS};
+use crate::models::{
+    IntervalGroup, IntervalStatistics, OverdueWaitingPatient, WaitingIntervalAnalytics, WaitingListAnalytics,
+    WAITING_INTERVALS,
+};
+use crate::models::{Priority, PriorityAlert, PriorityDueItem};
+use crate::models::{
+    normalize_slot_time, AmbulatorioBlockedSlot, AmbulatorioSession, AmbulatorioSlot, AmbulatorioSlotTemplate,
+    AMBULATORIO_DEFAULT_SLOTS,
+};
+use crate::models::{Appointment, AppointmentWithPatient, APPOINTMENT_STATUSES, APPOINTMENT_TYPES};
+use crate::models::{StatusHistoryEntry, WorklistEntry};
+use crate::models::{ImportCandidate, ImportReport, ImportRowReport};
+use crate::models::{Hl7IngestionLogEntry, HL7_OUTCOME_CREATED, HL7_OUTCOME_UPDATED};
+use crate::models::{
+    CathLabCase, CathLabDurationEstimate, CathLabSession, CathLabSlotSuggestion, CATHLAB_DEFAULT_CAPACITY,
+    CATHLAB_DEFAULT_CASE_MINUTES, CATHLAB_MIN_DURATION_SAMPLES,
+};
+use crate::models::{
+    MetricSummary, ProcedureAggregation, ProcedureGroupStatistics, PROCEDURE_GROUP_BY, PROCEDURE_METRICS,
+};
+use crate::models::{build_fts_query, ClinicalNoteHit, ClinicalNoteSnippet, CLINICAL_NOTE_FIELDS};
+use crate::models::{
+    calculate_bsa, parse_valve_size, risk_factor_label, CtPlanning, CtSizingResult, EcgRecording,
+    FollowUp, Medication, PacemakerRisk, PlanComparison, PlanComparisonItem, ProcedurePlan,
+    PROCEDURE_PLAN_FIELDS, normalize_valve_key, OutcomeRates, PatientRiskFactor, RiskFactorPrevalence, RiskFactorStatistics,
+    RISK_FACTOR_VOCABULARY,
+    OverdueFollowUp, ProcedureOutcome, SurvivalData, SurvivalRecord, FOLLOW_UP_YEARS,
+    ProsthesisModel, ValveModel, ValveSizeSuggestion,
+    ValveSizingChart, VivCompatibilityResult, VivSizeSuggestion, CT_CORONARY_HEIGHT_CAUTION_MM,
+    CT_CORONARY_HEIGHT_MIN_MM, CT_ILIOFEMORAL_MLD_MIN_MM, CT_SINUS_SMALL_MM, VIV_BSA_LARGE_M2,
+    VIV_TID_HIGH_RISK_MM, VIV_TID_MODERATE_RISK_MM,
+};
 use serde_json;
 
+/// Riga di seed delle tabelle di sizing: (modello, dimensione, diametro nominale, perimetro min/max, area min/max)
+type SizingChartSeed = (&'static str, &'static str, f64, Option<f64>, Option<f64>, Option<f64>, Option<f64>);
+
+/// Riga di seed del catalogo valvole: (codice, produttore, nome, tipo, misure, anulus min/max, introduttore Fr, alias)
+type ValveModelSeed = (
+    &'static str, &'static str, &'static str, &'static str, Vec<&'static str>, f64, f64, f64, Vec<&'static str>,
+);
+
+/// Riga di seed del catalogo protesi: (codice, produttore, nome, [(misura etichetta, diametro interno reale)])
+type ProsthesisSeed = (&'static str, &'static str, &'static str, Vec<(f64, f64)>);
+
 pub struct Database {
     conn: Mutex<Connection>,
 }
 
 impl Database {
+    /// Apre la connessione con i vincoli FOREIGN KEY attivi: SQLite li applica (con gli
+    /// ON DELETE CASCADE delle tabelle di stato e delle tabelle cliniche) solo se abilitati
+    /// sulla singola connessione. Le righe orfane lasciate quando erano disattivati vengono
+    /// rimosse da `remove_orphan_rows` durante l'inizializzazione dello schema.
+    fn open_connection(db_path: &std::path::Path) -> SqlResult<Connection> {
+        let conn = Connection::open(db_path)?;
+        conn.pragma_update(None, "foreign_keys", true)?;
+        Ok(conn)
+    }
+
     /// Crea una nuova connessione al database
     pub fn new(db_path: PathBuf) -> Result<Self, rusqlite::Error> {
-        let conn = Connection::open(db_path)?;
+        let conn = Self::open_connection(&db_path)?;
         let db = Database {
             conn: Mutex::new(conn),
         };
@@ -25,7 +79,7 @@ impl Database {
             std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
         }
 
-        let new_conn = Connection::open(&db_path).map_err(|e| e.to_string())?;
+        let new_conn = Self::open_connection(&db_path).map_err(|e| e.to_string())?;
         let mut guard = self
             .conn
             .lock()
@@ -93,7 +147,7 @@ impl Database {
             .map_err(|e| e.to_string())?;
 
         let result = (|| -> SqlResult<()> {
-            conn.execute(
+            let completed = conn.execute(
                 "INSERT OR IGNORE INTO patients_completato (patient_id)
                  SELECT ai.patient_id
                  FROM patients_in_attesa_intervento ai
@@ -117,6 +171,9 @@ impl Database {
                 [],
             )?;
 
+            if completed > 0 {
+                Self::schedule_follow_ups(conn, None, false)?;
+            }
             Ok(())
         })();
 
@@ -206,6 +263,28 @@ impl Database {
         let _ = conn.execute("ALTER TABLE patients ADD COLUMN altezza REAL", []);
         let _ = conn.execute("ALTER TABLE patients ADD COLUMN peso REAL", []);
         let _ = conn.execute("ALTER TABLE patients ADD COLUMN note TEXT", []);
+        // Normalizza la priorità ai valori dell'enum (etichette estese, maiuscole). Un valore
+        // non riconosciuto viene trascritto nelle note e azzerato: senza priorità il paziente
+        // è trattato come "media" in filtri, ordinamenti, scadenze e statistiche
+        conn.execute_batch(
+            "UPDATE patients SET priority = 'alta' WHERE LOWER(TRIM(priority)) LIKE 'alta%' AND priority <> 'alta';
+             UPDATE patients SET priority = 'media' WHERE LOWER(TRIM(priority)) LIKE 'media%' AND priority <> 'media';
+             UPDATE patients SET priority = 'bassa' WHERE LOWER(TRIM(priority)) LIKE 'bassa%' AND priority <> 'bassa';
+             UPDATE patients SET priority = NULL WHERE TRIM(priority) = '';",
+        )?;
+        let unknown_priorities = conn.execute(
+            "UPDATE patients SET
+                note = COALESCE(NULLIF(note, '') || char(10), '') || 'Priorità precedente non riconosciuta: ' || priority,
+                priority = NULL
+             WHERE priority NOT IN ('alta', 'media', 'bassa')",
+            [],
+        )?;
+        if unknown_priorities > 0 {
+            eprintln!(
+                "Priorità non riconosciute spostate nelle note: {} pazienti",
+                unknown_priorities
+            );
+        }
         let _ = conn.execute("ALTER TABLE patients ADD COLUMN ambulatorio_fattori TEXT", []);
         let _ = conn.execute("ALTER TABLE patients ADD COLUMN ambulatorio_data_visita TEXT", []);
         let _ = conn.execute("ALTER TABLE patients ADD COLUMN ambulatorio_orario_visita TEXT", []);
@@ -284,10 +363,18 @@ impl Database {
                 modello_valvola TEXT NOT NULL,
                 dimensione_valvola REAL,
                 pre_dilatazione INTEGER DEFAULT 0,
-                post_dilatazione INTEGER DEFAULT 0
+                post_dilatazione INTEGER DEFAULT 0,
+                protesica_catalogo_id INTEGER,
+                accesso_principale TEXT,
+                plan_id INTEGER,
+                operatore TEXT
             )",
             [],
         )?;
+        let _ = conn.execute("ALTER TABLE procedures ADD COLUMN protesica_catalogo_id INTEGER", []);
+        let _ = conn.execute("ALTER TABLE procedures ADD COLUMN accesso_principale TEXT", []);
+        let _ = conn.execute("ALTER TABLE procedures ADD COLUMN plan_id INTEGER", []);
+        let _ = conn.execute("ALTER TABLE procedures ADD COLUMN operatore TEXT", []);
 
         // Crea indici per performance
         conn.execute(
@@ -305,826 +392,7240 @@ impl Database {
             [],
         )?;
 
+        self.ensure_ct_planning_tables(&conn)?;
+        self.ensure_valve_catalogue_tables(&conn)?;
+        self.ensure_prosthesis_catalogue_tables(&conn)?;
+        self.ensure_outcome_tables(&conn)?;
+        self.ensure_follow_up_tables(&conn)?;
+        self.ensure_medication_tables(&conn)?;
+        self.ensure_risk_factor_tables(&conn)?;
+        self.ensure_ecg_tables(&conn)?;
+        self.ensure_procedure_plan_tables(&conn)?;
+        self.ensure_clinical_notes_index(&conn)?;
+        self.ensure_saved_search_tables(&conn)?;
+        self.ensure_status_history_table(&conn)?;
+        self.ensure_priority_alert_table(&conn)?;
+        self.ensure_ambulatorio_calendar_tables(&conn)?;
+        self.ensure_appointment_tables(&conn)?;
+        self.ensure_cathlab_tables(&conn)?;
+        self.ensure_hl7_ingestion_table(&conn)?;
+        Self::remove_orphan_rows(&conn)?;
+        Self::schedule_follow_ups(&conn, None, false)?;
+
         Ok(())
     }
 
-    /// Inserisce una nuova procedura
-    pub fn insert_procedure(&self, proc: &Procedure) -> Result<i64, String> {
-        let conn = self.conn.lock().unwrap();
+    /// Prima dell'attivazione dei FOREIGN KEY l'eliminazione di un paziente lasciava le righe
+    /// collegate (stati, appuntamenti, ...) senza padre. Non sono raggiungibili da nessuna vista,
+    /// ma bloccherebbero gli aggiornamenti: vengono eliminate, o scollegate se il vincolo è
+    /// ON DELETE SET NULL (registro HL7).
+    fn remove_orphan_rows(conn: &Connection) -> SqlResult<()> {
+        let orphans: Vec<(String, Option<i64>, i64)> = conn
+            .prepare("PRAGMA foreign_key_check")?
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(3)?)))?
+            .collect::<SqlResult<_>>()?;
+        let mut removed = 0;
+        for (table, rowid, fk_id) in orphans {
+            let Some(rowid) = rowid else {
+                continue;
+            };
+            let (column, on_delete): (String, String) = conn.query_row(
+                "SELECT \"from\", on_delete FROM pragma_foreign_key_list(?1) WHERE id = ?2 LIMIT 1",
+                params![table, fk_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?;
+            let sql = if on_delete.eq_ignore_ascii_case("SET NULL") {
+                format!("UPDATE \"{}\" SET \"{}\" = NULL WHERE rowid = ?1", table, column)
+            } else {
+                format!("DELETE FROM \"{}\" WHERE rowid = ?1", table)
+            };
+            removed += conn.execute(&sql, params![rowid])?;
+        }
+        if removed > 0 {
+            eprintln!("Righe orfane rimosse all'attivazione dei vincoli FOREIGN KEY: {}", removed);
+        }
+        Ok(())
+    }
 
+    /// Crea le tabelle di pianificazione TC e le tabelle di sizing delle valvole.
+    fn ensure_ct_planning_tables(&self, conn: &Connection) -> SqlResult<()> {
         conn.execute(
-            "INSERT INTO procedures (
-                nome, cognome, data_nascita, altezza, peso,
-                fe, vmax, gmax, gmed, ava, anulus_aortico,
-                valvola_protesica, protesica_modello, protesica_dimensione,
-                data_procedura, ora_inizio, ora_fine,
-                tipo_valvola, modello_valvola, dimensione_valvola,
-                pre_dilatazione, post_dilatazione
-            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22)",
-            params![
-                proc.nome, proc.cognome, proc.data_nascita, proc.altezza, proc.peso,
-                proc.fe, proc.vmax, proc.gmax, proc.gmed, proc.ava, proc.anulus_aortico,
-                proc.valvola_protesica, proc.protesica_modello, proc.protesica_dimensione,
-                proc.data_procedura, proc.ora_inizio, proc.ora_fine,
-                proc.tipo_valvola, proc.modello_valvola, proc.dimensione_valvola,
-                proc.pre_dilatazione, proc.post_dilatazione
-            ],
-        ).map_err(|e| e.to_string())?;
+            "CREATE TABLE IF NOT EXISTS ct_plannings (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                patient_id INTEGER NOT NULL,
+                created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+                updated_at TEXT DEFAULT CURRENT_TIMESTAMP,
+                data_tc TEXT,
+                anulus_perimetro REAL,
+                anulus_area REAL,
+                anulus_diametro_min REAL,
+                anulus_diametro_max REAL,
+                altezza_coronaria_sx REAL,
+                altezza_coronaria_dx REAL,
+                seno_valsalva_diametro REAL,
+                seno_valsalva_altezza REAL,
+                giunzione_st_diametro REAL,
+                giunzione_st_altezza REAL,
+                calcium_score REAL,
+                iliaca_dx_mld REAL,
+                iliaca_sx_mld REAL,
+                femorale_dx_mld REAL,
+                femorale_sx_mld REAL,
+                note TEXT,
+                FOREIGN KEY(patient_id) REFERENCES patients(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
 
-        Ok(conn.last_insert_rowid())
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_ct_plannings_patient ON ct_plannings(patient_id)",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS valve_sizing_charts (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                modello_valvola TEXT NOT NULL,
+                dimensione TEXT NOT NULL,
+                diametro_nominale REAL NOT NULL,
+                perimetro_min REAL,
+                perimetro_max REAL,
+                area_min REAL,
+                area_max REAL,
+                UNIQUE(modello_valvola, dimensione)
+            )",
+            [],
+        )?;
+
+        let existing: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM valve_sizing_charts",
+            [],
+            |row| row.get(0),
+        )?;
+        if existing == 0 {
+            self.seed_valve_sizing_charts(conn)?;
+        }
+
+        Ok(())
     }
 
-    /// Aggiorna una procedura esistente
-    pub fn update_procedure(&self, proc: &Procedure) -> Result<(), String> {
-        let conn = self.conn.lock().unwrap();
+    /// Popola le tabelle di sizing con i range indicativi dei produttori (modificabili dall'utente).
+    fn seed_valve_sizing_charts(&self, conn: &Connection) -> SqlResult<()> {
+        let charts: Vec<SizingChartSeed> = vec![
+            ("edwards_sapien_3", "20", 20.0, None, None, Some(273.0), Some(345.0)),
+            ("edwards_sapien_3", "23", 23.0, None, None, Some(338.0), Some(430.0)),
+            ("edwards_sapien_3", "26", 26.0, None, None, Some(430.0), Some(546.0)),
+            ("edwards_sapien_3", "29", 29.0, None, None, Some(540.0), Some(683.0)),
+            ("edwards_sapien_3_ultra", "20", 20.0, None, None, Some(273.0), Some(345.0)),
+            ("edwards_sapien_3_ultra", "23", 23.0, None, None, Some(338.0), Some(430.0)),
+            ("edwards_sapien_3_ultra", "26", 26.0, None, None, Some(430.0), Some(546.0)),
+            ("medtronic_evolut_r", "23", 23.0, Some(56.5), Some(62.8), None, None),
+            ("medtronic_evolut_r", "26", 26.0, Some(62.8), Some(72.3), None, None),
+            ("medtronic_evolut_r", "29", 29.0, Some(72.3), Some(81.7), None, None),
+            ("medtronic_evolut_r", "34", 34.0, Some(81.7), Some(94.2), None, None),
+            ("medtronic_evolut_pro", "23", 23.0, Some(56.5), Some(62.8), None, None),
+            ("medtronic_evolut_pro", "26", 26.0, Some(62.8), Some(72.3), None, None),
+            ("medtronic_evolut_pro", "29", 29.0, Some(72.3), Some(81.7), None, None),
+            ("medtronic_evolut_pro", "34", 34.0, Some(81.7), Some(94.2), None, None),
+            ("boston_accurate_neo2", "S (23)", 23.0, Some(66.0), Some(72.0), None, None),
+            ("boston_accurate_neo2", "M (25)", 25.0, Some(72.0), Some(79.0), None, None),
+            ("boston_accurate_neo2", "L (27)", 27.0, Some(79.0), Some(85.0), None, None),
+            ("abbott_portico_navitor", "23", 23.0, Some(60.0), Some(66.0), None, None),
+            ("abbott_portico_navitor", "25", 25.0, Some(66.0), Some(72.0), None, None),
+            ("abbott_portico_navitor", "27", 27.0, Some(72.0), Some(79.0), None, None),
+            ("abbott_portico_navitor", "29", 29.0, Some(79.0), Some(85.0), None, None),
+        ];
 
-        let id = proc.id.ok_or("Procedure ID is required for update")?;
+        for (modello, dimensione, diametro, p_min, p_max, a_min, a_max) in charts {
+            conn.execute(
+                "INSERT OR IGNORE INTO valve_sizing_charts (
+                    modello_valvola, dimensione, diametro_nominale,
+                    perimetro_min, perimetro_max, area_min, area_max
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![modello, dimensione, diametro, p_min, p_max, a_min, a_max],
+            )?;
+        }
+
+        Ok(())
+    }
 
+    /// Crea il catalogo valvole e lo popola con i modelli in uso se vuoto.
+    fn ensure_valve_catalogue_tables(&self, conn: &Connection) -> SqlResult<()> {
         conn.execute(
-            "UPDATE procedures SET
-                nome = ?1, cognome = ?2, data_nascita = ?3, altezza = ?4, peso = ?5,
-                fe = ?6, vmax = ?7, gmax = ?8, gmed = ?9, ava = ?10, anulus_aortico = ?11,
-                valvola_protesica = ?12, protesica_modello = ?13, protesica_dimensione = ?14,
-                data_procedura = ?15, ora_inizio = ?16, ora_fine = ?17,
-                tipo_valvola = ?18, modello_valvola = ?19, dimensione_valvola = ?20,
-                pre_dilatazione = ?21, post_dilatazione = ?22,
-                updated_at = CURRENT_TIMESTAMP
-            WHERE id = ?23",
-            params![
-                proc.nome, proc.cognome, proc.data_nascita, proc.altezza, proc.peso,
-                proc.fe, proc.vmax, proc.gmax, proc.gmed, proc.ava, proc.anulus_aortico,
-                proc.valvola_protesica, proc.protesica_modello, proc.protesica_dimensione,
-                proc.data_procedura, proc.ora_inizio, proc.ora_fine,
-                proc.tipo_valvola, proc.modello_valvola, proc.dimensione_valvola,
-                proc.pre_dilatazione, proc.post_dilatazione,
-                id
-            ],
-        ).map_err(|e| e.to_string())?;
+            "CREATE TABLE IF NOT EXISTS valve_models (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                codice TEXT NOT NULL UNIQUE,
+                produttore TEXT NOT NULL,
+                nome_commerciale TEXT NOT NULL,
+                tipo_valvola TEXT NOT NULL CHECK(tipo_valvola IN ('Balloon Expandable', 'Self Expandable')),
+                dimensioni TEXT NOT NULL,
+                anulus_min REAL,
+                anulus_max REAL,
+                introduttore_min_fr REAL,
+                alias TEXT
+            )",
+            [],
+        )?;
+
+        let existing: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM valve_models",
+            [],
+            |row| row.get(0),
+        )?;
+        if existing > 0 {
+            return Ok(());
+        }
+
+        let models: Vec<ValveModelSeed> = vec![
+            (
+                "edwards_sapien_3", "Edwards", "SAPIEN 3", "Balloon Expandable",
+                vec!["20", "23", "26", "29"], 18.6, 29.5, 14.0,
+                vec!["Edwards SAPIEN 3", "Sapien 3", "S3"],
+            ),
+            (
+                "edwards_sapien_3_ultra", "Edwards", "SAPIEN 3 Ultra", "Balloon Expandable",
+                vec!["20", "23", "26"], 18.6, 26.4, 14.0,
+                vec!["Edwards SAPIEN 3 Ultra", "Sapien 3 Ultra", "S3 Ultra"],
+            ),
+            (
+                "medtronic_evolut_r", "Medtronic", "Evolut R", "Self Expandable",
+                vec!["23", "26", "29", "34"], 18.0, 30.0, 14.0,
+                vec!["Medtronic CoreValve Evolut R", "CoreValve Evolut R"],
+            ),
+            (
+                "medtronic_evolut_pro", "Medtronic", "Evolut PRO / PRO+", "Self Expandable",
+                vec!["23", "26", "29", "34"], 18.0, 30.0, 14.0,
+                vec![
+                    "Medtronic CoreValve Evolut PRO",
+                    "Medtronic CoreValve Evolut PRO+",
+                    "Evolut PRO",
+                    "Evolut PRO+",
+                ],
+            ),
+            (
+                "boston_accurate_neo2", "Boston Scientific", "ACURATE neo2", "Self Expandable",
+                vec!["S (23)", "M (25)", "L (27)"], 21.0, 27.0, 14.0,
+                vec!["Boston Scientific ACURATE neo2", "Boston Acurate neo2"],
+            ),
+            (
+                "boston_accurate_neo", "Boston Scientific", "ACURATE neo", "Self Expandable",
+                vec!["S (23)", "M (25)", "L (27)"], 21.0, 27.0, 18.0,
+                vec!["Boston Scientific ACURATE neo", "Boston Acurate neo"],
+            ),
+            (
+                "abbott_portico_navitor", "Abbott", "Portico / Navitor", "Self Expandable",
+                vec!["23", "25", "27", "29"], 19.0, 27.0, 14.0,
+                vec!["Portico", "Navitor", "Abbott Portico", "Abbott Navitor"],
+            ),
+            (
+                "meril_myval", "Meril", "Myval", "Balloon Expandable",
+                vec!["20", "21.5", "23", "24.5", "26", "27.5", "29", "30.5"], 18.0, 30.5, 14.0,
+                vec!["Myval"],
+            ),
+            (
+                "biosensors_allegra", "Biosensors", "Allegra", "Self Expandable",
+                vec!["23", "27", "31"], 19.0, 28.0, 18.0,
+                vec!["Allegra"],
+            ),
+        ];
+
+        for (codice, produttore, nome, tipo, dimensioni, anulus_min, anulus_max, introduttore, alias) in models {
+            let dimensioni_json = serde_json::to_string(&dimensioni).unwrap_or_else(|_| "[]".to_string());
+            let alias_json = serde_json::to_string(&alias).unwrap_or_else(|_| "[]".to_string());
+            conn.execute(
+                "INSERT OR IGNORE INTO valve_models (
+                    codice, produttore, nome_commerciale, tipo_valvola, dimensioni,
+                    anulus_min, anulus_max, introduttore_min_fr, alias
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![codice, produttore, nome, tipo, dimensioni_json, anulus_min, anulus_max, introduttore, alias_json],
+            )?;
+        }
 
         Ok(())
     }
 
-    /// Elimina una procedura
-    pub fn delete_procedure(&self, id: i64) -> Result<(), String> {
-        let conn = self.conn.lock().unwrap();
+    /// Crea il catalogo delle protesi già impiantate (per valve-in-valve) e lo popola se vuoto.
+    /// I diametri interni reali sono indicativi e modificabili dall'utente.
+    fn ensure_prosthesis_catalogue_tables(&self, conn: &Connection) -> SqlResult<()> {
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS prosthesis_models (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                codice TEXT NOT NULL,
+                produttore TEXT NOT NULL,
+                nome_commerciale TEXT NOT NULL,
+                tipologia TEXT NOT NULL CHECK(tipologia IN ('chirurgica', 'transcatetere')),
+                dimensione_etichetta REAL NOT NULL,
+                diametro_interno_reale REAL NOT NULL,
+                UNIQUE(codice, dimensione_etichetta)
+            )",
+            [],
+        )?;
 
-        conn.execute("DELETE FROM procedures WHERE id = ?1", params![id])
-            .map_err(|e| e.to_string())?;
+        let existing: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM prosthesis_models",
+            [],
+            |row| row.get(0),
+        )?;
+        if existing > 0 {
+            return Ok(());
+        }
+
+        let prostheses: Vec<ProsthesisSeed> = vec![
+            (
+                "edwards_perimount", "Edwards", "Perimount",
+                vec![(19.0, 17.0), (21.0, 19.0), (23.0, 21.0), (25.0, 23.0), (27.0, 25.0)],
+            ),
+            (
+                "edwards_perimount_magna_ease", "Edwards", "Perimount Magna Ease",
+                vec![(19.0, 17.0), (21.0, 19.0), (23.0, 21.0), (25.0, 23.0), (27.0, 25.0)],
+            ),
+            (
+                "medtronic_hancock_ii", "Medtronic", "Hancock II",
+                vec![(21.0, 18.5), (23.0, 20.5), (25.0, 22.5), (27.0, 24.0)],
+            ),
+            (
+                "medtronic_mosaic", "Medtronic", "Mosaic",
+                vec![(21.0, 18.5), (23.0, 20.5), (25.0, 22.5), (27.0, 24.0)],
+            ),
+            (
+                "abbott_trifecta", "Abbott", "Trifecta",
+                vec![(19.0, 17.0), (21.0, 19.0), (23.0, 21.0), (25.0, 23.0)],
+            ),
+            (
+                "sorin_mitroflow", "Sorin", "Mitroflow",
+                vec![(19.0, 15.4), (21.0, 17.3), (23.0, 19.0), (25.0, 21.0)],
+            ),
+        ];
+
+        for (codice, produttore, nome, sizes) in prostheses {
+            for (etichetta, tid) in sizes {
+                conn.execute(
+                    "INSERT OR IGNORE INTO prosthesis_models (
+                        codice, produttore, nome_commerciale, tipologia,
+                        dimensione_etichetta, diametro_interno_reale
+                    ) VALUES (?1, ?2, ?3, 'chirurgica', ?4, ?5)",
+                    params![codice, produttore, nome, etichetta, tid],
+                )?;
+            }
+        }
 
         Ok(())
     }
 
-    /// Ottieni tutte le procedure (con filtri opzionali)
-    pub fn get_all_procedures(&self, filters: Option<ProcedureFilters>) -> Result<Vec<Procedure>, String> {
-        let conn = self.conn.lock().unwrap();
+    /// Crea la tabella degli esiti procedurali VARC-3 (uno per procedura).
+    fn ensure_outcome_tables(&self, conn: &Connection) -> SqlResult<()> {
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS procedure_outcomes (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                procedure_id INTEGER NOT NULL UNIQUE,
+                created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+                updated_at TEXT DEFAULT CURRENT_TIMESTAMP,
+                decesso INTEGER,
+                decesso_data TEXT,
+                decesso_causa TEXT,
+                ictus TEXT,
+                complicanza_vascolare TEXT,
+                complicanza_strutturale INTEGER,
+                sanguinamento_tipo INTEGER,
+                aki_stadio INTEGER,
+                nuovo_pacemaker INTEGER,
+                leak_paravalvolare TEXT,
+                ostruzione_coronarica INTEGER,
+                conversione_chirurgica INTEGER,
+                reintervento_valvola INTEGER,
+                posizionamento_corretto INTEGER,
+                gradiente_medio_post REAL,
+                vmax_post REAL,
+                note TEXT,
+                FOREIGN KEY(procedure_id) REFERENCES procedures(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+        Ok(())
+    }
 
-        let mut query = String::from("SELECT * FROM procedures WHERE 1=1");
-        let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![];
+    /// Crea la tabella dei controlli di follow-up post-TAVI.
+    fn ensure_follow_up_tables(&self, conn: &Connection) -> SqlResult<()> {
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS follow_ups (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                patient_id INTEGER NOT NULL,
+                created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+                updated_at TEXT DEFAULT CURRENT_TIMESTAMP,
+                tipo TEXT NOT NULL,
+                anno INTEGER NOT NULL,
+                data_prevista TEXT NOT NULL,
+                data_eseguita TEXT,
+                nyha_classe INTEGER,
+                gradiente_medio REAL,
+                gradiente_massimo REAL,
+                vmax REAL,
+                fe REAL,
+                leak_paravalvolare TEXT,
+                riospedalizzazione INTEGER,
+                riospedalizzazione_causa TEXT,
+                decesso INTEGER,
+                decesso_data TEXT,
+                decesso_causa TEXT,
+                note TEXT,
+                UNIQUE(patient_id, anno),
+                FOREIGN KEY(patient_id) REFERENCES patients(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
 
-        if let Some(filters) = filters {
-            // Filtro ricerca testuale
-            if let Some(search) = filters.search_query {
-                if !search.is_empty() {
-                    query.push_str(" AND (nome LIKE ?1 OR cognome LIKE ?1 OR modello_valvola LIKE ?1)");
-                    params.push(Box::new(format!("%{}%", search)));
-                }
-            }
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_follow_ups_data_prevista ON follow_ups(data_prevista)",
+            [],
+        )?;
 
-            // Filtro tipo valvola
-            if let Some(tipo) = filters.tipo_valvola {
-                if tipo != "all" {
-                    query.push_str(&format!(" AND tipo_valvola = ?{}", params.len() + 1));
-                    params.push(Box::new(tipo));
-                }
-            }
+        Ok(())
+    }
 
-            // Filtro periodo
-            if let Some(period) = filters.period {
-                if period != "all" {
-                    let days = match period.as_str() {
-                        "1m" => 30,
-                        "3m" => 90,
-                        "6m" => 180,
-                        "1y" => 365,
-                        _ => 0,
-                    };
+    /// Crea la tabella della terapia domiciliare strutturata. Alla prima creazione
+    /// importa il testo libero `apr` dei pazienti esistenti, un farmaco per riga.
+    fn ensure_medication_tables(&self, conn: &Connection) -> SqlResult<()> {
+        let exists: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'medications'",
+            [],
+            |row| row.get(0),
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS medications (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                patient_id INTEGER NOT NULL,
+                created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+                updated_at TEXT DEFAULT CURRENT_TIMESTAMP,
+                farmaco TEXT NOT NULL,
+                dose TEXT,
+                frequenza TEXT,
+                via TEXT,
+                data_inizio TEXT,
+                data_fine TEXT,
+                indicazione TEXT,
+                anticoagulante INTEGER NOT NULL DEFAULT 0,
+                antiaggregante INTEGER NOT NULL DEFAULT 0,
+                FOREIGN KEY(patient_id) REFERENCES patients(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_medications_patient ON medications(patient_id)",
+            [],
+        )?;
 
-                    if days > 0 {
-                        query.push_str(&format!(" AND data_procedura >= date('now', '-{} days')", days));
+        if !exists {
+            let mut stmt = conn.prepare(
+                "SELECT id, apr FROM patients WHERE apr IS NOT NULL AND TRIM(apr) != ''",
+            )?;
+            let rows: Vec<(i64, String)> = stmt
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<SqlResult<_>>()?;
+
+            for (patient_id, apr) in rows {
+                for line in apr.lines().map(|l| l.trim().trim_start_matches(['-', '*', '•']).trim()) {
+                    if line.is_empty() {
+                        continue;
                     }
+                    let med = Medication {
+                        id: None,
+                        patient_id,
+                        created_at: None,
+                        updated_at: None,
+                        farmaco: line.to_string(),
+                        dose: None,
+                        frequenza: None,
+                        via: None,
+                        data_inizio: None,
+                        data_fine: None,
+                        indicazione: None,
+                        anticoagulante: false,
+                        antiaggregante: false,
+                    }
+                    .classified();
+                    conn.execute(
+                        "INSERT INTO medications (patient_id, farmaco, anticoagulante, antiaggregante)
+                         VALUES (?1, ?2, ?3, ?4)",
+                        params![med.patient_id, med.farmaco, med.anticoagulante, med.antiaggregante],
+                    )?;
                 }
             }
         }
 
-        query.push_str(" ORDER BY data_procedura DESC, ora_inizio DESC");
+        Ok(())
+    }
 
-        let params_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+    /// Crea la tabella dei fattori di rischio CV codificati. Alla prima creazione
+    /// converte le stringhe libere di `ambulatorio_fattori` nel vocabolario controllato.
+    fn ensure_risk_factor_tables(&self, conn: &Connection) -> SqlResult<()> {
+        let exists: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'patient_risk_factors'",
+            [],
+            |row| row.get(0),
+        )?;
 
-        let mut stmt = conn.prepare(&query).map_err(|e| e.to_string())?;
-        let rows = stmt.query_map(params_refs.as_slice(), |row| {
-            Ok(Procedure {
-                id: Some(row.get(0)?),
-                created_at: row.get(1).ok(),
-                updated_at: row.get(2).ok(),
-                nome: row.get(3)?,
-                cognome: row.get(4)?,
-                data_nascita: row.get(5)?,
-                altezza: row.get(6).ok(),
-                peso: row.get(7).ok(),
-                fe: row.get(8).ok(),
-                vmax: row.get(9).ok(),
-                gmax: row.get(10).ok(),
-                gmed: row.get(11).ok(),
-                ava: row.get(12).ok(),
-                anulus_aortico: row.get(13).ok(),
-                valvola_protesica: row.get::<_, i32>(14)? != 0,
-                protesica_modello: row.get(15).ok(),
-                protesica_dimensione: row.get(16).ok(),
-                data_procedura: row.get(17)?,
-                ora_inizio: row.get(18)?,
-                ora_fine: row.get(19)?,
-                tipo_valvola: row.get(20)?,
-                modello_valvola: row.get(21)?,
-                dimensione_valvola: row.get(22).ok(),
-                pre_dilatazione: row.get::<_, i32>(23)? != 0,
-                post_dilatazione: row.get::<_, i32>(24)? != 0,
-            })
-        }).map_err(|e| e.to_string())?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS patient_risk_factors (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                patient_id INTEGER NOT NULL,
+                codice TEXT NOT NULL,
+                insulino_trattato INTEGER,
+                stato_fumo TEXT,
+                pack_years REAL,
+                note TEXT,
+                UNIQUE(patient_id, codice),
+                FOREIGN KEY(patient_id) REFERENCES patients(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
 
-        let procedures: Result<Vec<_>, _> = rows.collect();
-        procedures.map_err(|e| e.to_string())
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_patient_risk_factors_codice ON patient_risk_factors(codice)",
+            [],
+        )?;
+
+        if !exists {
+            let mut stmt = conn.prepare(
+                "SELECT id, ambulatorio_fattori FROM patients WHERE ambulatorio_fattori IS NOT NULL",
+            )?;
+            let rows: Vec<(i64, String)> = stmt
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<SqlResult<_>>()?;
+
+            for (patient_id, json) in rows {
+                let labels: Vec<String> = serde_json::from_str(&json).unwrap_or_default();
+                Self::sync_risk_factors_from_labels(conn, patient_id, &labels)?;
+            }
+        }
+
+        Ok(())
     }
 
-    /// Ottieni una procedura per ID
-    pub fn get_procedure_by_id(&self, id: i64) -> Result<Option<Procedure>, String> {
-        let conn = self.conn.lock().unwrap();
+    /// Allinea i fattori codificati alle etichette salvate in `ambulatorio_fattori`:
+    /// i codici riconosciuti vengono inseriti (preservando i dettagli già registrati),
+    /// quelli non più presenti vengono rimossi.
+    fn sync_risk_factors_from_labels(conn: &Connection, patient_id: i64, labels: &[String]) -> SqlResult<()> {
+        let factors: Vec<PatientRiskFactor> = labels
+            .iter()
+            .filter_map(|label| PatientRiskFactor::from_free_text(patient_id, label))
+            .collect();
 
-        let result = conn.query_row(
-            "SELECT * FROM procedures WHERE id = ?1",
-            params![id],
-            |row| {
-                Ok(Procedure {
-                    id: Some(row.get(0)?),
-                    created_at: row.get(1).ok(),
-                    updated_at: row.get(2).ok(),
-                    nome: row.get(3)?,
-                    cognome: row.get(4)?,
-                    data_nascita: row.get(5)?,
-                    altezza: row.get(6).ok(),
-                    peso: row.get(7).ok(),
-                    fe: row.get(8).ok(),
-                    vmax: row.get(9).ok(),
-                    gmax: row.get(10).ok(),
-                    gmed: row.get(11).ok(),
-                    ava: row.get(12).ok(),
-                    anulus_aortico: row.get(13).ok(),
-                    valvola_protesica: row.get::<_, i32>(14)? != 0,
-                    protesica_modello: row.get(15).ok(),
-                    protesica_dimensione: row.get(16).ok(),
-                    data_procedura: row.get(17)?,
-                    ora_inizio: row.get(18)?,
-                    ora_fine: row.get(19)?,
-                    tipo_valvola: row.get(20)?,
-                    modello_valvola: row.get(21)?,
-                    dimensione_valvola: row.get(22).ok(),
-                    pre_dilatazione: row.get::<_, i32>(23)? != 0,
-                    post_dilatazione: row.get::<_, i32>(24)? != 0,
-                })
-            },
-        );
+        for factor in &factors {
+            conn.execute(
+                "INSERT INTO patient_risk_factors (patient_id, codice, insulino_trattato, stato_fumo)
+                 VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT(patient_id, codice) DO UPDATE SET
+                    insulino_trattato = COALESCE(excluded.insulino_trattato, insulino_trattato),
+                    stato_fumo = COALESCE(excluded.stato_fumo, stato_fumo)",
+                params![patient_id, factor.codice, factor.insulino_trattato, factor.stato_fumo],
+            )?;
+        }
 
-        match result {
-            Ok(proc) => Ok(Some(proc)),
-            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-            Err(e) => Err(e.to_string()),
+        let codes: Vec<&str> = factors.iter().map(|f| f.codice.as_str()).collect();
+        let mut stmt = conn.prepare("SELECT codice FROM patient_risk_factors WHERE patient_id = ?1")?;
+        let existing: Vec<String> = stmt
+            .query_map(params![patient_id], |row| row.get(0))?
+            .collect::<SqlResult<_>>()?;
+        for codice in existing.iter().filter(|c| !codes.contains(&c.as_str())) {
+            conn.execute(
+                "DELETE FROM patient_risk_factors WHERE patient_id = ?1 AND codice = ?2",
+                params![patient_id, codice],
+            )?;
         }
-    }
 
-    /// Calcola le statistiche
-    pub fn calculate_statistics(&self, filters: Option<ProcedureFilters>) -> Result<Statistics, String> {
-        let procedures = self.get_all_procedures(filters)?;
+        Ok(())
+    }
 
-        let total = procedures.len() as i32;
+    /// Crea la tabella delle registrazioni ECG. Alla prima creazione converte i flag
+    /// ECG della scheda procedurale in una registrazione datata all'ultimo aggiornamento.
+    fn ensure_ecg_tables(&self, conn: &Connection) -> SqlResult<()> {
+        let exists: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'ecg_recordings'",
+            [],
+            |row| row.get(0),
+        )?;
 
-        if total == 0 {
-            return Ok(Statistics {
-                total_procedures: 0,
-                average_duration_minutes: 0.0,
-                pre_dilatazione_percentage: 0.0,
-                post_dilatazione_percentage: 0.0,
-                average_fe: None,
-                average_vmax: None,
-                average_gmax: None,
-                average_gmed: None,
-                average_ava: None,
-                balloon_expandable_count: 0,
-                self_expandable_count: 0,
-                top_valve_models: vec![],
-            });
-        }
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS ecg_recordings (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                patient_id INTEGER NOT NULL,
+                created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+                updated_at TEXT DEFAULT CURRENT_TIMESTAMP,
+                data_registrazione TEXT NOT NULL,
+                ritmo TEXT CHECK(ritmo IN ('sinusale', 'fa', 'flutter', 'stimolato', 'altro')),
+                frequenza_cardiaca INTEGER,
+                pr_ms INTEGER,
+                qrs_ms INTEGER,
+                bbs INTEGER NOT NULL DEFAULT 0,
+                bbd INTEGER NOT NULL DEFAULT 0,
+                eas INTEGER NOT NULL DEFAULT 0,
+                bav_grado INTEGER CHECK(bav_grado IN (1, 2, 3)),
+                note TEXT,
+                FOREIGN KEY(patient_id) REFERENCES patients(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
 
-        // Calcola medie
-        let mut total_duration = 0;
-        let mut pre_count = 0;
-        let mut post_count = 0;
-        let mut balloon_count = 0;
-        let mut self_count = 0;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_ecg_recordings_patient ON ecg_recordings(patient_id, data_registrazione)",
+            [],
+        )?;
 
-        let mut fe_sum = 0.0;
-        let mut fe_count = 0;
-        let mut vmax_sum = 0.0;
-        let mut vmax_count = 0;
-        let mut gmax_sum = 0.0;
-        let mut gmax_count = 0;
-        let mut gmed_sum = 0.0;
-        let mut gmed_count = 0;
-        let mut ava_sum = 0.0;
-        let mut ava_count = 0;
+        if !exists {
+            conn.execute(
+                "INSERT INTO ecg_recordings (patient_id, data_registrazione, ritmo, bbs, bbd, eas, bav_grado)
+                 SELECT id,
+                        DATE(COALESCE(updated_at, created_at, CURRENT_TIMESTAMP)),
+                        CASE WHEN procedurale_ecg_ritmo_stimolato = 1 THEN 'stimolato'
+                             WHEN procedurale_ecg_fa = 1 THEN 'fa'
+                             WHEN procedurale_ecg_ritmo_sinusale = 1 THEN 'sinusale' END,
+                        COALESCE(procedurale_ecg_bbs, 0),
+                        COALESCE(procedurale_ecg_bbd, 0),
+                        COALESCE(procedurale_ecg_eas, 0),
+                        CASE WHEN procedurale_ecg_bav_primo = 1 THEN 1 END
+                 FROM patients
+                 WHERE COALESCE(procedurale_ecg_ritmo_sinusale, 0) + COALESCE(procedurale_ecg_fa, 0)
+                     + COALESCE(procedurale_ecg_bbs, 0) + COALESCE(procedurale_ecg_bbd, 0)
+                     + COALESCE(procedurale_ecg_eas, 0) + COALESCE(procedurale_ecg_bav_primo, 0)
+                     + COALESCE(procedurale_ecg_ritmo_stimolato, 0) > 0",
+                [],
+            )?;
+        }
 
-        let mut model_counts: std::collections::HashMap<String, i32> = std::collections::HashMap::new();
+        Ok(())
+    }
 
-        for proc in &procedures {
-            if let Some(duration) = proc.calculate_duration_minutes() {
-                total_duration += duration;
-            }
+    /// Crea la tabella dei piani procedurali versionati. Alla prima creazione i campi
+    /// `procedurale_*` dei pazienti diventano la versione 1 del rispettivo piano.
+    fn ensure_procedure_plan_tables(&self, conn: &Connection) -> SqlResult<()> {
+        let exists: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'procedure_plans'",
+            [],
+            |row| row.get(0),
+        )?;
 
-            if proc.pre_dilatazione {
-                pre_count += 1;
-            }
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS procedure_plans (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                patient_id INTEGER NOT NULL,
+                versione INTEGER NOT NULL,
+                created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+                motivo_revisione TEXT,
+                allergia_mdc TEXT,
+                preparazione_mdc TEXT,
+                creatinina TEXT,
+                egfr TEXT,
+                hb TEXT,
+                altro TEXT,
+                anestesia TEXT,
+                coronarografia TEXT,
+                coronarografia_note TEXT,
+                pacemaker TEXT,
+                pacemaker_note TEXT,
+                accesso_principale_fem TEXT,
+                accesso_principale_altro TEXT,
+                accesso_protezione TEXT,
+                accesso_protezione_note TEXT,
+                altri_accessi TEXT,
+                diametro_pallone_femorale TEXT,
+                guida_safari TEXT,
+                protezione_osti TEXT,
+                valvuloplastica TEXT,
+                valvuloplastica_note TEXT,
+                bioprotesi_modello TEXT,
+                bioprotesi_dimensione TEXT,
+                UNIQUE(patient_id, versione),
+                FOREIGN KEY(patient_id) REFERENCES patients(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
 
-            if proc.post_dilatazione {
-                post_count += 1;
+        if !exists {
+            let mut stmt = conn.prepare("SELECT id FROM patients")?;
+            let patient_ids: Vec<i64> = stmt
+                .query_map([], |row| row.get(0))?
+                .collect::<SqlResult<_>>()?;
+            for patient_id in patient_ids {
+                Self::snapshot_plan_from_patient(conn, patient_id)?;
             }
+        }
 
-            if proc.tipo_valvola == "Balloon Expandable" {
-                balloon_count += 1;
-            } else {
-                self_count += 1;
-            }
+        Ok(())
+    }
 
-            if let Some(fe) = proc.fe {
-                fe_sum += fe;
-                fe_count += 1;
-            }
-            if let Some(vmax) = proc.vmax {
-                vmax_sum += vmax;
-                vmax_count += 1;
-            }
-            if let Some(gmax) = proc.gmax {
-                gmax_sum += gmax;
-                gmax_count += 1;
-            }
-            if let Some(gmed) = proc.gmed {
-                gmed_sum += gmed;
-                gmed_count += 1;
-            }
-            if let Some(ava) = proc.ava {
-                ava_sum += ava;
-                ava_count += 1;
-            }
+    /// Registra una nuova versione del piano se i campi `procedurale_*` del paziente
+    /// differiscono dall'ultima versione salvata (la ripianificazione non sovrascrive lo storico).
+    fn snapshot_plan_from_patient(conn: &Connection, patient_id: i64) -> SqlResult<()> {
+        let patient_columns: Vec<String> = PROCEDURE_PLAN_FIELDS
+            .iter()
+            .map(|f| format!("procedurale_{}", f))
+            .collect();
+        let values: Vec<Option<String>> = conn.query_row(
+            &format!("SELECT {} FROM patients WHERE id = ?1", patient_columns.join(", ")),
+            params![patient_id],
+            |row| (0..PROCEDURE_PLAN_FIELDS.len()).map(|i| row.get(i)).collect(),
+        )?;
+        let values: Vec<Option<String>> = values
+            .into_iter()
+            .map(|v| v.filter(|s| !s.trim().is_empty()))
+            .collect();
+        if values.iter().all(Option::is_none) {
+            return Ok(());
+        }
 
-            *model_counts.entry(proc.modello_valvola.clone()).or_insert(0) += 1;
+        let latest: Option<Vec<Option<String>>> = conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM procedure_plans WHERE patient_id = ?1 ORDER BY versione DESC LIMIT 1",
+                    PROCEDURE_PLAN_FIELDS.join(", ")
+                ),
+                params![patient_id],
+                |row| (0..PROCEDURE_PLAN_FIELDS.len()).map(|i| row.get(i)).collect(),
+            )
+            .map(Some)
+            .or_else(|e| match e {
+                rusqlite::Error::QueryReturnedNoRows => Ok(None),
+                other => Err(other),
+            })?;
+        if latest.as_ref() == Some(&values) {
+            return Ok(());
         }
 
-        // Top 5 modelli
-        let mut top_models: Vec<(String, i32)> = model_counts.into_iter().collect();
-        top_models.sort_by(|a, b| b.1.cmp(&a.1));
-        top_models.truncate(5);
+        let placeholders: Vec<String> = (0..PROCEDURE_PLAN_FIELDS.len())
+            .map(|i| format!("?{}", i + 2))
+            .collect();
+        let mut sql_params: Vec<&dyn rusqlite::ToSql> = vec![&patient_id];
+        sql_params.extend(values.iter().map(|v| v as &dyn rusqlite::ToSql));
+        conn.execute(
+            &format!(
+                "INSERT INTO procedure_plans (patient_id, versione, {})
+                 VALUES (?1, (SELECT COALESCE(MAX(versione), 0) + 1 FROM procedure_plans WHERE patient_id = ?1), {})",
+                PROCEDURE_PLAN_FIELDS.join(", "),
+                placeholders.join(", ")
+            ),
+            sql_params.as_slice(),
+        )?;
+        Ok(())
+    }
 
-        Ok(Statistics {
-            total_procedures: total,
-            average_duration_minutes: if total > 0 {
-                total_duration as f64 / total as f64
-            } else {
-                0.0
-            },
-            pre_dilatazione_percentage: (pre_count as f64 / total as f64) * 100.0,
-            post_dilatazione_percentage: (post_count as f64 / total as f64) * 100.0,
-            average_fe: if fe_count > 0 {
-                Some(fe_sum / fe_count as f64)
-            } else {
-                None
-            },
-            average_vmax: if vmax_count > 0 {
-                Some(vmax_sum / vmax_count as f64)
-            } else {
-                None
-            },
-            average_gmax: if gmax_count > 0 {
-                Some(gmax_sum / gmax_count as f64)
-            } else {
-                None
-            },
-            average_gmed: if gmed_count > 0 {
-                Some(gmed_sum / gmed_count as f64)
-            } else {
-                None
-            },
-            average_ava: if ava_count > 0 {
-                Some(ava_sum / ava_count as f64)
-            } else {
-                None
-            },
-            balloon_expandable_count: balloon_count,
-            self_expandable_count: self_count,
-            top_valve_models: top_models,
-        })
+    /// Riporta il piano sui campi `procedurale_*` del paziente. La doppia scrittura è voluta:
+    /// `procedure_plans` conserva lo storico, mentre i campi del paziente restano la copia
+    /// corrente letta dal form, dai template e dagli export. Ogni salvataggio del piano passa
+    /// da qui e ogni salvataggio del paziente da `snapshot_plan_from_patient`, così le due
+    /// copie non divergono.
+    fn mirror_plan_to_patient(conn: &Connection, plan: &ProcedurePlan) -> SqlResult<()> {
+        let assignments: Vec<String> = PROCEDURE_PLAN_FIELDS
+            .iter()
+            .enumerate()
+            .map(|(i, f)| format!("procedurale_{} = ?{}", f, i + 1))
+            .collect();
+        let values = plan.field_values();
+        let mut sql_params: Vec<&dyn rusqlite::ToSql> =
+            values.iter().map(|v| *v as &dyn rusqlite::ToSql).collect();
+        sql_params.push(&plan.patient_id);
+        conn.execute(
+            &format!(
+                "UPDATE patients SET {}, updated_at = CURRENT_TIMESTAMP WHERE id = ?{}",
+                assignments.join(", "),
+                PROCEDURE_PLAN_FIELDS.len() + 1
+            ),
+            sql_params.as_slice(),
+        )?;
+        Ok(())
     }
 
-    // ========================================================================
-    // PATIENT OPERATIONS
-    // ========================================================================
+    /// Testo indicizzato come "note procedurali": le note libere della scheda procedurale
+    fn procedural_notes_sql(prefix: &str) -> String {
+        [
+            "procedurale_altro",
+            "procedurale_coronarografia_note",
+            "procedurale_pacemaker_note",
+            "procedurale_accesso_protezione_note",
+            "procedurale_altri_accessi",
+            "procedurale_valvuloplastica_note",
+        ]
+        .iter()
+        .map(|column| format!("COALESCE({}.{}, '')", prefix, column))
+        .collect::<Vec<_>>()
+        .join(" || ' ' || ")
+    }
 
-    fn ensure_ambulatorio_slot_available(
-        &self,
-        conn: &Connection,
-        visit_date: Option<&str>,
-        visit_time: Option<&str>,
-        exclude_patient_id: Option<i64>,
-    ) -> Result<(), String> {
-        let date = visit_date
-            .map(str::trim)
-            .filter(|value| !value.is_empty());
-        let time = visit_time
-            .map(str::trim)
-            .filter(|value| !value.is_empty());
+    /// Crea l'indice full-text (FTS5) delle note cliniche, mantenuto allineato da trigger.
+    /// Il tokenizer rimuove gli accenti: "gia" trova "già".
+    fn ensure_clinical_notes_index(&self, conn: &Connection) -> SqlResult<()> {
+        let exists: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'patients_fts'",
+            [],
+            |row| row.get(0),
+        )?;
 
-        let (date, time) = match (date, time) {
-            (Some(date), Some(time)) => (date, time),
-            _ => return Ok(()),
-        };
+        conn.execute(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS patients_fts USING fts5(
+                note, anamnesi_cardiologica, apr, visita_odierna, conclusioni, note_procedurali,
+                tokenize = 'unicode61 remove_diacritics 2'
+            )",
+            [],
+        )?;
 
-        let conflicts: i64 = if let Some(patient_id) = exclude_patient_id {
-            conn.query_row(
-                "SELECT COUNT(1) FROM patients
-                 WHERE ambulatorio_data_visita = ?1
-                   AND ambulatorio_orario_visita = ?2
-                   AND id <> ?3",
-                params![date, time, patient_id],
-                |row| row.get(0),
-            )
-            .map_err(|e| e.to_string())?
-        } else {
-            conn.query_row(
-                "SELECT COUNT(1) FROM patients
-                 WHERE ambulatorio_data_visita = ?1
-                   AND ambulatorio_orario_visita = ?2",
-                params![date, time],
-                |row| row.get(0),
+        let columns = "note, anamnesi_cardiologica, apr, visita_odierna, conclusioni";
+        let insert_from = |prefix: &str| {
+            format!(
+                "INSERT INTO patients_fts (rowid, {columns}, note_procedurali)
+                 VALUES ({p}.id, {p}.note, {p}.anamnesi_cardiologica, {p}.apr, {p}.visita_odierna,
+                         {p}.conclusioni, {notes});",
+                columns = columns,
+                p = prefix,
+                notes = Self::procedural_notes_sql(prefix)
             )
-            .map_err(|e| e.to_string())?
         };
 
-        if conflicts > 0 {
-            return Err(format!(
-                "Lo slot {} alle {} è già occupato da un altro paziente",
-                date, time
-            ));
+        conn.execute(
+            &format!(
+                "CREATE TRIGGER IF NOT EXISTS patients_fts_insert AFTER INSERT ON patients BEGIN {} END",
+                insert_from("new")
+            ),
+            [],
+        )?;
+        conn.execute(
+            &format!(
+                "CREATE TRIGGER IF NOT EXISTS patients_fts_update AFTER UPDATE ON patients BEGIN
+                    DELETE FROM patients_fts WHERE rowid = old.id;
+                    {}
+                 END",
+                insert_from("new")
+            ),
+            [],
+        )?;
+        conn.execute(
+            "CREATE TRIGGER IF NOT EXISTS patients_fts_delete AFTER DELETE ON patients BEGIN
+                DELETE FROM patients_fts WHERE rowid = old.id;
+             END",
+            [],
+        )?;
+
+        if !exists {
+            conn.execute(
+                &format!(
+                    "INSERT INTO patients_fts (rowid, {columns}, note_procedurali)
+                     SELECT p.id, p.note, p.anamnesi_cardiologica, p.apr, p.visita_odierna,
+                            p.conclusioni, {notes}
+                     FROM patients p",
+                    columns = columns,
+                    notes = Self::procedural_notes_sql("p")
+                ),
+                [],
+            )?;
         }
 
         Ok(())
     }
 
-    /// Inserisce un nuovo paziente (stato iniziale: Da valutare)
-    pub fn insert_patient(&self, patient: &Patient) -> Result<i64, String> {
-        let conn = self.conn.lock().unwrap();
-
-        self.ensure_ambulatorio_slot_available(
-            &conn,
-            patient.ambulatorio_data_visita.as_deref(),
-            patient.ambulatorio_orario_visita.as_deref(),
-            None,
+    /// Storico dei cambi di stato, alimentato da trigger sulle tabelle di stato.
+    /// Alla prima creazione viene inizializzato con lo stato corrente di ogni paziente.
+    fn ensure_status_history_table(&self, conn: &Connection) -> SqlResult<()> {
+        let exists: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'patient_status_history'",
+            [],
+            |row| row.get(0),
         )?;
 
-        conn.execute("BEGIN TRANSACTION", []).map_err(|e| e.to_string())?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS patient_status_history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                patient_id INTEGER NOT NULL,
+                stato TEXT NOT NULL,
+                changed_at TEXT DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY(patient_id) REFERENCES patients(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_status_history_patient ON patient_status_history(patient_id, stato)",
+            [],
+        )?;
 
-        let fattori_json = patient.ambulatorio_fattori.as_ref().and_then(|f| serde_json::to_string(f).ok());
+        let statuses = [
+            ("Da valutare", "patients_da_valutare"),
+            ("In corso di accertamenti", "patients_in_attesa_esami"),
+            ("In attesa di TAVI", "patients_in_attesa_intervento"),
+            ("Non candidabile a TAVI", "patients_non_candidabile"),
+            ("TAVI eseguita", "patients_completato"),
+        ];
+
+        for (label, table) in statuses {
+            if !exists {
+                conn.execute(
+                    &format!(
+                        "INSERT INTO patient_status_history (patient_id, stato, changed_at)
+                         SELECT patient_id, '{}', COALESCE(created_at, CURRENT_TIMESTAMP) FROM {}",
+                        label, table
+                    ),
+                    [],
+                )?;
+            }
+            conn.execute(
+                &format!(
+                    "CREATE TRIGGER IF NOT EXISTS {table}_history AFTER INSERT ON {table} BEGIN
+                        INSERT INTO patient_status_history (patient_id, stato, changed_at)
+                        VALUES (new.patient_id, '{label}', COALESCE(new.created_at, CURRENT_TIMESTAMP));
+                     END",
+                    table = table,
+                    label = label
+                ),
+                [],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Stato degli avvisi di scadenza priorità (presa visione / posticipo) per paziente
+    fn ensure_priority_alert_table(&self, conn: &Connection) -> SqlResult<()> {
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS priority_alert_states (
+                patient_id INTEGER PRIMARY KEY,
+                acknowledged_due_date TEXT,
+                acknowledged_at TEXT,
+                snoozed_until TEXT,
+                updated_at TEXT DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY(patient_id) REFERENCES patients(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+        Ok(())
+    }
+
+    /// Calendario ambulatoriale: sedute (date aperte), modelli di slot per giorno della
+    /// settimana e slot bloccati. Alla prima creazione i giorni feriali ricevono gli slot standard.
+    fn ensure_ambulatorio_calendar_tables(&self, conn: &Connection) -> SqlResult<()> {
+        let exists: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'ambulatorio_slot_templates'",
+            [],
+            |row| row.get(0),
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS ambulatorio_sessions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                data TEXT NOT NULL UNIQUE,
+                note TEXT,
+                created_at TEXT DEFAULT CURRENT_TIMESTAMP
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS ambulatorio_slot_templates (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                giorno_settimana INTEGER NOT NULL CHECK(giorno_settimana BETWEEN 1 AND 7),
+                orario TEXT NOT NULL,
+                tipo TEXT NOT NULL DEFAULT 'standard' CHECK(tipo IN ('standard', 'tc')),
+                durata_minuti INTEGER,
+                attivo INTEGER NOT NULL DEFAULT 1,
+                UNIQUE(giorno_settimana, orario)
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS ambulatorio_blocked_slots (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                data TEXT NOT NULL,
+                orario TEXT NOT NULL,
+                motivo TEXT,
+                created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+                UNIQUE(data, orario)
+            )",
+            [],
+        )?;
+
+        if !exists {
+            for giorno in 1..=5 {
+                for (orario, tipo) in AMBULATORIO_DEFAULT_SLOTS {
+                    conn.execute(
+                        "INSERT OR IGNORE INTO ambulatorio_slot_templates (giorno_settimana, orario, tipo)
+                         VALUES (?1, ?2, ?3)",
+                        params![giorno, orario, tipo],
+                    )?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Crea la tabella degli appuntamenti ambulatoriali. Alla prima creazione importa le visite
+    /// già registrate sul paziente: prenotate se future, 'pregresso' se passate (non si sa se
+    /// la visita sia stata eseguita).
+    fn ensure_appointment_tables(&self, conn: &Connection) -> SqlResult<()> {
+        let exists: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'appointments'",
+            [],
+            |row| row.get(0),
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS appointments (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                patient_id INTEGER NOT NULL,
+                data TEXT NOT NULL,
+                orario TEXT,
+                tipo TEXT NOT NULL DEFAULT 'prima_visita'
+                    CHECK(tipo IN ('prima_visita', 'rivalutazione', 'follow_up_post_tavi', 'tc')),
+                stato TEXT NOT NULL DEFAULT 'prenotato'
+                    CHECK(stato IN ('prenotato', 'eseguito', 'pregresso', 'non_presentato', 'annullato')),
+                medico_titolo TEXT,
+                medico_nome TEXT,
+                note TEXT,
+                referto_path TEXT,
+                created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+                updated_at TEXT DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY(patient_id) REFERENCES patients(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_appointments_patient ON appointments(patient_id)",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_appointments_slot ON appointments(data, orario)",
+            [],
+        )?;
+
+        if !exists {
+            conn.execute(
+                "INSERT INTO appointments (patient_id, data, orario, tipo, stato, medico_titolo, medico_nome)
+                 SELECT id, TRIM(ambulatorio_data_visita),
+                        NULLIF(TRIM(COALESCE(ambulatorio_orario_visita, '')), ''),
+                        'prima_visita',
+                        CASE WHEN TRIM(ambulatorio_data_visita) < date('now', 'localtime')
+                             THEN 'pregresso' ELSE 'prenotato' END,
+                        medico_titolo, medico_nome
+                 FROM patients
+                 WHERE TRIM(COALESCE(ambulatorio_data_visita, '')) <> ''",
+                [],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Crea le tabelle del planner di sala (sedute TAVI e casi). Alla prima creazione le date
+    /// TAVI future già assegnate diventano sedute con i rispettivi casi.
+    fn ensure_cathlab_tables(&self, conn: &Connection) -> SqlResult<()> {
+        let exists: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'cathlab_sessions'",
+            [],
+            |row| row.get(0),
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS cathlab_sessions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                data TEXT NOT NULL UNIQUE,
+                capacita INTEGER NOT NULL DEFAULT 3 CHECK(capacita >= 1),
+                ora_inizio TEXT NOT NULL DEFAULT '08:00',
+                anestesista INTEGER NOT NULL DEFAULT 0,
+                chirurgo_vascolare INTEGER NOT NULL DEFAULT 0,
+                note TEXT,
+                created_at TEXT DEFAULT CURRENT_TIMESTAMP
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS cathlab_cases (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                session_id INTEGER NOT NULL,
+                patient_id INTEGER NOT NULL,
+                ordine INTEGER NOT NULL,
+                durata_minuti INTEGER,
+                note TEXT,
+                created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+                UNIQUE(session_id, patient_id),
+                FOREIGN KEY(session_id) REFERENCES cathlab_sessions(id) ON DELETE CASCADE,
+                FOREIGN KEY(patient_id) REFERENCES patients(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_cathlab_cases_patient ON cathlab_cases(patient_id)",
+            [],
+        )?;
+
+        if !exists {
+            // Le risorse si assumono disponibili: i casi erano già stati programmati
+            conn.execute(
+                "INSERT INTO cathlab_sessions (data, capacita, anestesista, chirurgo_vascolare)
+                 SELECT TRIM(data_tavi), MAX(?1, COUNT(*)), 1, 1 FROM patients
+                 WHERE DATE(TRIM(data_tavi)) IS NOT NULL AND TRIM(data_tavi) >= date('now', 'localtime')
+                 GROUP BY TRIM(data_tavi)",
+                params![CATHLAB_DEFAULT_CAPACITY],
+            )?;
+            conn.execute(
+                "INSERT INTO cathlab_cases (session_id, patient_id, ordine)
+                 SELECT s.id, p.id, ROW_NUMBER() OVER (PARTITION BY s.id ORDER BY p.id)
+                 FROM patients p JOIN cathlab_sessions s ON s.data = TRIM(p.data_tavi)",
+                [],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Registro dei messaggi HL7 acquisiti dalla cartella di ingresso
+    fn ensure_hl7_ingestion_table(&self, conn: &Connection) -> SqlResult<()> {
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS hl7_ingestion_log (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                received_at TEXT DEFAULT CURRENT_TIMESTAMP,
+                file_name TEXT NOT NULL,
+                message_type TEXT,
+                control_id TEXT,
+                sending_facility TEXT,
+                esito TEXT NOT NULL CHECK(esito IN ('creato', 'aggiornato', 'ignorato', 'errore')),
+                patient_id INTEGER,
+                dettaglio TEXT,
+                FOREIGN KEY(patient_id) REFERENCES patients(id) ON DELETE SET NULL
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_hl7_ingestion_received ON hl7_ingestion_log(received_at)",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_hl7_ingestion_control_id ON hl7_ingestion_log(control_id)",
+            [],
+        )?;
+        Ok(())
+    }
+
+    /// Crea la tabella delle ricerche salvate (liste intelligenti) con alcune liste predefinite.
+    fn ensure_saved_search_tables(&self, conn: &Connection) -> SqlResult<()> {
+        let exists: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'saved_searches'",
+            [],
+            |row| row.get(0),
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS saved_searches (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                nome TEXT NOT NULL UNIQUE,
+                descrizione TEXT,
+                filters TEXT NOT NULL,
+                mostra_in_dashboard INTEGER NOT NULL DEFAULT 1,
+                ordine INTEGER NOT NULL DEFAULT 0,
+                created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+                updated_at TEXT DEFAULT CURRENT_TIMESTAMP
+            )",
+            [],
+        )?;
+
+        if !exists {
+            let defaults = [
+                (
+                    "Alta priorità in attesa da oltre 30 giorni",
+                    PatientFilters {
+                        status: Some("In attesa di TAVI".to_string()),
+                        priority: Some("alta".to_string()),
+                        min_days_in_status: Some(30),
+                        sort_by: Some("status_created_at".to_string()),
+                        sort_dir: Some("asc".to_string()),
+                        ..Default::default()
+                    },
+                ),
+                (
+                    "In attesa di TAVI da oltre 90 giorni",
+                    PatientFilters {
+                        status: Some("In attesa di TAVI".to_string()),
+                        min_days_in_status: Some(90),
+                        sort_by: Some("status_created_at".to_string()),
+                        sort_dir: Some("asc".to_string()),
+                        ..Default::default()
+                    },
+                ),
+                (
+                    "Accertamenti in corso da oltre 60 giorni",
+                    PatientFilters {
+                        status: Some("In corso di accertamenti".to_string()),
+                        min_days_in_status: Some(60),
+                        ..Default::default()
+                    },
+                ),
+                (
+                    "eGFR < 30 in attesa di TAVI",
+                    PatientFilters {
+                        status: Some("In attesa di TAVI".to_string()),
+                        egfr_below: Some(30.0),
+                        ..Default::default()
+                    },
+                ),
+            ];
+            for (ordine, (nome, filters)) in defaults.iter().enumerate() {
+                let json = serde_json::to_string(filters).unwrap_or_else(|_| "{}".to_string());
+                conn.execute(
+                    "INSERT OR IGNORE INTO saved_searches (nome, filters, ordine) VALUES (?1, ?2, ?3)",
+                    params![nome, json, ordine as i32],
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Pianifica i controlli a 30 giorni, 1 anno e annuali per i pazienti con TAVI eseguita
+    /// (tutti, o solo `patient_id`). Crea i controlli mancanti senza toccare quelli già
+    /// pianificati, anche se modificati dall'utente; con `ricalcola` (data TAVI cambiata)
+    /// riallinea alla nuova data i controlli non ancora eseguiti.
+    /// Va chiamata al passaggio in "TAVI eseguita" e quando cambia la data TAVI, non in lettura.
+    fn schedule_follow_ups(conn: &Connection, patient_id: Option<i64>, ricalcola: bool) -> SqlResult<()> {
+        let due_date_sql = "CASE WHEN ?1 = 0 THEN DATE(p.data_tavi, '+30 days')
+                                 ELSE DATE(p.data_tavi, '+' || ?1 || ' years') END";
+
+        if let (Some(patient_id), true) = (patient_id, ricalcola) {
+            conn.execute(
+                "UPDATE follow_ups SET
+                    data_prevista = (
+                        SELECT CASE WHEN follow_ups.anno = 0 THEN DATE(p.data_tavi, '+30 days')
+                                    ELSE DATE(p.data_tavi, '+' || follow_ups.anno || ' years') END
+                        FROM patients p WHERE p.id = follow_ups.patient_id
+                    ),
+                    updated_at = CURRENT_TIMESTAMP
+                 WHERE patient_id = ?1
+                   AND data_eseguita IS NULL
+                   AND EXISTS (SELECT 1 FROM patients p WHERE p.id = ?1 AND DATE(p.data_tavi) IS NOT NULL)",
+                params![patient_id],
+            )?;
+        }
+
+        for anno in 0..=FOLLOW_UP_YEARS {
+            let tipo = match anno {
+                0 => "30_giorni",
+                1 => "1_anno",
+                _ => "annuale",
+            };
+            conn.execute(
+                &format!(
+                    "INSERT OR IGNORE INTO follow_ups (patient_id, tipo, anno, data_prevista)
+                     SELECT c.patient_id, ?2, ?1, {}
+                     FROM patients_completato c
+                     INNER JOIN patients p ON p.id = c.patient_id
+                     WHERE DATE(p.data_tavi) IS NOT NULL
+                       AND (?3 IS NULL OR c.patient_id = ?3)
+                       AND NOT EXISTS (
+                         SELECT 1 FROM follow_ups d
+                         WHERE d.patient_id = c.patient_id AND d.decesso = 1
+                       )",
+                    due_date_sql
+                ),
+                params![anno, tipo, patient_id],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn load_valve_models(conn: &Connection) -> SqlResult<Vec<ValveModel>> {
+        let mut stmt = conn.prepare(
+            "SELECT * FROM valve_models ORDER BY produttore, nome_commerciale",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            let dimensioni: Vec<String> = row
+                .get::<_, Option<String>>("dimensioni")?
+                .and_then(|json| serde_json::from_str(&json).ok())
+                .unwrap_or_default();
+            let alias: Vec<String> = row
+                .get::<_, Option<String>>("alias")?
+                .and_then(|json| serde_json::from_str(&json).ok())
+                .unwrap_or_default();
+            Ok(ValveModel {
+                id: Some(row.get("id")?),
+                codice: row.get("codice")?,
+                produttore: row.get("produttore")?,
+                nome_commerciale: row.get("nome_commerciale")?,
+                tipo_valvola: row.get("tipo_valvola")?,
+                dimensioni,
+                anulus_min: row.get("anulus_min").ok(),
+                anulus_max: row.get("anulus_max").ok(),
+                introduttore_min_fr: row.get("introduttore_min_fr").ok(),
+                alias,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Verifica che il piano procedurale collegato alla procedura esista
+    fn validate_procedure_plan_link(conn: &Connection, proc: &Procedure) -> Result<(), String> {
+        if let Some(plan_id) = proc.plan_id {
+            let exists: bool = conn
+                .query_row(
+                    "SELECT COUNT(*) > 0 FROM procedure_plans WHERE id = ?1",
+                    params![plan_id],
+                    |row| row.get(0),
+                )
+                .map_err(|e| e.to_string())?;
+            if !exists {
+                return Err(format!("Piano procedurale {} non trovato", plan_id));
+            }
+        }
+        Ok(())
+    }
+
+    /// Vero se la procedura esiste già con la stessa valvola (modello, tipo e misura): i dati
+    /// registrati prima del catalogo restano salvabili anche se non vi corrispondono
+    fn stored_valve_unchanged(conn: &Connection, proc: &Procedure) -> Result<bool, String> {
+        let Some(id) = proc.id else {
+            return Ok(false);
+        };
+        let stored: Option<(String, String, Option<f64>)> = conn
+            .query_row(
+                "SELECT modello_valvola, tipo_valvola, dimensione_valvola FROM procedures WHERE id = ?1",
+                params![id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .map(Some)
+            .or_else(|e| match e {
+                rusqlite::Error::QueryReturnedNoRows => Ok(None),
+                other => Err(other.to_string()),
+            })?;
+        Ok(stored.is_some_and(|(modello, tipo, dimensione)| {
+            modello.trim() == proc.modello_valvola.trim()
+                && tipo == proc.tipo_valvola
+                && match (dimensione, proc.dimensione_valvola) {
+                    (Some(a), Some(b)) => (a - b).abs() < 0.01,
+                    (None, None) => true,
+                    _ => false,
+                }
+        }))
+    }
+
+    /// Verifica che valvola, tipo e misura di una procedura siano coerenti con il catalogo.
+    fn validate_procedure_valve(conn: &Connection, proc: &Procedure) -> Result<(), String> {
+        let catalogue = Self::load_valve_models(conn).map_err(|e| e.to_string())?;
+        let model = catalogue
+            .iter()
+            .find(|m| m.matches(&proc.modello_valvola))
+            .ok_or_else(|| format!(
+                "Modello valvola non presente nel catalogo: {}",
+                proc.modello_valvola
+            ))?;
+
+        if model.tipo_valvola != proc.tipo_valvola {
+            return Err(format!(
+                "Il modello {} è {}, non {}",
+                model.display_name(),
+                model.tipo_valvola,
+                proc.tipo_valvola
+            ));
+        }
+
+        if let Some(size) = proc.dimensione_valvola {
+            let sizes = model.nominal_sizes();
+            if !sizes.is_empty() && !sizes.iter().any(|s| (s - size).abs() < 0.01) {
+                return Err(format!(
+                    "Misura {} mm non disponibile per {} (misure: {})",
+                    size,
+                    model.display_name(),
+                    model.dimensioni.join(", ")
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Inserisce una nuova procedura
+    pub fn insert_procedure(&self, proc: &Procedure) -> Result<i64, String> {
+        let conn = self.conn.lock().unwrap();
+        Self::insert_procedure_row(&conn, proc)
+    }
+
+    fn insert_procedure_row(conn: &Connection, proc: &Procedure) -> Result<i64, String> {
+        Self::validate_procedure_valve(conn, proc)?;
+        Self::validate_procedure_plan_link(conn, proc)?;
+        let protesica_catalogo_id = proc.protesica_catalogo_id.filter(|_| proc.valvola_protesica);
+
+        conn.execute(
+            "INSERT INTO procedures (
+                nome, cognome, data_nascita, altezza, peso,
+                fe, vmax, gmax, gmed, ava, anulus_aortico,
+                valvola_protesica, protesica_modello, protesica_dimensione,
+                data_procedura, ora_inizio, ora_fine,
+                tipo_valvola, modello_valvola, dimensione_valvola,
+                pre_dilatazione, post_dilatazione, protesica_catalogo_id,
+                accesso_principale, plan_id, operatore
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25, ?26)",
+            params![
+                proc.nome, proc.cognome, proc.data_nascita, proc.altezza, proc.peso,
+                proc.fe, proc.vmax, proc.gmax, proc.gmed, proc.ava, proc.anulus_aortico,
+                proc.valvola_protesica, proc.protesica_modello, proc.protesica_dimensione,
+                proc.data_procedura, proc.ora_inizio, proc.ora_fine,
+                proc.tipo_valvola, proc.modello_valvola, proc.dimensione_valvola,
+                proc.pre_dilatazione, proc.post_dilatazione, protesica_catalogo_id,
+                proc.accesso_principale, proc.plan_id, proc.operatore
+            ],
+        ).map_err(|e| e.to_string())?;
+
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Aggiorna una procedura esistente
+    pub fn update_procedure(&self, proc: &Procedure) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+        Self::update_procedure_row(&conn, proc)
+    }
+
+    fn update_procedure_row(conn: &Connection, proc: &Procedure) -> Result<(), String> {
+        let id = proc.id.ok_or("Procedure ID is required for update")?;
+        if !Self::stored_valve_unchanged(conn, proc)? {
+            Self::validate_procedure_valve(conn, proc)?;
+        }
+        Self::validate_procedure_plan_link(conn, proc)?;
+        let protesica_catalogo_id = proc.protesica_catalogo_id.filter(|_| proc.valvola_protesica);
+
+        conn.execute(
+            "UPDATE procedures SET
+                nome = ?1, cognome = ?2, data_nascita = ?3, altezza = ?4, peso = ?5,
+                fe = ?6, vmax = ?7, gmax = ?8, gmed = ?9, ava = ?10, anulus_aortico = ?11,
+                valvola_protesica = ?12, protesica_modello = ?13, protesica_dimensione = ?14,
+                data_procedura = ?15, ora_inizio = ?16, ora_fine = ?17,
+                tipo_valvola = ?18, modello_valvola = ?19, dimensione_valvola = ?20,
+                pre_dilatazione = ?21, post_dilatazione = ?22, protesica_catalogo_id = ?23,
+                accesso_principale = ?24, plan_id = ?25, operatore = ?26,
+                updated_at = CURRENT_TIMESTAMP
+            WHERE id = ?27",
+            params![
+                proc.nome, proc.cognome, proc.data_nascita, proc.altezza, proc.peso,
+                proc.fe, proc.vmax, proc.gmax, proc.gmed, proc.ava, proc.anulus_aortico,
+                proc.valvola_protesica, proc.protesica_modello, proc.protesica_dimensione,
+                proc.data_procedura, proc.ora_inizio, proc.ora_fine,
+                proc.tipo_valvola, proc.modello_valvola, proc.dimensione_valvola,
+                proc.pre_dilatazione, proc.post_dilatazione, protesica_catalogo_id,
+                proc.accesso_principale, proc.plan_id, proc.operatore,
+                id
+            ],
+        ).map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+
+    /// Elimina una procedura
+    pub fn delete_procedure(&self, id: i64) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+
+        conn.execute("DELETE FROM procedures WHERE id = ?1", params![id])
+            .map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+
+    /// Ottieni tutte le procedure (con filtri opzionali)
+    /// Condizioni (" AND ...") dei filtri procedure, accodando i parametri
+    fn build_procedure_filter_clause(
+        filters: &ProcedureFilters,
+        params: &mut Vec<Box<dyn rusqlite::ToSql>>,
+    ) -> Result<String, String> {
+        let mut clause = String::new();
+
+        // Filtro ricerca testuale
+        if let Some(search) = filters.search_query.as_deref().filter(|s| !s.is_empty()) {
+            params.push(Box::new(format!("%{}%", search)));
+            let n = params.len();
+            clause.push_str(&format!(" AND (nome LIKE ?{n} OR cognome LIKE ?{n} OR modello_valvola LIKE ?{n})"));
+        }
+
+        // Filtro tipo valvola
+        if let Some(tipo) = filters.tipo_valvola.as_deref().filter(|t| *t != "all") {
+            params.push(Box::new(tipo.to_string()));
+            clause.push_str(&format!(" AND tipo_valvola = ?{}", params.len()));
+        }
+
+        // Filtro periodo
+        if let Some(period) = filters.period.as_deref().filter(|p| *p != "all") {
+            let days = match period {
+                "1m" => 30,
+                "3m" => 90,
+                "6m" => 180,
+                "1y" => 365,
+                _ => 0,
+            };
+
+            if days > 0 {
+                clause.push_str(&format!(" AND data_procedura >= date('now', '-{} days')", days));
+            }
+        }
+
+        // Intervallo di date esplicito
+        for (value, op) in [(&filters.date_from, ">="), (&filters.date_to, "<=")] {
+            if let Some(date) = value.as_deref().map(str::trim).filter(|d| !d.is_empty()) {
+                chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                    .map_err(|_| format!("Data non valida: {}", date))?;
+                params.push(Box::new(date.to_string()));
+                clause.push_str(&format!(" AND data_procedura {} ?{}", op, params.len()));
+            }
+        }
+
+        Ok(clause)
+    }
+
+    pub fn get_all_procedures(&self, filters: Option<ProcedureFilters>) -> Result<Vec<Procedure>, String> {
+        let conn = self.conn.lock().unwrap();
+
+        let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![];
+        let mut query = String::from("SELECT * FROM procedures WHERE 1=1");
+        query.push_str(&Self::build_procedure_filter_clause(&filters.unwrap_or_default(), &mut params)?);
+
+        query.push_str(" ORDER BY data_procedura DESC, ora_inizio DESC");
+
+        let params_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+
+        let mut stmt = conn.prepare(&query).map_err(|e| e.to_string())?;
+        let rows = stmt.query_map(params_refs.as_slice(), |row| {
+            Ok(Procedure {
+                id: Some(row.get(0)?),
+                created_at: row.get(1).ok(),
+                updated_at: row.get(2).ok(),
+                nome: row.get(3)?,
+                cognome: row.get(4)?,
+                data_nascita: row.get(5)?,
+                altezza: row.get(6).ok(),
+                peso: row.get(7).ok(),
+                fe: row.get(8).ok(),
+                vmax: row.get(9).ok(),
+                gmax: row.get(10).ok(),
+                gmed: row.get(11).ok(),
+                ava: row.get(12).ok(),
+                anulus_aortico: row.get(13).ok(),
+                valvola_protesica: row.get::<_, i32>(14)? != 0,
+                protesica_modello: row.get(15).ok(),
+                protesica_dimensione: row.get(16).ok(),
+                data_procedura: row.get(17)?,
+                ora_inizio: row.get(18)?,
+                ora_fine: row.get(19)?,
+                tipo_valvola: row.get(20)?,
+                modello_valvola: row.get(21)?,
+                dimensione_valvola: row.get(22).ok(),
+                pre_dilatazione: row.get::<_, i32>(23)? != 0,
+                post_dilatazione: row.get::<_, i32>(24)? != 0,
+                protesica_catalogo_id: row.get(25).ok(),
+                accesso_principale: row.get(26).ok(),
+                plan_id: row.get(27).ok(),
+                operatore: row.get(28).ok(),
+            })
+        }).map_err(|e| e.to_string())?;
+
+        let procedures: Result<Vec<_>, _> = rows.collect();
+        procedures.map_err(|e| e.to_string())
+    }
+
+    /// Ottieni una procedura per ID
+    pub fn get_procedure_by_id(&self, id: i64) -> Result<Option<Procedure>, String> {
+        let conn = self.conn.lock().unwrap();
+
+        let result = conn.query_row(
+            "SELECT * FROM procedures WHERE id = ?1",
+            params![id],
+            |row| {
+                Ok(Procedure {
+                    id: Some(row.get(0)?),
+                    created_at: row.get(1).ok(),
+                    updated_at: row.get(2).ok(),
+                    nome: row.get(3)?,
+                    cognome: row.get(4)?,
+                    data_nascita: row.get(5)?,
+                    altezza: row.get(6).ok(),
+                    peso: row.get(7).ok(),
+                    fe: row.get(8).ok(),
+                    vmax: row.get(9).ok(),
+                    gmax: row.get(10).ok(),
+                    gmed: row.get(11).ok(),
+                    ava: row.get(12).ok(),
+                    anulus_aortico: row.get(13).ok(),
+                    valvola_protesica: row.get::<_, i32>(14)? != 0,
+                    protesica_modello: row.get(15).ok(),
+                    protesica_dimensione: row.get(16).ok(),
+                    data_procedura: row.get(17)?,
+                    ora_inizio: row.get(18)?,
+                    ora_fine: row.get(19)?,
+                    tipo_valvola: row.get(20)?,
+                    modello_valvola: row.get(21)?,
+                    dimensione_valvola: row.get(22).ok(),
+                    pre_dilatazione: row.get::<_, i32>(23)? != 0,
+                    post_dilatazione: row.get::<_, i32>(24)? != 0,
+                    protesica_catalogo_id: row.get(25).ok(),
+                    accesso_principale: row.get(26).ok(),
+                    plan_id: row.get(27).ok(),
+                    operatore: row.get(28).ok(),
+                })
+            },
+        );
+
+        match result {
+            Ok(proc) => Ok(Some(proc)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// Calcola le statistiche
+    pub fn calculate_statistics(&self, filters: Option<ProcedureFilters>) -> Result<Statistics, String> {
+        let procedures = self.get_all_procedures(filters)?;
+        let catalogue = self.get_valve_models()?;
+        let outcome_rates = {
+            let procedure_ids: std::collections::HashSet<i64> =
+                procedures.iter().filter_map(|p| p.id).collect();
+            let outcomes: Vec<ProcedureOutcome> = self
+                .get_all_procedure_outcomes()?
+                .into_iter()
+                .filter(|o| procedure_ids.contains(&o.procedure_id))
+                .collect();
+            OutcomeRates::from_outcomes(&outcomes)
+        };
+
+        let total = procedures.len() as i32;
+
+        if total == 0 {
+            return Ok(Statistics {
+                total_procedures: 0,
+                average_duration_minutes: 0.0,
+                pre_dilatazione_percentage: 0.0,
+                post_dilatazione_percentage: 0.0,
+                average_fe: None,
+                average_vmax: None,
+                average_gmax: None,
+                average_gmed: None,
+                average_ava: None,
+                balloon_expandable_count: 0,
+                self_expandable_count: 0,
+                top_valve_models: vec![],
+                native_procedures: 0,
+                viv_procedures: 0,
+                average_duration_native_minutes: None,
+                average_duration_viv_minutes: None,
+                outcome_rates,
+            });
+        }
+
+        // Calcola medie
+        let mut total_duration = 0;
+        let mut pre_count = 0;
+        let mut post_count = 0;
+        let mut balloon_count = 0;
+        let mut self_count = 0;
+
+        let mut fe_sum = 0.0;
+        let mut fe_count = 0;
+        let mut vmax_sum = 0.0;
+        let mut vmax_count = 0;
+        let mut gmax_sum = 0.0;
+        let mut gmax_count = 0;
+        let mut gmed_sum = 0.0;
+        let mut gmed_count = 0;
+        let mut ava_sum = 0.0;
+        let mut ava_count = 0;
+
+        let mut model_counts: std::collections::HashMap<String, i32> = std::collections::HashMap::new();
+
+        let mut native_count = 0;
+        let mut viv_count = 0;
+        let mut native_duration = 0;
+        let mut native_duration_count = 0;
+        let mut viv_duration = 0;
+        let mut viv_duration_count = 0;
+
+        for proc in &procedures {
+            if let Some(duration) = proc.calculate_duration_minutes() {
+                total_duration += duration;
+                if proc.valvola_protesica {
+                    viv_duration += duration;
+                    viv_duration_count += 1;
+                } else {
+                    native_duration += duration;
+                    native_duration_count += 1;
+                }
+            }
+
+            if proc.valvola_protesica {
+                viv_count += 1;
+            } else {
+                native_count += 1;
+            }
+
+            if proc.pre_dilatazione {
+                pre_count += 1;
+            }
+
+            if proc.post_dilatazione {
+                post_count += 1;
+            }
+
+            if proc.tipo_valvola == "Balloon Expandable" {
+                balloon_count += 1;
+            } else {
+                self_count += 1;
+            }
+
+            if let Some(fe) = proc.fe {
+                fe_sum += fe;
+                fe_count += 1;
+            }
+            if let Some(vmax) = proc.vmax {
+                vmax_sum += vmax;
+                vmax_count += 1;
+            }
+            if let Some(gmax) = proc.gmax {
+                gmax_sum += gmax;
+                gmax_count += 1;
+            }
+            if let Some(gmed) = proc.gmed {
+                gmed_sum += gmed;
+                gmed_count += 1;
+            }
+            if let Some(ava) = proc.ava {
+                ava_sum += ava;
+                ava_count += 1;
+            }
+
+            // Raggruppa le varianti di scrittura sul nome del catalogo
+            let model_name = catalogue
+                .iter()
+                .find(|m| m.matches(&proc.modello_valvola))
+                .map(|m| m.display_name())
+                .unwrap_or_else(|| proc.modello_valvola.clone());
+            *model_counts.entry(model_name).or_insert(0) += 1;
+        }
+
+        // Top 5 modelli
+        let mut top_models: Vec<(String, i32)> = model_counts.into_iter().collect();
+        top_models.sort_by(|a, b| b.1.cmp(&a.1));
+        top_models.truncate(5);
+
+        Ok(Statistics {
+            total_procedures: total,
+            average_duration_minutes: if total > 0 {
+                total_duration as f64 / total as f64
+            } else {
+                0.0
+            },
+            pre_dilatazione_percentage: (pre_count as f64 / total as f64) * 100.0,
+            post_dilatazione_percentage: (post_count as f64 / total as f64) * 100.0,
+            average_fe: if fe_count > 0 {
+                Some(fe_sum / fe_count as f64)
+            } else {
+                None
+            },
+            average_vmax: if vmax_count > 0 {
+                Some(vmax_sum / vmax_count as f64)
+            } else {
+                None
+            },
+            average_gmax: if gmax_count > 0 {
+                Some(gmax_sum / gmax_count as f64)
+            } else {
+                None
+            },
+            average_gmed: if gmed_count > 0 {
+                Some(gmed_sum / gmed_count as f64)
+            } else {
+                None
+            },
+            average_ava: if ava_count > 0 {
+                Some(ava_sum / ava_count as f64)
+            } else {
+                None
+            },
+            balloon_expandable_count: balloon_count,
+            self_expandable_count: self_count,
+            top_valve_models: top_models,
+            native_procedures: native_count,
+            viv_procedures: viv_count,
+            average_duration_native_minutes: if native_duration_count > 0 {
+                Some(native_duration as f64 / native_duration_count as f64)
+            } else {
+                None
+            },
+            average_duration_viv_minutes: if viv_duration_count > 0 {
+                Some(viv_duration as f64 / viv_duration_count as f64)
+            } else {
+                None
+            },
+            outcome_rates,
+        })
+    }
+
+    /// Espressione SQL della chiave di raggruppamento delle procedure
+    fn procedure_group_sql(group_by: &str) -> Option<&'static str> {
+        let expr = match group_by {
+            "totale" => "'Totale'",
+            "mese" => "COALESCE(strftime('%Y-%m', data_procedura), 'Data non valida')",
+            "trimestre" => "COALESCE(strftime('%Y', data_procedura) || '-T' || ((CAST(strftime('%m', data_procedura) AS INTEGER) + 2) / 3), 'Data non valida')",
+            "anno" => "COALESCE(strftime('%Y', data_procedura), 'Data non valida')",
+            "tipo_valvola" => "tipo_valvola",
+            "modello" => "TRIM(modello_valvola)",
+            "operatore" => "COALESCE(NULLIF(TRIM(operatore), ''), 'Non specificato')",
+            "accesso" => "COALESCE(NULLIF(TRIM(accesso_principale), ''), 'Non specificato')",
+            _ => return None,
+        };
+        Some(expr)
+    }
+
+    /// Espressione SQL del campo numerico (NULL se mancante)
+    fn procedure_metric_sql(campo: &str) -> &'static str {
+        match campo {
+            // Stessa logica di Procedure::calculate_duration_minutes
+            "durata" => "CASE WHEN time(ora_inizio) IS NOT NULL AND time(ora_fine) IS NOT NULL
+                          THEN CAST(ROUND((julianday('2000-01-01 ' || time(ora_fine))
+                                         - julianday('2000-01-01 ' || time(ora_inizio))) * 1440) AS INTEGER)
+                          END",
+            "fe" => "fe",
+            "vmax" => "vmax",
+            "gmax" => "gmax",
+            "gmed" => "gmed",
+            "ava" => "ava",
+            "anulus_aortico" => "anulus_aortico",
+            _ => "dimensione_valvola",
+        }
+    }
+
+    /// Quantile con interpolazione lineare (come IntervalStatistics::from_days) su righe
+    /// numerate `rn` (da 0) di `n` valori ordinati per gruppo
+    fn quantile_sql(q: f64) -> String {
+        let pos = format!("({} * (n - 1))", q);
+        format!(
+            "SUM(CASE WHEN rn = CAST({pos} AS INTEGER) THEN val * (1 - ({pos} - CAST({pos} AS INTEGER)))
+                      WHEN rn = CAST({pos} AS INTEGER) + 1 THEN val * ({pos} - CAST({pos} AS INTEGER))
+                      ELSE 0 END)",
+            pos = pos
+        )
+    }
+
+    /// Statistiche per gruppo calcolate interamente in SQL (conteggi, medie, quartili, dati mancanti)
+    fn aggregate_procedures(
+        conn: &Connection,
+        group_sql: &str,
+        filter_clause: &str,
+        params: &[&dyn rusqlite::ToSql],
+    ) -> Result<std::collections::BTreeMap<String, ProcedureGroupStatistics>, String> {
+        let mut groups: std::collections::BTreeMap<String, ProcedureGroupStatistics> =
+            std::collections::BTreeMap::new();
+
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT {group} AS grp, COUNT(*),
+                        SUM(tipo_valvola = 'Balloon Expandable'), SUM(tipo_valvola = 'Self Expandable'),
+                        SUM(valvola_protesica <> 0), SUM(pre_dilatazione <> 0), SUM(post_dilatazione <> 0)
+                 FROM procedures WHERE 1=1{filters}
+                 GROUP BY grp",
+                group = group_sql,
+                filters = filter_clause
+            ))
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params, |row| {
+                Ok(ProcedureGroupStatistics {
+                    gruppo: row.get(0)?,
+                    totale: row.get(1)?,
+                    balloon_expandable: row.get(2)?,
+                    self_expandable: row.get(3)?,
+                    valve_in_valve: row.get(4)?,
+                    pre_dilatazione: row.get(5)?,
+                    post_dilatazione: row.get(6)?,
+                    metriche: Vec::new(),
+                    variazione_percentuale: None,
+                })
+            })
+            .map_err(|e| e.to_string())?;
+        for row in rows {
+            let group = row.map_err(|e| e.to_string())?;
+            groups.insert(group.gruppo.clone(), group);
+        }
+
+        for (campo, etichetta) in PROCEDURE_METRICS {
+            let mut stmt = conn
+                .prepare(&format!(
+                    "WITH base AS (
+                        SELECT {group} AS grp, {metric} AS val FROM procedures WHERE 1=1{filters}
+                     ),
+                     ranked AS (
+                        SELECT grp, val,
+                               ROW_NUMBER() OVER (PARTITION BY grp ORDER BY val) - 1 AS rn,
+                               COUNT(*) OVER (PARTITION BY grp) AS n
+                        FROM base WHERE val IS NOT NULL
+                     )
+                     SELECT b.grp, b.n_val, b.totale - b.n_val, b.media, b.minimo, b.massimo,
+                            q.q1, q.mediana, q.q3
+                     FROM (
+                        SELECT grp, COUNT(*) AS totale, COUNT(val) AS n_val, AVG(val) AS media,
+                               MIN(val) AS minimo, MAX(val) AS massimo
+                        FROM base GROUP BY grp
+                     ) b
+                     LEFT JOIN (
+                        SELECT grp, {q1} AS q1, {q2} AS mediana, {q3} AS q3 FROM ranked GROUP BY grp
+                     ) q ON q.grp = b.grp",
+                    group = group_sql,
+                    metric = Self::procedure_metric_sql(campo),
+                    filters = filter_clause,
+                    q1 = Self::quantile_sql(0.25),
+                    q2 = Self::quantile_sql(0.5),
+                    q3 = Self::quantile_sql(0.75),
+                ))
+                .map_err(|e| e.to_string())?;
+            let rows = stmt
+                .query_map(params, |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        MetricSummary {
+                            campo: campo.to_string(),
+                            etichetta: etichetta.to_string(),
+                            n: row.get(1)?,
+                            mancanti: row.get(2)?,
+                            media: row.get(3)?,
+                            min: row.get(4)?,
+                            max: row.get(5)?,
+                            q1: row.get(6)?,
+                            mediana: row.get(7)?,
+                            q3: row.get(8)?,
+                        },
+                    ))
+                })
+                .map_err(|e| e.to_string())?;
+            for row in rows {
+                let (gruppo, summary) = row.map_err(|e| e.to_string())?;
+                if let Some(group) = groups.get_mut(&gruppo) {
+                    group.metriche.push(summary);
+                }
+            }
+        }
+
+        Ok(groups)
+    }
+
+    /// Gruppo senza procedure (periodi vuoti delle serie temporali)
+    fn empty_procedure_group(gruppo: &str) -> ProcedureGroupStatistics {
+        ProcedureGroupStatistics {
+            gruppo: gruppo.to_string(),
+            metriche: PROCEDURE_METRICS
+                .iter()
+                .map(|(campo, etichetta)| MetricSummary {
+                    campo: campo.to_string(),
+                    etichetta: etichetta.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Periodo successivo per i raggruppamenti temporali ("2024-03", "2024-T1", "2024")
+    fn next_period(group_by: &str, key: &str) -> Option<String> {
+        match group_by {
+            "mese" => {
+                let date = chrono::NaiveDate::parse_from_str(&format!("{}-01", key), "%Y-%m-%d").ok()?;
+                let next = date.checked_add_months(chrono::Months::new(1))?;
+                Some(next.format("%Y-%m").to_string())
+            }
+            "trimestre" => {
+                let (year, quarter) = key.split_once("-T")?;
+                let (year, quarter): (i32, u32) = (year.parse().ok()?, quarter.parse().ok()?);
+                Some(if quarter >= 4 {
+                    format!("{}-T1", year + 1)
+                } else {
+                    format!("{}-T{}", year, quarter + 1)
+                })
+            }
+            "anno" => Some((key.parse::<i32>().ok()? + 1).to_string()),
+            _ => None,
+        }
+    }
+
+    /// Statistiche aggregate delle procedure raggruppate per periodo, valvola, operatore o accesso.
+    /// I raggruppamenti temporali sono in ordine cronologico, senza buchi, con la variazione
+    /// percentuale sul periodo precedente; gli altri in ordine di numerosità.
+    pub fn get_procedure_aggregation(
+        &self,
+        group_by: &str,
+        filters: Option<ProcedureFilters>,
+    ) -> Result<ProcedureAggregation, String> {
+        let group_sql = Self::procedure_group_sql(group_by).ok_or_else(|| {
+            format!(
+                "Raggruppamento non valido: {} (ammessi: {})",
+                group_by,
+                PROCEDURE_GROUP_BY.join(", ")
+            )
+        })?;
+
+        let conn = self.conn.lock().unwrap();
+        let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![];
+        let filter_clause = Self::build_procedure_filter_clause(&filters.unwrap_or_default(), &mut params)?;
+        let params_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+
+        let complessivo = Self::aggregate_procedures(&conn, "'Totale'", &filter_clause, &params_refs)?
+            .into_values()
+            .next()
+            .unwrap_or_else(|| Self::empty_procedure_group("Totale"));
+        let mut by_key = Self::aggregate_procedures(&conn, group_sql, &filter_clause, &params_refs)?;
+
+        let is_time_series = matches!(group_by, "mese" | "trimestre" | "anno");
+        let gruppi = if is_time_series {
+            let invalid = by_key.remove("Data non valida");
+            let mut series: Vec<ProcedureGroupStatistics> = Vec::new();
+            if let (Some(first), Some(last)) = (by_key.keys().next().cloned(), by_key.keys().last().cloned()) {
+                let mut key = Some(first);
+                while let Some(current) = key {
+                    let mut group = by_key
+                        .remove(&current)
+                        .unwrap_or_else(|| Self::empty_procedure_group(&current));
+                    if let Some(previous) = series.last() {
+                        if previous.totale > 0 {
+                            group.variazione_percentuale = Some(
+                                (group.totale - previous.totale) as f64 / previous.totale as f64 * 100.0,
+                            );
+                        }
+                    }
+                    series.push(group);
+                    key = if current == last { None } else { Self::next_period(group_by, &current) };
+                }
+            }
+            series.extend(invalid);
+            series
+        } else {
+            let mut groups: Vec<ProcedureGroupStatistics> = by_key.into_values().collect();
+            groups.sort_by(|a, b| b.totale.cmp(&a.totale).then_with(|| a.gruppo.cmp(&b.gruppo)));
+            groups
+        };
+
+        Ok(ProcedureAggregation {
+            group_by: group_by.to_string(),
+            complessivo,
+            gruppi,
+        })
+    }
+
+    // ========================================================================
+    // PATIENT OPERATIONS
+    // ========================================================================
+
+    fn ensure_ambulatorio_slot_available(
+        &self,
+        conn: &Connection,
+        visit_date: Option<&str>,
+        visit_time: Option<&str>,
+        exclude_patient_id: Option<i64>,
+    ) -> Result<(), String> {
+        let date = visit_date
+            .map(str::trim)
+            .filter(|value| !value.is_empty());
+        let time = visit_time
+            .map(str::trim)
+            .filter(|value| !value.is_empty());
+
+        let (date, time) = match (date, time) {
+            (Some(date), Some(time)) => (date, time),
+            _ => return Ok(()),
+        };
+
+        let conflicts: i64 = conn
+            .query_row(
+                "SELECT COUNT(1) FROM appointments a JOIN patients p ON p.id = a.patient_id
+                 WHERE a.data = ?1 AND a.orario = ?2 AND a.stato <> 'annullato'
+                   AND (?3 IS NULL OR a.patient_id <> ?3)",
+                params![date, time, exclude_patient_id],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+
+        if conflicts > 0 {
+            return Err(format!(
+                "Lo slot {} alle {} è già occupato da un altro paziente",
+                date, time
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Visita fissata o spostata dalla scheda paziente: data e orario devono corrispondere a
+    /// uno slot libero del calendario. Se non cambiano resta valida la prenotazione esistente;
+    /// le date passate registrano una visita pregressa e non occupano slot.
+    fn ensure_ambulatorio_visit_bookable(
+        &self,
+        conn: &Connection,
+        patient: &Patient,
+        patient_id: Option<i64>,
+    ) -> Result<(), String> {
+        let date = patient
+            .ambulatorio_data_visita
+            .as_deref()
+            .map(str::trim)
+            .filter(|value| !value.is_empty());
+        let time = patient
+            .ambulatorio_orario_visita
+            .as_deref()
+            .map(str::trim)
+            .filter(|value| !value.is_empty());
+        let (date, time) = match (date, time) {
+            (Some(date), Some(time)) => (date, time),
+            _ => return Ok(()),
+        };
+        let today = chrono::Local::now().date_naive();
+        if Self::parse_calendar_date(date).is_ok_and(|d| d < today) {
+            return Ok(());
+        }
+
+        let Some(patient_id) = patient_id else {
+            return self.validate_ambulatorio_booking(conn, date, time, None).map(|_| ());
+        };
+        let (stored_date, stored_time): (Option<String>, Option<String>) = conn
+            .query_row(
+                "SELECT ambulatorio_data_visita, ambulatorio_orario_visita FROM patients WHERE id = ?1",
+                params![patient_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .map_err(|e| e.to_string())?;
+        let unchanged = stored_date.as_deref().map(str::trim) == Some(date)
+            && stored_time.as_deref().and_then(normalize_slot_time) == normalize_slot_time(time);
+        if unchanged {
+            return Ok(());
+        }
+
+        let current = Self::pending_appointment(conn, patient_id)?.and_then(|a| a.id);
+        self.validate_ambulatorio_booking(conn, date, time, current).map(|_| ())
+    }
+
+    /// Inserisce un nuovo paziente (stato iniziale: Da valutare)
+    pub fn insert_patient(&self, patient: &Patient) -> Result<i64, String> {
+        let conn = self.conn.lock().unwrap();
+
+        self.ensure_ambulatorio_visit_bookable(&conn, patient, None)?;
+        Self::ensure_cathlab_capacity(&conn, patient.data_tavi.as_deref(), None)?;
+
+        // Riga e dati collegati (fattori di rischio, piano, agenda) nella stessa transazione
+        conn.execute("BEGIN TRANSACTION", []).map_err(|e| e.to_string())?;
+        let result = Self::insert_patient_row(&conn, patient).and_then(|patient_id| {
+            Self::sync_patient_details(&conn, patient_id, patient).map(|_| patient_id)
+        });
+        match result {
+            Ok(patient_id) => {
+                conn.execute("COMMIT", []).map_err(|e| e.to_string())?;
+                Ok(patient_id)
+            }
+            Err(e) => {
+                conn.execute("ROLLBACK", []).ok();
+                Err(e)
+            }
+        }
+    }
+
+    /// Riga del paziente e stato iniziale, senza gestione della transazione
+    fn insert_patient_row(conn: &Connection, patient: &Patient) -> Result<i64, String> {
+        let fattori_json = patient.ambulatorio_fattori.as_ref().and_then(|f| serde_json::to_string(f).ok());
 
         let result = conn.execute(
             "INSERT INTO patients (
                 nome, cognome, data_nascita, luogo_nascita, codice_fiscale, telefono, email, provenienza, sesso, altezza, peso, note,
                 ambulatorio_fattori, anamnesi_cardiologica, apr, visita_odierna, conclusioni, medico_titolo, medico_nome, medico_specializzando_titolo, medico_specializzando_nome,
                 procedurale_allergia_mdc, procedurale_preparazione_mdc, procedurale_creatinina, procedurale_egfr, procedurale_hb, procedurale_altro, data_tavi,
-                procedurale_ecg_ritmo_sinusale, procedurale_ecg_fa, procedurale_ecg_bbs, procedurale_ecg_bbd, procedurale_ecg_eas, procedurale_ecg_bav_primo, procedurale_ecg_ritmo_stimolato,
                 procedurale_anestesia, procedurale_coronarografia, procedurale_coronarografia_note, procedurale_pacemaker, procedurale_pacemaker_note,
                 procedurale_accesso_principale_fem, procedurale_accesso_principale_altro, procedurale_accesso_protezione, procedurale_accesso_protezione_note,
                 procedurale_altri_accessi, procedurale_diametro_pallone_femorale, procedurale_guida_safari, procedurale_protezione_osti,
                 procedurale_valvuloplastica, procedurale_valvuloplastica_note, procedurale_bioprotesi_modello, procedurale_bioprotesi_dimensione,
                 priority, ambulatorio_data_visita, ambulatorio_orario_visita
             )
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20,
-                     ?21, ?22, ?23, ?24, ?25, ?26, ?27, ?28, ?29, ?30, ?31, ?32, ?33,
-                     ?34, ?35, ?36, ?37, ?38, ?39, ?40, ?41, ?42, ?43, ?44, ?45, ?46, ?47, ?48, ?49, ?50, ?51, ?52, ?53, ?54, ?55)",
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20,
+                     ?21, ?22, ?23, ?24, ?25, ?26, ?27, ?28, ?29, ?30, ?31, ?32, ?33,
+                     ?34, ?35, ?36, ?37, ?38, ?39, ?40, ?41, ?42, ?43, ?44, ?45, ?46, ?47, ?48)",
+            params![
+                patient.nome,
+                patient.cognome,
+                patient.data_nascita,
+                patient.luogo_nascita,
+                patient.codice_fiscale,
+                patient.telefono,
+                patient.email,
+                patient.provenienza,
+                patient.sesso,
+                patient.altezza,
+                patient.peso,
+                patient.note,
+                fattori_json,
+                patient.anamnesi_cardiologica,
+                patient.apr,
+                patient.visita_odierna,
+                patient.conclusioni,
+                patient.medico_titolo,
+                patient.medico_nome,
+                patient.medico_specializzando_titolo,
+                patient.medico_specializzando_nome,
+                patient.procedurale_allergia_mdc,
+                patient.procedurale_preparazione_mdc,
+                patient.procedurale_creatinina,
+                patient.procedurale_egfr,
+                patient.procedurale_hb,
+                patient.procedurale_altro,
+                patient.data_tavi,
+                patient.procedurale_anestesia,
+                patient.procedurale_coronarografia,
+                patient.procedurale_coronarografia_note,
+                patient.procedurale_pacemaker,
+                patient.procedurale_pacemaker_note,
+                patient.procedurale_accesso_principale_fem,
+                patient.procedurale_accesso_principale_altro,
+                patient.procedurale_accesso_protezione,
+                patient.procedurale_accesso_protezione_note,
+                patient.procedurale_altri_accessi,
+                patient.procedurale_diametro_pallone_femorale,
+                patient.procedurale_guida_safari,
+                patient.procedurale_protezione_osti,
+                patient.procedurale_valvuloplastica,
+                patient.procedurale_valvuloplastica_note,
+                patient.procedurale_bioprotesi_modello,
+                patient.procedurale_bioprotesi_dimensione,
+                patient.priority.map(|p| p.as_str()),
+                patient.ambulatorio_data_visita,
+                patient.ambulatorio_orario_visita,
+            ],
+        );
+
+        result.map_err(|e| e.to_string())?;
+        let patient_id = conn.last_insert_rowid();
+
+        // Inserisci in patients_da_valutare (stato iniziale)
+        conn.execute(
+            "INSERT INTO patients_da_valutare (patient_id) VALUES (?1)",
+            params![patient_id],
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(patient_id)
+    }
+
+    /// Allinea fattori di rischio, ECG, piano procedurale, appuntamento e seduta di sala ai campi del paziente
+    fn sync_patient_details(conn: &Connection, patient_id: i64, patient: &Patient) -> Result<(), String> {
+        if let Some(fattori) = &patient.ambulatorio_fattori {
+            Self::sync_risk_factors_from_labels(conn, patient_id, fattori)
+                .map_err(|e| e.to_string())?;
+        }
+        Self::record_ecg_from_legacy_flags(conn, patient_id, patient)
+            .and_then(|_| Self::sync_legacy_ecg_flags(conn, patient_id))
+            .map_err(|e| e.to_string())?;
+        Self::snapshot_plan_from_patient(conn, patient_id)
+            .map_err(|e| e.to_string())?;
+        Self::sync_appointment_from_patient(conn, patient_id, patient)?;
+        Self::sync_cathlab_case_from_patient(conn, patient_id, patient.data_tavi.as_deref())?;
+        Ok(())
+    }
+
+    /// Aggiorna anagrafica paziente (non cambia stato)
+    pub fn update_patient(&self, patient: &Patient) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+        let id = patient.id.ok_or("Patient ID is required for update")?;
+
+        Self::with_immediate_transaction(&conn, || {
+            self.ensure_ambulatorio_visit_bookable(&conn, patient, Some(id))?;
+            Self::ensure_cathlab_capacity(&conn, patient.data_tavi.as_deref(), Some(id))?;
+            Self::update_patient_row(&conn, patient)?;
+            Self::sync_patient_details(&conn, id, patient)
+        })
+    }
+
+    /// Aggiorna la riga del paziente senza toccare stato e tabelle collegate, salvo i
+    /// follow-up che seguono un cambio di data TAVI
+    fn update_patient_row(conn: &Connection, patient: &Patient) -> Result<(), String> {
+        let id = patient.id.ok_or("Patient ID is required for update")?;
+        let previous_tavi: Option<String> = conn
+            .query_row("SELECT data_tavi FROM patients WHERE id = ?1", params![id], |row| row.get(0))
+            .map_err(|e| e.to_string())?;
+        let fattori_json = patient.ambulatorio_fattori.as_ref().and_then(|f| serde_json::to_string(f).ok());
+
+        conn.execute(
+            "UPDATE patients SET
+                nome = ?1, cognome = ?2, data_nascita = ?3,
+                luogo_nascita = ?4, codice_fiscale = ?5, telefono = ?6,
+                email = ?7, provenienza = ?8, sesso = ?9, altezza = ?10, peso = ?11,
+                note = ?12,
+                ambulatorio_fattori = ?13, anamnesi_cardiologica = ?14, apr = ?15,
+                visita_odierna = ?16, conclusioni = ?17, medico_titolo = ?18, medico_nome = ?19,
+                medico_specializzando_titolo = ?20, medico_specializzando_nome = ?21,
+                procedurale_allergia_mdc = ?22, procedurale_preparazione_mdc = ?23, procedurale_creatinina = ?24,
+                procedurale_egfr = ?25, procedurale_hb = ?26, procedurale_altro = ?27, data_tavi = ?28,
+                procedurale_anestesia = ?29, procedurale_coronarografia = ?30, procedurale_coronarografia_note = ?31,
+                procedurale_pacemaker = ?32, procedurale_pacemaker_note = ?33,
+                procedurale_accesso_principale_fem = ?34, procedurale_accesso_principale_altro = ?35,
+                procedurale_accesso_protezione = ?36, procedurale_accesso_protezione_note = ?37,
+                procedurale_altri_accessi = ?38, procedurale_diametro_pallone_femorale = ?39,
+                procedurale_guida_safari = ?40, procedurale_protezione_osti = ?41,
+                procedurale_valvuloplastica = ?42, procedurale_valvuloplastica_note = ?43,
+                procedurale_bioprotesi_modello = ?44, procedurale_bioprotesi_dimensione = ?45,
+                priority = ?46,
+                ambulatorio_data_visita = ?47,
+                ambulatorio_orario_visita = ?48,
+                updated_at = CURRENT_TIMESTAMP
+             WHERE id = ?49",
+            params![
+                patient.nome,
+                patient.cognome,
+                patient.data_nascita,
+                patient.luogo_nascita,
+                patient.codice_fiscale,
+                patient.telefono,
+                patient.email,
+                patient.provenienza,
+                patient.sesso,
+                patient.altezza,
+                patient.peso,
+                patient.note,
+                fattori_json,
+                patient.anamnesi_cardiologica,
+                patient.apr,
+                patient.visita_odierna,
+                patient.conclusioni,
+                patient.medico_titolo,
+                patient.medico_nome,
+                patient.medico_specializzando_titolo,
+                patient.medico_specializzando_nome,
+                patient.procedurale_allergia_mdc,
+                patient.procedurale_preparazione_mdc,
+                patient.procedurale_creatinina,
+                patient.procedurale_egfr,
+                patient.procedurale_hb,
+                patient.procedurale_altro,
+                patient.data_tavi,
+                patient.procedurale_anestesia,
+                patient.procedurale_coronarografia,
+                patient.procedurale_coronarografia_note,
+                patient.procedurale_pacemaker,
+                patient.procedurale_pacemaker_note,
+                patient.procedurale_accesso_principale_fem,
+                patient.procedurale_accesso_principale_altro,
+                patient.procedurale_accesso_protezione,
+                patient.procedurale_accesso_protezione_note,
+                patient.procedurale_altri_accessi,
+                patient.procedurale_diametro_pallone_femorale,
+                patient.procedurale_guida_safari,
+                patient.procedurale_protezione_osti,
+                patient.procedurale_valvuloplastica,
+                patient.procedurale_valvuloplastica_note,
+                patient.procedurale_bioprotesi_modello,
+                patient.procedurale_bioprotesi_dimensione,
+                patient.priority.map(|p| p.as_str()),
+                patient.ambulatorio_data_visita,
+                patient.ambulatorio_orario_visita,
+                id,
+            ],
+        ).map_err(|e| e.to_string())?;
+
+        let tavi_changed = previous_tavi.as_deref().map(str::trim) != patient.data_tavi.as_deref().map(str::trim);
+        if tavi_changed {
+            Self::schedule_follow_ups(conn, Some(id), true).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Elimina paziente (CASCADE rimuove automaticamente da tabelle stato)
+    pub fn delete_patient(&self, id: i64) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM patients WHERE id = ?1", params![id])
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /// Query base: tutti i pazienti con lo stato corrente e la data di ingresso nello stato
+    const PATIENTS_WITH_STATUS_SQL: &'static str = "SELECT p.*, 'Da valutare' as status, dv.created_at as status_created_at
+             FROM patients p INNER JOIN patients_da_valutare dv ON p.id = dv.patient_id
+             UNION ALL
+             SELECT p.*, 'In corso di accertamenti' as status, ae.created_at as status_created_at
+             FROM patients p INNER JOIN patients_in_attesa_esami ae ON p.id = ae.patient_id
+             UNION ALL
+             SELECT p.*, 'In attesa di TAVI' as status, ai.created_at as status_created_at
+             FROM patients p INNER JOIN patients_in_attesa_intervento ai ON p.id = ai.patient_id
+             UNION ALL
+             SELECT p.*, 'Non candidabile a TAVI' as status, nc.created_at as status_created_at
+             FROM patients p INNER JOIN patients_non_candidabile nc ON p.id = nc.patient_id
+             UNION ALL
+             SELECT p.*, 'TAVI eseguita' as status, c.created_at as status_created_at
+             FROM patients p INNER JOIN patients_completato c ON p.id = c.patient_id";
+
+    /// Costruisce la clausola WHERE della ricerca pazienti, accodando i parametri
+    fn build_patient_filter_clause(
+        conn: &Connection,
+        filters: &PatientFilters,
+        params: &mut Vec<Box<dyn rusqlite::ToSql>>,
+    ) -> Result<String, String> {
+        let non_empty = |value: &Option<String>| {
+            value.as_deref().map(str::trim).filter(|v| !v.is_empty()).map(str::to_string)
+        };
+        let mut conditions: Vec<String> = Vec::new();
+        // Nelle condizioni "?X" indica il parametro appena accodato (anche se ripetuto)
+        let mut push = |condition: &str, value: Box<dyn rusqlite::ToSql>| {
+            params.push(value);
+            conditions.push(condition.replace("?X", &format!("?{}", params.len())));
+        };
+
+        if let Some(search) = non_empty(&filters.search_query) {
+            push(
+                "(nome LIKE ?X OR cognome LIKE ?X OR codice_fiscale LIKE ?X)",
+                Box::new(format!("%{}%", search)),
+            );
+        }
+        if let Some(status) = non_empty(&filters.status).filter(|s| s != "all") {
+            push("status = ?X", Box::new(status));
+        }
+        if let Some(priority) = non_empty(&filters.priority) {
+            push("COALESCE(priority, 'media') = ?X", Box::new(priority));
+        }
+        if let Some(provenienza) = non_empty(&filters.provenienza) {
+            push("provenienza LIKE ?X", Box::new(format!("%{}%", provenienza)));
+        }
+        if let Some(sesso) = non_empty(&filters.sesso) {
+            push("UPPER(sesso) = UPPER(?X)", Box::new(sesso));
+        }
+        // Età in anni compiuti alla data odierna
+        let age_sql = "(CAST(strftime('%Y', 'now') AS INTEGER) - CAST(strftime('%Y', data_nascita) AS INTEGER)
+                        - (strftime('%m-%d', 'now') < strftime('%m-%d', data_nascita)))";
+        if let Some(age_min) = filters.age_min {
+            push(&format!("{} >= ?X", age_sql), Box::new(age_min));
+        }
+        if let Some(age_max) = filters.age_max {
+            push(&format!("{} <= ?X", age_sql), Box::new(age_max));
+        }
+        if let Some(from) = non_empty(&filters.data_tavi_from) {
+            push("data_tavi >= ?X", Box::new(from));
+        }
+        if let Some(to) = non_empty(&filters.data_tavi_to) {
+            push("data_tavi <= ?X", Box::new(to));
+        }
+        if let Some(from) = non_empty(&filters.visit_date_from) {
+            push("ambulatorio_data_visita >= ?X", Box::new(from));
+        }
+        if let Some(to) = non_empty(&filters.visit_date_to) {
+            push("ambulatorio_data_visita <= ?X", Box::new(to));
+        }
+        if let Some(medico) = non_empty(&filters.medico) {
+            push(
+                "(medico_nome LIKE ?X OR medico_specializzando_nome LIKE ?X)",
+                Box::new(format!("%{}%", medico)),
+            );
+        }
+        if let Some(egfr) = filters.egfr_below {
+            push(
+                "(TRIM(COALESCE(procedurale_egfr, '')) != ''
+                  AND CAST(REPLACE(procedurale_egfr, ',', '.') AS REAL) < ?X)",
+                Box::new(egfr),
+            );
+        }
+        if let Some(valve) = non_empty(&filters.valve_model) {
+            // I pazienti salvano il codice del catalogo: si accettano anche nome commerciale e alias
+            let catalogue = Self::load_valve_models(conn).map_err(|e| e.to_string())?;
+            match catalogue.iter().find(|m| m.matches(&valve)) {
+                Some(model) => push("procedurale_bioprotesi_modello = ?X", Box::new(model.codice.clone())),
+                None => push("procedurale_bioprotesi_modello LIKE ?X", Box::new(format!("%{}%", valve))),
+            }
+        }
+        if let Some(text) = non_empty(&filters.text_query) {
+            push("(note LIKE ?X OR conclusioni LIKE ?X)", Box::new(format!("%{}%", text)));
+        }
+        let days_in_status = "(julianday('now') - julianday(status_created_at))";
+        if let Some(days) = filters.min_days_in_status {
+            push(&format!("{} >= ?X", days_in_status), Box::new(days));
+        }
+        if let Some(days) = filters.max_days_in_status {
+            push(&format!("{} <= ?X", days_in_status), Box::new(days));
+        }
+
+        if conditions.is_empty() {
+            Ok(String::new())
+        } else {
+            Ok(format!(" WHERE {}", conditions.join(" AND ")))
+        }
+    }
+
+    /// Clausola ORDER BY dalla whitelist dei campi ordinabili (default: ingresso nello stato, decrescente)
+    fn build_patient_order_clause(filters: &PatientFilters) -> String {
+        let field = filters
+            .sort_by
+            .as_deref()
+            .filter(|f| PATIENT_SORT_FIELDS.contains(f))
+            .unwrap_or("status_created_at");
+        let direction = match filters.sort_dir.as_deref() {
+            Some(dir) if dir.eq_ignore_ascii_case("asc") => "ASC",
+            _ => "DESC",
+        };
+        let expression = match field {
+            "cognome" => "cognome COLLATE NOCASE {dir}, nome COLLATE NOCASE {dir}".to_string(),
+            "priority" => "CASE COALESCE(priority, 'media') WHEN 'alta' THEN 3 WHEN 'media' THEN 2 ELSE 1 END {dir}, status_created_at ASC".to_string(),
+            // Date mancanti sempre in fondo
+            "data_tavi" | "ambulatorio_data_visita" => format!("NULLIF({}, '') IS NULL, {} {{dir}}", field, field),
+            other => format!("{} {{dir}}", other),
+        };
+        format!(" ORDER BY {}, id DESC", expression.replace("{dir}", direction))
+    }
+
+    /// Ottieni tutti i pazienti con status
+    pub fn get_all_patients_with_status(&self, filters: Option<PatientFilters>) -> Result<Vec<PatientWithStatus>, String> {
+        let conn = self.conn.lock().unwrap();
+        self.ensure_status_tables(&conn).map_err(|e| e.to_string())?;
+        self.auto_mark_tavi_completed(&conn)?;
+
+        let filters = filters.unwrap_or_default();
+        let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![];
+        let where_clause = Self::build_patient_filter_clause(&conn, &filters, &mut params)?;
+        let mut query = format!(
+            "SELECT * FROM ({}){}{}",
+            Self::PATIENTS_WITH_STATUS_SQL,
+            where_clause,
+            Self::build_patient_order_clause(&filters)
+        );
+        if let Some(limit) = filters.limit {
+            query.push_str(&format!(" LIMIT {} OFFSET {}", limit.max(0), filters.offset.unwrap_or(0).max(0)));
+        }
+
+        let params_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+
+        let mut stmt = conn.prepare(&query).map_err(|e| e.to_string())?;
+        let rows = stmt.query_map(params_refs.as_slice(), |row| {
+            let fattori: Option<Vec<String>> = match row.get::<_, Option<String>>("ambulatorio_fattori") {
+                Ok(Some(json)) => serde_json::from_str(&json).ok(),
+                Ok(None) => None,
+                Err(_) => None,
+            };
+            Ok(PatientWithStatus {
+                patient: Patient {
+                    id: Some(row.get("id")?),
+                    created_at: row.get("created_at").ok(),
+                    updated_at: row.get("updated_at").ok(),
+                    nome: row.get("nome")?,
+                    cognome: row.get("cognome")?,
+                    data_nascita: row.get("data_nascita")?,
+                    luogo_nascita: row.get("luogo_nascita").ok(),
+                    codice_fiscale: row.get("codice_fiscale").ok(),
+                    telefono: row.get("telefono").ok(),
+                    email: row.get("email").ok(),
+                    provenienza: row.get("provenienza").ok(),
+                    sesso: row.get("sesso").ok(),
+                    priority: row
+                        .get::<_, Option<String>>("priority")
+                        .ok()
+                        .flatten()
+                        .and_then(|v| Priority::parse(&v)),
+                    altezza: row.get("altezza").ok(),
+                    peso: row.get("peso").ok(),
+                    note: row.get("note").ok(),
+                    ambulatorio_fattori: fattori,
+                    anamnesi_cardiologica: row.get("anamnesi_cardiologica").ok(),
+                    apr: row.get("apr").ok(),
+                    visita_odierna: row.get("visita_odierna").ok(),
+                    conclusioni: row.get("conclusioni").ok(),
+                    medico_titolo: row.get("medico_titolo").ok(),
+                    medico_nome: row.get("medico_nome").ok(),
+                    medico_specializzando_titolo: row.get("medico_specializzando_titolo").ok(),
+                    medico_specializzando_nome: row.get("medico_specializzando_nome").ok(),
+                    ambulatorio_data_visita: row.get("ambulatorio_data_visita").ok(),
+                    ambulatorio_orario_visita: row.get("ambulatorio_orario_visita").ok(),
+                    procedurale_allergia_mdc: row.get("procedurale_allergia_mdc").ok(),
+                    procedurale_preparazione_mdc: row.get("procedurale_preparazione_mdc").ok(),
+                    procedurale_creatinina: row.get("procedurale_creatinina").ok(),
+                    procedurale_egfr: row.get("procedurale_egfr").ok(),
+                    procedurale_hb: row.get("procedurale_hb").ok(),
+                    procedurale_altro: row.get("procedurale_altro").ok(),
+                    data_tavi: row.get("data_tavi").ok(),
+                    procedurale_ecg_ritmo_sinusale: row.get("procedurale_ecg_ritmo_sinusale").ok(),
+                    procedurale_ecg_fa: row.get("procedurale_ecg_fa").ok(),
+                    procedurale_ecg_bbs: row.get("procedurale_ecg_bbs").ok(),
+                    procedurale_ecg_bbd: row.get("procedurale_ecg_bbd").ok(),
+                    procedurale_ecg_eas: row.get("procedurale_ecg_eas").ok(),
+                    procedurale_ecg_bav_primo: row.get("procedurale_ecg_bav_primo").ok(),
+                    procedurale_ecg_ritmo_stimolato: row.get("procedurale_ecg_ritmo_stimolato").ok(),
+                    procedurale_anestesia: row.get("procedurale_anestesia").ok(),
+                    procedurale_coronarografia: row.get("procedurale_coronarografia").ok(),
+                    procedurale_coronarografia_note: row.get("procedurale_coronarografia_note").ok(),
+                    procedurale_pacemaker: row.get("procedurale_pacemaker").ok(),
+                    procedurale_pacemaker_note: row.get("procedurale_pacemaker_note").ok(),
+                    procedurale_accesso_principale_fem: row.get("procedurale_accesso_principale_fem").ok(),
+                    procedurale_accesso_principale_altro: row.get("procedurale_accesso_principale_altro").ok(),
+                    procedurale_accesso_protezione: row.get("procedurale_accesso_protezione").ok(),
+                    procedurale_accesso_protezione_note: row.get("procedurale_accesso_protezione_note").ok(),
+                    procedurale_altri_accessi: row.get("procedurale_altri_accessi").ok(),
+                    procedurale_diametro_pallone_femorale: row.get("procedurale_diametro_pallone_femorale").ok(),
+                    procedurale_guida_safari: row.get("procedurale_guida_safari").ok(),
+                    procedurale_protezione_osti: row.get("procedurale_protezione_osti").ok(),
+                    procedurale_valvuloplastica: row.get("procedurale_valvuloplastica").ok(),
+                    procedurale_valvuloplastica_note: row.get("procedurale_valvuloplastica_note").ok(),
+                    procedurale_bioprotesi_modello: row.get("procedurale_bioprotesi_modello").ok(),
+                    procedurale_bioprotesi_dimensione: row.get("procedurale_bioprotesi_dimensione").ok(),
+                },
+                status: row.get("status")?,
+                status_created_at: row.get("status_created_at")?,
+            })
+        }).map_err(|e| e.to_string())?;
+
+        let patients: Result<Vec<_>, _> = rows.collect();
+        patients.map_err(|e| e.to_string())
+    }
+
+    /// Storico dei passaggi di stato, in ordine cronologico per paziente.
+    /// Con `patient_ids` si limita ai pazienti indicati (es. quelli di una lista filtrata).
+    pub fn get_status_history(&self, patient_ids: Option<&[i64]>) -> Result<Vec<StatusHistoryEntry>, String> {
+        let conn = self.conn.lock().unwrap();
+        self.ensure_status_tables(&conn).map_err(|e| e.to_string())?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT h.patient_id, p.nome, p.cognome, h.stato, h.changed_at
+                 FROM patient_status_history h JOIN patients p ON p.id = h.patient_id
+                 ORDER BY p.cognome, p.nome, h.patient_id, h.changed_at, h.id",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok(StatusHistoryEntry {
+                    patient_id: row.get(0)?,
+                    nome: row.get(1)?,
+                    cognome: row.get(2)?,
+                    stato: row.get(3)?,
+                    changed_at: row.get::<_, Option<String>>(4)?.unwrap_or_default(),
+                })
+            })
+            .map_err(|e| e.to_string())?;
+
+        let wanted: Option<std::collections::HashSet<i64>> = patient_ids.map(|ids| ids.iter().copied().collect());
+        let mut history = Vec::new();
+        for row in rows {
+            let entry = row.map_err(|e| e.to_string())?;
+            if wanted.as_ref().is_none_or(|ids| ids.contains(&entry.patient_id)) {
+                history.push(entry);
+            }
+        }
+        Ok(history)
+    }
+
+    /// Numero di pazienti che soddisfano i filtri (paginazione esclusa)
+    fn count_patients_matching(conn: &Connection, filters: &PatientFilters) -> Result<i64, String> {
+        let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![];
+        let where_clause = Self::build_patient_filter_clause(conn, filters, &mut params)?;
+        let params_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+        conn.query_row(
+            &format!(
+                "SELECT COUNT(*) FROM ({}){}",
+                Self::PATIENTS_WITH_STATUS_SQL,
+                where_clause
+            ),
+            params_refs.as_slice(),
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())
+    }
+
+    /// Ricerca avanzata con paginazione: restituisce la pagina richiesta e il totale dei risultati
+    pub fn search_patients(&self, filters: PatientFilters) -> Result<PatientSearchResult, String> {
+        let patients = self.get_all_patients_with_status(Some(filters.clone()))?;
+
+        let conn = self.conn.lock().unwrap();
+        let total = Self::count_patients_matching(&conn, &filters)?;
+
+        Ok(PatientSearchResult {
+            patients,
+            total,
+            limit: filters.limit,
+            offset: filters.offset.unwrap_or(0).max(0),
+        })
+    }
+
+    /// Ottieni singolo paziente per ID
+    pub fn get_patient_by_id(&self, id: i64) -> Result<Option<PatientWithStatus>, String> {
+        let all_patients = self.get_all_patients_with_status(None)?;
+        Ok(all_patients.into_iter().find(|p| p.patient.id == Some(id)))
+    }
+
+    /// Cambia stato paziente
+    pub fn change_patient_status(&self, patient_id: i64, new_status: PatientStatus) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+        self.ensure_status_tables(&conn).map_err(|e| e.to_string())?;
+
+        conn.execute("BEGIN TRANSACTION", []).map_err(|e| e.to_string())?;
+
+        // Rimuovi da tutte le tabelle di stato
+        let tables = vec![
+            "patients_da_valutare",
+            "patients_in_attesa_esami",
+            "patients_in_attesa_intervento",
+            "patients_non_candidabile",
+            "patients_completato",
+        ];
+
+        for table in tables {
+            match conn.execute(
+                &format!("DELETE FROM {} WHERE patient_id = ?1", table),
+                params![patient_id],
+            ) {
+                Ok(_) => {},
+                Err(e) => {
+                    conn.execute("ROLLBACK", []).ok();
+                    return Err(e.to_string());
+                }
+            }
+        }
+
+        // Inserisci nella nuova tabella
+        let new_table = new_status.to_table_name();
+        match conn
+            .execute(&format!("INSERT INTO {} (patient_id) VALUES (?1)", new_table), params![patient_id])
+            .and_then(|_| match new_status {
+                PatientStatus::Completato => Self::schedule_follow_ups(&conn, Some(patient_id), false),
+                _ => Ok(()),
+            }) {
+            Ok(_) => {
+                conn.execute("COMMIT", []).map_err(|e| e.to_string())?;
+                Ok(())
+            }
+            Err(e) => {
+                conn.execute("ROLLBACK", []).ok();
+                Err(e.to_string())
+            }
+        }
+    }
+
+    /// Ottieni contatori per stato
+    pub fn get_patient_status_counts(&self) -> Result<Vec<PatientStatusCount>, String> {
+        let conn = self.conn.lock().unwrap();
+        self.ensure_status_tables(&conn).map_err(|e| e.to_string())?;
+        self.auto_mark_tavi_completed(&conn)?;
+
+        let statuses = vec![
+            ("Da valutare", "patients_da_valutare"),
+            ("In corso di accertamenti", "patients_in_attesa_esami"),
+            ("In attesa di TAVI", "patients_in_attesa_intervento"),
+            ("Non candidabile a TAVI", "patients_non_candidabile"),
+            ("TAVI eseguita", "patients_completato"),
+        ];
+
+        let mut counts = Vec::new();
+
+        for (label, table) in statuses {
+            let count: i32 = conn.query_row(
+                &format!("SELECT COUNT(*) FROM {}", table),
+                [],
+                |row| row.get(0),
+            ).map_err(|e| e.to_string())?;
+
+            counts.push(PatientStatusCount {
+                status: label.to_string(),
+                count,
+            });
+        }
+
+        Ok(counts)
+    }
+
+    /// Ottieni pazienti per stato specifico
+    pub fn get_patients_by_status(&self, status: &str) -> Result<Vec<PatientWithStatus>, String> {
+        let filters = PatientFilters {
+            status: Some(status.to_string()),
+            ..Default::default()
+        };
+        self.get_all_patients_with_status(Some(filters))
+    }
+
+    // ========================================================================
+    // CT PLANNING OPERATIONS
+    // ========================================================================
+
+    fn map_ct_planning_row(row: &rusqlite::Row) -> SqlResult<CtPlanning> {
+        Ok(CtPlanning {
+            id: Some(row.get("id")?),
+            patient_id: row.get("patient_id")?,
+            created_at: row.get("created_at").ok(),
+            updated_at: row.get("updated_at").ok(),
+            data_tc: row.get("data_tc").ok(),
+            anulus_perimetro: row.get("anulus_perimetro").ok(),
+            anulus_area: row.get("anulus_area").ok(),
+            anulus_diametro_min: row.get("anulus_diametro_min").ok(),
+            anulus_diametro_max: row.get("anulus_diametro_max").ok(),
+            altezza_coronaria_sx: row.get("altezza_coronaria_sx").ok(),
+            altezza_coronaria_dx: row.get("altezza_coronaria_dx").ok(),
+            seno_valsalva_diametro: row.get("seno_valsalva_diametro").ok(),
+            seno_valsalva_altezza: row.get("seno_valsalva_altezza").ok(),
+            giunzione_st_diametro: row.get("giunzione_st_diametro").ok(),
+            giunzione_st_altezza: row.get("giunzione_st_altezza").ok(),
+            calcium_score: row.get("calcium_score").ok(),
+            iliaca_dx_mld: row.get("iliaca_dx_mld").ok(),
+            iliaca_sx_mld: row.get("iliaca_sx_mld").ok(),
+            femorale_dx_mld: row.get("femorale_dx_mld").ok(),
+            femorale_sx_mld: row.get("femorale_sx_mld").ok(),
+            note: row.get("note").ok(),
+            diametro_medio: None,
+            diametro_da_perimetro: None,
+            diametro_da_area: None,
+            indice_eccentricita: None,
+        }
+        .with_derived())
+    }
+
+    fn map_valve_sizing_chart_row(row: &rusqlite::Row) -> SqlResult<ValveSizingChart> {
+        Ok(ValveSizingChart {
+            id: Some(row.get("id")?),
+            modello_valvola: row.get("modello_valvola")?,
+            dimensione: row.get("dimensione")?,
+            diametro_nominale: row.get("diametro_nominale")?,
+            perimetro_min: row.get("perimetro_min").ok(),
+            perimetro_max: row.get("perimetro_max").ok(),
+            area_min: row.get("area_min").ok(),
+            area_max: row.get("area_max").ok(),
+        })
+    }
+
+    /// Inserisce una nuova pianificazione TC
+    pub fn insert_ct_planning(&self, planning: &CtPlanning) -> Result<i64, String> {
+        let conn = self.conn.lock().unwrap();
+
+        conn.execute(
+            "INSERT INTO ct_plannings (
+                patient_id, data_tc,
+                anulus_perimetro, anulus_area, anulus_diametro_min, anulus_diametro_max,
+                altezza_coronaria_sx, altezza_coronaria_dx,
+                seno_valsalva_diametro, seno_valsalva_altezza,
+                giunzione_st_diametro, giunzione_st_altezza, calcium_score,
+                iliaca_dx_mld, iliaca_sx_mld, femorale_dx_mld, femorale_sx_mld, note
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)",
+            params![
+                planning.patient_id, planning.data_tc,
+                planning.anulus_perimetro, planning.anulus_area,
+                planning.anulus_diametro_min, planning.anulus_diametro_max,
+                planning.altezza_coronaria_sx, planning.altezza_coronaria_dx,
+                planning.seno_valsalva_diametro, planning.seno_valsalva_altezza,
+                planning.giunzione_st_diametro, planning.giunzione_st_altezza, planning.calcium_score,
+                planning.iliaca_dx_mld, planning.iliaca_sx_mld,
+                planning.femorale_dx_mld, planning.femorale_sx_mld, planning.note
+            ],
+        ).map_err(|e| e.to_string())?;
+
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Aggiorna una pianificazione TC esistente
+    pub fn update_ct_planning(&self, planning: &CtPlanning) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+
+        let id = planning.id.ok_or("CT planning ID is required for update")?;
+
+        conn.execute(
+            "UPDATE ct_plannings SET
+                patient_id = ?1, data_tc = ?2,
+                anulus_perimetro = ?3, anulus_area = ?4, anulus_diametro_min = ?5, anulus_diametro_max = ?6,
+                altezza_coronaria_sx = ?7, altezza_coronaria_dx = ?8,
+                seno_valsalva_diametro = ?9, seno_valsalva_altezza = ?10,
+                giunzione_st_diametro = ?11, giunzione_st_altezza = ?12, calcium_score = ?13,
+                iliaca_dx_mld = ?14, iliaca_sx_mld = ?15, femorale_dx_mld = ?16, femorale_sx_mld = ?17,
+                note = ?18,
+                updated_at = CURRENT_TIMESTAMP
+            WHERE id = ?19",
+            params![
+                planning.patient_id, planning.data_tc,
+                planning.anulus_perimetro, planning.anulus_area,
+                planning.anulus_diametro_min, planning.anulus_diametro_max,
+                planning.altezza_coronaria_sx, planning.altezza_coronaria_dx,
+                planning.seno_valsalva_diametro, planning.seno_valsalva_altezza,
+                planning.giunzione_st_diametro, planning.giunzione_st_altezza, planning.calcium_score,
+                planning.iliaca_dx_mld, planning.iliaca_sx_mld,
+                planning.femorale_dx_mld, planning.femorale_sx_mld, planning.note,
+                id
+            ],
+        ).map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+
+    /// Elimina una pianificazione TC
+    pub fn delete_ct_planning(&self, id: i64) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM ct_plannings WHERE id = ?1", params![id])
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /// Ottieni le pianificazioni TC di un paziente (più recenti prima)
+    pub fn get_ct_plannings_by_patient(&self, patient_id: i64) -> Result<Vec<CtPlanning>, String> {
+        let conn = self.conn.lock().unwrap();
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT * FROM ct_plannings WHERE patient_id = ?1
+                 ORDER BY COALESCE(data_tc, created_at) DESC, id DESC",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![patient_id], Self::map_ct_planning_row)
+            .map_err(|e| e.to_string())?;
+
+        let plannings: Result<Vec<_>, _> = rows.collect();
+        plannings.map_err(|e| e.to_string())
+    }
+
+    /// Ottieni una pianificazione TC per ID
+    pub fn get_ct_planning_by_id(&self, id: i64) -> Result<Option<CtPlanning>, String> {
+        let conn = self.conn.lock().unwrap();
+
+        let result = conn.query_row(
+            "SELECT * FROM ct_plannings WHERE id = ?1",
+            params![id],
+            Self::map_ct_planning_row,
+        );
+
+        match result {
+            Ok(planning) => Ok(Some(planning)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// Ottieni le tabelle di sizing (tutte o di un singolo modello)
+    pub fn get_valve_sizing_charts(&self, modello_valvola: Option<&str>) -> Result<Vec<ValveSizingChart>, String> {
+        let conn = self.conn.lock().unwrap();
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT * FROM valve_sizing_charts
+                 WHERE ?1 IS NULL OR modello_valvola = ?1
+                 ORDER BY modello_valvola, diametro_nominale",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![modello_valvola], Self::map_valve_sizing_chart_row)
+            .map_err(|e| e.to_string())?;
+
+        let charts: Result<Vec<_>, _> = rows.collect();
+        charts.map_err(|e| e.to_string())
+    }
+
+    /// Inserisce o aggiorna una riga della tabella di sizing
+    pub fn save_valve_sizing_chart(&self, chart: &ValveSizingChart) -> Result<i64, String> {
+        let conn = self.conn.lock().unwrap();
+
+        if let Some(id) = chart.id {
+            conn.execute(
+                "UPDATE valve_sizing_charts SET
+                    modello_valvola = ?1, dimensione = ?2, diametro_nominale = ?3,
+                    perimetro_min = ?4, perimetro_max = ?5, area_min = ?6, area_max = ?7
+                 WHERE id = ?8",
+                params![
+                    chart.modello_valvola, chart.dimensione, chart.diametro_nominale,
+                    chart.perimetro_min, chart.perimetro_max, chart.area_min, chart.area_max,
+                    id
+                ],
+            ).map_err(|e| e.to_string())?;
+            return Ok(id);
+        }
+
+        conn.execute(
+            "INSERT INTO valve_sizing_charts (
+                modello_valvola, dimensione, diametro_nominale,
+                perimetro_min, perimetro_max, area_min, area_max
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                chart.modello_valvola, chart.dimensione, chart.diametro_nominale,
+                chart.perimetro_min, chart.perimetro_max, chart.area_min, chart.area_max
+            ],
+        ).map_err(|e| e.to_string())?;
+
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Elimina una riga della tabella di sizing
+    pub fn delete_valve_sizing_chart(&self, id: i64) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM valve_sizing_charts WHERE id = ?1", params![id])
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /// Suggerisce le misure di valvola compatibili con le misure TC dell'anulus.
+    /// Segnala inoltre osti coronarici bassi e accessi iliofemorali piccoli.
+    pub fn suggest_valve_sizes(
+        &self,
+        ct_planning_id: i64,
+        modello_valvola: Option<&str>,
+    ) -> Result<CtSizingResult, String> {
+        let planning = self
+            .get_ct_planning_by_id(ct_planning_id)?
+            .ok_or_else(|| "Pianificazione TC non trovata".to_string())?;
+        let charts = self.get_valve_sizing_charts(modello_valvola)?;
+
+        let mut suggestions = Vec::new();
+        for chart in &charts {
+            let perimeter_match = match (planning.anulus_perimetro, chart.perimetro_min, chart.perimetro_max) {
+                (Some(value), Some(min), Some(max)) if value >= min && value <= max => Some(value),
+                _ => None,
+            };
+            let area_match = match (planning.anulus_area, chart.area_min, chart.area_max) {
+                (Some(value), Some(min), Some(max)) if value >= min && value <= max => Some(value),
+                _ => None,
+            };
+
+            if let Some(perimeter) = perimeter_match {
+                let nominal = chart.diametro_nominale * std::f64::consts::PI;
+                suggestions.push(ValveSizeSuggestion {
+                    modello_valvola: chart.modello_valvola.clone(),
+                    dimensione: chart.dimensione.clone(),
+                    criterio: "perimetro".to_string(),
+                    oversizing_percentuale: Some((nominal / perimeter - 1.0) * 100.0),
+                });
+            } else if let Some(area) = area_match {
+                let nominal = std::f64::consts::PI * (chart.diametro_nominale / 2.0).powi(2);
+                suggestions.push(ValveSizeSuggestion {
+                    modello_valvola: chart.modello_valvola.clone(),
+                    dimensione: chart.dimensione.clone(),
+                    criterio: "area".to_string(),
+                    oversizing_percentuale: Some((nominal / area - 1.0) * 100.0),
+                });
+            }
+        }
+
+        let mut warnings = Vec::new();
+        if planning.anulus_perimetro.is_none() && planning.anulus_area.is_none() {
+            warnings.push("Perimetro e area dell'anulus non disponibili: impossibile proporre misure".to_string());
+        } else if suggestions.is_empty() {
+            warnings.push("Nessuna misura compatibile con le tabelle di sizing disponibili".to_string());
+        }
+
+        let small_sinus = planning
+            .seno_valsalva_diametro
+            .map(|d| d < CT_SINUS_SMALL_MM)
+            .unwrap_or(false);
+        let mut protezione_osti_consigliata = false;
+        for (label, height) in [
+            ("sinistro", planning.altezza_coronaria_sx),
+            ("destro", planning.altezza_coronaria_dx),
+        ] {
+            if let Some(h) = height {
+                if h < CT_CORONARY_HEIGHT_MIN_MM {
+                    protezione_osti_consigliata = true;
+                    warnings.push(format!(
+                        "Ostio coronarico {} basso ({:.1} mm < {:.0} mm): valutare protezione osti",
+                        label, h, CT_CORONARY_HEIGHT_MIN_MM
+                    ));
+                } else if h < CT_CORONARY_HEIGHT_CAUTION_MM && small_sinus {
+                    protezione_osti_consigliata = true;
+                    warnings.push(format!(
+                        "Ostio coronarico {} a {:.1} mm con seni di Valsalva piccoli: valutare protezione osti",
+                        label, h
+                    ));
+                }
+            }
+        }
+
+        for (label, mld) in [
+            ("iliaca destra", planning.iliaca_dx_mld),
+            ("iliaca sinistra", planning.iliaca_sx_mld),
+            ("femorale destra", planning.femorale_dx_mld),
+            ("femorale sinistra", planning.femorale_sx_mld),
+        ] {
+            if let Some(value) = mld {
+                if value < CT_ILIOFEMORAL_MLD_MIN_MM {
+                    warnings.push(format!(
+                        "Diametro luminale minimo {} ridotto ({:.1} mm)",
+                        label, value
+                    ));
+                }
+            }
+        }
+
+        Ok(CtSizingResult {
+            ct_planning_id,
+            suggestions,
+            warnings,
+            protezione_osti_consigliata,
+        })
+    }
+
+    // ========================================================================
+    // VALVE CATALOGUE OPERATIONS
+    // ========================================================================
+
+    /// Ottieni tutti i modelli del catalogo valvole
+    pub fn get_valve_models(&self) -> Result<Vec<ValveModel>, String> {
+        let conn = self.conn.lock().unwrap();
+        Self::load_valve_models(&conn).map_err(|e| e.to_string())
+    }
+
+    /// Trova il modello di catalogo corrispondente a un nome libero o a un codice
+    pub fn find_valve_model(&self, name: &str) -> Result<Option<ValveModel>, String> {
+        let catalogue = self.get_valve_models()?;
+        Ok(catalogue.into_iter().find(|m| m.matches(name)))
+    }
+
+    /// Inserisce un nuovo modello nel catalogo
+    pub fn insert_valve_model(&self, model: &ValveModel) -> Result<i64, String> {
+        let conn = self.conn.lock().unwrap();
+
+        let dimensioni_json = serde_json::to_string(&model.dimensioni).map_err(|e| e.to_string())?;
+        let alias_json = serde_json::to_string(&model.alias).map_err(|e| e.to_string())?;
+
+        conn.execute(
+            "INSERT INTO valve_models (
+                codice, produttore, nome_commerciale, tipo_valvola, dimensioni,
+                anulus_min, anulus_max, introduttore_min_fr, alias
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                model.codice, model.produttore, model.nome_commerciale, model.tipo_valvola,
+                dimensioni_json, model.anulus_min, model.anulus_max, model.introduttore_min_fr,
+                alias_json
+            ],
+        ).map_err(|e| e.to_string())?;
+
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Aggiorna un modello del catalogo
+    pub fn update_valve_model(&self, model: &ValveModel) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+
+        let id = model.id.ok_or("Valve model ID is required for update")?;
+        let dimensioni_json = serde_json::to_string(&model.dimensioni).map_err(|e| e.to_string())?;
+        let alias_json = serde_json::to_string(&model.alias).map_err(|e| e.to_string())?;
+
+        conn.execute(
+            "UPDATE valve_models SET
+                codice = ?1, produttore = ?2, nome_commerciale = ?3, tipo_valvola = ?4,
+                dimensioni = ?5, anulus_min = ?6, anulus_max = ?7, introduttore_min_fr = ?8,
+                alias = ?9
+            WHERE id = ?10",
+            params![
+                model.codice, model.produttore, model.nome_commerciale, model.tipo_valvola,
+                dimensioni_json, model.anulus_min, model.anulus_max, model.introduttore_min_fr,
+                alias_json, id
+            ],
+        ).map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+
+    /// Elimina un modello dal catalogo
+    pub fn delete_valve_model(&self, id: i64) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM valve_models WHERE id = ?1", params![id])
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /// Restituisce gli avvisi non bloccanti di una procedura rispetto al catalogo
+    /// (anulus fuori range del modello).
+    pub fn check_procedure_valve(&self, proc: &Procedure) -> Result<Vec<String>, String> {
+        let conn = self.conn.lock().unwrap();
+        let mut warnings = Vec::new();
+        if let Err(e) = Self::validate_procedure_valve(&conn, proc) {
+            if !Self::stored_valve_unchanged(&conn, proc)? {
+                return Err(e);
+            }
+            warnings.push(format!("{} (dato registrato prima del catalogo)", e));
+        }
+        Self::validate_procedure_plan_link(&conn, proc)?;
+
+        let catalogue = Self::load_valve_models(&conn).map_err(|e| e.to_string())?;
+        if let (Some(model), Some(anulus)) = (
+            catalogue.iter().find(|m| m.matches(&proc.modello_valvola)),
+            proc.anulus_aortico,
+        ) {
+            let below = model.anulus_min.map(|min| anulus < min).unwrap_or(false);
+            let above = model.anulus_max.map(|max| anulus > max).unwrap_or(false);
+            if below || above {
+                warnings.push(format!(
+                    "Anulus {} mm fuori dal range indicato per {} ({}-{} mm)",
+                    anulus,
+                    model.display_name(),
+                    model.anulus_min.map(|v| v.to_string()).unwrap_or_else(|| "?".to_string()),
+                    model.anulus_max.map(|v| v.to_string()).unwrap_or_else(|| "?".to_string()),
+                ));
+            }
+        }
+
+        Ok(warnings)
+    }
+
+    // ========================================================================
+    // VALVE-IN-VALVE OPERATIONS
+    // ========================================================================
+
+    fn map_prosthesis_row(row: &rusqlite::Row) -> SqlResult<ProsthesisModel> {
+        Ok(ProsthesisModel {
+            id: Some(row.get("id")?),
+            codice: row.get("codice")?,
+            produttore: row.get("produttore")?,
+            nome_commerciale: row.get("nome_commerciale")?,
+            tipologia: row.get("tipologia")?,
+            dimensione_etichetta: row.get("dimensione_etichetta")?,
+            diametro_interno_reale: row.get("diametro_interno_reale")?,
+        })
+    }
+
+    /// Ottieni il catalogo delle protesi impiantabili in precedenza
+    pub fn get_prosthesis_models(&self) -> Result<Vec<ProsthesisModel>, String> {
+        let conn = self.conn.lock().unwrap();
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT * FROM prosthesis_models
+                 ORDER BY produttore, nome_commerciale, dimensione_etichetta",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], Self::map_prosthesis_row)
+            .map_err(|e| e.to_string())?;
+
+        let models: Result<Vec<_>, _> = rows.collect();
+        models.map_err(|e| e.to_string())
+    }
+
+    /// Ottieni una protesi del catalogo per ID
+    pub fn get_prosthesis_model_by_id(&self, id: i64) -> Result<Option<ProsthesisModel>, String> {
+        let conn = self.conn.lock().unwrap();
+
+        let result = conn.query_row(
+            "SELECT * FROM prosthesis_models WHERE id = ?1",
+            params![id],
+            Self::map_prosthesis_row,
+        );
+
+        match result {
+            Ok(model) => Ok(Some(model)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// Inserisce o aggiorna una protesi del catalogo
+    pub fn save_prosthesis_model(&self, model: &ProsthesisModel) -> Result<i64, String> {
+        let conn = self.conn.lock().unwrap();
+
+        if let Some(id) = model.id {
+            conn.execute(
+                "UPDATE prosthesis_models SET
+                    codice = ?1, produttore = ?2, nome_commerciale = ?3, tipologia = ?4,
+                    dimensione_etichetta = ?5, diametro_interno_reale = ?6
+                 WHERE id = ?7",
+                params![
+                    model.codice, model.produttore, model.nome_commerciale, model.tipologia,
+                    model.dimensione_etichetta, model.diametro_interno_reale, id
+                ],
+            ).map_err(|e| e.to_string())?;
+            return Ok(id);
+        }
+
+        conn.execute(
+            "INSERT INTO prosthesis_models (
+                codice, produttore, nome_commerciale, tipologia,
+                dimensione_etichetta, diametro_interno_reale
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                model.codice, model.produttore, model.nome_commerciale, model.tipologia,
+                model.dimensione_etichetta, model.diametro_interno_reale
+            ],
+        ).map_err(|e| e.to_string())?;
+
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Elimina una protesi dal catalogo (le procedure collegate perdono il riferimento)
+    pub fn delete_prosthesis_model(&self, id: i64) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE procedures SET protesica_catalogo_id = NULL WHERE protesica_catalogo_id = ?1",
+            params![id],
+        ).map_err(|e| e.to_string())?;
+        conn.execute("DELETE FROM prosthesis_models WHERE id = ?1", params![id])
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /// Valuta la compatibilità valve-in-valve: propone le misure TAVI adatte al diametro
+    /// interno reale della protesi e stima il rischio di mismatch protesi-paziente.
+    pub fn check_viv_compatibility(
+        &self,
+        prosthesis_id: i64,
+        altezza: Option<f64>,
+        peso: Option<f64>,
+    ) -> Result<VivCompatibilityResult, String> {
+        let protesi = self
+            .get_prosthesis_model_by_id(prosthesis_id)?
+            .ok_or_else(|| "Protesi non trovata nel catalogo".to_string())?;
+        let catalogue = self.get_valve_models()?;
+        let tid = protesi.diametro_interno_reale;
+
+        let mut suggestions = Vec::new();
+        for model in &catalogue {
+            // Balloon expandable: misura pari o poco superiore al TID; self expandable: oversizing maggiore
+            let (min_diff, max_diff) = if model.tipo_valvola == "Balloon Expandable" {
+                (0.0, 3.0)
+            } else {
+                (2.0, 7.0)
+            };
+            for label in &model.dimensioni {
+                if let Some(size) = parse_valve_size(label) {
+                    let diff = size - tid;
+                    if diff >= min_diff && diff <= max_diff {
+                        suggestions.push(VivSizeSuggestion {
+                            codice_valvola: model.codice.clone(),
+                            nome_valvola: model.display_name(),
+                            tipo_valvola: model.tipo_valvola.clone(),
+                            dimensione: label.clone(),
+                            differenza_mm: diff,
+                        });
+                    }
+                }
+            }
+        }
+
+        let bsa = calculate_bsa(altezza, peso);
+        let large_patient = bsa.map(|v| v > VIV_BSA_LARGE_M2).unwrap_or(false);
+        let rischio_mismatch = if tid < VIV_TID_HIGH_RISK_MM
+            || (tid < VIV_TID_MODERATE_RISK_MM && large_patient)
+        {
+            "elevato"
+        } else if tid < VIV_TID_MODERATE_RISK_MM {
+            "moderato"
+        } else {
+            "basso"
+        };
+
+        let mut warnings = Vec::new();
+        if rischio_mismatch == "elevato" {
+            warnings.push(format!(
+                "Diametro interno reale {:.1} mm: rischio elevato di mismatch protesi-paziente, valutare valvola sopra-anulare o frattura della protesi",
+                tid
+            ));
+        } else if rischio_mismatch == "moderato" {
+            warnings.push(format!(
+                "Diametro interno reale {:.1} mm: possibile mismatch protesi-paziente",
+                tid
+            ));
+        }
+        if suggestions.is_empty() {
+            warnings.push(format!("Nessuna misura del catalogo compatibile con {}", protesi.display_name()));
+        }
+
+        Ok(VivCompatibilityResult {
+            protesi,
+            superficie_corporea: bsa,
+            rischio_mismatch: rischio_mismatch.to_string(),
+            suggestions,
+            warnings,
+        })
+    }
+
+    // ========================================================================
+    // PROCEDURE OUTCOME OPERATIONS (VARC-3)
+    // ========================================================================
+
+    fn map_outcome_row(row: &rusqlite::Row) -> SqlResult<ProcedureOutcome> {
+        Ok(ProcedureOutcome {
+            id: Some(row.get("id")?),
+            procedure_id: row.get("procedure_id")?,
+            created_at: row.get("created_at").ok(),
+            updated_at: row.get("updated_at").ok(),
+            decesso: row.get("decesso").ok(),
+            decesso_data: row.get("decesso_data").ok(),
+            decesso_causa: row.get("decesso_causa").ok(),
+            ictus: row.get("ictus").ok(),
+            complicanza_vascolare: row.get("complicanza_vascolare").ok(),
+            complicanza_strutturale: row.get("complicanza_strutturale").ok(),
+            sanguinamento_tipo: row.get("sanguinamento_tipo").ok(),
+            aki_stadio: row.get("aki_stadio").ok(),
+            nuovo_pacemaker: row.get("nuovo_pacemaker").ok(),
+            leak_paravalvolare: row.get("leak_paravalvolare").ok(),
+            ostruzione_coronarica: row.get("ostruzione_coronarica").ok(),
+            conversione_chirurgica: row.get("conversione_chirurgica").ok(),
+            reintervento_valvola: row.get("reintervento_valvola").ok(),
+            posizionamento_corretto: row.get("posizionamento_corretto").ok(),
+            gradiente_medio_post: row.get("gradiente_medio_post").ok(),
+            vmax_post: row.get("vmax_post").ok(),
+            note: row.get("note").ok(),
+            device_success: None,
+            early_safety: None,
+        }
+        .with_derived())
+    }
+
+    /// Ottieni l'esito registrato per una procedura
+    pub fn get_procedure_outcome(&self, procedure_id: i64) -> Result<Option<ProcedureOutcome>, String> {
+        let conn = self.conn.lock().unwrap();
+
+        let result = conn.query_row(
+            "SELECT * FROM procedure_outcomes WHERE procedure_id = ?1",
+            params![procedure_id],
+            Self::map_outcome_row,
+        );
+
+        match result {
+            Ok(outcome) => Ok(Some(outcome)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// Ottieni tutti gli esiti registrati
+    pub fn get_all_procedure_outcomes(&self) -> Result<Vec<ProcedureOutcome>, String> {
+        let conn = self.conn.lock().unwrap();
+
+        let mut stmt = conn
+            .prepare("SELECT * FROM procedure_outcomes ORDER BY procedure_id")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], Self::map_outcome_row)
+            .map_err(|e| e.to_string())?;
+
+        let outcomes: Result<Vec<_>, _> = rows.collect();
+        outcomes.map_err(|e| e.to_string())
+    }
+
+    /// Salva l'esito di una procedura (inserisce o sostituisce quello esistente)
+    pub fn save_procedure_outcome(&self, outcome: &ProcedureOutcome) -> Result<i64, String> {
+        outcome.validate()?;
+        let conn = self.conn.lock().unwrap();
+
+        conn.execute(
+            "INSERT INTO procedure_outcomes (
+                procedure_id, decesso, decesso_data, decesso_causa, ictus,
+                complicanza_vascolare, complicanza_strutturale, sanguinamento_tipo, aki_stadio,
+                nuovo_pacemaker, leak_paravalvolare, ostruzione_coronarica, conversione_chirurgica,
+                reintervento_valvola, posizionamento_corretto, gradiente_medio_post, vmax_post, note
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)
+            ON CONFLICT(procedure_id) DO UPDATE SET
+                decesso = excluded.decesso,
+                decesso_data = excluded.decesso_data,
+                decesso_causa = excluded.decesso_causa,
+                ictus = excluded.ictus,
+                complicanza_vascolare = excluded.complicanza_vascolare,
+                complicanza_strutturale = excluded.complicanza_strutturale,
+                sanguinamento_tipo = excluded.sanguinamento_tipo,
+                aki_stadio = excluded.aki_stadio,
+                nuovo_pacemaker = excluded.nuovo_pacemaker,
+                leak_paravalvolare = excluded.leak_paravalvolare,
+                ostruzione_coronarica = excluded.ostruzione_coronarica,
+                conversione_chirurgica = excluded.conversione_chirurgica,
+                reintervento_valvola = excluded.reintervento_valvola,
+                posizionamento_corretto = excluded.posizionamento_corretto,
+                gradiente_medio_post = excluded.gradiente_medio_post,
+                vmax_post = excluded.vmax_post,
+                note = excluded.note,
+                updated_at = CURRENT_TIMESTAMP",
+            params![
+                outcome.procedure_id, outcome.decesso, outcome.decesso_data, outcome.decesso_causa,
+                outcome.ictus, outcome.complicanza_vascolare, outcome.complicanza_strutturale,
+                outcome.sanguinamento_tipo, outcome.aki_stadio, outcome.nuovo_pacemaker,
+                outcome.leak_paravalvolare, outcome.ostruzione_coronarica,
+                outcome.conversione_chirurgica, outcome.reintervento_valvola,
+                outcome.posizionamento_corretto, outcome.gradiente_medio_post, outcome.vmax_post,
+                outcome.note
+            ],
+        ).map_err(|e| e.to_string())?;
+
+        conn.query_row(
+            "SELECT id FROM procedure_outcomes WHERE procedure_id = ?1",
+            params![outcome.procedure_id],
+            |row| row.get(0),
+        ).map_err(|e| e.to_string())
+    }
+
+    /// Elimina l'esito di una procedura
+    pub fn delete_procedure_outcome(&self, procedure_id: i64) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "DELETE FROM procedure_outcomes WHERE procedure_id = ?1",
+            params![procedure_id],
+        ).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    // ========================================================================
+    // FOLLOW-UP OPERATIONS
+    // ========================================================================
+
+    fn map_follow_up_row(row: &rusqlite::Row) -> SqlResult<FollowUp> {
+        Ok(FollowUp {
+            id: Some(row.get("id")?),
+            patient_id: row.get("patient_id")?,
+            created_at: row.get("created_at").ok(),
+            updated_at: row.get("updated_at").ok(),
+            tipo: row.get("tipo")?,
+            anno: row.get("anno")?,
+            data_prevista: row.get("data_prevista")?,
+            data_eseguita: row.get("data_eseguita").ok(),
+            nyha_classe: row.get("nyha_classe").ok(),
+            gradiente_medio: row.get("gradiente_medio").ok(),
+            gradiente_massimo: row.get("gradiente_massimo").ok(),
+            vmax: row.get("vmax").ok(),
+            fe: row.get("fe").ok(),
+            leak_paravalvolare: row.get("leak_paravalvolare").ok(),
+            riospedalizzazione: row.get("riospedalizzazione").ok(),
+            riospedalizzazione_causa: row.get("riospedalizzazione_causa").ok(),
+            decesso: row.get("decesso").ok(),
+            decesso_data: row.get("decesso_data").ok(),
+            decesso_causa: row.get("decesso_causa").ok(),
+            note: row.get("note").ok(),
+        })
+    }
+
+    /// Ottieni i controlli di follow-up di un paziente (pianificati ed eseguiti)
+    pub fn get_follow_ups(&self, patient_id: i64) -> Result<Vec<FollowUp>, String> {
+        let conn = self.conn.lock().unwrap();
+        self.auto_mark_tavi_completed(&conn)?;
+
+        let mut stmt = conn
+            .prepare("SELECT * FROM follow_ups WHERE patient_id = ?1 ORDER BY anno")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![patient_id], Self::map_follow_up_row)
+            .map_err(|e| e.to_string())?;
+
+        let follow_ups: Result<Vec<_>, _> = rows.collect();
+        follow_ups.map_err(|e| e.to_string())
+    }
+
+    /// Registra l'esito di un controllo di follow-up
+    pub fn update_follow_up(&self, follow_up: &FollowUp) -> Result<(), String> {
+        follow_up.validate()?;
+        let conn = self.conn.lock().unwrap();
+        let id = follow_up.id.ok_or("Follow-up ID is required for update")?;
+
+        conn.execute(
+            "UPDATE follow_ups SET
+                data_prevista = ?1, data_eseguita = ?2, nyha_classe = ?3,
+                gradiente_medio = ?4, gradiente_massimo = ?5, vmax = ?6, fe = ?7,
+                leak_paravalvolare = ?8, riospedalizzazione = ?9, riospedalizzazione_causa = ?10,
+                decesso = ?11, decesso_data = ?12, decesso_causa = ?13, note = ?14,
+                updated_at = CURRENT_TIMESTAMP
+             WHERE id = ?15",
+            params![
+                follow_up.data_prevista, follow_up.data_eseguita, follow_up.nyha_classe,
+                follow_up.gradiente_medio, follow_up.gradiente_massimo, follow_up.vmax, follow_up.fe,
+                follow_up.leak_paravalvolare, follow_up.riospedalizzazione,
+                follow_up.riospedalizzazione_causa, follow_up.decesso, follow_up.decesso_data,
+                follow_up.decesso_causa, follow_up.note,
+                id
+            ],
+        ).map_err(|e| e.to_string())?;
+
+        // Dopo un decesso i controlli successivi non ancora eseguiti non sono più dovuti
+        if follow_up.decesso.unwrap_or(false) {
+            conn.execute(
+                "DELETE FROM follow_ups
+                 WHERE patient_id = ?1 AND anno > ?2 AND data_eseguita IS NULL",
+                params![follow_up.patient_id, follow_up.anno],
+            ).map_err(|e| e.to_string())?;
+        }
+
+        Ok(())
+    }
+
+    /// Ottieni i controlli scaduti e non ancora eseguiti
+    pub fn get_overdue_follow_ups(&self) -> Result<Vec<OverdueFollowUp>, String> {
+        let conn = self.conn.lock().unwrap();
+        self.ensure_status_tables(&conn).map_err(|e| e.to_string())?;
+        self.auto_mark_tavi_completed(&conn)?;
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT f.*, p.nome AS paziente_nome, p.cognome AS paziente_cognome,
+                        p.telefono AS paziente_telefono, p.data_tavi AS paziente_data_tavi,
+                        CAST(JULIANDAY(DATE('now', 'localtime')) - JULIANDAY(f.data_prevista) AS INTEGER) AS giorni_ritardo
+                 FROM follow_ups f
+                 INNER JOIN patients p ON p.id = f.patient_id
+                 INNER JOIN patients_completato c ON c.patient_id = f.patient_id
+                 WHERE f.data_eseguita IS NULL
+                   AND f.data_prevista < DATE('now', 'localtime')
+                   AND NOT EXISTS (
+                     SELECT 1 FROM follow_ups d
+                     WHERE d.patient_id = f.patient_id AND d.decesso = 1
+                   )
+                 ORDER BY f.data_prevista",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok(OverdueFollowUp {
+                    follow_up: Self::map_follow_up_row(row)?,
+                    nome: row.get("paziente_nome")?,
+                    cognome: row.get("paziente_cognome")?,
+                    telefono: row.get("paziente_telefono").ok(),
+                    data_tavi: row.get("paziente_data_tavi")?,
+                    giorni_ritardo: row.get("giorni_ritardo")?,
+                })
+            })
+            .map_err(|e| e.to_string())?;
+
+        let overdue: Result<Vec<_>, _> = rows.collect();
+        overdue.map_err(|e| e.to_string())
+    }
+
+    /// Dati di sopravvivenza (tempo all'evento/censura) dei pazienti con TAVI eseguita
+    pub fn get_survival_data(&self) -> Result<SurvivalData, String> {
+        let conn = self.conn.lock().unwrap();
+        self.ensure_status_tables(&conn).map_err(|e| e.to_string())?;
+        self.auto_mark_tavi_completed(&conn)?;
+
+        // Decessi dai follow-up (senza data: quella del controllo in cui è stato registrato)
+        // e dagli esiti periprocedurali; le procedure sono collegate per anagrafica.
+        let mut stmt = conn
+            .prepare(
+                "SELECT p.id, DATE(p.data_tavi) AS data_tavi,
+                        (SELECT MIN(COALESCE(f.decesso_data, f.data_eseguita, f.data_prevista)) FROM follow_ups f
+                          WHERE f.patient_id = p.id AND f.decesso = 1) AS data_decesso,
+                        (SELECT f.decesso_causa FROM follow_ups f
+                          WHERE f.patient_id = p.id AND f.decesso = 1 LIMIT 1) AS causa_decesso,
+                        (SELECT MAX(f.data_eseguita) FROM follow_ups f
+                          WHERE f.patient_id = p.id) AS ultimo_controllo,
+                        (SELECT MIN(COALESCE(o.decesso_data, pr.data_procedura))
+                           FROM procedure_outcomes o
+                           INNER JOIN procedures pr ON pr.id = o.procedure_id
+                          WHERE o.decesso = 1
+                            AND UPPER(TRIM(pr.cognome)) = UPPER(TRIM(p.cognome))
+                            AND UPPER(TRIM(pr.nome)) = UPPER(TRIM(p.nome))
+                            AND TRIM(pr.data_nascita) = TRIM(p.data_nascita)) AS data_decesso_procedurale,
+                        (SELECT o.decesso_causa
+                           FROM procedure_outcomes o
+                           INNER JOIN procedures pr ON pr.id = o.procedure_id
+                          WHERE o.decesso = 1
+                            AND UPPER(TRIM(pr.cognome)) = UPPER(TRIM(p.cognome))
+                            AND UPPER(TRIM(pr.nome)) = UPPER(TRIM(p.nome))
+                            AND TRIM(pr.data_nascita) = TRIM(p.data_nascita)
+                          LIMIT 1) AS causa_decesso_procedurale
+                 FROM patients p
+                 INNER JOIN patients_completato c ON c.patient_id = p.id
+                 WHERE DATE(p.data_tavi) IS NOT NULL
+                 ORDER BY p.data_tavi",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                    row.get::<_, Option<String>>(5)?,
+                    row.get::<_, Option<String>>(6)?,
+                ))
+            })
+            .map_err(|e| e.to_string())?;
+
+        let parse = |value: &str| chrono::NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d").ok();
+        let mut records = Vec::new();
+        for row in rows {
+            let (
+                patient_id,
+                data_tavi,
+                data_decesso,
+                causa_decesso,
+                ultimo_controllo,
+                data_decesso_procedurale,
+                causa_decesso_procedurale,
+            ) = row.map_err(|e| e.to_string())?;
+            let start = match parse(&data_tavi) {
+                Some(date) => date,
+                None => continue,
+            };
+            // Vale il decesso registrato per primo
+            let death = [
+                (data_decesso.as_deref().and_then(parse), causa_decesso),
+                (data_decesso_procedurale.as_deref().and_then(parse), causa_decesso_procedurale),
+            ]
+            .into_iter()
+            .filter_map(|(date, causa)| date.map(|d| (d, causa)))
+            .min_by_key(|(date, _)| *date);
+            let decesso = death.is_some();
+            let causa_decesso = death.as_ref().and_then(|(_, causa)| causa.clone());
+            let end = match &death {
+                Some((date, _)) => *date,
+                None => ultimo_controllo.as_deref().and_then(parse).unwrap_or(start),
+            }
+            .max(start);
+            records.push(SurvivalRecord {
+                patient_id,
+                data_tavi,
+                data_ultimo_contatto: end.format("%Y-%m-%d").to_string(),
+                giorni: (end - start).num_days().max(0),
+                decesso,
+                decesso_cardiovascolare: decesso
+                    && causa_decesso.as_deref() == Some("cardiovascolare"),
+            });
+        }
+
+        Ok(SurvivalData::from_records(records))
+    }
+
+    // ========================================================================
+    // MEDICATION OPERATIONS
+    // ========================================================================
+
+    fn map_medication_row(row: &rusqlite::Row) -> SqlResult<Medication> {
+        Ok(Medication {
+            id: Some(row.get("id")?),
+            patient_id: row.get("patient_id")?,
+            created_at: row.get("created_at").ok(),
+            updated_at: row.get("updated_at").ok(),
+            farmaco: row.get("farmaco")?,
+            dose: row.get("dose").ok(),
+            frequenza: row.get("frequenza").ok(),
+            via: row.get("via").ok(),
+            data_inizio: row.get("data_inizio").ok(),
+            data_fine: row.get("data_fine").ok(),
+            indicazione: row.get("indicazione").ok(),
+            anticoagulante: row.get("anticoagulante").unwrap_or(false),
+            antiaggregante: row.get("antiaggregante").unwrap_or(false),
+        })
+    }
+
+    /// Ottieni la terapia domiciliare di un paziente
+    pub fn get_medications(&self, patient_id: i64) -> Result<Vec<Medication>, String> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare(
+                "SELECT * FROM medications WHERE patient_id = ?1
+                 ORDER BY anticoagulante DESC, antiaggregante DESC, farmaco COLLATE NOCASE",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![patient_id], Self::map_medication_row)
+            .map_err(|e| e.to_string())?;
+
+        let medications: Result<Vec<_>, _> = rows.collect();
+        medications.map_err(|e| e.to_string())
+    }
+
+    /// Inserisce o aggiorna un farmaco; i flag antitrombotici sono ricalcolati dal nome
+    pub fn save_medication(&self, medication: &Medication) -> Result<i64, String> {
+        if medication.farmaco.trim().is_empty() {
+            return Err("Il nome del farmaco è obbligatorio".to_string());
+        }
+        if let (Some(inizio), Some(fine)) = (&medication.data_inizio, &medication.data_fine) {
+            if !inizio.is_empty() && !fine.is_empty() && fine < inizio {
+                return Err("La data di fine terapia precede la data di inizio".to_string());
+            }
+        }
+
+        let med = medication.clone().classified();
+        let conn = self.conn.lock().unwrap();
+
+        match med.id {
+            Some(id) => {
+                conn.execute(
+                    "UPDATE medications SET
+                        farmaco = ?1, dose = ?2, frequenza = ?3, via = ?4, data_inizio = ?5,
+                        data_fine = ?6, indicazione = ?7, anticoagulante = ?8, antiaggregante = ?9,
+                        updated_at = CURRENT_TIMESTAMP
+                     WHERE id = ?10",
+                    params![
+                        med.farmaco.trim(), med.dose, med.frequenza, med.via, med.data_inizio,
+                        med.data_fine, med.indicazione, med.anticoagulante, med.antiaggregante,
+                        id
+                    ],
+                ).map_err(|e| e.to_string())?;
+                Ok(id)
+            }
+            None => {
+                conn.execute(
+                    "INSERT INTO medications (
+                        patient_id, farmaco, dose, frequenza, via, data_inizio, data_fine,
+                        indicazione, anticoagulante, antiaggregante
+                    ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                    params![
+                        med.patient_id, med.farmaco.trim(), med.dose, med.frequenza, med.via,
+                        med.data_inizio, med.data_fine, med.indicazione,
+                        med.anticoagulante, med.antiaggregante
+                    ],
+                ).map_err(|e| e.to_string())?;
+                Ok(conn.last_insert_rowid())
+            }
+        }
+    }
+
+    /// Elimina un farmaco dalla terapia domiciliare
+    pub fn delete_medication(&self, id: i64) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM medications WHERE id = ?1", params![id])
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    // ========================================================================
+    // RISK FACTOR OPERATIONS
+    // ========================================================================
+
+    fn map_risk_factor_row(row: &rusqlite::Row) -> SqlResult<PatientRiskFactor> {
+        Ok(PatientRiskFactor {
+            id: Some(row.get("id")?),
+            patient_id: row.get("patient_id")?,
+            codice: row.get("codice")?,
+            insulino_trattato: row.get("insulino_trattato").ok(),
+            stato_fumo: row.get("stato_fumo").ok(),
+            pack_years: row.get("pack_years").ok(),
+            note: row.get("note").ok(),
+        })
+    }
+
+    fn load_risk_factors(conn: &Connection, patient_id: i64) -> SqlResult<Vec<PatientRiskFactor>> {
+        let mut stmt = conn.prepare("SELECT * FROM patient_risk_factors WHERE patient_id = ?1")?;
+        let rows = stmt.query_map(params![patient_id], Self::map_risk_factor_row)?;
+        let mut factors: Vec<PatientRiskFactor> = rows.collect::<SqlResult<_>>()?;
+        // Ordine del vocabolario, come nel referto
+        factors.sort_by_key(|f| {
+            RISK_FACTOR_VOCABULARY
+                .iter()
+                .position(|(codice, _)| *codice == f.codice)
+                .unwrap_or(usize::MAX)
+        });
+        Ok(factors)
+    }
+
+    /// Ottieni i fattori di rischio codificati di un paziente
+    pub fn get_patient_risk_factors(&self, patient_id: i64) -> Result<Vec<PatientRiskFactor>, String> {
+        let conn = self.conn.lock().unwrap();
+        Self::load_risk_factors(&conn, patient_id).map_err(|e| e.to_string())
+    }
+
+    /// Sostituisce i fattori di rischio di un paziente e riallinea `ambulatorio_fattori`,
+    /// conservando le eventuali voci libere non riconducibili al vocabolario.
+    pub fn save_patient_risk_factors(
+        &self,
+        patient_id: i64,
+        factors: &[PatientRiskFactor],
+    ) -> Result<(), String> {
+        for factor in factors {
+            factor.validate()?;
+        }
+        let conn = self.conn.lock().unwrap();
+
+        let current_json: Option<String> = conn
+            .query_row(
+                "SELECT ambulatorio_fattori FROM patients WHERE id = ?1",
+                params![patient_id],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        let free_labels: Vec<String> = current_json
+            .and_then(|json| serde_json::from_str::<Vec<String>>(&json).ok())
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|label| PatientRiskFactor::from_free_text(patient_id, label).is_none())
+            .collect();
+
+        Self::with_immediate_transaction(&conn, || {
+            conn.execute(
+                "DELETE FROM patient_risk_factors WHERE patient_id = ?1",
+                params![patient_id],
+            )
+            .map_err(|e| e.to_string())?;
+            let mut labels = Vec::new();
+            for factor in factors {
+                conn.execute(
+                    "INSERT INTO patient_risk_factors (
+                        patient_id, codice, insulino_trattato, stato_fumo, pack_years, note
+                    ) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                    ON CONFLICT(patient_id, codice) DO UPDATE SET
+                        insulino_trattato = excluded.insulino_trattato,
+                        stato_fumo = excluded.stato_fumo,
+                        pack_years = excluded.pack_years,
+                        note = excluded.note",
+                    params![
+                        patient_id, factor.codice, factor.insulino_trattato,
+                        factor.stato_fumo, factor.pack_years, factor.note
+                    ],
+                )
+                .map_err(|e| e.to_string())?;
+                // Etichette compatibili con il form ambulatoriale
+                let label = risk_factor_label(&factor.codice).unwrap_or_default();
+                labels.push(match (factor.codice.as_str(), factor.stato_fumo.as_deref()) {
+                    ("fumo", Some(stato)) => format!("{} ({})", label, stato),
+                    _ => label.to_string(),
+                });
+            }
+            labels.extend(free_labels);
+            let json = serde_json::to_string(&labels).unwrap_or_else(|_| "[]".to_string());
+            conn.execute(
+                "UPDATE patients SET ambulatorio_fattori = ?1, updated_at = CURRENT_TIMESTAMP WHERE id = ?2",
+                params![json, patient_id],
+            )
+            .map_err(|e| e.to_string())?;
+            Ok(())
+        })
+    }
+
+    /// Descrizioni dei fattori di rischio per il referto ambulatoriale
+    pub fn describe_patient_risk_factors(&self, patient_id: i64) -> Result<Vec<String>, String> {
+        Ok(self
+            .get_patient_risk_factors(patient_id)?
+            .iter()
+            .map(PatientRiskFactor::describe)
+            .collect())
+    }
+
+    /// Prevalenza dei fattori di rischio in lista d'attesa e nella popolazione trattata
+    pub fn get_risk_factor_statistics(&self) -> Result<RiskFactorStatistics, String> {
+        let conn = self.conn.lock().unwrap();
+        self.ensure_status_tables(&conn).map_err(|e| e.to_string())?;
+        self.auto_mark_tavi_completed(&conn)?;
+
+        let count = |table: &str| -> Result<i32, String> {
+            conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get(0))
+                .map_err(|e| e.to_string())
+        };
+        let totale_lista_attesa = count("patients_in_attesa_intervento")?;
+        let totale_trattati = count("patients_completato")?;
+
+        let counts_by_code = |table: &str| -> Result<std::collections::HashMap<String, i32>, String> {
+            let mut stmt = conn
+                .prepare(&format!(
+                    "SELECT rf.codice, COUNT(*) FROM patient_risk_factors rf
+                     INNER JOIN {} s ON s.patient_id = rf.patient_id
+                     GROUP BY rf.codice",
+                    table
+                ))
+                .map_err(|e| e.to_string())?;
+            let rows = stmt
+                .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i32>(1)?)))
+                .map_err(|e| e.to_string())?;
+            rows.collect::<SqlResult<std::collections::HashMap<_, _>>>().map_err(|e| e.to_string())
+        };
+        let waiting = counts_by_code("patients_in_attesa_intervento")?;
+        let treated = counts_by_code("patients_completato")?;
+
+        let percentage = |n: i32, total: i32| {
+            if total > 0 { n as f64 / total as f64 * 100.0 } else { 0.0 }
+        };
+
+        let fattori = RISK_FACTOR_VOCABULARY
+            .iter()
+            .map(|(codice, etichetta)| {
+                let lista_attesa = waiting.get(*codice).copied().unwrap_or(0);
+                let trattati = treated.get(*codice).copied().unwrap_or(0);
+                RiskFactorPrevalence {
+                    codice: codice.to_string(),
+                    etichetta: etichetta.to_string(),
+                    lista_attesa,
+                    lista_attesa_percentuale: percentage(lista_attesa, totale_lista_attesa),
+                    trattati,
+                    trattati_percentuale: percentage(trattati, totale_trattati),
+                }
+            })
+            .collect();
+
+        Ok(RiskFactorStatistics {
+            totale_lista_attesa,
+            totale_trattati,
+            fattori,
+        })
+    }
+
+    // ========================================================================
+    // ECG OPERATIONS
+    // ========================================================================
+
+    fn map_ecg_row(row: &rusqlite::Row) -> SqlResult<EcgRecording> {
+        Ok(EcgRecording {
+            id: Some(row.get("id")?),
+            patient_id: row.get("patient_id")?,
+            created_at: row.get("created_at").ok(),
+            updated_at: row.get("updated_at").ok(),
+            data_registrazione: row.get("data_registrazione")?,
+            ritmo: row.get("ritmo").ok(),
+            frequenza_cardiaca: row.get("frequenza_cardiaca").ok(),
+            pr_ms: row.get("pr_ms").ok(),
+            qrs_ms: row.get("qrs_ms").ok(),
+            bbs: row.get("bbs").unwrap_or(false),
+            bbd: row.get("bbd").unwrap_or(false),
+            eas: row.get("eas").unwrap_or(false),
+            bav_grado: row.get("bav_grado").ok(),
+            note: row.get("note").ok(),
+        })
+    }
+
+    fn load_latest_ecg(conn: &Connection, patient_id: i64) -> SqlResult<Option<EcgRecording>> {
+        let mut stmt = conn.prepare(
+            "SELECT * FROM ecg_recordings WHERE patient_id = ?1
+             ORDER BY data_registrazione DESC, id DESC LIMIT 1",
+        )?;
+        let mut rows = stmt.query_map(params![patient_id], Self::map_ecg_row)?;
+        rows.next().transpose()
+    }
+
+    /// Flag della scheda procedurale corrispondenti a un ECG, nell'ordine delle colonne:
+    /// sinusale, FA, BBS, BBD, EAS, BAV I grado, ritmo stimolato
+    fn legacy_ecg_flags(ecg: Option<&EcgRecording>) -> [bool; 7] {
+        let ritmo = ecg.and_then(|e| e.ritmo.as_deref());
+        let flag = |f: fn(&EcgRecording) -> bool| ecg.map(f).unwrap_or(false);
+        [
+            ritmo == Some("sinusale"),
+            ritmo == Some("fa"),
+            flag(|e| e.bbs),
+            flag(|e| e.bbd),
+            flag(|e| e.eas),
+            flag(|e| e.has_first_degree_av_block()),
+            ritmo == Some("stimolato"),
+        ]
+    }
+
+    /// I flag ECG della scheda procedurale sono una vista dell'ECG più recente e non vengono
+    /// salvati con il paziente: se dal form arrivano valori diversi, la modifica diventa una
+    /// nuova registrazione datata oggi, così il rischio pacemaker resta allineato.
+    fn record_ecg_from_legacy_flags(conn: &Connection, patient_id: i64, patient: &Patient) -> SqlResult<()> {
+        let latest = Self::load_latest_ecg(conn, patient_id)?;
+        let current = Self::legacy_ecg_flags(latest.as_ref());
+        let submitted = [
+            patient.procedurale_ecg_ritmo_sinusale,
+            patient.procedurale_ecg_fa,
+            patient.procedurale_ecg_bbs,
+            patient.procedurale_ecg_bbd,
+            patient.procedurale_ecg_eas,
+            patient.procedurale_ecg_bav_primo,
+            patient.procedurale_ecg_ritmo_stimolato,
+        ];
+        if !submitted.iter().zip(current).any(|(value, stored)| value.is_some_and(|v| v != stored)) {
+            return Ok(());
+        }
+
+        let value = |i: usize| submitted[i].unwrap_or(current[i]);
+        let ritmo = if value(6) {
+            Some("stimolato")
+        } else if value(1) {
+            Some("fa")
+        } else if value(0) {
+            Some("sinusale")
+        } else {
+            None
+        };
+        // Il form non distingue i BAV di grado superiore: restano quelli già registrati
+        let bav_grado = if value(5) {
+            Some(1)
+        } else {
+            latest.as_ref().and_then(|e| e.bav_grado).filter(|grado| *grado >= 2)
+        };
+        conn.execute(
+            "INSERT INTO ecg_recordings (patient_id, data_registrazione, ritmo, bbs, bbd, eas, bav_grado, note)
+             VALUES (?1, DATE('now', 'localtime'), ?2, ?3, ?4, ?5, ?6, 'Dalla scheda procedurale')",
+            params![patient_id, ritmo, value(2), value(3), value(4), bav_grado],
+        )?;
+        Ok(())
+    }
+
+    /// Riporta l'ECG più recente sui flag della scheda procedurale, usati dal form e dal template
+    fn sync_legacy_ecg_flags(conn: &Connection, patient_id: i64) -> SqlResult<()> {
+        let latest = Self::load_latest_ecg(conn, patient_id)?;
+        let flags = Self::legacy_ecg_flags(latest.as_ref());
+        conn.execute(
+            "UPDATE patients SET
+                procedurale_ecg_ritmo_sinusale = ?1, procedurale_ecg_fa = ?2,
+                procedurale_ecg_bbs = ?3, procedurale_ecg_bbd = ?4, procedurale_ecg_eas = ?5,
+                procedurale_ecg_bav_primo = ?6, procedurale_ecg_ritmo_stimolato = ?7,
+                updated_at = CURRENT_TIMESTAMP
+             WHERE id = ?8",
+            params![flags[0], flags[1], flags[2], flags[3], flags[4], flags[5], flags[6], patient_id],
+        )?;
+        Ok(())
+    }
+
+    /// Ottieni gli ECG di un paziente, dal più recente
+    pub fn get_ecg_recordings(&self, patient_id: i64) -> Result<Vec<EcgRecording>, String> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare(
+                "SELECT * FROM ecg_recordings WHERE patient_id = ?1
+                 ORDER BY data_registrazione DESC, id DESC",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![patient_id], Self::map_ecg_row)
+            .map_err(|e| e.to_string())?;
+
+        let recordings: Result<Vec<_>, _> = rows.collect();
+        recordings.map_err(|e| e.to_string())
+    }
+
+    /// Ottieni l'ECG più recente di un paziente
+    pub fn get_latest_ecg(&self, patient_id: i64) -> Result<Option<EcgRecording>, String> {
+        let conn = self.conn.lock().unwrap();
+        Self::load_latest_ecg(&conn, patient_id).map_err(|e| e.to_string())
+    }
+
+    /// Inserisce o aggiorna una registrazione ECG
+    pub fn save_ecg_recording(&self, ecg: &EcgRecording) -> Result<i64, String> {
+        ecg.validate()?;
+        let conn = self.conn.lock().unwrap();
+
+        let id = match ecg.id {
+            Some(id) => {
+                conn.execute(
+                    "UPDATE ecg_recordings SET
+                        data_registrazione = ?1, ritmo = ?2, frequenza_cardiaca = ?3, pr_ms = ?4,
+                        qrs_ms = ?5, bbs = ?6, bbd = ?7, eas = ?8, bav_grado = ?9, note = ?10,
+                        updated_at = CURRENT_TIMESTAMP
+                     WHERE id = ?11",
+                    params![
+                        ecg.data_registrazione, ecg.ritmo, ecg.frequenza_cardiaca, ecg.pr_ms,
+                        ecg.qrs_ms, ecg.bbs, ecg.bbd, ecg.eas, ecg.bav_grado, ecg.note,
+                        id
+                    ],
+                ).map_err(|e| e.to_string())?;
+                id
+            }
+            None => {
+                conn.execute(
+                    "INSERT INTO ecg_recordings (
+                        patient_id, data_registrazione, ritmo, frequenza_cardiaca, pr_ms, qrs_ms,
+                        bbs, bbd, eas, bav_grado, note
+                    ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                    params![
+                        ecg.patient_id, ecg.data_registrazione, ecg.ritmo, ecg.frequenza_cardiaca,
+                        ecg.pr_ms, ecg.qrs_ms, ecg.bbs, ecg.bbd, ecg.eas, ecg.bav_grado, ecg.note
+                    ],
+                ).map_err(|e| e.to_string())?;
+                conn.last_insert_rowid()
+            }
+        };
+
+        Self::sync_legacy_ecg_flags(&conn, ecg.patient_id).map_err(|e| e.to_string())?;
+        Ok(id)
+    }
+
+    /// Elimina una registrazione ECG
+    pub fn delete_ecg_recording(&self, id: i64) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+        let patient_id: Option<i64> = conn
+            .query_row(
+                "SELECT patient_id FROM ecg_recordings WHERE id = ?1",
+                params![id],
+                |row| row.get(0),
+            )
+            .ok();
+        conn.execute("DELETE FROM ecg_recordings WHERE id = ?1", params![id])
+            .map_err(|e| e.to_string())?;
+        if let Some(patient_id) = patient_id {
+            Self::sync_legacy_ecg_flags(&conn, patient_id).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Rischio di pacemaker post-TAVI dall'ECG più recente e dalla valvola del piano corrente
+    pub fn get_pacemaker_risk(&self, patient_id: i64) -> Result<PacemakerRisk, String> {
+        let valve_code = self
+            .get_current_procedure_plan(patient_id)?
+            .and_then(|plan| plan.bioprotesi_modello);
+        self.assess_pacemaker_risk(patient_id, valve_code.as_deref())
+    }
+
+    /// Rischio di pacemaker post-TAVI per una specifica valvola pianificata
+    pub fn assess_pacemaker_risk(
+        &self,
+        patient_id: i64,
+        valve_code: Option<&str>,
+    ) -> Result<PacemakerRisk, String> {
+        let latest = self.get_latest_ecg(patient_id)?;
+        let self_expandable = match valve_code {
+            Some(code) if !code.trim().is_empty() => self
+                .find_valve_model(code)?
+                .map(|m| m.tipo_valvola == "Self Expandable"),
+            _ => None,
+        };
+        Ok(PacemakerRisk::assess(latest.as_ref(), self_expandable))
+    }
+    // ========================================================================
+    // PROCEDURE PLAN OPERATIONS
+    // ========================================================================
+
+    fn map_procedure_plan_row(row: &rusqlite::Row) -> SqlResult<ProcedurePlan> {
+        Ok(ProcedurePlan {
+            id: Some(row.get("id")?),
+            patient_id: row.get("patient_id")?,
+            versione: row.get("versione").ok(),
+            created_at: row.get("created_at").ok(),
+            motivo_revisione: row.get("motivo_revisione").ok(),
+            allergia_mdc: row.get("allergia_mdc").ok(),
+            preparazione_mdc: row.get("preparazione_mdc").ok(),
+            creatinina: row.get("creatinina").ok(),
+            egfr: row.get("egfr").ok(),
+            hb: row.get("hb").ok(),
+            altro: row.get("altro").ok(),
+            anestesia: row.get("anestesia").ok(),
+            coronarografia: row.get("coronarografia").ok(),
+            coronarografia_note: row.get("coronarografia_note").ok(),
+            pacemaker: row.get("pacemaker").ok(),
+            pacemaker_note: row.get("pacemaker_note").ok(),
+            accesso_principale_fem: row.get("accesso_principale_fem").ok(),
+            accesso_principale_altro: row.get("accesso_principale_altro").ok(),
+            accesso_protezione: row.get("accesso_protezione").ok(),
+            accesso_protezione_note: row.get("accesso_protezione_note").ok(),
+            altri_accessi: row.get("altri_accessi").ok(),
+            diametro_pallone_femorale: row.get("diametro_pallone_femorale").ok(),
+            guida_safari: row.get("guida_safari").ok(),
+            protezione_osti: row.get("protezione_osti").ok(),
+            valvuloplastica: row.get("valvuloplastica").ok(),
+            valvuloplastica_note: row.get("valvuloplastica_note").ok(),
+            bioprotesi_modello: row.get("bioprotesi_modello").ok(),
+            bioprotesi_dimensione: row.get("bioprotesi_dimensione").ok(),
+        })
+    }
+
+    /// Ottieni tutte le versioni del piano procedurale di un paziente, dalla più recente
+    pub fn get_procedure_plans(&self, patient_id: i64) -> Result<Vec<ProcedurePlan>, String> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare("SELECT * FROM procedure_plans WHERE patient_id = ?1 ORDER BY versione DESC")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![patient_id], Self::map_procedure_plan_row)
+            .map_err(|e| e.to_string())?;
+
+        let plans: Result<Vec<_>, _> = rows.collect();
+        plans.map_err(|e| e.to_string())
+    }
+
+    pub fn get_procedure_plan(&self, id: i64) -> Result<Option<ProcedurePlan>, String> {
+        let conn = self.conn.lock().unwrap();
+        let result = conn.query_row(
+            "SELECT * FROM procedure_plans WHERE id = ?1",
+            params![id],
+            Self::map_procedure_plan_row,
+        );
+
+        match result {
+            Ok(plan) => Ok(Some(plan)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// Versione corrente (più recente) del piano procedurale
+    pub fn get_current_procedure_plan(&self, patient_id: i64) -> Result<Option<ProcedurePlan>, String> {
+        Ok(self.get_procedure_plans(patient_id)?.into_iter().next())
+    }
+
+    /// Salva il piano come nuova versione e lo rende il piano corrente del paziente
+    pub fn save_procedure_plan(&self, plan: &ProcedurePlan) -> Result<i64, String> {
+        if plan.is_empty() {
+            return Err("Il piano procedurale non contiene dati".to_string());
+        }
+        let conn = self.conn.lock().unwrap();
+
+        let placeholders: Vec<String> = (0..PROCEDURE_PLAN_FIELDS.len())
+            .map(|i| format!("?{}", i + 3))
+            .collect();
+        // Stringhe vuote salvate come NULL, come nelle versioni create dal form paziente
+        let values: Vec<Option<String>> = plan
+            .field_values()
+            .iter()
+            .map(|v| (*v).clone().filter(|s| !s.trim().is_empty()))
+            .collect();
+        let mut sql_params: Vec<&dyn rusqlite::ToSql> = vec![&plan.patient_id, &plan.motivo_revisione];
+        sql_params.extend(values.iter().map(|v| v as &dyn rusqlite::ToSql));
+
+        Self::with_immediate_transaction(&conn, || {
+            conn.execute(
+                &format!(
+                    "INSERT INTO procedure_plans (patient_id, versione, motivo_revisione, {})
+                     VALUES (?1, (SELECT COALESCE(MAX(versione), 0) + 1 FROM procedure_plans WHERE patient_id = ?1), ?2, {})",
+                    PROCEDURE_PLAN_FIELDS.join(", "),
+                    placeholders.join(", ")
+                ),
+                sql_params.as_slice(),
+            )
+            .map_err(|e| e.to_string())?;
+            let id = conn.last_insert_rowid();
+            Self::mirror_plan_to_patient(&conn, plan).map_err(|e| e.to_string())?;
+            Ok(id)
+        })
+    }
+
+    /// Elimina una versione del piano non ancora collegata a una procedura eseguita
+    pub fn delete_procedure_plan(&self, id: i64) -> Result<(), String> {
+        let plan = self
+            .get_procedure_plan(id)?
+            .ok_or_else(|| "Piano procedurale non trovato".to_string())?;
+        let conn = self.conn.lock().unwrap();
+
+        let linked: i32 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM procedures WHERE plan_id = ?1",
+                params![id],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        if linked > 0 {
+            return Err("Il piano è collegato a una procedura eseguita e non può essere eliminato".to_string());
+        }
+
+        conn.execute("DELETE FROM procedure_plans WHERE id = ?1", params![id])
+            .map_err(|e| e.to_string())?;
+
+        // Il paziente torna a mostrare la versione precedente, se presente
+        let previous = conn
+            .query_row(
+                "SELECT * FROM procedure_plans WHERE patient_id = ?1 ORDER BY versione DESC LIMIT 1",
+                params![plan.patient_id],
+                Self::map_procedure_plan_row,
+            )
+            .ok();
+        if let Some(previous) = previous {
+            Self::mirror_plan_to_patient(&conn, &previous).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Confronto tra piano e procedura eseguita: valvola, dimensione, accesso e pre-dilatazione
+    pub fn compare_plan_with_procedure(&self, procedure_id: i64) -> Result<PlanComparison, String> {
+        let proc = self
+            .get_procedure_by_id(procedure_id)?
+            .ok_or_else(|| "Procedura non trovata".to_string())?;
+        let plan_id = proc
+            .plan_id
+            .ok_or_else(|| "La procedura non è collegata a un piano".to_string())?;
+        let plan = self
+            .get_procedure_plan(plan_id)?
+            .ok_or_else(|| "Piano procedurale non trovato".to_string())?;
+
+        let catalogue = self.get_valve_models()?;
+        let find_model = |name: &str| catalogue.iter().find(|m| m.matches(name));
+        let planned_model = plan.bioprotesi_modello.as_deref().filter(|m| !m.trim().is_empty());
+        let model_item = PlanComparisonItem {
+            campo: "Modello valvola".to_string(),
+            pianificato: planned_model.map(|m| {
+                find_model(m).map(|v| v.display_name()).unwrap_or_else(|| m.to_string())
+            }),
+            eseguito: Some(proc.modello_valvola.clone()),
+            concordante: planned_model.map(|m| match (find_model(m), find_model(&proc.modello_valvola)) {
+                (Some(a), Some(b)) => a.codice == b.codice,
+                _ => normalize_valve_key(m) == normalize_valve_key(&proc.modello_valvola),
+            }),
+        };
+
+        let planned_size = plan.bioprotesi_dimensione.as_deref().and_then(parse_valve_size);
+        let size_item = PlanComparisonItem {
+            campo: "Dimensione valvola".to_string(),
+            pianificato: planned_size.map(|s| format!("{} mm", s)),
+            eseguito: proc.dimensione_valvola.map(|s| format!("{} mm", s)),
+            concordante: planned_size.map(|planned| {
+                proc.dimensione_valvola.is_some_and(|actual| (planned - actual).abs() < 0.01)
+            }),
+        };
+
+        let planned_access = plan.accesso_principale_fem.clone().filter(|a| !a.trim().is_empty());
+        let access_item = PlanComparisonItem {
+            campo: "Accesso principale".to_string(),
+            concordante: planned_access
+                .as_ref()
+                .map(|planned| proc.accesso_principale.as_ref() == Some(planned)),
+            pianificato: planned_access,
+            eseguito: proc.accesso_principale.clone(),
+        };
+
+        let planned_predil = match plan.valvuloplastica.as_deref() {
+            Some("si") => Some(true),
+            Some("no") => Some(false),
+            _ => None,
+        };
+        let yes_no = |v: bool| if v { "Sì".to_string() } else { "No".to_string() };
+        let predil_item = PlanComparisonItem {
+            campo: "Pre-dilatazione".to_string(),
+            pianificato: planned_predil.map(yes_no),
+            eseguito: Some(yes_no(proc.pre_dilatazione)),
+            concordante: planned_predil.map(|planned| planned == proc.pre_dilatazione),
+        };
+
+        Ok(PlanComparison {
+            procedure_id,
+            plan_id,
+            versione: plan.versione.unwrap_or_default(),
+            voci: vec![model_item, size_item, access_item, predil_item],
+        })
+    }
+
+    // ========================================================================
+    // CLINICAL NOTES SEARCH
+    // ========================================================================
+
+    /// Ricerca full-text nelle note cliniche, ordinata per pertinenza (bm25).
+    /// `campo` limita la ricerca a una colonna (es. "conclusioni").
+    pub fn search_clinical_notes(
+        &self,
+        query: &str,
+        campo: Option<&str>,
+        limit: Option<i64>,
+    ) -> Result<Vec<ClinicalNoteHit>, String> {
+        let Some(fts_query) = build_fts_query(query) else {
+            return Ok(Vec::new());
+        };
+        let fts_query = match campo.filter(|c| !c.is_empty()) {
+            Some(column) => {
+                if !CLINICAL_NOTE_FIELDS.iter().any(|(name, _)| *name == column) {
+                    return Err(format!("Campo di ricerca non valido: {}", column));
+                }
+                format!("{} : ({})", column, fts_query)
+            }
+            None => fts_query,
+        };
+
+        // Marcatori di controllo per i termini trovati: il testo viene escapato prima di
+        // sostituirli con <mark>, così lo snippet è HTML sicuro.
+        const MARK_OPEN: &str = "\u{2}";
+        const MARK_CLOSE: &str = "\u{3}";
+        let snippet_columns: Vec<String> = (0..CLINICAL_NOTE_FIELDS.len())
+            .map(|i| format!("snippet(patients_fts, {}, char(2), char(3), '…', 16)", i))
+            .collect();
+
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT p.id, p.nome, p.cognome, bm25(patients_fts) AS score, {}
+                 FROM patients_fts
+                 INNER JOIN patients p ON p.id = patients_fts.rowid
+                 WHERE patients_fts MATCH ?1
+                 ORDER BY score
+                 LIMIT ?2",
+                snippet_columns.join(", ")
+            ))
+            .map_err(|e| e.to_string())?;
+
+        let rows = stmt
+            .query_map(params![fts_query, limit.unwrap_or(50).max(1)], |row| {
+                let mut snippets = Vec::new();
+                for (i, (campo, etichetta)) in CLINICAL_NOTE_FIELDS.iter().enumerate() {
+                    let raw: Option<String> = row.get(4 + i)?;
+                    let Some(raw) = raw.filter(|text| text.contains(MARK_OPEN)) else {
+                        continue;
+                    };
+                    let snippet = raw
+                        .trim()
+                        .replace('&', "&amp;")
+                        .replace('<', "&lt;")
+                        .replace('>', "&gt;")
+                        .replace(MARK_OPEN, "<mark>")
+                        .replace(MARK_CLOSE, "</mark>");
+                    snippets.push(ClinicalNoteSnippet {
+                        campo: campo.to_string(),
+                        etichetta: etichetta.to_string(),
+                        snippet,
+                    });
+                }
+                Ok(ClinicalNoteHit {
+                    patient_id: row.get(0)?,
+                    nome: row.get(1)?,
+                    cognome: row.get(2)?,
+                    score: row.get(3)?,
+                    snippets,
+                })
+            })
+            .map_err(|e| e.to_string())?;
+
+        let hits: Result<Vec<_>, _> = rows.collect();
+        hits.map_err(|e| e.to_string())
+    }
+
+    // ========================================================================
+    // SAVED SEARCH OPERATIONS
+    // ========================================================================
+
+    fn map_saved_search_row(row: &rusqlite::Row) -> SqlResult<SavedSearch> {
+        let filters_json: String = row.get("filters")?;
+        Ok(SavedSearch {
+            id: Some(row.get("id")?),
+            nome: row.get("nome")?,
+            descrizione: row.get("descrizione").ok(),
+            filters: serde_json::from_str(&filters_json).map_err(|e| {
+                rusqlite::Error::FromSqlConversionFailure(
+                    filters_json.len(),
+                    rusqlite::types::Type::Text,
+                    Box::new(e),
+                )
+            })?,
+            mostra_in_dashboard: row.get("mostra_in_dashboard").unwrap_or(true),
+            ordine: row.get("ordine").unwrap_or(0),
+            created_at: row.get("created_at").ok(),
+            updated_at: row.get("updated_at").ok(),
+        })
+    }
+
+    /// Ottieni le ricerche salvate nell'ordine della dashboard
+    pub fn get_saved_searches(&self) -> Result<Vec<SavedSearch>, String> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare("SELECT * FROM saved_searches ORDER BY ordine, nome COLLATE NOCASE")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], Self::map_saved_search_row)
+            .map_err(|e| e.to_string())?;
+
+        let searches: Result<Vec<_>, _> = rows.collect();
+        searches.map_err(|e| e.to_string())
+    }
+
+    pub fn get_saved_search_by_name(&self, nome: &str) -> Result<Option<SavedSearch>, String> {
+        let conn = self.conn.lock().unwrap();
+        let result = conn.query_row(
+            "SELECT * FROM saved_searches WHERE nome = ?1 COLLATE NOCASE",
+            params![nome.trim()],
+            Self::map_saved_search_row,
+        );
+
+        match result {
+            Ok(search) => Ok(Some(search)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// Inserisce o aggiorna una ricerca salvata (il nome è univoco)
+    pub fn save_saved_search(&self, search: &SavedSearch) -> Result<i64, String> {
+        let nome = search.nome.trim();
+        if nome.is_empty() {
+            return Err("Il nome della ricerca è obbligatorio".to_string());
+        }
+        let filters_json = serde_json::to_string(&search.filters).map_err(|e| e.to_string())?;
+        let conn = self.conn.lock().unwrap();
+
+        let result = match search.id {
+            Some(id) => conn
+                .execute(
+                    "UPDATE saved_searches SET
+                        nome = ?1, descrizione = ?2, filters = ?3, mostra_in_dashboard = ?4,
+                        ordine = ?5, updated_at = CURRENT_TIMESTAMP
+                     WHERE id = ?6",
+                    params![
+                        nome, search.descrizione, filters_json, search.mostra_in_dashboard,
+                        search.ordine, id
+                    ],
+                )
+                .map(|updated| (updated > 0).then_some(id)),
+            None => conn
+                .execute(
+                    "INSERT INTO saved_searches (nome, descrizione, filters, mostra_in_dashboard, ordine)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![
+                        nome, search.descrizione, filters_json, search.mostra_in_dashboard,
+                        search.ordine
+                    ],
+                )
+                .map(|_| Some(conn.last_insert_rowid())),
+        };
+
+        result
+            .map_err(|e| match e {
+                rusqlite::Error::SqliteFailure(err, _)
+                    if err.code == rusqlite::ErrorCode::ConstraintViolation =>
+                {
+                    format!("Esiste già una ricerca salvata con nome \"{}\"", nome)
+                }
+                other => other.to_string(),
+            })?
+            .ok_or_else(|| format!("Ricerca salvata {} non trovata", search.id.unwrap_or_default()))
+    }
+
+    pub fn delete_saved_search(&self, id: i64) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM saved_searches WHERE id = ?1", params![id])
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /// Esegue una ricerca salvata per nome
+    pub fn run_saved_search(&self, nome: &str) -> Result<PatientSearchResult, String> {
+        let search = self
+            .get_saved_search_by_name(nome)?
+            .ok_or_else(|| format!("Ricerca salvata \"{}\" non trovata", nome))?;
+        self.search_patients(search.filters)
+    }
+
+    /// Conteggi delle liste intelligenti mostrate in dashboard
+    pub fn get_smart_list_counts(&self) -> Result<Vec<SmartListCount>, String> {
+        let searches = self.get_saved_searches()?;
+        let conn = self.conn.lock().unwrap();
+        self.ensure_status_tables(&conn).map_err(|e| e.to_string())?;
+        self.auto_mark_tavi_completed(&conn)?;
+
+        searches
+            .into_iter()
+            .filter(|s| s.mostra_in_dashboard)
+            .map(|search| {
+                Ok(SmartListCount {
+                    id: search.id.unwrap_or_default(),
+                    count: Self::count_patients_matching(&conn, &search.filters)?,
+                    nome: search.nome,
+                })
+            })
+            .collect()
+    }
+
+    // ========================================================================
+    // WAITING LIST ANALYTICS
+    // ========================================================================
+
+    /// Tempi del percorso (invio → visita → decisione Heart Team → TAVI) per priorità e
+    /// provenienza, più l'elenco dei pazienti in lista d'attesa oltre il target di priorità.
+    /// L'invio coincide con la registrazione del paziente, la decisione con il primo ingresso
+    /// in "In attesa di TAVI" o "Non candidabile a TAVI".
+    pub fn get_waiting_list_analytics(&self) -> Result<WaitingListAnalytics, String> {
+        let conn = self.conn.lock().unwrap();
+        self.ensure_status_tables(&conn).map_err(|e| e.to_string())?;
+        self.auto_mark_tavi_completed(&conn)?;
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT p.priority, p.provenienza, p.created_at, p.ambulatorio_data_visita, p.data_tavi,
+                        (SELECT MIN(h.changed_at) FROM patient_status_history h
+                          WHERE h.patient_id = p.id
+                            AND h.stato IN ('In attesa di TAVI', 'Non candidabile a TAVI')) AS decisione,
+                        (SELECT MIN(h.changed_at) FROM patient_status_history h
+                          WHERE h.patient_id = p.id AND h.stato = 'In attesa di TAVI') AS decisione_tavi
+                 FROM patients p",
+            )
+            .map_err(|e| e.to_string())?;
+
+        struct Row {
+            priority: Option<String>,
+            provenienza: Option<String>,
+            created_at: Option<String>,
+            data_visita: Option<String>,
+            data_tavi: Option<String>,
+            decisione: Option<String>,
+            decisione_tavi: Option<String>,
+        }
+
+        let rows = stmt
+            .query_map([], |row| {
+                Ok(Row {
+                    priority: row.get(0)?,
+                    provenienza: row.get(1)?,
+                    created_at: row.get(2)?,
+                    data_visita: row.get(3)?,
+                    data_tavi: row.get(4)?,
+                    decisione: row.get(5)?,
+                    decisione_tavi: row.get(6)?,
+                })
+            })
+            .map_err(|e| e.to_string())?
+            .collect::<SqlResult<Vec<_>>>()
+            .map_err(|e| e.to_string())?;
+
+        // Le date possono essere timestamp completi: si considera solo la parte YYYY-MM-DD
+        let parse = |value: &Option<String>| {
+            value
+                .as_deref()
+                .and_then(|v| v.trim().get(..10))
+                .and_then(|v| chrono::NaiveDate::parse_from_str(v, "%Y-%m-%d").ok())
+        };
+        let days_between = |from: &Option<String>, to: &Option<String>| match (parse(from), parse(to)) {
+            (Some(from), Some(to)) if to >= from => Some((to - from).num_days()),
+            _ => None,
+        };
+        let group_label = |value: &Option<String>| {
+            value
+                .as_deref()
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(str::to_string)
+                .unwrap_or_else(|| "Non specificata".to_string())
+        };
+        let today = chrono::Local::now().date_naive();
+
+        struct Sample {
+            intervallo: &'static str,
+            priorita: String,
+            provenienza: String,
+            giorni: i64,
+        }
+        let mut samples: Vec<Sample> = Vec::new();
+
+        for row in &rows {
+            // Senza priorità il paziente conta come "media", come nella lista d'attesa
+            let priority = row
+                .priority
+                .as_deref()
+                .and_then(Priority::parse)
+                .unwrap_or(Priority::Media)
+                .as_str()
+                .to_string();
+            let provenienza = group_label(&row.provenienza);
+            let performed_tavi = row.data_tavi.clone().filter(|_| parse(&row.data_tavi).is_some_and(|d| d <= today));
+
+            let intervals = [
+                ("invio_visita", days_between(&row.created_at, &row.data_visita)),
+                ("visita_decisione", days_between(&row.data_visita, &row.decisione)),
+                ("decisione_tavi", days_between(&row.decisione_tavi, &performed_tavi)),
+            ];
+            for (codice, days) in intervals {
+                if let Some(days) = days {
+                    samples.push(Sample {
+                        intervallo: codice,
+                        priorita: priority.clone(),
+                        provenienza: provenienza.clone(),
+                        giorni: days,
+                    });
+                }
+            }
+        }
+
+        let mut overdue: Vec<OverdueWaitingPatient> = Self::collect_priority_due_items(&conn)?
+            .into_iter()
+            .filter_map(|item| {
+                let target = item.priority.target_days()?;
+                let giorni_oltre_target = item.days_overdue(today);
+                if giorni_oltre_target <= 0 {
+                    return None;
+                }
+                Some(OverdueWaitingPatient {
+                    patient_id: item.patient_id,
+                    nome: item.nome,
+                    cognome: item.cognome,
+                    priority: item.priority,
+                    provenienza: item.provenienza,
+                    data_decisione: item.data_ingresso_lista,
+                    giorni_attesa: item.giorni_attesa,
+                    target_giorni: target,
+                    giorni_oltre_target,
+                    data_tavi_programmata: item.data_tavi,
+                })
+            })
+            .collect();
+        overdue.sort_by_key(|p| std::cmp::Reverse(p.giorni_oltre_target));
+
+        let build_groups = |codice: &str, key: &dyn Fn(&Sample) -> String| {
+            let mut groups: std::collections::BTreeMap<String, Vec<i64>> = std::collections::BTreeMap::new();
+            for sample in samples.iter().filter(|s| s.intervallo == codice) {
+                groups.entry(key(sample)).or_default().push(sample.giorni);
+            }
+            groups
+        };
+        let to_group = |gruppo: String, days: &[i64], target: Option<i64>| {
+            let entro_target = target.map(|t| days.iter().filter(|d| **d <= t).count() as i32);
+            IntervalGroup {
+                statistiche: IntervalStatistics::from_days(days),
+                target_giorni: target,
+                entro_target,
+                percentuale_entro_target: entro_target
+                    .filter(|_| !days.is_empty())
+                    .map(|n| n as f64 / days.len() as f64 * 100.0),
+                gruppo,
+            }
+        };
+
+        let intervalli = WAITING_INTERVALS
+            .iter()
+            .map(|(codice, etichetta)| {
+                let all: Vec<i64> = samples.iter().filter(|s| s.intervallo == *codice).map(|s| s.giorni).collect();
+                // Il target di priorità riguarda solo l'attesa tra decisione e TAVI
+                let with_target = *codice == "decisione_tavi";
+
+                let mut by_priority: Vec<(String, Vec<i64>)> =
+                    build_groups(codice, &|s| s.priorita.clone()).into_iter().collect();
+                by_priority.sort_by_key(|(gruppo, _)| Priority::parse(gruppo).map(|p| p.rank()));
+                let per_priorita = by_priority
+                    .into_iter()
+                    .map(|(gruppo, days)| {
+                        let target = if with_target {
+                            Priority::parse(&gruppo).and_then(|p| p.target_days())
+                        } else {
+                            None
+                        };
+                        to_group(gruppo, &days, target)
+                    })
+                    .collect();
+
+                let per_provenienza = build_groups(codice, &|s| s.provenienza.clone())
+                    .into_iter()
+                    .map(|(gruppo, days)| to_group(gruppo, &days, None))
+                    .collect();
+
+                WaitingIntervalAnalytics {
+                    intervallo: codice.to_string(),
+                    etichetta: etichetta.to_string(),
+                    complessivo: IntervalStatistics::from_days(&all),
+                    per_priorita,
+                    per_provenienza,
+                }
+            })
+            .collect();
+
+        Ok(WaitingListAnalytics {
+            intervalli,
+            pazienti_oltre_target: overdue,
+        })
+    }
+
+    // ========================================================================
+    // PRIORITY ALERTS
+    // ========================================================================
+
+    /// Ordina i pazienti in lista per priorità, poi per scadenza e per ingresso in lista
+    fn sort_by_priority_and_due(items: &mut [PriorityDueItem]) {
+        items.sort_by(|a, b| {
+            a.priority
+                .rank()
+                .cmp(&b.priority.rank())
+                .then_with(|| match (&a.data_scadenza, &b.data_scadenza) {
+                    (Some(x), Some(y)) => x.cmp(y),
+                    (Some(_), None) => std::cmp::Ordering::Less,
+                    (None, Some(_)) => std::cmp::Ordering::Greater,
+                    (None, None) => std::cmp::Ordering::Equal,
+                })
+                .then_with(|| a.data_ingresso_lista.cmp(&b.data_ingresso_lista))
+        });
+    }
+
+    /// Pazienti "In attesa di TAVI" con la scadenza calcolata dalla priorità.
+    /// L'ingresso in lista è il primo passaggio in "In attesa di TAVI" (storico stati),
+    /// in mancanza la data di ingresso nello stato corrente.
+    fn collect_priority_due_items(conn: &Connection) -> Result<Vec<PriorityDueItem>, String> {
+        let mut stmt = conn
+            .prepare(
+                "SELECT p.id, p.nome, p.cognome, p.priority, p.provenienza, p.data_tavi,
+                        COALESCE(
+                            (SELECT MIN(h.changed_at) FROM patient_status_history h
+                              WHERE h.patient_id = p.id AND h.stato = 'In attesa di TAVI'),
+                            ai.created_at
+                        ) AS ingresso
+                 FROM patients p
+                 INNER JOIN patients_in_attesa_intervento ai ON ai.patient_id = p.id",
+            )
+            .map_err(|e| e.to_string())?;
+
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                    row.get::<_, Option<String>>(5)?,
+                    row.get::<_, Option<String>>(6)?,
+                ))
+            })
+            .map_err(|e| e.to_string())?;
+
+        let today = chrono::Local::now().date_naive();
+        let mut items = Vec::new();
+        for row in rows {
+            let (patient_id, nome, cognome, priority, provenienza, data_tavi, ingresso) =
+                row.map_err(|e| e.to_string())?;
+            let entry = match ingresso
+                .as_deref()
+                .and_then(|v| v.trim().get(..10))
+                .and_then(|v| chrono::NaiveDate::parse_from_str(v, "%Y-%m-%d").ok())
+            {
+                Some(date) => date,
+                None => continue,
+            };
+            let priority = priority.as_deref().and_then(Priority::parse).unwrap_or(Priority::Media);
+
+            items.push(PriorityDueItem {
+                patient_id,
+                nome,
+                cognome,
+                priority,
+                provenienza: provenienza.filter(|v| !v.trim().is_empty()),
+                data_ingresso_lista: entry.format("%Y-%m-%d").to_string(),
+                data_scadenza: priority
+                    .target_days()
+                    .map(|days| (entry + chrono::Duration::days(days)).format("%Y-%m-%d").to_string()),
+                giorni_attesa: (today - entry).num_days(),
+                data_tavi: data_tavi.filter(|v| !v.trim().is_empty()),
+            });
+        }
+
+        Ok(items)
+    }
+
+    /// Avvisi per i pazienti oltre la scadenza di priorità, ordinati per ritardo.
+    /// Con `include_suppressed` restituisce anche quelli già visti o posticipati.
+    pub fn get_priority_alerts(&self, include_suppressed: bool) -> Result<Vec<PriorityAlert>, String> {
+        let conn = self.conn.lock().unwrap();
+        self.ensure_status_tables(&conn).map_err(|e| e.to_string())?;
+        self.auto_mark_tavi_completed(&conn)?;
+
+        let items = Self::collect_priority_due_items(&conn)?;
+        let today = chrono::Local::now().date_naive();
+
+        let mut alerts = Vec::new();
+        for item in items {
+            let giorni_ritardo = item.days_overdue(today);
+            let data_scadenza = match item.data_scadenza {
+                Some(due) if giorni_ritardo > 0 => due,
+                _ => continue,
+            };
+
+            let state = conn.query_row(
+                "SELECT acknowledged_due_date, snoozed_until FROM priority_alert_states WHERE patient_id = ?1",
+                params![item.patient_id],
+                |row| Ok((row.get::<_, Option<String>>(0)?, row.get::<_, Option<String>>(1)?)),
+            );
+            let (acknowledged_due_date, snoozed_until) = match state {
+                Ok(state) => state,
+                Err(rusqlite::Error::QueryReturnedNoRows) => (None, None),
+                Err(e) => return Err(e.to_string()),
+            };
+
+            let alert = PriorityAlert {
+                patient_id: item.patient_id,
+                nome: item.nome,
+                cognome: item.cognome,
+                priority: item.priority,
+                data_ingresso_lista: item.data_ingresso_lista,
+                // La presa visione vale solo per la scadenza vista: se cambia, l'avviso ricompare
+                acknowledged: acknowledged_due_date.as_deref() == Some(data_scadenza.as_str()),
+                data_scadenza,
+                giorni_ritardo,
+                data_tavi: item.data_tavi,
+                snoozed_until,
+            };
+            if include_suppressed || !alert.is_suppressed(today) {
+                alerts.push(alert);
+            }
+        }
+
+        alerts.sort_by(|a, b| {
+            a.priority
+                .rank()
+                .cmp(&b.priority.rank())
+                .then_with(|| b.giorni_ritardo.cmp(&a.giorni_ritardo))
+        });
+        Ok(alerts)
+    }
+
+    /// Presa visione dell'avviso per la scadenza corrente del paziente
+    pub fn acknowledge_priority_alert(&self, patient_id: i64) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+        let due = Self::collect_priority_due_items(&conn)?
+            .into_iter()
+            .find(|item| item.patient_id == patient_id)
+            .and_then(|item| item.data_scadenza)
+            .ok_or_else(|| "Il paziente non ha una scadenza di priorità attiva".to_string())?;
+
+        conn.execute(
+            "INSERT INTO priority_alert_states (patient_id, acknowledged_due_date, acknowledged_at, updated_at)
+             VALUES (?1, ?2, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP)
+             ON CONFLICT(patient_id) DO UPDATE SET
+                acknowledged_due_date = excluded.acknowledged_due_date,
+                acknowledged_at = excluded.acknowledged_at,
+                updated_at = CURRENT_TIMESTAMP",
+            params![patient_id, due],
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /// Posticipa l'avviso fino alla data indicata (inclusa); None rimuove il posticipo
+    pub fn snooze_priority_alert(&self, patient_id: i64, until: Option<String>) -> Result<(), String> {
+        let until = until.map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+        if let Some(date) = &until {
+            chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map_err(|_| format!("Data di posticipo non valida: {}", date))?;
+        }
+
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO priority_alert_states (patient_id, snoozed_until, updated_at)
+             VALUES (?1, ?2, CURRENT_TIMESTAMP)
+             ON CONFLICT(patient_id) DO UPDATE SET
+                snoozed_until = excluded.snoozed_until,
+                updated_at = CURRENT_TIMESTAMP",
+            params![patient_id, until],
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    // ========================================================================
+    // AMBULATORIO CALENDAR OPERATIONS
+    // ========================================================================
+
+    fn parse_calendar_date(value: &str) -> Result<chrono::NaiveDate, String> {
+        chrono::NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d")
+            .map_err(|_| format!("Data non valida: {}", value))
+    }
+
+    pub fn get_ambulatorio_slot_templates(&self) -> Result<Vec<AmbulatorioSlotTemplate>, String> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, giorno_settimana, orario, tipo, durata_minuti, attivo
+                 FROM ambulatorio_slot_templates ORDER BY giorno_settimana, orario",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok(AmbulatorioSlotTemplate {
+                    id: Some(row.get(0)?),
+                    giorno_settimana: row.get(1)?,
+                    orario: row.get(2)?,
+                    tipo: row.get(3)?,
+                    durata_minuti: row.get(4)?,
+                    attivo: row.get(5)?,
+                })
+            })
+            .map_err(|e| e.to_string())?;
+
+        let templates: Result<Vec<_>, _> = rows.collect();
+        templates.map_err(|e| e.to_string())
+    }
+
+    pub fn save_ambulatorio_slot_template(&self, template: &AmbulatorioSlotTemplate) -> Result<i64, String> {
+        template.validate()?;
+        let orario = normalize_slot_time(&template.orario).unwrap_or_default();
+        let conn = self.conn.lock().unwrap();
+
+        let result = match template.id {
+            Some(id) => conn
+                .execute(
+                    "UPDATE ambulatorio_slot_templates SET
+                        giorno_settimana = ?1, orario = ?2, tipo = ?3, durata_minuti = ?4, attivo = ?5
+                     WHERE id = ?6",
+                    params![template.giorno_settimana, orario, template.tipo, template.durata_minuti, template.attivo, id],
+                )
+                .map(|_| id),
+            None => conn
+                .execute(
+                    "INSERT INTO ambulatorio_slot_templates (giorno_settimana, orario, tipo, durata_minuti, attivo)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![template.giorno_settimana, orario, template.tipo, template.durata_minuti, template.attivo],
+                )
+                .map(|_| conn.last_insert_rowid()),
+        };
+
+        result.map_err(|e| match e {
+            rusqlite::Error::SqliteFailure(err, _)
+                if err.code == rusqlite::ErrorCode::ConstraintViolation =>
+            {
+                format!("Esiste già uno slot alle {} per questo giorno della settimana", orario)
+            }
+            other => other.to_string(),
+        })
+    }
+
+    pub fn delete_ambulatorio_slot_template(&self, id: i64) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM ambulatorio_slot_templates WHERE id = ?1", params![id])
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /// Sedute ambulatoriali (date aperte) nell'intervallo, con il numero di prenotazioni
+    pub fn get_ambulatorio_sessions(
+        &self,
+        date_from: Option<&str>,
+        date_to: Option<&str>,
+    ) -> Result<Vec<AmbulatorioSession>, String> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare(
+                "SELECT s.id, s.data, s.note,
+                        (SELECT COUNT(*) FROM appointments a JOIN patients p ON p.id = a.patient_id
+                          WHERE a.data = s.data AND a.orario IS NOT NULL AND a.stato <> 'annullato')
+                 FROM ambulatorio_sessions s
+                 WHERE (?1 IS NULL OR s.data >= ?1) AND (?2 IS NULL OR s.data <= ?2)
+                 ORDER BY s.data",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![date_from, date_to], |row| {
+                Ok(AmbulatorioSession {
+                    id: Some(row.get(0)?),
+                    data: row.get(1)?,
+                    note: row.get(2)?,
+                    prenotazioni: row.get(3)?,
+                })
+            })
+            .map_err(|e| e.to_string())?;
+
+        let sessions: Result<Vec<_>, _> = rows.collect();
+        sessions.map_err(|e| e.to_string())
+    }
+
+    pub fn get_ambulatorio_open_dates(&self) -> Result<Vec<String>, String> {
+        Ok(self
+            .get_ambulatorio_sessions(None, None)?
+            .into_iter()
+            .map(|s| s.data)
+            .collect())
+    }
+
+    pub fn open_ambulatorio_session(&self, data: &str, note: Option<String>) -> Result<i64, String> {
+        let date = Self::parse_calendar_date(data)?.format("%Y-%m-%d").to_string();
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO ambulatorio_sessions (data, note) VALUES (?1, ?2)
+             ON CONFLICT(data) DO UPDATE SET note = excluded.note",
+            params![date, note],
+        )
+        .map_err(|e| e.to_string())?;
+        conn.query_row(
+            "SELECT id FROM ambulatorio_sessions WHERE data = ?1",
+            params![date],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())
+    }
+
+    /// Chiude una seduta; rifiutato se ci sono ancora pazienti prenotati
+    pub fn close_ambulatorio_session(&self, data: &str) -> Result<(), String> {
+        let date = Self::parse_calendar_date(data)?.format("%Y-%m-%d").to_string();
+        let conn = self.conn.lock().unwrap();
+        let booked: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM appointments a JOIN patients p ON p.id = a.patient_id
+                 WHERE a.data = ?1 AND a.stato = 'prenotato'",
+                params![date],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        if booked > 0 {
+            return Err(format!(
+                "Impossibile chiudere il {}: {} pazienti prenotati da spostare",
+                date, booked
+            ));
+        }
+
+        Self::with_immediate_transaction(&conn, || {
+            conn.execute("DELETE FROM ambulatorio_sessions WHERE data = ?1", params![date])
+                .and_then(|_| conn.execute("DELETE FROM ambulatorio_blocked_slots WHERE data = ?1", params![date]))
+                .map_err(|e| e.to_string())?;
+            Ok(())
+        })
+    }
+
+    /// Allinea le sedute alle date aperte indicate. Con `remove_missing` chiude anche le
+    /// sedute non elencate (usato quando le date arrivano dalle impostazioni del frontend).
+    pub fn sync_ambulatorio_sessions(&self, dates: &[String], remove_missing: bool) -> Result<(), String> {
+        let dates: Vec<String> = dates
+            .iter()
+            .filter_map(|d| d.trim().get(..10))
+            .filter_map(|d| chrono::NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+            .map(|d| d.format("%Y-%m-%d").to_string())
+            .collect();
+
+        let conn = self.conn.lock().unwrap();
+        Self::with_immediate_transaction(&conn, || {
+            for date in &dates {
+                conn.execute(
+                    "INSERT OR IGNORE INTO ambulatorio_sessions (data) VALUES (?1)",
+                    params![date],
+                )
+                .map_err(|e| e.to_string())?;
+            }
+            if remove_missing {
+                let json = serde_json::to_string(&dates).unwrap_or_else(|_| "[]".to_string());
+                conn.execute(
+                    "DELETE FROM ambulatorio_sessions WHERE data NOT IN (SELECT value FROM json_each(?1))",
+                    params![json],
+                )
+                .map_err(|e| e.to_string())?;
+            }
+            Ok(())
+        })
+    }
+
+    pub fn get_ambulatorio_blocked_slots(
+        &self,
+        date_from: Option<&str>,
+        date_to: Option<&str>,
+    ) -> Result<Vec<AmbulatorioBlockedSlot>, String> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, data, orario, motivo FROM ambulatorio_blocked_slots
+                 WHERE (?1 IS NULL OR data >= ?1) AND (?2 IS NULL OR data <= ?2)
+                 ORDER BY data, orario",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![date_from, date_to], |row| {
+                Ok(AmbulatorioBlockedSlot {
+                    id: Some(row.get(0)?),
+                    data: row.get(1)?,
+                    orario: row.get(2)?,
+                    motivo: row.get(3)?,
+                })
+            })
+            .map_err(|e| e.to_string())?;
+
+        let slots: Result<Vec<_>, _> = rows.collect();
+        slots.map_err(|e| e.to_string())
+    }
+
+    /// Blocca uno slot del modello; rifiutato se lo slot è già prenotato
+    pub fn block_ambulatorio_slot(&self, data: &str, orario: &str, motivo: Option<String>) -> Result<i64, String> {
+        let date = Self::parse_calendar_date(data)?.format("%Y-%m-%d").to_string();
+        let time = normalize_slot_time(orario).ok_or_else(|| format!("Orario non valido: {}", orario))?;
+        let conn = self.conn.lock().unwrap();
+        self.ensure_ambulatorio_slot_available(&conn, Some(&date), Some(&time), None)?;
+
+        conn.execute(
+            "INSERT INTO ambulatorio_blocked_slots (data, orario, motivo) VALUES (?1, ?2, ?3)
+             ON CONFLICT(data, orario) DO UPDATE SET motivo = excluded.motivo",
+            params![date, time, motivo],
+        )
+        .map_err(|e| e.to_string())?;
+        conn.query_row(
+            "SELECT id FROM ambulatorio_blocked_slots WHERE data = ?1 AND orario = ?2",
+            params![date, time],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())
+    }
+
+    pub fn unblock_ambulatorio_slot(&self, id: i64) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM ambulatorio_blocked_slots WHERE id = ?1", params![id])
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /// Slot del modello per una data (solo modelli attivi), in ordine di orario
+    fn template_slots_for_date(conn: &Connection, date: chrono::NaiveDate) -> Result<Vec<AmbulatorioSlot>, String> {
+        use chrono::Datelike;
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT orario, tipo, durata_minuti FROM ambulatorio_slot_templates
+                 WHERE giorno_settimana = ?1 AND attivo = 1 ORDER BY orario",
+            )
+            .map_err(|e| e.to_string())?;
+        let data = date.format("%Y-%m-%d").to_string();
+        let rows = stmt
+            .query_map(params![date.weekday().number_from_monday()], |row| {
+                Ok(AmbulatorioSlot {
+                    data: data.clone(),
+                    orario: row.get(0)?,
+                    tipo: row.get(1)?,
+                    durata_minuti: row.get(2)?,
+                })
+            })
+            .map_err(|e| e.to_string())?;
+
+        let slots: Result<Vec<_>, _> = rows.collect();
+        slots.map_err(|e| e.to_string())
+    }
+
+    /// Slot liberi (non prenotati né bloccati) nelle sedute comprese nell'intervallo
+    pub fn get_ambulatorio_free_slots(&self, date_from: &str, date_to: &str) -> Result<Vec<AmbulatorioSlot>, String> {
+        let from = Self::parse_calendar_date(date_from)?;
+        let to = Self::parse_calendar_date(date_to)?;
+        if to < from {
+            return Err("La data finale precede la data iniziale".to_string());
+        }
+        let (from, to) = (from.format("%Y-%m-%d").to_string(), to.format("%Y-%m-%d").to_string());
+
+        let sessions: Vec<String> = self
+            .get_ambulatorio_sessions(Some(&from), Some(&to))?
+            .into_iter()
+            .map(|s| s.data)
+            .collect();
+        let blocked: std::collections::HashSet<(String, String)> = self
+            .get_ambulatorio_blocked_slots(Some(&from), Some(&to))?
+            .into_iter()
+            .map(|b| (b.data, b.orario))
+            .collect();
+
+        let conn = self.conn.lock().unwrap();
+        let mut booked: std::collections::HashSet<(String, String)> = std::collections::HashSet::new();
+        {
+            let mut stmt = conn
+                .prepare(
+                    "SELECT a.data, a.orario FROM appointments a JOIN patients p ON p.id = a.patient_id
+                     WHERE a.data >= ?1 AND a.data <= ?2
+                       AND a.orario IS NOT NULL AND a.stato <> 'annullato'",
+                )
+                .map_err(|e| e.to_string())?;
+            let rows = stmt
+                .query_map(params![from, to], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))
+                .map_err(|e| e.to_string())?;
+            for row in rows {
+                let (date, time) = row.map_err(|e| e.to_string())?;
+                if let Some(time) = normalize_slot_time(&time) {
+                    booked.insert((date, time));
+                }
+            }
+        }
+
+        let mut free = Vec::new();
+        for session in sessions {
+            let date = Self::parse_calendar_date(&session)?;
+            for slot in Self::template_slots_for_date(&conn, date)? {
+                let key = (slot.data.clone(), slot.orario.clone());
+                if !booked.contains(&key) && !blocked.contains(&key) {
+                    free.push(slot);
+                }
+            }
+        }
+        Ok(free)
+    }
+
+    /// Verifica che data e orario corrispondano a uno slot libero del calendario:
+    /// seduta aperta, orario presente nel modello del giorno, non bloccato né occupato.
+    fn validate_ambulatorio_booking(
+        &self,
+        conn: &Connection,
+        data: &str,
+        orario: &str,
+        exclude_appointment_id: Option<i64>,
+    ) -> Result<(String, String), String> {
+        let date = Self::parse_calendar_date(data)?;
+        let date_str = date.format("%Y-%m-%d").to_string();
+        let time = normalize_slot_time(orario).ok_or_else(|| format!("Orario non valido: {}", orario))?;
+
+        let open: bool = conn
+            .query_row(
+                "SELECT COUNT(*) > 0 FROM ambulatorio_sessions WHERE data = ?1",
+                params![date_str],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        if !open {
+            return Err(format!("L'ambulatorio non è aperto il {}", date_str));
+        }
+
+        if !Self::template_slots_for_date(conn, date)?.iter().any(|s| s.orario == time) {
+            return Err(format!("Le {} non sono uno slot previsto per il {}", time, date_str));
+        }
+
+        let blocked: bool = conn
+            .query_row(
+                "SELECT COUNT(*) > 0 FROM ambulatorio_blocked_slots WHERE data = ?1 AND orario = ?2",
+                params![date_str, time],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        if blocked {
+            return Err(format!("Lo slot {} alle {} è bloccato", date_str, time));
+        }
+
+        let taken: bool = conn
+            .query_row(
+                "SELECT COUNT(*) > 0 FROM appointments a JOIN patients p ON p.id = a.patient_id
+                 WHERE a.data = ?1 AND a.orario = ?2 AND a.stato <> 'annullato'
+                   AND (?3 IS NULL OR a.id <> ?3)",
+                params![date_str, time, exclude_appointment_id],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        if taken {
+            return Err(format!(
+                "Lo slot {} alle {} è già occupato da un altro paziente",
+                date_str, time
+            ));
+        }
+        Ok((date_str, time))
+    }
+
+    /// Prossimo appuntamento ancora prenotato del paziente (il più vicino a oggi), se presente
+    fn pending_appointment(conn: &Connection, patient_id: i64) -> Result<Option<Appointment>, String> {
+        Ok(Self::current_appointment(conn, patient_id)?.filter(|a| a.stato == "prenotato"))
+    }
+
+    /// Prenota o sposta la visita ambulatoriale in un'unica transazione
+    fn assign_ambulatorio_slot(
+        &self,
+        patient_id: i64,
+        data: &str,
+        orario: &str,
+        expect_existing: bool,
+    ) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+        Self::with_immediate_transaction(&conn, || {
+            let patient_exists: bool = conn
+                .query_row("SELECT COUNT(*) > 0 FROM patients WHERE id = ?1", params![patient_id], |row| row.get(0))
+                .map_err(|e| e.to_string())?;
+            if !patient_exists {
+                return Err(format!("Paziente {} non trovato", patient_id));
+            }
+
+            let current = Self::pending_appointment(&conn, patient_id)?;
+            match (&current, expect_existing) {
+                (Some(a), false) => {
+                    return Err(format!(
+                        "Il paziente ha già una visita il {} alle {}: usare lo spostamento",
+                        a.data,
+                        a.orario.as_deref().unwrap_or("-")
+                    ));
+                }
+                (None, true) => return Err("Il paziente non ha una visita da spostare".to_string()),
+                _ => {}
+            }
+
+            match current {
+                Some(appointment) => {
+                    let (date, time) = self.validate_ambulatorio_booking(&conn, data, orario, appointment.id)?;
+                    conn.execute(
+                        "UPDATE appointments SET data = ?1, orario = ?2, updated_at = CURRENT_TIMESTAMP WHERE id = ?3",
+                        params![date, time, appointment.id],
+                    )
+                    .map_err(|e| e.to_string())?;
+                }
+                None => {
+                    let (date, time) = self.validate_ambulatorio_booking(&conn, data, orario, None)?;
+                    conn.execute(
+                        "INSERT INTO appointments (patient_id, data, orario, tipo, stato, medico_titolo, medico_nome)
+                         SELECT id, ?2, ?3, ?4, 'prenotato', medico_titolo, medico_nome FROM patients WHERE id = ?1",
+                        params![patient_id, date, time, Self::default_appointment_type(&conn, patient_id)?],
+                    )
+                    .map_err(|e| e.to_string())?;
+                }
+            }
+            Self::mirror_appointment_to_patient(&conn, patient_id)
+        })
+    }
+
+    pub fn book_ambulatorio_slot(&self, patient_id: i64, data: &str, orario: &str) -> Result<(), String> {
+        self.assign_ambulatorio_slot(patient_id, data, orario, false)
+    }
+
+    pub fn move_ambulatorio_booking(&self, patient_id: i64, data: &str, orario: &str) -> Result<(), String> {
+        self.assign_ambulatorio_slot(patient_id, data, orario, true)
+    }
+
+    /// Annulla la visita prenotata del paziente (l'appuntamento resta nello storico come annullato)
+    pub fn cancel_ambulatorio_booking(&self, patient_id: i64) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+        let appointment = Self::pending_appointment(&conn, patient_id)?
+            .ok_or_else(|| "Il paziente non ha una visita prenotata".to_string())?;
+        conn.execute(
+            "UPDATE appointments SET stato = 'annullato', updated_at = CURRENT_TIMESTAMP WHERE id = ?1",
+            params![appointment.id],
+        )
+        .map_err(|e| e.to_string())?;
+        Self::mirror_appointment_to_patient(&conn, patient_id)
+    }
+
+    // ========================================================================
+    // APPOINTMENT OPERATIONS
+    // ========================================================================
+
+    const APPOINTMENT_COLUMNS: &'static str = "a.id, a.patient_id, a.created_at, a.updated_at, a.data, a.orario, a.tipo, a.stato,
+        a.medico_titolo, a.medico_nome, a.note, a.referto_path";
+
+    fn map_appointment_row(row: &rusqlite::Row) -> SqlResult<Appointment> {
+        Ok(Appointment {
+            id: Some(row.get(0)?),
+            patient_id: row.get(1)?,
+            created_at: row.get(2)?,
+            updated_at: row.get(3)?,
+            data: row.get(4)?,
+            orario: row.get(5)?,
+            tipo: row.get(6)?,
+            stato: row.get(7)?,
+            medico_titolo: row.get(8)?,
+            medico_nome: row.get(9)?,
+            note: row.get(10)?,
+            referto_path: row.get(11)?,
+        })
+    }
+
+    fn load_appointment(conn: &Connection, id: i64) -> Result<Appointment, String> {
+        let sql = format!("SELECT {} FROM appointments a WHERE a.id = ?1", Self::APPOINTMENT_COLUMNS);
+        match conn.query_row(&sql, params![id], Self::map_appointment_row) {
+            Ok(appointment) => Ok(appointment),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Err(format!("Appuntamento {} non trovato", id)),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// Appuntamento che rappresenta la visita corrente del paziente: il prossimo ancora
+    /// prenotato, altrimenti l'ultimo prenotato scaduto, altrimenti l'ultimo non annullato.
+    fn current_appointment(conn: &Connection, patient_id: i64) -> Result<Option<Appointment>, String> {
+        let sql = format!(
+            "SELECT {} FROM appointments a
+             WHERE a.patient_id = ?1 AND a.stato <> 'annullato'
+             ORDER BY CASE
+                         WHEN a.stato = 'prenotato' AND a.data >= date('now', 'localtime') THEN 0
+                         WHEN a.stato = 'prenotato' THEN 1
+                         ELSE 2
+                      END,
+                      CASE WHEN a.stato = 'prenotato' AND a.data >= date('now', 'localtime')
+                           THEN a.data || ' ' || COALESCE(a.orario, '') END ASC,
+                      a.data || ' ' || COALESCE(a.orario, '') DESC
+             LIMIT 1",
+            Self::APPOINTMENT_COLUMNS
+        );
+        match conn.query_row(&sql, params![patient_id], Self::map_appointment_row) {
+            Ok(appointment) => Ok(Some(appointment)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// Tipo proposto per una nuova visita: rivalutazione se il paziente è già stato visitato
+    fn default_appointment_type(conn: &Connection, patient_id: i64) -> Result<&'static str, String> {
+        let visited: bool = conn
+            .query_row(
+                "SELECT COUNT(*) > 0 FROM appointments WHERE patient_id = ?1 AND stato IN ('eseguito', 'pregresso')",
+                params![patient_id],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(if visited { "rivalutazione" } else { "prima_visita" })
+    }
+
+    /// Riporta sulle colonne ambulatorio_* del paziente l'appuntamento corrente, così le viste
+    /// e i referti che leggono ancora il paziente restano coerenti.
+    fn mirror_appointment_to_patient(conn: &Connection, patient_id: i64) -> Result<(), String> {
+        let current = Self::current_appointment(conn, patient_id)?;
+        conn.execute(
+            "UPDATE patients SET ambulatorio_data_visita = ?1, ambulatorio_orario_visita = ?2,
+                    updated_at = CURRENT_TIMESTAMP
+             WHERE id = ?3",
             params![
-                patient.nome,
-                patient.cognome,
-                patient.data_nascita,
-                patient.luogo_nascita,
-                patient.codice_fiscale,
-                patient.telefono,
-                patient.email,
-                patient.provenienza,
-                patient.sesso,
-                patient.altezza,
-                patient.peso,
-                patient.note,
-                fattori_json,
-                patient.anamnesi_cardiologica,
-                patient.apr,
-                patient.visita_odierna,
-                patient.conclusioni,
-                patient.medico_titolo,
-                patient.medico_nome,
-                patient.medico_specializzando_titolo,
-                patient.medico_specializzando_nome,
-                patient.procedurale_allergia_mdc,
-                patient.procedurale_preparazione_mdc,
-                patient.procedurale_creatinina,
-                patient.procedurale_egfr,
-                patient.procedurale_hb,
-                patient.procedurale_altro,
-                patient.data_tavi,
-                patient.procedurale_ecg_ritmo_sinusale,
-                patient.procedurale_ecg_fa,
-                patient.procedurale_ecg_bbs,
-                patient.procedurale_ecg_bbd,
-                patient.procedurale_ecg_eas,
-                patient.procedurale_ecg_bav_primo,
-                patient.procedurale_ecg_ritmo_stimolato,
-                patient.procedurale_anestesia,
-                patient.procedurale_coronarografia,
-                patient.procedurale_coronarografia_note,
-                patient.procedurale_pacemaker,
-                patient.procedurale_pacemaker_note,
-                patient.procedurale_accesso_principale_fem,
-                patient.procedurale_accesso_principale_altro,
-                patient.procedurale_accesso_protezione,
-                patient.procedurale_accesso_protezione_note,
-                patient.procedurale_altri_accessi,
-                patient.procedurale_diametro_pallone_femorale,
-                patient.procedurale_guida_safari,
-                patient.procedurale_protezione_osti,
-                patient.procedurale_valvuloplastica,
-                patient.procedurale_valvuloplastica_note,
-                patient.procedurale_bioprotesi_modello,
-                patient.procedurale_bioprotesi_dimensione,
-                patient.priority,
-                patient.ambulatorio_data_visita,
-                patient.ambulatorio_orario_visita,
+                current.as_ref().map(|a| a.data.clone()),
+                current.as_ref().and_then(|a| a.orario.clone()),
+                patient_id
             ],
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /// Allinea gli appuntamenti alla data/orario di visita salvati dalla scheda paziente:
+    /// sposta la visita prenotata, ne crea una nuova o la annulla se i campi sono stati svuotati.
+    fn sync_appointment_from_patient(conn: &Connection, patient_id: i64, patient: &Patient) -> Result<(), String> {
+        let date = patient
+            .ambulatorio_data_visita
+            .as_deref()
+            .map(str::trim)
+            .filter(|value| !value.is_empty());
+        let time = patient
+            .ambulatorio_orario_visita
+            .as_deref()
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(|value| normalize_slot_time(value).unwrap_or_else(|| value.to_string()));
+
+        let current = Self::current_appointment(conn, patient_id)?;
+        let result = match (date, current) {
+            (None, Some(appointment)) if appointment.stato == "prenotato" => conn.execute(
+                "UPDATE appointments SET stato = 'annullato', updated_at = CURRENT_TIMESTAMP WHERE id = ?1",
+                params![appointment.id],
+            ),
+            (None, _) => return Ok(()),
+            (Some(date), Some(appointment)) if appointment.data == date && appointment.orario == time => {
+                return Ok(());
+            }
+            (Some(date), Some(appointment)) if appointment.stato == "prenotato" => conn.execute(
+                "UPDATE appointments
+                 SET data = ?1, orario = ?2,
+                     stato = CASE WHEN ?1 < date('now', 'localtime') THEN 'pregresso' ELSE 'prenotato' END,
+                     updated_at = CURRENT_TIMESTAMP
+                 WHERE id = ?3",
+                params![date, time, appointment.id],
+            ),
+            (Some(date), _) => conn.execute(
+                "INSERT INTO appointments (patient_id, data, orario, tipo, stato, medico_titolo, medico_nome)
+                 VALUES (?1, ?2, ?3, ?4,
+                         CASE WHEN ?2 < date('now', 'localtime') THEN 'pregresso' ELSE 'prenotato' END,
+                         ?5, ?6)",
+                params![
+                    patient_id,
+                    date,
+                    time,
+                    Self::default_appointment_type(conn, patient_id)?,
+                    patient.medico_titolo,
+                    patient.medico_nome
+                ],
+            ),
+        };
+        result.map_err(|e| e.to_string())?;
+        Self::mirror_appointment_to_patient(conn, patient_id)
+    }
+
+    /// Storico degli appuntamenti di un paziente, dal più recente
+    pub fn get_patient_appointments(&self, patient_id: i64) -> Result<Vec<Appointment>, String> {
+        let conn = self.conn.lock().unwrap();
+        let sql = format!(
+            "SELECT {} FROM appointments a WHERE a.patient_id = ?1
+             ORDER BY a.data DESC, a.orario DESC",
+            Self::APPOINTMENT_COLUMNS
+        );
+        let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![patient_id], Self::map_appointment_row)
+            .map_err(|e| e.to_string())?;
+
+        let appointments: Result<Vec<_>, _> = rows.collect();
+        appointments.map_err(|e| e.to_string())
+    }
+
+    /// Agenda degli appuntamenti nell'intervallo, opzionalmente filtrata per stato
+    pub fn get_appointments(
+        &self,
+        date_from: Option<&str>,
+        date_to: Option<&str>,
+        stato: Option<&str>,
+    ) -> Result<Vec<AppointmentWithPatient>, String> {
+        let conn = self.conn.lock().unwrap();
+        let sql = format!(
+            "SELECT {}, p.nome, p.cognome FROM appointments a JOIN patients p ON p.id = a.patient_id
+             WHERE (?1 IS NULL OR a.data >= ?1) AND (?2 IS NULL OR a.data <= ?2)
+               AND (?3 IS NULL OR a.stato = ?3)
+             ORDER BY a.data, a.orario",
+            Self::APPOINTMENT_COLUMNS
         );
+        let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![date_from, date_to, stato], |row| {
+                Ok(AppointmentWithPatient {
+                    appointment: Self::map_appointment_row(row)?,
+                    nome: row.get(12)?,
+                    cognome: row.get(13)?,
+                })
+            })
+            .map_err(|e| e.to_string())?;
+
+        let appointments: Result<Vec<_>, _> = rows.collect();
+        appointments.map_err(|e| e.to_string())
+    }
+
+    pub fn get_appointment(&self, id: i64) -> Result<Appointment, String> {
+        let conn = self.conn.lock().unwrap();
+        Self::load_appointment(&conn, id)
+    }
+
+    /// Crea o aggiorna un appuntamento. Gli appuntamenti prenotati devono cadere in uno slot
+    /// libero del calendario; quelli già eseguiti o annullati possono essere registrati liberamente.
+    pub fn save_appointment(&self, appointment: &Appointment) -> Result<i64, String> {
+        appointment.validate()?;
+        let conn = self.conn.lock().unwrap();
+        Self::with_immediate_transaction(&conn, || {
+            let patient_exists: bool = conn
+                .query_row(
+                    "SELECT COUNT(*) > 0 FROM patients WHERE id = ?1",
+                    params![appointment.patient_id],
+                    |row| row.get(0),
+                )
+                .map_err(|e| e.to_string())?;
+            if !patient_exists {
+                return Err(format!("Paziente {} non trovato", appointment.patient_id));
+            }
+
+            let previous = appointment.id.map(|id| Self::load_appointment(&conn, id)).transpose()?;
+            let mut data = appointment.data.trim().to_string();
+            let mut orario = appointment
+                .orario
+                .as_deref()
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(|value| normalize_slot_time(value).unwrap_or_else(|| value.to_string()));
+
+            let slot_changed = previous
+                .as_ref()
+                .is_none_or(|p| p.stato != "prenotato" || p.data != data || p.orario != orario);
+            if appointment.stato == "prenotato" && slot_changed {
+                let (date, time) = self.validate_ambulatorio_booking(
+                    &conn,
+                    &data,
+                    orario.as_deref().unwrap_or_default(),
+                    appointment.id,
+                )?;
+                data = date;
+                orario = Some(time);
+            }
+
+            let id = match appointment.id {
+                Some(id) => {
+                    conn.execute(
+                        "UPDATE appointments SET
+                            patient_id = ?1, data = ?2, orario = ?3, tipo = ?4, stato = ?5,
+                            medico_titolo = ?6, medico_nome = ?7, note = ?8,
+                            updated_at = CURRENT_TIMESTAMP
+                         WHERE id = ?9",
+                        params![
+                            appointment.patient_id,
+                            data,
+                            orario,
+                            appointment.tipo,
+                            appointment.stato,
+                            appointment.medico_titolo,
+                            appointment.medico_nome,
+                            appointment.note,
+                            id
+                        ],
+                    )
+                    .map_err(|e| e.to_string())?;
+                    id
+                }
+                None => {
+                    conn.execute(
+                        "INSERT INTO appointments (
+                            patient_id, data, orario, tipo, stato, medico_titolo, medico_nome, note
+                         ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                        params![
+                            appointment.patient_id,
+                            data,
+                            orario,
+                            appointment.tipo,
+                            appointment.stato,
+                            appointment.medico_titolo,
+                            appointment.medico_nome,
+                            appointment.note
+                        ],
+                    )
+                    .map_err(|e| e.to_string())?;
+                    conn.last_insert_rowid()
+                }
+            };
+
+            Self::mirror_appointment_to_patient(&conn, appointment.patient_id)?;
+            if let Some(previous) = previous.filter(|p| p.patient_id != appointment.patient_id) {
+                Self::mirror_appointment_to_patient(&conn, previous.patient_id)?;
+            }
+            Ok(id)
+        })
+    }
+
+    /// Cambia lo stato di un appuntamento (eseguito, non presentato, annullato, di nuovo prenotato)
+    pub fn set_appointment_status(&self, id: i64, stato: &str) -> Result<(), String> {
+        let mut appointment = self.get_appointment(id)?;
+        appointment.stato = stato.to_string();
+        self.save_appointment(&appointment).map(|_| ())
+    }
+
+    pub fn delete_appointment(&self, id: i64) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+        Self::with_immediate_transaction(&conn, || {
+            let appointment = Self::load_appointment(&conn, id)?;
+            conn.execute("DELETE FROM appointments WHERE id = ?1", params![id])
+                .map_err(|e| e.to_string())?;
+            Self::mirror_appointment_to_patient(&conn, appointment.patient_id)
+        })
+    }
+
+    /// Registra il percorso dell'ultimo referto generato per l'appuntamento
+    pub fn set_appointment_referto(&self, id: i64, referto_path: &str) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE appointments SET referto_path = ?1, updated_at = CURRENT_TIMESTAMP WHERE id = ?2",
+            params![referto_path, id],
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    // ========================================================================
+    // CATH-LAB PLANNER OPERATIONS
+    // ========================================================================
+
+    /// Stime di durata per tipo di valvola (mediana delle durate registrate), più la stima
+    /// complessiva 'Tutte' usata quando il tipo non è noto o i dati sono insufficienti.
+    fn cathlab_duration_estimates(procedures: &[Procedure]) -> Vec<CathLabDurationEstimate> {
+        let mut by_type: std::collections::BTreeMap<String, Vec<i64>> = std::collections::BTreeMap::new();
+        let mut all = Vec::new();
+        for proc in procedures {
+            let Some(minutes) = proc.calculate_duration_minutes().filter(|m| *m > 0) else {
+                continue;
+            };
+            all.push(minutes as i64);
+            let tipo = proc.tipo_valvola.trim();
+            if !tipo.is_empty() {
+                by_type.entry(tipo.to_string()).or_default().push(minutes as i64);
+            }
+        }
+
+        let summarize = |tipo: &str, minutes: &[i64], fallback: i32| {
+            let stats = IntervalStatistics::from_days(minutes);
+            let media = if minutes.is_empty() {
+                None
+            } else {
+                Some(minutes.iter().sum::<i64>() as f64 / minutes.len() as f64)
+            };
+            let stima = match stats.mediana {
+                Some(mediana) if minutes.len() >= CATHLAB_MIN_DURATION_SAMPLES => mediana.round() as i32,
+                _ => fallback,
+            };
+            CathLabDurationEstimate {
+                tipo_valvola: tipo.to_string(),
+                procedure_count: minutes.len() as i32,
+                media_minuti: media,
+                mediana_minuti: stats.mediana,
+                stima_minuti: stima,
+            }
+        };
+
+        let overall = summarize("Tutte", &all, CATHLAB_DEFAULT_CASE_MINUTES);
+        let mut estimates: Vec<CathLabDurationEstimate> = by_type
+            .iter()
+            .map(|(tipo, minutes)| summarize(tipo, minutes, overall.stima_minuti))
+            .collect();
+        estimates.push(overall);
+        estimates
+    }
+
+    fn estimate_case_minutes(estimates: &[CathLabDurationEstimate], tipo_valvola: Option<&str>) -> i32 {
+        tipo_valvola
+            .and_then(|tipo| estimates.iter().find(|e| e.tipo_valvola == tipo))
+            .or_else(|| estimates.iter().find(|e| e.tipo_valvola == "Tutte"))
+            .map(|e| e.stima_minuti)
+            .unwrap_or(CATHLAB_DEFAULT_CASE_MINUTES)
+    }
+
+    pub fn get_cathlab_duration_estimates(&self) -> Result<Vec<CathLabDurationEstimate>, String> {
+        let procedures = self.get_all_procedures(None)?;
+        Ok(Self::cathlab_duration_estimates(&procedures))
+    }
+
+    /// Requisiti del caso ricavati dal piano procedurale corrente (colonne procedurale_* del
+    /// paziente): anestesia generale, accesso chirurgico e tipo della valvola pianificata.
+    fn cathlab_case_requirements(
+        conn: &Connection,
+        patient_id: i64,
+        catalogue: &[ValveModel],
+    ) -> Result<(bool, bool, Option<String>), String> {
+        let (anestesia, accesso, modello): (Option<String>, Option<String>, Option<String>) = conn
+            .query_row(
+                "SELECT procedurale_anestesia, procedurale_accesso_principale_fem, procedurale_bioprotesi_modello
+                 FROM patients WHERE id = ?1",
+                params![patient_id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .map_err(|e| match e {
+                rusqlite::Error::QueryReturnedNoRows => format!("Paziente {} non trovato", patient_id),
+                other => other.to_string(),
+            })?;
+
+        let anestesia_generale = anestesia
+            .as_deref()
+            .is_some_and(|a| a.trim().eq_ignore_ascii_case("generale"));
+        let accesso_chirurgico = accesso
+            .as_deref()
+            .is_some_and(|a| a.trim().starts_with("chirurgico"));
+        let tipo_valvola = modello
+            .as_deref()
+            .filter(|m| !m.trim().is_empty())
+            .and_then(|m| catalogue.iter().find(|v| v.matches(m)))
+            .map(|v| v.tipo_valvola.clone());
+        Ok((anestesia_generale, accesso_chirurgico, tipo_valvola))
+    }
+
+    fn map_cathlab_session_row(row: &rusqlite::Row) -> SqlResult<CathLabSession> {
+        Ok(CathLabSession {
+            id: Some(row.get(0)?),
+            data: row.get(1)?,
+            capacita: row.get(2)?,
+            ora_inizio: row.get(3)?,
+            anestesista: row.get(4)?,
+            chirurgo_vascolare: row.get(5)?,
+            note: row.get(6)?,
+            casi: Vec::new(),
+            minuti_pianificati: 0,
+        })
+    }
+
+    fn load_cathlab_session(conn: &Connection, id: i64) -> Result<CathLabSession, String> {
+        match conn.query_row(
+            "SELECT id, data, capacita, ora_inizio, anestesista, chirurgo_vascolare, note
+             FROM cathlab_sessions WHERE id = ?1",
+            params![id],
+            Self::map_cathlab_session_row,
+        ) {
+            Ok(session) => Ok(session),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Err(format!("Seduta {} non trovata", id)),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// Orario HH:MM ottenuto sommando i minuti all'ora di inizio della seduta
+    fn cathlab_clock(ora_inizio: &str, minutes: i32) -> String {
+        chrono::NaiveTime::parse_from_str(ora_inizio, "%H:%M")
+            .map(|start| (start + chrono::Duration::minutes(minutes as i64)).format("%H:%M").to_string())
+            .unwrap_or_else(|_| ora_inizio.to_string())
+    }
+
+    /// Sedute nell'intervallo con i casi in ordine, durata stimata e orario previsto di ciascuno
+    fn load_cathlab_sessions(
+        conn: &Connection,
+        date_from: Option<&str>,
+        date_to: Option<&str>,
+        estimates: &[CathLabDurationEstimate],
+        catalogue: &[ValveModel],
+    ) -> Result<Vec<CathLabSession>, String> {
+        let mut sessions: Vec<CathLabSession> = {
+            let mut stmt = conn
+                .prepare(
+                    "SELECT id, data, capacita, ora_inizio, anestesista, chirurgo_vascolare, note
+                     FROM cathlab_sessions
+                     WHERE (?1 IS NULL OR data >= ?1) AND (?2 IS NULL OR data <= ?2)
+                     ORDER BY data",
+                )
+                .map_err(|e| e.to_string())?;
+            let rows = stmt
+                .query_map(params![date_from, date_to], Self::map_cathlab_session_row)
+                .map_err(|e| e.to_string())?;
+            let sessions: Result<Vec<_>, _> = rows.collect();
+            sessions.map_err(|e| e.to_string())?
+        };
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT c.id, c.patient_id, c.ordine, c.durata_minuti, c.note, p.nome, p.cognome, p.priority
+                 FROM cathlab_cases c JOIN patients p ON p.id = c.patient_id
+                 WHERE c.session_id = ?1
+                 ORDER BY c.ordine, c.id",
+            )
+            .map_err(|e| e.to_string())?;
+
+        for session in &mut sessions {
+            let session_id = session.id.unwrap_or_default();
+            let rows = stmt
+                .query_map(params![session_id], |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, i64>(1)?,
+                        row.get::<_, i32>(2)?,
+                        row.get::<_, Option<i32>>(3)?,
+                        row.get::<_, Option<String>>(4)?,
+                        row.get::<_, String>(5)?,
+                        row.get::<_, String>(6)?,
+                        row.get::<_, Option<String>>(7)?,
+                    ))
+                })
+                .map_err(|e| e.to_string())?;
+
+            let mut elapsed = 0;
+            for row in rows {
+                let (id, patient_id, ordine, durata_manuale, note, nome, cognome, priority) =
+                    row.map_err(|e| e.to_string())?;
+                let (anestesia_generale, accesso_chirurgico, tipo_valvola) =
+                    Self::cathlab_case_requirements(conn, patient_id, catalogue)?;
+                let durata = durata_manuale
+                    .unwrap_or_else(|| Self::estimate_case_minutes(estimates, tipo_valvola.as_deref()));
+                session.casi.push(CathLabCase {
+                    id: Some(id),
+                    session_id,
+                    patient_id,
+                    nome,
+                    cognome,
+                    priority: priority.as_deref().and_then(Priority::parse),
+                    ordine,
+                    avvisi: session.missing_resources(anestesia_generale, accesso_chirurgico),
+                    tipo_valvola,
+                    durata_manuale_minuti: durata_manuale,
+                    durata_stimata_minuti: durata,
+                    orario_stimato: Self::cathlab_clock(&session.ora_inizio, elapsed),
+                    anestesia_generale,
+                    accesso_chirurgico,
+                    note,
+                });
+                elapsed += durata;
+            }
+            session.minuti_pianificati = elapsed;
+        }
+
+        Ok(sessions)
+    }
+
+    pub fn get_cathlab_sessions(
+        &self,
+        date_from: Option<&str>,
+        date_to: Option<&str>,
+    ) -> Result<Vec<CathLabSession>, String> {
+        let estimates = self.get_cathlab_duration_estimates()?;
+        let catalogue = self.get_valve_models()?;
+        let conn = self.conn.lock().unwrap();
+        Self::load_cathlab_sessions(&conn, date_from, date_to, &estimates, &catalogue)
+    }
 
-        match result {
-            Ok(_) => {
-                let patient_id = conn.last_insert_rowid();
-
-                // Inserisci in patients_da_valutare (stato iniziale)
-                match conn.execute(
-                    "INSERT INTO patients_da_valutare (patient_id) VALUES (?1)",
-                    params![patient_id],
-                ) {
-                    Ok(_) => {
-                        conn.execute("COMMIT", []).map_err(|e| e.to_string())?;
-                        Ok(patient_id)
-                    }
-                    Err(e) => {
-                        conn.execute("ROLLBACK", []).ok();
-                        Err(e.to_string())
+    /// Crea o aggiorna una seduta; la capacità non può scendere sotto i casi già assegnati
+    /// e le risorse ridotte devono restare compatibili con i casi già in lista
+    pub fn save_cathlab_session(&self, session: &CathLabSession) -> Result<i64, String> {
+        session.validate()?;
+        let data = session.data.trim().to_string();
+        let ora_inizio = normalize_slot_time(&session.ora_inizio).unwrap_or_default();
+        let catalogue = self.get_valve_models()?;
+        let conn = self.conn.lock().unwrap();
+
+        let duplicate = |e: rusqlite::Error| match e {
+            rusqlite::Error::SqliteFailure(err, _)
+                if err.code == rusqlite::ErrorCode::ConstraintViolation =>
+            {
+                format!("Esiste già una seduta TAVI il {}", data)
+            }
+            other => other.to_string(),
+        };
+
+        Self::with_immediate_transaction(&conn, || match session.id {
+            Some(id) => {
+                let previous = Self::load_cathlab_session(&conn, id)?;
+                let patient_ids: Vec<i64> = {
+                    let mut stmt = conn
+                        .prepare("SELECT patient_id FROM cathlab_cases WHERE session_id = ?1 ORDER BY ordine")
+                        .map_err(|e| e.to_string())?;
+                    let rows = stmt
+                        .query_map(params![id], |row| row.get(0))
+                        .map_err(|e| e.to_string())?;
+                    rows.collect::<SqlResult<Vec<i64>>>().map_err(|e| e.to_string())?
+                };
+                let cases = patient_ids.len() as i32;
+                if session.capacita < cases {
+                    return Err(format!(
+                        "La seduta ha già {} casi: ridurre la capacità a {} richiede di spostarne alcuni",
+                        cases, session.capacita
+                    ));
+                }
+
+                conn.execute(
+                    "UPDATE cathlab_sessions SET
+                        data = ?1, capacita = ?2, ora_inizio = ?3, anestesista = ?4, chirurgo_vascolare = ?5, note = ?6
+                     WHERE id = ?7",
+                    params![data, session.capacita, ora_inizio, session.anestesista, session.chirurgo_vascolare, session.note, id],
+                )
+                .map_err(duplicate)?;
+
+                if (previous.anestesista && !session.anestesista)
+                    || (previous.chirurgo_vascolare && !session.chirurgo_vascolare)
+                {
+                    let updated = CathLabSession {
+                        id: Some(id),
+                        data: data.clone(),
+                        ora_inizio: ora_inizio.clone(),
+                        ..session.clone()
+                    };
+                    for patient_id in &patient_ids {
+                        Self::ensure_cathlab_case_allowed(&conn, &updated, *patient_id, &catalogue)?;
                     }
                 }
+
+                // I pazienti della seduta seguono il cambio di data
+                conn.execute(
+                    "UPDATE patients SET data_tavi = ?1, updated_at = CURRENT_TIMESTAMP
+                     WHERE data_tavi = ?2 AND id IN (SELECT patient_id FROM cathlab_cases WHERE session_id = ?3)",
+                    params![data, previous.data, id],
+                )
+                .map_err(|e| e.to_string())?;
+                Ok(id)
+            }
+            None => {
+                conn.execute(
+                    "INSERT INTO cathlab_sessions (data, capacita, ora_inizio, anestesista, chirurgo_vascolare, note)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    params![data, session.capacita, ora_inizio, session.anestesista, session.chirurgo_vascolare, session.note],
+                )
+                .map_err(duplicate)?;
+                Ok(conn.last_insert_rowid())
+            }
+        })
+    }
+
+    /// Elimina una seduta vuota
+    pub fn delete_cathlab_session(&self, id: i64) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+        let cases: i64 = conn
+            .query_row("SELECT COUNT(*) FROM cathlab_cases WHERE session_id = ?1", params![id], |row| row.get(0))
+            .map_err(|e| e.to_string())?;
+        if cases > 0 {
+            return Err(format!("Impossibile eliminare la seduta: {} casi da spostare", cases));
+        }
+        conn.execute("DELETE FROM cathlab_sessions WHERE id = ?1", params![id])
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /// Verifica capacità e risorse della seduta per il paziente
+    fn ensure_cathlab_case_allowed(
+        conn: &Connection,
+        session: &CathLabSession,
+        patient_id: i64,
+        catalogue: &[ValveModel],
+    ) -> Result<(), String> {
+        let session_id = session.id.unwrap_or_default();
+        let cases: i32 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM cathlab_cases WHERE session_id = ?1 AND patient_id <> ?2",
+                params![session_id, patient_id],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        if cases >= session.capacita {
+            return Err(format!(
+                "La seduta TAVI del {} è al completo ({} casi)",
+                session.data, session.capacita
+            ));
+        }
+
+        let (anestesia_generale, accesso_chirurgico, _) =
+            Self::cathlab_case_requirements(conn, patient_id, catalogue)?;
+        let missing = session.missing_resources(anestesia_generale, accesso_chirurgico);
+        if !missing.is_empty() {
+            return Err(format!("Seduta del {}: {}", session.data, missing.join("; ")));
+        }
+        Ok(())
+    }
+
+    /// Riassegna l'ordine 1..n dei casi di una seduta mantenendo la sequenza attuale
+    fn compact_cathlab_order(conn: &Connection, session_id: i64) -> Result<(), String> {
+        conn.execute(
+            "UPDATE cathlab_cases SET ordine = (
+                SELECT COUNT(*) FROM cathlab_cases c2
+                WHERE c2.session_id = cathlab_cases.session_id
+                  AND (c2.ordine < cathlab_cases.ordine OR (c2.ordine = cathlab_cases.ordine AND c2.id <= cathlab_cases.id))
+             )
+             WHERE session_id = ?1",
+            params![session_id],
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /// Caso ancora da eseguire (seduta da oggi in poi) del paziente, se presente
+    fn pending_cathlab_case(conn: &Connection, patient_id: i64) -> Result<Option<(i64, i64, String)>, String> {
+        let result = conn.query_row(
+            "SELECT c.id, s.id, s.data FROM cathlab_cases c JOIN cathlab_sessions s ON s.id = c.session_id
+             WHERE c.patient_id = ?1 AND s.data >= date('now', 'localtime')
+             ORDER BY s.data LIMIT 1",
+            params![patient_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        );
+        match result {
+            Ok(found) => Ok(Some(found)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// Esegue `f` in una transazione BEGIN IMMEDIATE
+    fn with_immediate_transaction<T>(
+        conn: &Connection,
+        f: impl FnOnce() -> Result<T, String>,
+    ) -> Result<T, String> {
+        conn.execute("BEGIN IMMEDIATE", []).map_err(|e| e.to_string())?;
+        match f() {
+            Ok(value) => {
+                conn.execute("COMMIT", []).map_err(|e| e.to_string())?;
+                Ok(value)
             }
             Err(e) => {
                 conn.execute("ROLLBACK", []).ok();
-                Err(e.to_string())
+                Err(e)
             }
         }
     }
 
-    /// Aggiorna anagrafica paziente (non cambia stato)
-    pub fn update_patient(&self, patient: &Patient) -> Result<(), String> {
+    /// Aggiunge il paziente in coda alla seduta e ne imposta la data TAVI
+    pub fn add_cathlab_case(
+        &self,
+        session_id: i64,
+        patient_id: i64,
+        durata_minuti: Option<i32>,
+        note: Option<String>,
+    ) -> Result<i64, String> {
+        if durata_minuti.is_some_and(|d| d <= 0) {
+            return Err("La durata del caso deve essere positiva".to_string());
+        }
+        let catalogue = self.get_valve_models()?;
         let conn = self.conn.lock().unwrap();
-        let id = patient.id.ok_or("Patient ID is required for update")?;
-        self.ensure_ambulatorio_slot_available(
-            &conn,
-            patient.ambulatorio_data_visita.as_deref(),
-            patient.ambulatorio_orario_visita.as_deref(),
-            Some(id),
-        )?;
-        let fattori_json = patient.ambulatorio_fattori.as_ref().and_then(|f| serde_json::to_string(f).ok());
 
-        conn.execute(
-            "UPDATE patients SET
-                nome = ?1, cognome = ?2, data_nascita = ?3,
-                luogo_nascita = ?4, codice_fiscale = ?5, telefono = ?6,
-                email = ?7, provenienza = ?8, sesso = ?9, altezza = ?10, peso = ?11,
-                note = ?12,
-                ambulatorio_fattori = ?13, anamnesi_cardiologica = ?14, apr = ?15,
-                visita_odierna = ?16, conclusioni = ?17, medico_titolo = ?18, medico_nome = ?19,
-                medico_specializzando_titolo = ?20, medico_specializzando_nome = ?21,
-                procedurale_allergia_mdc = ?22, procedurale_preparazione_mdc = ?23, procedurale_creatinina = ?24,
-                procedurale_egfr = ?25, procedurale_hb = ?26, procedurale_altro = ?27, data_tavi = ?28,
-                procedurale_ecg_ritmo_sinusale = ?29, procedurale_ecg_fa = ?30, procedurale_ecg_bbs = ?31,
-                procedurale_ecg_bbd = ?32, procedurale_ecg_eas = ?33, procedurale_ecg_bav_primo = ?34,
-                procedurale_ecg_ritmo_stimolato = ?35,
-                procedurale_anestesia = ?36, procedurale_coronarografia = ?37, procedurale_coronarografia_note = ?38,
-                procedurale_pacemaker = ?39, procedurale_pacemaker_note = ?40,
-                procedurale_accesso_principale_fem = ?41, procedurale_accesso_principale_altro = ?42,
-                procedurale_accesso_protezione = ?43, procedurale_accesso_protezione_note = ?44,
-                procedurale_altri_accessi = ?45, procedurale_diametro_pallone_femorale = ?46,
-                procedurale_guida_safari = ?47, procedurale_protezione_osti = ?48,
-                procedurale_valvuloplastica = ?49, procedurale_valvuloplastica_note = ?50,
-                procedurale_bioprotesi_modello = ?51, procedurale_bioprotesi_dimensione = ?52,
-                priority = ?53,
-                ambulatorio_data_visita = ?54,
-                ambulatorio_orario_visita = ?55,
-                updated_at = CURRENT_TIMESTAMP
-             WHERE id = ?56",
-            params![
-                patient.nome,
-                patient.cognome,
-                patient.data_nascita,
-                patient.luogo_nascita,
-                patient.codice_fiscale,
-                patient.telefono,
-                patient.email,
-                patient.provenienza,
-                patient.sesso,
-                patient.altezza,
-                patient.peso,
-                patient.note,
-                fattori_json,
-                patient.anamnesi_cardiologica,
-                patient.apr,
-                patient.visita_odierna,
-                patient.conclusioni,
-                patient.medico_titolo,
-                patient.medico_nome,
-                patient.medico_specializzando_titolo,
-                patient.medico_specializzando_nome,
-                patient.procedurale_allergia_mdc,
-                patient.procedurale_preparazione_mdc,
-                patient.procedurale_creatinina,
-                patient.procedurale_egfr,
-                patient.procedurale_hb,
-                patient.procedurale_altro,
-                patient.data_tavi,
-                patient.procedurale_ecg_ritmo_sinusale,
-                patient.procedurale_ecg_fa,
-                patient.procedurale_ecg_bbs,
-                patient.procedurale_ecg_bbd,
-                patient.procedurale_ecg_eas,
-                patient.procedurale_ecg_bav_primo,
-                patient.procedurale_ecg_ritmo_stimolato,
-                patient.procedurale_anestesia,
-                patient.procedurale_coronarografia,
-                patient.procedurale_coronarografia_note,
-                patient.procedurale_pacemaker,
-                patient.procedurale_pacemaker_note,
-                patient.procedurale_accesso_principale_fem,
-                patient.procedurale_accesso_principale_altro,
-                patient.procedurale_accesso_protezione,
-                patient.procedurale_accesso_protezione_note,
-                patient.procedurale_altri_accessi,
-                patient.procedurale_diametro_pallone_femorale,
-                patient.procedurale_guida_safari,
-                patient.procedurale_protezione_osti,
-                patient.procedurale_valvuloplastica,
-                patient.procedurale_valvuloplastica_note,
-                patient.procedurale_bioprotesi_modello,
-                patient.procedurale_bioprotesi_dimensione,
-                patient.priority,
-                patient.ambulatorio_data_visita,
-                patient.ambulatorio_orario_visita,
-                id,
-            ],
-        ).map_err(|e| e.to_string())?;
+        Self::with_immediate_transaction(&conn, || {
+            let session = Self::load_cathlab_session(&conn, session_id)?;
+            if let Some((_, _, data)) = Self::pending_cathlab_case(&conn, patient_id)? {
+                return Err(format!(
+                    "Il paziente è già programmato il {}: usare lo spostamento",
+                    data
+                ));
+            }
+            Self::ensure_cathlab_case_allowed(&conn, &session, patient_id, &catalogue)?;
 
-        Ok(())
+            conn.execute(
+                "INSERT INTO cathlab_cases (session_id, patient_id, ordine, durata_minuti, note)
+                 VALUES (?1, ?2, (SELECT COALESCE(MAX(ordine), 0) + 1 FROM cathlab_cases WHERE session_id = ?1), ?3, ?4)",
+                params![session_id, patient_id, durata_minuti, note],
+            )
+            .map_err(|e| match e {
+                rusqlite::Error::SqliteFailure(err, _)
+                    if err.code == rusqlite::ErrorCode::ConstraintViolation =>
+                {
+                    "Il paziente è già in questa seduta".to_string()
+                }
+                other => other.to_string(),
+            })?;
+            let id = conn.last_insert_rowid();
+            conn.execute(
+                "UPDATE patients SET data_tavi = ?1, updated_at = CURRENT_TIMESTAMP WHERE id = ?2",
+                params![session.data, patient_id],
+            )
+            .map_err(|e| e.to_string())?;
+            Ok(id)
+        })
     }
 
-    /// Elimina paziente (CASCADE rimuove automaticamente da tabelle stato)
-    pub fn delete_patient(&self, id: i64) -> Result<(), String> {
+    /// Sposta un caso in coda a un'altra seduta
+    pub fn move_cathlab_case(&self, case_id: i64, session_id: i64) -> Result<(), String> {
+        let catalogue = self.get_valve_models()?;
         let conn = self.conn.lock().unwrap();
-        conn.execute("DELETE FROM patients WHERE id = ?1", params![id])
+
+        Self::with_immediate_transaction(&conn, || {
+            let (patient_id, from_session): (i64, i64) = conn
+                .query_row(
+                    "SELECT patient_id, session_id FROM cathlab_cases WHERE id = ?1",
+                    params![case_id],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .map_err(|e| match e {
+                    rusqlite::Error::QueryReturnedNoRows => format!("Caso {} non trovato", case_id),
+                    other => other.to_string(),
+                })?;
+            if from_session == session_id {
+                return Ok(());
+            }
+
+            let session = Self::load_cathlab_session(&conn, session_id)?;
+            Self::ensure_cathlab_case_allowed(&conn, &session, patient_id, &catalogue)?;
+            conn.execute(
+                "UPDATE cathlab_cases SET session_id = ?1,
+                        ordine = (SELECT COALESCE(MAX(ordine), 0) + 1 FROM cathlab_cases WHERE session_id = ?1)
+                 WHERE id = ?2",
+                params![session_id, case_id],
+            )
+            .map_err(|e| e.to_string())?;
+            Self::compact_cathlab_order(&conn, from_session)?;
+            conn.execute(
+                "UPDATE patients SET data_tavi = ?1, updated_at = CURRENT_TIMESTAMP WHERE id = ?2",
+                params![session.data, patient_id],
+            )
+            .map_err(|e| e.to_string())?;
+            Ok(())
+        })
+    }
+
+    /// Toglie un caso dalla seduta; la data TAVI del paziente viene azzerata se coincideva
+    pub fn remove_cathlab_case(&self, case_id: i64) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+
+        Self::with_immediate_transaction(&conn, || {
+            let (patient_id, session_id, data): (i64, i64, String) = conn
+                .query_row(
+                    "SELECT c.patient_id, c.session_id, s.data
+                     FROM cathlab_cases c JOIN cathlab_sessions s ON s.id = c.session_id
+                     WHERE c.id = ?1",
+                    params![case_id],
+                    |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+                )
+                .map_err(|e| match e {
+                    rusqlite::Error::QueryReturnedNoRows => format!("Caso {} non trovato", case_id),
+                    other => other.to_string(),
+                })?;
+            conn.execute("DELETE FROM cathlab_cases WHERE id = ?1", params![case_id])
+                .map_err(|e| e.to_string())?;
+            Self::compact_cathlab_order(&conn, session_id)?;
+            conn.execute(
+                "UPDATE patients SET data_tavi = NULL, updated_at = CURRENT_TIMESTAMP
+                 WHERE id = ?1 AND data_tavi = ?2",
+                params![patient_id, data],
+            )
+            .map_err(|e| e.to_string())?;
+            Ok(())
+        })
+    }
+
+    /// Imposta l'ordine dei casi della seduta secondo la sequenza di id indicata
+    pub fn reorder_cathlab_cases(&self, session_id: i64, case_ids: &[i64]) -> Result<(), String> {
+        let conn = self.conn.lock().unwrap();
+
+        Self::with_immediate_transaction(&conn, || {
+            let mut current: Vec<i64> = {
+                let mut stmt = conn
+                    .prepare("SELECT id FROM cathlab_cases WHERE session_id = ?1")
+                    .map_err(|e| e.to_string())?;
+                let rows = stmt
+                    .query_map(params![session_id], |row| row.get(0))
+                    .map_err(|e| e.to_string())?;
+                let ids: Result<Vec<i64>, _> = rows.collect();
+                ids.map_err(|e| e.to_string())?
+            };
+            let mut requested = case_ids.to_vec();
+            current.sort_unstable();
+            requested.sort_unstable();
+            if current != requested {
+                return Err("L'ordinamento deve includere tutti e soli i casi della seduta".to_string());
+            }
+
+            for (index, id) in case_ids.iter().enumerate() {
+                conn.execute(
+                    "UPDATE cathlab_cases SET ordine = ?1 WHERE id = ?2",
+                    params![index as i32 + 1, id],
+                )
+                .map_err(|e| e.to_string())?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Aggiorna durata manuale (None = stima storica) e note di un caso
+    pub fn update_cathlab_case(&self, case_id: i64, durata_minuti: Option<i32>, note: Option<String>) -> Result<(), String> {
+        if durata_minuti.is_some_and(|d| d <= 0) {
+            return Err("La durata del caso deve essere positiva".to_string());
+        }
+        let conn = self.conn.lock().unwrap();
+        let updated = conn
+            .execute(
+                "UPDATE cathlab_cases SET durata_minuti = ?1, note = ?2 WHERE id = ?3",
+                params![durata_minuti, note, case_id],
+            )
             .map_err(|e| e.to_string())?;
+        if updated == 0 {
+            return Err(format!("Caso {} non trovato", case_id));
+        }
+        Ok(())
+    }
+
+    /// Rifiuta una data TAVI inserita dalla scheda paziente se la seduta di quel giorno è piena.
+    /// Senza seduta vale la capacità predefinita, contando i pazienti già fissati in quella data.
+    fn ensure_cathlab_capacity(conn: &Connection, data_tavi: Option<&str>, patient_id: Option<i64>) -> Result<(), String> {
+        let Some(date) = data_tavi.map(str::trim).filter(|d| !d.is_empty()) else {
+            return Ok(());
+        };
+        let result = conn.query_row(
+            "SELECT s.capacita,
+                    (SELECT COUNT(*) FROM cathlab_cases c WHERE c.session_id = s.id AND (?2 IS NULL OR c.patient_id <> ?2))
+             FROM cathlab_sessions s WHERE s.data = ?1",
+            params![date, patient_id],
+            |row| Ok((row.get::<_, i32>(0)?, row.get::<_, i32>(1)?)),
+        );
+        let (capacita, cases) = match result {
+            Ok(counts) => counts,
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                // Le date passate registrano TAVI già eseguite e non sono vincolate dal planner
+                if date < chrono::Local::now().format("%Y-%m-%d").to_string().as_str() {
+                    return Ok(());
+                }
+                let scheduled: i32 = conn
+                    .query_row(
+                        "SELECT COUNT(*) FROM patients WHERE TRIM(data_tavi) = ?1 AND (?2 IS NULL OR id <> ?2)",
+                        params![date, patient_id],
+                        |row| row.get(0),
+                    )
+                    .map_err(|e| e.to_string())?;
+                (CATHLAB_DEFAULT_CAPACITY, scheduled)
+            }
+            Err(e) => return Err(e.to_string()),
+        };
+        if cases >= capacita {
+            return Err(format!(
+                "La seduta TAVI del {} è al completo ({} casi)",
+                date, capacita
+            ));
+        }
+        Ok(())
+    }
+
+    /// Allinea i casi del planner alla data TAVI salvata dalla scheda paziente: toglie il
+    /// paziente dalle sedute future di altre date e lo accoda alla seduta del giorno, se esiste,
+    /// dopo aver verificato capacità e risorse (anestesista, chirurgo vascolare).
+    fn sync_cathlab_case_from_patient(conn: &Connection, patient_id: i64, data_tavi: Option<&str>) -> Result<(), String> {
+        let date = data_tavi.map(str::trim).filter(|d| !d.is_empty()).unwrap_or_default();
+
+        let stale: Vec<(i64, i64)> = {
+            let mut stmt = conn
+                .prepare(
+                    "SELECT c.id, c.session_id FROM cathlab_cases c JOIN cathlab_sessions s ON s.id = c.session_id
+                     WHERE c.patient_id = ?1 AND s.data >= date('now', 'localtime') AND s.data <> ?2",
+                )
+                .map_err(|e| e.to_string())?;
+            let rows = stmt
+                .query_map(params![patient_id, date], |row| Ok((row.get(0)?, row.get(1)?)))
+                .map_err(|e| e.to_string())?;
+            let stale: Result<Vec<_>, _> = rows.collect();
+            stale.map_err(|e| e.to_string())?
+        };
+        for (case_id, session_id) in stale {
+            conn.execute("DELETE FROM cathlab_cases WHERE id = ?1", params![case_id])
+                .map_err(|e| e.to_string())?;
+            Self::compact_cathlab_order(conn, session_id)?;
+        }
+
+        if date.is_empty() || date < chrono::Local::now().format("%Y-%m-%d").to_string().as_str() {
+            return Ok(());
+        }
+        let session = match conn.query_row(
+            "SELECT id, data, capacita, ora_inizio, anestesista, chirurgo_vascolare, note
+             FROM cathlab_sessions WHERE data = ?1",
+            params![date],
+            Self::map_cathlab_session_row,
+        ) {
+            Ok(session) => session,
+            Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(()),
+            Err(e) => return Err(e.to_string()),
+        };
+        let catalogue = Self::load_valve_models(conn).map_err(|e| e.to_string())?;
+        Self::ensure_cathlab_case_allowed(conn, &session, patient_id, &catalogue)?;
+
+        let session_id = session.id.unwrap_or_default();
+        conn.execute(
+            "INSERT INTO cathlab_cases (session_id, patient_id, ordine)
+             SELECT ?1, ?2, (SELECT COALESCE(MAX(ordine), 0) + 1 FROM cathlab_cases WHERE session_id = ?1)
+             WHERE NOT EXISTS (SELECT 1 FROM cathlab_cases WHERE session_id = ?1 AND patient_id = ?2)",
+            params![session_id, patient_id],
+        )
+        .map_err(|e| e.to_string())?;
         Ok(())
     }
 
-    /// Ottieni tutti i pazienti con status
-    pub fn get_all_patients_with_status(&self, filters: Option<PatientFilters>) -> Result<Vec<PatientWithStatus>, String> {
-        let conn = self.conn.lock().unwrap();
-        self.ensure_status_tables(&conn).map_err(|e| e.to_string())?;
-        self.auto_mark_tavi_completed(&conn)?;
+    /// Propone una seduta per i pazienti in lista senza data TAVI, in ordine di priorità e
+    /// scadenza: ciascuno va nella prima seduta con posti liberi e le risorse richieste.
+    pub fn suggest_cathlab_slots(
+        &self,
+        date_from: Option<&str>,
+        date_to: Option<&str>,
+    ) -> Result<Vec<CathLabSlotSuggestion>, String> {
+        let estimates = self.get_cathlab_duration_estimates()?;
+        let catalogue = self.get_valve_models()?;
+        let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+        let from = date_from.map(str::trim).filter(|d| !d.is_empty()).unwrap_or(&today).to_string();
+
+        let conn = self.conn.lock().unwrap();
+        self.ensure_status_tables(&conn).map_err(|e| e.to_string())?;
+        self.auto_mark_tavi_completed(&conn)?;
+
+        let mut sessions = Self::load_cathlab_sessions(&conn, Some(&from), date_to, &estimates, &catalogue)?;
+        let mut pending: Vec<PriorityDueItem> = Self::collect_priority_due_items(&conn)?
+            .into_iter()
+            .filter(|item| item.data_tavi.is_none())
+            .collect();
+        Self::sort_by_priority_and_due(&mut pending);
+
+        let mut suggestions = Vec::new();
+        for item in pending {
+            let (anestesia_generale, accesso_chirurgico, tipo_valvola) =
+                Self::cathlab_case_requirements(&conn, item.patient_id, &catalogue)?;
+            let durata = Self::estimate_case_minutes(&estimates, tipo_valvola.as_deref());
+
+            let with_room: Vec<&mut CathLabSession> = sessions
+                .iter_mut()
+                .filter(|s| (s.casi.len() as i32) < s.capacita)
+                .collect();
+            let has_room = !with_room.is_empty();
+            let target = with_room
+                .into_iter()
+                .find(|s| s.missing_resources(anestesia_generale, accesso_chirurgico).is_empty());
+
+            let mut suggestion = CathLabSlotSuggestion {
+                patient_id: item.patient_id,
+                nome: item.nome.clone(),
+                cognome: item.cognome.clone(),
+                priority: item.priority,
+                data_scadenza: item.data_scadenza.clone(),
+                session_id: None,
+                data: None,
+                ordine: None,
+                orario_stimato: None,
+                durata_stimata_minuti: durata,
+                entro_scadenza: false,
+                motivo: None,
+            };
+
+            match target {
+                Some(session) => {
+                    let ordine = session.casi.len() as i32 + 1;
+                    suggestion.session_id = session.id;
+                    suggestion.data = Some(session.data.clone());
+                    suggestion.ordine = Some(ordine);
+                    suggestion.orario_stimato = Some(Self::cathlab_clock(&session.ora_inizio, session.minuti_pianificati));
+                    suggestion.entro_scadenza = item
+                        .data_scadenza
+                        .as_deref()
+                        .map(|due| session.data.as_str() <= due)
+                        .unwrap_or(true);
+
+                    // Posto occupato virtualmente per i pazienti successivi
+                    session.casi.push(CathLabCase {
+                        id: None,
+                        session_id: session.id.unwrap_or_default(),
+                        patient_id: item.patient_id,
+                        nome: item.nome,
+                        cognome: item.cognome,
+                        priority: Some(item.priority),
+                        ordine,
+                        tipo_valvola,
+                        durata_manuale_minuti: None,
+                        durata_stimata_minuti: durata,
+                        orario_stimato: suggestion.orario_stimato.clone().unwrap_or_default(),
+                        anestesia_generale,
+                        accesso_chirurgico,
+                        avvisi: Vec::new(),
+                        note: None,
+                    });
+                    session.minuti_pianificati += durata;
+                }
+                None if has_room => {
+                    suggestion.motivo = Some("Nessuna seduta con posti liberi dispone delle risorse richieste".to_string());
+                }
+                None => {
+                    suggestion.motivo = Some("Nessuna seduta con posti liberi nell'intervallo".to_string());
+                }
+            }
+            suggestions.push(suggestion);
+        }
+
+        Ok(suggestions)
+    }
+
+    // ========================================================================
+    // DAILY WORKLISTS
+    // ========================================================================
+
+    fn parse_worklist_date(data: &str) -> Result<chrono::NaiveDate, String> {
+        chrono::NaiveDate::parse_from_str(data.trim(), "%Y-%m-%d")
+            .map_err(|_| format!("Data non valida: {}", data))
+    }
+
+    /// Visite ambulatoriali del giorno in ordine di orario (esclusi gli appuntamenti annullati)
+    pub fn get_ambulatorio_worklist(&self, data: &str) -> Result<Vec<WorklistEntry>, String> {
+        let date = Self::parse_worklist_date(data)?;
+        let data = date.format("%Y-%m-%d").to_string();
+        let catalogue = self.get_valve_models()?;
+        let patients: std::collections::HashMap<i64, Patient> = self
+            .get_all_patients_with_status(None)?
+            .into_iter()
+            .filter_map(|item| item.patient.id.map(|id| (id, item.patient)))
+            .collect();
+
+        let mut entries = Vec::new();
+        for item in self.get_appointments(Some(&data), Some(&data), None)? {
+            let appointment = item.appointment;
+            if appointment.stato == "annullato" {
+                continue;
+            }
+            let Some(patient) = patients.get(&appointment.patient_id) else {
+                continue;
+            };
+            let mut entry = WorklistEntry::from_patient(patient, date, &catalogue);
+            let tipo = APPOINTMENT_TYPES
+                .iter()
+                .find(|(code, _)| *code == appointment.tipo)
+                .map(|(_, label)| label.to_string())
+                .unwrap_or_else(|| appointment.tipo.clone());
+            entry.attivita = Some(match appointment.stato.as_str() {
+                "prenotato" => tipo,
+                stato => {
+                    let stato = APPOINTMENT_STATUSES
+                        .iter()
+                        .find(|(code, _)| *code == stato)
+                        .map(|(_, label)| *label)
+                        .unwrap_or(stato);
+                    format!("{} ({})", tipo, stato.to_lowercase())
+                }
+            });
+            entry.orario = appointment.orario.clone();
+            entry.note = appointment.note.clone().filter(|n| !n.trim().is_empty());
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    /// Casi della giornata di sala nell'ordine della seduta, con orario stimato; in coda i pazienti
+    /// con `data_tavi` nel giorno ma non ancora assegnati a una seduta
+    pub fn get_tavi_worklist(&self, data: &str) -> Result<Vec<WorklistEntry>, String> {
+        let date = Self::parse_worklist_date(data)?;
+        let data = date.format("%Y-%m-%d").to_string();
+        let catalogue = self.get_valve_models()?;
+        let patients: std::collections::HashMap<i64, Patient> = self
+            .get_all_patients_with_status(None)?
+            .into_iter()
+            .filter_map(|item| item.patient.id.map(|id| (id, item.patient)))
+            .collect();
+
+        let mut entries = Vec::new();
+        let mut scheduled = std::collections::HashSet::new();
+        for session in self.get_cathlab_sessions(Some(&data), Some(&data))? {
+            let total = session.casi.len();
+            for case in session.casi {
+                let Some(patient) = patients.get(&case.patient_id) else {
+                    continue;
+                };
+                let mut entry = WorklistEntry::from_patient(patient, date, &catalogue);
+                entry.orario = Some(case.orario_stimato.clone());
+                entry.attivita = Some(format!("Caso {} di {}", case.ordine, total));
+                entry.avvisi = case.avvisi;
+                entry.note = case.note.filter(|n| !n.trim().is_empty());
+                scheduled.insert(case.patient_id);
+                entries.push(entry);
+            }
+        }
+
+        let mut unscheduled: Vec<&Patient> = patients
+            .iter()
+            .filter(|(id, p)| {
+                !scheduled.contains(*id) && p.data_tavi.as_deref().map(str::trim) == Some(data.as_str())
+            })
+            .map(|(_, p)| p)
+            .collect();
+        unscheduled.sort_by(|a, b| (&a.cognome, &a.nome).cmp(&(&b.cognome, &b.nome)));
+        for patient in unscheduled {
+            let mut entry = WorklistEntry::from_patient(patient, date, &catalogue);
+            entry.attivita = Some("Non assegnato a seduta".to_string());
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+
+    // ========================================================================
+    // IMPORT
+    // ========================================================================
+
+    /// Chiavi di riconoscimento del paziente: codice fiscale, poi cognome + nome + data di nascita
+    fn patient_import_keys(values: &serde_json::Map<String, serde_json::Value>) -> Vec<String> {
+        let text = |key: &str| {
+            values
+                .get(key)
+                .and_then(|v| v.as_str())
+                .map(|v| v.trim().to_uppercase())
+                .unwrap_or_default()
+        };
+        let mut keys = Vec::new();
+        let cf = text("codice_fiscale");
+        if !cf.is_empty() {
+            keys.push(format!("cf:{}", cf));
+        }
+        keys.push(format!("anagrafica:{}|{}|{}", text("cognome"), text("nome"), text("data_nascita")));
+        keys
+    }
+
+    /// Una procedura è la stessa se coincidono paziente e data della procedura
+    fn procedure_import_keys(values: &serde_json::Map<String, serde_json::Value>) -> Vec<String> {
+        let text = |key: &str| {
+            values
+                .get(key)
+                .and_then(|v| v.as_str())
+                .map(|v| v.trim().to_uppercase())
+                .unwrap_or_default()
+        };
+        vec![format!(
+            "procedura:{}|{}|{}|{}",
+            text("cognome"),
+            text("nome"),
+            text("data_nascita"),
+            text("data_procedura")
+        )]
+    }
+
+    /// Applica le righe importate dentro la transazione corrente, una SAVEPOINT per riga:
+    /// una riga che fallisce viene scartata senza annullare le altre
+    fn apply_import<T>(
+        conn: &Connection,
+        ops: &ImportOperations<'_, T>,
+        existing: Vec<T>,
+        candidates: Vec<ImportCandidate>,
+        update_existing: bool,
+        dry_run: bool,
+    ) -> Result<ImportReport, String>
+    where
+        T: serde::Serialize + serde::de::DeserializeOwned,
+    {
+        let mut known: std::collections::HashMap<String, (i64, serde_json::Map<String, serde_json::Value>)> =
+            std::collections::HashMap::new();
+        for record in existing {
+            let Ok(serde_json::Value::Object(values)) = serde_json::to_value(&record) else {
+                continue;
+            };
+            let Some(id) = values.get("id").and_then(|v| v.as_i64()) else {
+                continue;
+            };
+            for key in (ops.keys)(&values) {
+                known.entry(key).or_insert_with(|| (id, values.clone()));
+            }
+        }
+
+        let mut report = ImportReport {
+            target: ops.target.to_string(),
+            simulazione: dry_run,
+            totale: candidates.len(),
+            da_inserire: 0,
+            da_aggiornare: 0,
+            scartate: 0,
+            righe: Vec::new(),
+        };
+        let mut seen: std::collections::HashMap<String, usize> = std::collections::HashMap::new();
+
+        for candidate in candidates {
+            let keys = (ops.keys)(&candidate.valori);
+            // Descrizione provvisoria per le righe che non arrivano a diventare un record
+            let descrizione = ["cognome", "nome"]
+                .iter()
+                .filter_map(|key| candidate.valori.get(*key).and_then(|v| v.as_str()))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let mut row = ImportRowReport {
+                riga: candidate.riga,
+                azione: "scarta".to_string(),
+                record_id: None,
+                descrizione,
+                errori: candidate.errori,
+                avvisi: candidate.avvisi,
+            };
 
-        let mut query = String::from(
-            "SELECT p.*, 'Da valutare' as status, dv.created_at as status_created_at
-             FROM patients p INNER JOIN patients_da_valutare dv ON p.id = dv.patient_id
-             UNION ALL
-             SELECT p.*, 'In corso di accertamenti' as status, ae.created_at as status_created_at
-             FROM patients p INNER JOIN patients_in_attesa_esami ae ON p.id = ae.patient_id
-             UNION ALL
-             SELECT p.*, 'In attesa di TAVI' as status, ai.created_at as status_created_at
-             FROM patients p INNER JOIN patients_in_attesa_intervento ai ON p.id = ai.patient_id
-             UNION ALL
-             SELECT p.*, 'Non candidabile a TAVI' as status, nc.created_at as status_created_at
-             FROM patients p INNER JOIN patients_non_candidabile nc ON p.id = nc.patient_id
-             UNION ALL
-             SELECT p.*, 'TAVI eseguita' as status, c.created_at as status_created_at
-             FROM patients p INNER JOIN patients_completato c ON p.id = c.patient_id"
-        );
+            if let Some(first) = keys.iter().find_map(|key| seen.get(key)) {
+                row.errori.push(format!("Duplicato della riga {}", first));
+            }
+            let matched = keys.iter().find_map(|key| known.get(key));
+            if matched.is_some() && !update_existing {
+                row.errori.push("Record già presente in archivio".to_string());
+            }
 
-        let mut params: Vec<Box<dyn rusqlite::ToSql>> = vec![];
+            // Aggiornamento: i valori importati si sovrappongono al record esistente
+            let mut values = match matched {
+                Some((_, current)) => current.clone(),
+                None => ops.defaults.clone(),
+            };
+            values.extend(candidate.valori);
 
-        if let Some(filters) = filters {
-            if let Some(search) = filters.search_query {
-                if !search.is_empty() {
-                    query = format!(
-                        "SELECT * FROM ({}) WHERE nome LIKE ?1 OR cognome LIKE ?1 OR codice_fiscale LIKE ?1",
-                        query
-                    );
-                    params.push(Box::new(format!("%{}%", search)));
+            if row.errori.is_empty() {
+                let outcome = serde_json::from_value::<T>(serde_json::Value::Object(values))
+                    .map_err(|e| format!("Dati non validi: {}", e))
+                    .and_then(|record| {
+                        row.descrizione = (ops.describe)(&record);
+                        conn.execute("SAVEPOINT import_riga", []).map_err(|e| e.to_string())?;
+                        let result = match matched {
+                            Some((id, _)) => (ops.update)(conn, *id, &record).map(|_| *id),
+                            None => (ops.insert)(conn, &record),
+                        };
+                        match result {
+                            Ok(id) => {
+                                conn.execute("RELEASE import_riga", []).map_err(|e| e.to_string())?;
+                                Ok(id)
+                            }
+                            Err(e) => {
+                                conn.execute("ROLLBACK TO import_riga", []).ok();
+                                conn.execute("RELEASE import_riga", []).ok();
+                                Err(e)
+                            }
+                        }
+                    });
+                match outcome {
+                    Ok(id) => {
+                        if matched.is_some() {
+                            row.azione = "aggiorna".to_string();
+                            row.record_id = Some(id);
+                            report.da_aggiornare += 1;
+                        } else {
+                            row.azione = "inserisci".to_string();
+                            row.record_id = if dry_run { None } else { Some(id) };
+                            report.da_inserire += 1;
+                        }
+                    }
+                    Err(e) => row.errori.push(e),
                 }
             }
 
-            if let Some(status_filter) = filters.status {
-                if status_filter != "all" {
-                    query = format!("SELECT * FROM ({}) WHERE status = ?{}", query, params.len() + 1);
-                    params.push(Box::new(status_filter));
+            if row.azione == "scarta" {
+                report.scartate += 1;
+            } else {
+                for key in keys {
+                    seen.entry(key).or_insert(row.riga);
                 }
             }
+            report.righe.push(row);
         }
 
-        query.push_str(" ORDER BY status_created_at DESC");
+        Ok(report)
+    }
 
-        let params_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
+    /// Esegue l'importazione in un'unica transazione; con `dry_run` la annulla restituendo solo il resoconto
+    fn run_import<T>(
+        conn: &Connection,
+        ops: &ImportOperations<'_, T>,
+        existing: Vec<T>,
+        candidates: Vec<ImportCandidate>,
+        update_existing: bool,
+        dry_run: bool,
+    ) -> Result<ImportReport, String>
+    where
+        T: serde::Serialize + serde::de::DeserializeOwned,
+    {
+        conn.execute("BEGIN IMMEDIATE", []).map_err(|e| e.to_string())?;
+        match Self::apply_import(conn, ops, existing, candidates, update_existing, dry_run) {
+            Ok(report) => {
+                let end = if dry_run { "ROLLBACK" } else { "COMMIT" };
+                conn.execute(end, []).map_err(|e| e.to_string())?;
+                Ok(report)
+            }
+            Err(e) => {
+                conn.execute("ROLLBACK", []).ok();
+                Err(e)
+            }
+        }
+    }
 
-        let mut stmt = conn.prepare(&query).map_err(|e| e.to_string())?;
-        let rows = stmt.query_map(params_refs.as_slice(), |row| {
-            let fattori: Option<Vec<String>> = match row.get::<_, Option<String>>("ambulatorio_fattori") {
-                Ok(Some(json)) => serde_json::from_str(&json).ok(),
-                Ok(None) => None,
-                Err(_) => None,
-            };
-            Ok(PatientWithStatus {
-                patient: Patient {
-                    id: Some(row.get("id")?),
-                    created_at: row.get("created_at").ok(),
-                    updated_at: row.get("updated_at").ok(),
-                    nome: row.get("nome")?,
-                    cognome: row.get("cognome")?,
-                    data_nascita: row.get("data_nascita")?,
-                    luogo_nascita: row.get("luogo_nascita").ok(),
-                    codice_fiscale: row.get("codice_fiscale").ok(),
-                    telefono: row.get("telefono").ok(),
-                    email: row.get("email").ok(),
-                    provenienza: row.get("provenienza").ok(),
-                    sesso: row.get("sesso").ok(),
-                    priority: row.get("priority").ok(),
-                    altezza: row.get("altezza").ok(),
-                    peso: row.get("peso").ok(),
-                    note: row.get("note").ok(),
-                    ambulatorio_fattori: fattori,
-                    anamnesi_cardiologica: row.get("anamnesi_cardiologica").ok(),
-                    apr: row.get("apr").ok(),
-                    visita_odierna: row.get("visita_odierna").ok(),
-                    conclusioni: row.get("conclusioni").ok(),
-                    medico_titolo: row.get("medico_titolo").ok(),
-                    medico_nome: row.get("medico_nome").ok(),
-                    medico_specializzando_titolo: row.get("medico_specializzando_titolo").ok(),
-                    medico_specializzando_nome: row.get("medico_specializzando_nome").ok(),
-                    ambulatorio_data_visita: row.get("ambulatorio_data_visita").ok(),
-                    ambulatorio_orario_visita: row.get("ambulatorio_orario_visita").ok(),
-                    procedurale_allergia_mdc: row.get("procedurale_allergia_mdc").ok(),
-                    procedurale_preparazione_mdc: row.get("procedurale_preparazione_mdc").ok(),
-                    procedurale_creatinina: row.get("procedurale_creatinina").ok(),
-                    procedurale_egfr: row.get("procedurale_egfr").ok(),
-                    procedurale_hb: row.get("procedurale_hb").ok(),
-                    procedurale_altro: row.get("procedurale_altro").ok(),
-                    data_tavi: row.get("data_tavi").ok(),
-                    procedurale_ecg_ritmo_sinusale: row.get("procedurale_ecg_ritmo_sinusale").ok(),
-                    procedurale_ecg_fa: row.get("procedurale_ecg_fa").ok(),
-                    procedurale_ecg_bbs: row.get("procedurale_ecg_bbs").ok(),
-                    procedurale_ecg_bbd: row.get("procedurale_ecg_bbd").ok(),
-                    procedurale_ecg_eas: row.get("procedurale_ecg_eas").ok(),
-                    procedurale_ecg_bav_primo: row.get("procedurale_ecg_bav_primo").ok(),
-                    procedurale_ecg_ritmo_stimolato: row.get("procedurale_ecg_ritmo_stimolato").ok(),
-                    procedurale_anestesia: row.get("procedurale_anestesia").ok(),
-                    procedurale_coronarografia: row.get("procedurale_coronarografia").ok(),
-                    procedurale_coronarografia_note: row.get("procedurale_coronarografia_note").ok(),
-                    procedurale_pacemaker: row.get("procedurale_pacemaker").ok(),
-                    procedurale_pacemaker_note: row.get("procedurale_pacemaker_note").ok(),
-                    procedurale_accesso_principale_fem: row.get("procedurale_accesso_principale_fem").ok(),
-                    procedurale_accesso_principale_altro: row.get("procedurale_accesso_principale_altro").ok(),
-                    procedurale_accesso_protezione: row.get("procedurale_accesso_protezione").ok(),
-                    procedurale_accesso_protezione_note: row.get("procedurale_accesso_protezione_note").ok(),
-                    procedurale_altri_accessi: row.get("procedurale_altri_accessi").ok(),
-                    procedurale_diametro_pallone_femorale: row.get("procedurale_diametro_pallone_femorale").ok(),
-                    procedurale_guida_safari: row.get("procedurale_guida_safari").ok(),
-                    procedurale_protezione_osti: row.get("procedurale_protezione_osti").ok(),
-                    procedurale_valvuloplastica: row.get("procedurale_valvuloplastica").ok(),
-                    procedurale_valvuloplastica_note: row.get("procedurale_valvuloplastica_note").ok(),
-                    procedurale_bioprotesi_modello: row.get("procedurale_bioprotesi_modello").ok(),
-                    procedurale_bioprotesi_dimensione: row.get("procedurale_bioprotesi_dimensione").ok(),
-                },
-                status: row.get("status")?,
-                status_created_at: row.get("status_created_at")?,
-            })
-        }).map_err(|e| e.to_string())?;
+    /// Importa pazienti (nuovi in stato "Da valutare"), riconoscendo quelli già presenti
+    pub fn import_patients(
+        &self,
+        candidates: Vec<ImportCandidate>,
+        update_existing: bool,
+        dry_run: bool,
+    ) -> Result<ImportReport, String> {
+        let existing: Vec<Patient> = self
+            .get_all_patients_with_status(None)?
+            .into_iter()
+            .map(|item| item.patient)
+            .collect();
+        let conn = self.conn.lock().unwrap();
 
-        let patients: Result<Vec<_>, _> = rows.collect();
-        patients.map_err(|e| e.to_string())
+        let insert = |conn: &Connection, patient: &Patient| -> Result<i64, String> {
+            self.ensure_ambulatorio_visit_bookable(conn, patient, None)?;
+            Self::ensure_cathlab_capacity(conn, patient.data_tavi.as_deref(), None)?;
+            let patient_id = Self::insert_patient_row(conn, patient)?;
+            Self::sync_patient_details(conn, patient_id, patient)?;
+            Ok(patient_id)
+        };
+        let update = |conn: &Connection, id: i64, patient: &Patient| -> Result<(), String> {
+            self.ensure_ambulatorio_visit_bookable(conn, patient, Some(id))?;
+            Self::ensure_cathlab_capacity(conn, patient.data_tavi.as_deref(), Some(id))?;
+            Self::update_patient_row(conn, patient)?;
+            Self::sync_patient_details(conn, id, patient)
+        };
+        let ops = ImportOperations {
+            target: "patients",
+            defaults: serde_json::Map::new(),
+            keys: Self::patient_import_keys,
+            describe: |p: &Patient| format!("{} {} ({})", p.cognome, p.nome, p.data_nascita),
+            insert: &insert,
+            update: &update,
+        };
+        Self::run_import(&conn, &ops, existing, candidates, update_existing, dry_run)
     }
 
-    /// Ottieni singolo paziente per ID
-    pub fn get_patient_by_id(&self, id: i64) -> Result<Option<PatientWithStatus>, String> {
-        let all_patients = self.get_all_patients_with_status(None)?;
-        Ok(all_patients.into_iter().find(|p| p.patient.id == Some(id)))
+    /// Importa procedure, riconoscendo quelle già registrate per lo stesso paziente e data
+    pub fn import_procedures(
+        &self,
+        candidates: Vec<ImportCandidate>,
+        update_existing: bool,
+        dry_run: bool,
+    ) -> Result<ImportReport, String> {
+        let existing = self.get_all_procedures(None)?;
+        let conn = self.conn.lock().unwrap();
+
+        let update = |conn: &Connection, _id: i64, proc: &Procedure| Self::update_procedure_row(conn, proc);
+        let ops = ImportOperations {
+            target: "procedures",
+            defaults: ["valvola_protesica", "pre_dilatazione", "post_dilatazione"]
+                .into_iter()
+                .map(|key| (key.to_string(), serde_json::Value::Bool(false)))
+                .collect(),
+            keys: Self::procedure_import_keys,
+            describe: |p: &Procedure| format!("{} {} - procedura del {}", p.cognome, p.nome, p.data_procedura),
+            insert: &Self::insert_procedure_row,
+            update: &update,
+        };
+        Self::run_import(&conn, &ops, existing, candidates, update_existing, dry_run)
     }
 
-    /// Cambia stato paziente
-    pub fn change_patient_status(&self, patient_id: i64, new_status: PatientStatus) -> Result<(), String> {
+    // ========================================================================
+    // HL7 INGESTION
+    // ========================================================================
+
+    /// Pazienti con il codice fiscale indicato (confronto senza maiuscole/spazi)
+    pub fn find_patient_ids_by_codice_fiscale(&self, codice_fiscale: &str) -> Result<Vec<i64>, String> {
         let conn = self.conn.lock().unwrap();
-        self.ensure_status_tables(&conn).map_err(|e| e.to_string())?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT id FROM patients
+                 WHERE UPPER(REPLACE(TRIM(codice_fiscale), ' ', '')) = UPPER(REPLACE(TRIM(?1), ' ', ''))
+                 ORDER BY id",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![codice_fiscale], |row| row.get(0))
+            .map_err(|e| e.to_string())?;
+        let ids: Result<Vec<i64>, _> = rows.collect();
+        ids.map_err(|e| e.to_string())
+    }
 
-        conn.execute("BEGIN TRANSACTION", []).map_err(|e| e.to_string())?;
+    pub fn insert_hl7_ingestion_log(&self, entry: &Hl7IngestionLogEntry) -> Result<i64, String> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO hl7_ingestion_log (
+                file_name, message_type, control_id, sending_facility, esito, patient_id, dettaglio
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                entry.file_name,
+                entry.message_type,
+                entry.control_id,
+                entry.sending_facility,
+                entry.esito,
+                entry.patient_id,
+                entry.dettaglio
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+        Ok(conn.last_insert_rowid())
+    }
 
-        // Rimuovi da tutte le tabelle di stato
-        let tables = vec![
-            "patients_da_valutare",
-            "patients_in_attesa_esami",
-            "patients_in_attesa_intervento",
-            "patients_non_candidabile",
-            "patients_completato",
-        ];
+    /// Data del primo messaggio con lo stesso control ID e mittente già applicato
+    /// (paziente creato o aggiornato), se presente
+    pub fn find_applied_hl7_message(
+        &self,
+        control_id: &str,
+        sending_facility: Option<&str>,
+    ) -> Result<Option<String>, String> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT MIN(received_at) FROM hl7_ingestion_log
+             WHERE control_id = ?1 AND sending_facility IS ?2 AND esito IN (?3, ?4)",
+            params![control_id, sending_facility, HL7_OUTCOME_CREATED, HL7_OUTCOME_UPDATED],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())
+    }
 
-        for table in tables {
-            match conn.execute(
-                &format!("DELETE FROM {} WHERE patient_id = ?1", table),
-                params![patient_id],
-            ) {
-                Ok(_) => {},
-                Err(e) => {
-                    conn.execute("ROLLBACK", []).ok();
-                    return Err(e.to_string());
-                }
-            }
+    /// Registro di acquisizione, dal più recente, opzionalmente filtrato per esito
+    pub fn get_hl7_ingestion_log(&self, limit: i64, esito: Option<&str>) -> Result<Vec<Hl7IngestionLogEntry>, String> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, received_at, file_name, message_type, control_id, sending_facility, esito, patient_id, dettaglio
+                 FROM hl7_ingestion_log
+                 WHERE (?1 IS NULL OR esito = ?1)
+                 ORDER BY received_at DESC, id DESC
+                 LIMIT ?2",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![esito, limit.max(1)], |row| {
+                Ok(Hl7IngestionLogEntry {
+                    id: row.get(0)?,
+                    received_at: row.get(1)?,
+                    file_name: row.get(2)?,
+                    message_type: row.get(3)?,
+                    control_id: row.get(4)?,
+                    sending_facility: row.get(5)?,
+                    esito: row.get(6)?,
+                    patient_id: row.get(7)?,
+                    dettaglio: row.get(8)?,
+                })
+            })
+            .map_err(|e| e.to_string())?;
+        let entries: Result<Vec<_>, _> = rows.collect();
+        entries.map_err(|e| e.to_string())
+    }
+}
+
+/// Operazioni specifiche di una destinazione di importazione (pazienti o procedure)
+struct ImportOperations<'a, T> {
+    target: &'static str,
+    defaults: serde_json::Map<String, serde_json::Value>,  // valori per i nuovi record
+    keys: fn(&serde_json::Map<String, serde_json::Value>) -> Vec<String>,
+    describe: fn(&T) -> String,
+    insert: &'a dyn Fn(&Connection, &T) -> Result<i64, String>,
+    update: &'a dyn Fn(&Connection, i64, &T) -> Result<(), String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_db() -> Database {
+        Database::new(PathBuf::from(":memory:")).unwrap()
+    }
+
+    /// Data relativa a oggi in formato YYYY-MM-DD
+    fn day(offset: i64) -> String {
+        (chrono::Local::now().date_naive() + chrono::Duration::days(offset))
+            .format("%Y-%m-%d")
+            .to_string()
+    }
+
+    fn new_patient(cognome: &str) -> Patient {
+        serde_json::from_value(serde_json::json!({
+            "cognome": cognome,
+            "nome": "Test",
+            "data_nascita": "1940-01-01",
+        }))
+        .unwrap()
+    }
+
+    fn insert_patient(db: &Database, patient: &Patient) -> Patient {
+        let id = db.insert_patient(patient).unwrap();
+        db.get_patient_by_id(id).unwrap().unwrap().patient
+    }
+
+    fn count(db: &Database, sql: &str) -> i64 {
+        db.conn.lock().unwrap().query_row(sql, [], |row| row.get(0)).unwrap()
+    }
+
+    fn log_entry(patient_id: Option<i64>) -> Hl7IngestionLogEntry {
+        Hl7IngestionLogEntry {
+            id: None,
+            received_at: None,
+            file_name: "a04.hl7".to_string(),
+            message_type: Some("ADT^A04".to_string()),
+            control_id: Some("MSG0001".to_string()),
+            sending_facility: None,
+            esito: "creato".to_string(),
+            patient_id,
+            dettaglio: None,
         }
+    }
 
-        // Inserisci nella nuova tabella
-        let new_table = new_status.to_table_name();
-        match conn.execute(
-            &format!("INSERT INTO {} (patient_id) VALUES (?1)", new_table),
-            params![patient_id],
-        ) {
-            Ok(_) => {
-                conn.execute("COMMIT", []).map_err(|e| e.to_string())?;
-                Ok(())
-            }
-            Err(e) => {
-                conn.execute("ROLLBACK", []).ok();
-                Err(e.to_string())
-            }
+    #[test]
+    fn delete_patient_cascades_to_dependent_rows() {
+        let db = test_db();
+        let mut patient = new_patient("Rossi");
+        patient.ambulatorio_data_visita = Some(day(-30));
+        let id = insert_patient(&db, &patient).id.unwrap();
+        db.insert_hl7_ingestion_log(&log_entry(Some(id))).unwrap();
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM patients_da_valutare"), 1);
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM appointments"), 1);
+
+        db.delete_patient(id).unwrap();
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM patients_da_valutare"), 0);
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM appointments"), 0);
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM patient_status_history"), 0);
+        // Il registro HL7 resta, scollegato dal paziente
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM hl7_ingestion_log WHERE patient_id IS NULL"), 1);
+        assert!(db.insert_hl7_ingestion_log(&log_entry(Some(id))).is_err());
+    }
+
+    #[test]
+    fn opening_removes_orphan_rows_left_without_foreign_keys() {
+        let dir = std::env::temp_dir().join(format!("tavi-orfani-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("tavi.db");
+        let kept = {
+            let db = Database::new(path.clone()).unwrap();
+            let kept = insert_patient(&db, &new_patient("Bianchi")).id.unwrap();
+            let removed = insert_patient(&db, &new_patient("Rossi")).id.unwrap();
+            db.insert_hl7_ingestion_log(&log_entry(Some(removed))).unwrap();
+            // Eliminazione come avveniva prima dei FOREIGN KEY: le righe collegate restano
+            let conn = db.conn.lock().unwrap();
+            conn.pragma_update(None, "foreign_keys", false).unwrap();
+            conn.execute("DELETE FROM patients WHERE id = ?1", params![removed]).unwrap();
+            kept
+        };
+
+        let db = Database::new(path).unwrap();
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM patients_da_valutare"), 1);
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM hl7_ingestion_log WHERE patient_id IS NULL"), 1);
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM pragma_foreign_key_check"), 0);
+        let patients = db.get_all_patients_with_status(None).unwrap();
+        assert_eq!(patients.len(), 1);
+        assert_eq!(patients[0].patient.id, Some(kept));
+        db.change_patient_status(kept, PatientStatus::InAttesaEsami).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn sheet_visit_moved_to_the_past_is_recorded_as_pregresso() {
+        let db = test_db();
+        let mut patient = new_patient("Rossi");
+        patient.ambulatorio_data_visita = Some(day(10));
+        let mut patient = insert_patient(&db, &patient);
+        let id = patient.id.unwrap();
+        let appointments = db.get_patient_appointments(id).unwrap();
+        assert_eq!(appointments.len(), 1);
+        assert_eq!(appointments[0].stato, "prenotato");
+
+        patient.ambulatorio_data_visita = Some(day(-3));
+        db.update_patient(&patient).unwrap();
+        let appointments = db.get_patient_appointments(id).unwrap();
+        assert_eq!(appointments.len(), 1);
+        assert_eq!(appointments[0].data, day(-3));
+        assert_eq!(appointments[0].stato, "pregresso");
+
+        patient.ambulatorio_data_visita = Some(day(5));
+        db.update_patient(&patient).unwrap();
+        let appointments = db.get_patient_appointments(id).unwrap();
+        assert_eq!(appointments.len(), 2);
+        assert_eq!(appointments[0].stato, "prenotato");
+        assert_eq!(appointments[1].stato, "pregresso");
+    }
+
+    fn cathlab_session(data: String, anestesista: bool) -> CathLabSession {
+        CathLabSession {
+            id: None,
+            data,
+            capacita: 4,
+            ora_inizio: "08:00".to_string(),
+            anestesista,
+            chirurgo_vascolare: true,
+            note: None,
+            casi: Vec::new(),
+            minuti_pianificati: 0,
         }
     }
 
-    /// Ottieni contatori per stato
-    pub fn get_patient_status_counts(&self) -> Result<Vec<PatientStatusCount>, String> {
-        let conn = self.conn.lock().unwrap();
-        self.ensure_status_tables(&conn).map_err(|e| e.to_string())?;
-        self.auto_mark_tavi_completed(&conn)?;
+    #[test]
+    fn tavi_date_without_session_uses_default_capacity() {
+        let db = test_db();
+        let mut patient = new_patient("Rossi");
+        patient.data_tavi = Some(day(20));
+        for _ in 0..CATHLAB_DEFAULT_CAPACITY {
+            insert_patient(&db, &patient);
+        }
+        let error = db.insert_patient(&patient).unwrap_err();
+        assert!(error.contains("al completo"), "{}", error);
 
-        let statuses = vec![
-            ("Da valutare", "patients_da_valutare"),
-            ("In corso di accertamenti", "patients_in_attesa_esami"),
-            ("In attesa di TAVI", "patients_in_attesa_intervento"),
-            ("Non candidabile a TAVI", "patients_non_candidabile"),
-            ("TAVI eseguita", "patients_completato"),
-        ];
+        // Le date passate registrano TAVI eseguite e non hanno limite
+        patient.data_tavi = Some(day(-20));
+        for _ in 0..=CATHLAB_DEFAULT_CAPACITY {
+            insert_patient(&db, &patient);
+        }
+    }
 
-        let mut counts = Vec::new();
+    #[test]
+    fn session_update_keeps_existing_cases_compatible() {
+        let db = test_db();
+        let session_id = db.save_cathlab_session(&cathlab_session(day(30), true)).unwrap();
+        let mut patient = new_patient("Rossi");
+        patient.procedurale_anestesia = Some("generale".to_string());
+        let patient_id = insert_patient(&db, &patient).id.unwrap();
+        db.add_cathlab_case(session_id, patient_id, None, None).unwrap();
 
-        for (label, table) in statuses {
-            let count: i32 = conn.query_row(
-                &format!("SELECT COUNT(*) FROM {}", table),
-                [],
-                |row| row.get(0),
-            ).map_err(|e| e.to_string())?;
+        let mut update = cathlab_session(day(31), false);
+        update.id = Some(session_id);
+        let error = db.save_cathlab_session(&update).unwrap_err();
+        assert!(error.contains("anestesista"), "{}", error);
+        // Nulla cambia se la verifica fallisce
+        let stored = db.get_cathlab_sessions(None, None).unwrap();
+        assert_eq!(stored[0].data, day(30));
+        assert!(stored[0].anestesista);
+        assert_eq!(db.get_patient_by_id(patient_id).unwrap().unwrap().patient.data_tavi, Some(day(30)));
 
-            counts.push(PatientStatusCount {
-                status: label.to_string(),
-                count,
-            });
+        update.anestesista = true;
+        db.save_cathlab_session(&update).unwrap();
+        assert_eq!(db.get_patient_by_id(patient_id).unwrap().unwrap().patient.data_tavi, Some(day(31)));
+        let stored = db.get_cathlab_sessions(None, None).unwrap();
+        assert_eq!(stored[0].casi.len(), 1);
+    }
+
+    #[test]
+    fn patients_without_priority_count_as_media() {
+        let db = test_db();
+        let mut ids = Vec::new();
+        for (cognome, priority) in [("Rossi", Some(Priority::Alta)), ("Bianchi", None), ("Verdi", Some(Priority::Bassa)), ("Neri", None)] {
+            let mut patient = new_patient(cognome);
+            patient.priority = priority;
+            patient.ambulatorio_data_visita = Some(day(-10));
+            ids.push(insert_patient(&db, &patient).id.unwrap());
+        }
+        // Valore importato da una versione precedente, non riconosciuto
+        db.conn
+            .lock()
+            .unwrap()
+            .execute("UPDATE patients SET priority = 'urgente' WHERE id = ?1", params![ids[3]])
+            .unwrap();
+        db.initialize_schema().unwrap();
+        let legacy = db.get_patient_by_id(ids[3]).unwrap().unwrap().patient;
+        assert_eq!(legacy.priority, None);
+        assert!(legacy.note.unwrap_or_default().contains("urgente"));
+        for id in &ids {
+            db.change_patient_status(*id, PatientStatus::InAttesaIntervento).unwrap();
         }
 
-        Ok(counts)
+        let filters = PatientFilters { priority: Some("media".to_string()), ..Default::default() };
+        let mut media: Vec<String> = db
+            .get_all_patients_with_status(Some(filters))
+            .unwrap()
+            .into_iter()
+            .map(|item| item.patient.cognome)
+            .collect();
+        media.sort();
+        assert_eq!(media, vec!["Bianchi", "Neri"]);
+
+        let filters = PatientFilters { sort_by: Some("priority".to_string()), ..Default::default() };
+        let sorted: Vec<String> = db
+            .get_all_patients_with_status(Some(filters))
+            .unwrap()
+            .into_iter()
+            .map(|item| item.patient.cognome)
+            .collect();
+        assert_eq!(sorted.first().map(String::as_str), Some("Rossi"));
+        assert_eq!(sorted.last().map(String::as_str), Some("Verdi"));
+
+        let items = Database::collect_priority_due_items(&db.conn.lock().unwrap()).unwrap();
+        let item = items.iter().find(|item| item.patient_id == ids[1]).unwrap();
+        assert_eq!(item.priority, Priority::Media);
+        assert_eq!(item.data_scadenza, Some(day(90)));
+
+        let analytics = db.get_waiting_list_analytics().unwrap();
+        let decision = analytics.intervalli.iter().find(|i| i.intervallo == "visita_decisione").unwrap();
+        let groups: Vec<(&str, i32)> = decision
+            .per_priorita
+            .iter()
+            .map(|g| (g.gruppo.as_str(), g.statistiche.n))
+            .collect();
+        assert_eq!(groups, vec![("alta", 1), ("media", 2), ("bassa", 1)]);
     }
 
-    /// Ottieni pazienti per stato specifico
-    pub fn get_patients_by_status(&self, status: &str) -> Result<Vec<PatientWithStatus>, String> {
-        let filters = PatientFilters {
-            search_query: None,
-            status: Some(status.to_string()),
-        };
-        self.get_all_patients_with_status(Some(filters))
+    fn completed_patient(db: &Database, cognome: &str, data_tavi: String) -> Patient {
+        let mut patient = new_patient(cognome);
+        patient.data_tavi = Some(data_tavi);
+        let patient = insert_patient(db, &patient);
+        db.change_patient_status(patient.id.unwrap(), PatientStatus::Completato).unwrap();
+        patient
+    }
+
+    fn record_follow_up(db: &Database, patient_id: i64, anno: i32, update: impl FnOnce(&mut FollowUp)) {
+        let mut follow_up = db
+            .get_follow_ups(patient_id)
+            .unwrap()
+            .into_iter()
+            .find(|f| f.anno == anno)
+            .unwrap();
+        update(&mut follow_up);
+        db.update_follow_up(&follow_up).unwrap();
+    }
+
+    #[test]
+    fn follow_ups_are_planned_on_completion_and_follow_the_tavi_date() {
+        let db = test_db();
+        let mut patient = new_patient("Rossi");
+        patient.data_tavi = Some(day(-100));
+        let mut patient = insert_patient(&db, &patient);
+        let id = patient.id.unwrap();
+        // Le letture non pianificano nulla
+        db.get_all_patients_with_status(None).unwrap();
+        assert!(db.get_follow_ups(id).unwrap().is_empty());
+
+        db.change_patient_status(id, PatientStatus::Completato).unwrap();
+        let follow_ups = db.get_follow_ups(id).unwrap();
+        assert_eq!(follow_ups.len(), (FOLLOW_UP_YEARS + 1) as usize);
+        assert_eq!(follow_ups[0].data_prevista, day(-70));
+        record_follow_up(&db, id, 0, |f| f.data_eseguita = Some(day(-68)));
+
+        patient.data_tavi = Some(day(-90));
+        db.update_patient(&patient).unwrap();
+        let follow_ups = db.get_follow_ups(id).unwrap();
+        // Il controllo eseguito resta com'era, quelli da eseguire seguono la nuova data
+        assert_eq!(follow_ups[0].data_prevista, day(-70));
+        assert_eq!(follow_ups[0].data_eseguita, Some(day(-68)));
+        let sql = format!(
+            "SELECT COUNT(*) FROM follow_ups WHERE anno = 1 AND data_prevista = DATE('{}', '+1 years')",
+            day(-90)
+        );
+        assert_eq!(count(&db, &sql), 1);
+    }
+
+    #[test]
+    fn survival_data_censors_at_last_visit_and_counts_deaths() {
+        let db = test_db();
+        let deceased = completed_patient(&db, "Rossi", day(-400)).id.unwrap();
+        record_follow_up(&db, deceased, 0, |f| f.data_eseguita = Some(day(-370)));
+        record_follow_up(&db, deceased, 1, |f| {
+            f.data_eseguita = Some(day(-35));
+            f.decesso = Some(true);
+            f.decesso_data = Some(day(-40));
+            f.decesso_causa = Some("cardiovascolare".to_string());
+        });
+        let alive = completed_patient(&db, "Bianchi", day(-200)).id.unwrap();
+        record_follow_up(&db, alive, 0, |f| f.data_eseguita = Some(day(-170)));
+        // Senza controlli eseguiti il paziente è censurato il giorno della TAVI
+        completed_patient(&db, "Verdi", day(-10));
+
+        let survival = db.get_survival_data().unwrap();
+        assert_eq!(survival.records.len(), 3);
+        let record = |id: i64| survival.records.iter().find(|r| r.patient_id == id).unwrap();
+        assert_eq!(record(deceased).giorni, 360);
+        assert!(record(deceased).decesso && record(deceased).decesso_cardiovascolare);
+        assert_eq!(record(alive).giorni, 30);
+        assert!(!record(alive).decesso);
+        assert_eq!(record(alive).data_ultimo_contatto, day(-170));
+
+        let last = survival.curva.last().unwrap();
+        assert_eq!((last.giorni, last.a_rischio, last.eventi), (360, 1, 1));
+        assert_eq!(last.sopravvivenza, 0.0);
+        let censored = survival.curva.iter().find(|p| p.giorni == 30).unwrap();
+        assert_eq!((censored.censurati, censored.sopravvivenza), (1, 1.0));
     }
 }
@@ -0,0 +1,869 @@
+use crate::import::parse_decimal_it;
+use crate::models::{Appointment, Patient, Procedure, ValveModel, APPOINTMENT_TYPES};
+use chrono::{Local, NaiveDate, NaiveDateTime, TimeZone};
+use regex::Regex;
+use serde_json::{json, Map, Value};
+use std::collections::HashSet;
+use std::sync::OnceLock;
+
+/// Codice fiscale: identificativo nazionale secondo HL7 Italia
+const FHIR_SYSTEM_CODICE_FISCALE: &str = "http://hl7.it/sid/codiceFiscale";
+/// Identificativi interni del registro
+const FHIR_SYSTEM_PATIENT_ID: &str = "urn:registro-tavi:paziente";
+const FHIR_SYSTEM_PROCEDURE_ID: &str = "urn:registro-tavi:procedura";
+const FHIR_SYSTEM_APPOINTMENT_ID: &str = "urn:registro-tavi:appuntamento";
+const FHIR_SYSTEM_SNOMED: &str = "http://snomed.info/sct";
+const FHIR_SYSTEM_LOINC: &str = "http://loinc.org";
+const FHIR_SYSTEM_UCUM: &str = "http://unitsofmeasure.org";
+const FHIR_SYSTEM_ACT_CODE: &str = "http://terminology.hl7.org/CodeSystem/v3-ActCode";
+const FHIR_SYSTEM_OBSERVATION_CATEGORY: &str = "http://terminology.hl7.org/CodeSystem/observation-category";
+
+/// Esami di laboratorio del paziente: (campo, codice LOINC, descrizione, unità UCUM)
+const FHIR_LAB_OBSERVATIONS: [(&str, &str, &str, &str); 3] = [
+    ("creatinina", "2160-0", "Creatinina sierica", "mg/dL"),
+    ("egfr", "33914-3", "eGFR", "mL/min/{1.73_m2}"),
+    ("hb", "718-7", "Emoglobina", "g/dL"),
+];
+
+// ============================================================================
+// IDENTIFICATIVI
+// ============================================================================
+
+/// UUID deterministico (versione 8, RFC 9562) dai primi 128 bit dello SHA-256 di tipo e
+/// chiave della risorsa: esportazioni ripetute producono gli stessi riferimenti
+pub fn resource_uuid(resource_type: &str, key: &str) -> String {
+    let digest = crate::research::sha256(format!("{}/{}", resource_type, key).as_bytes());
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    bytes[6] = (bytes[6] & 0x0f) | 0x80;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+}
+
+/// Risorsa con il suo indirizzo nel Bundle (`urn:uuid:...`)
+#[derive(Debug, Clone)]
+pub struct FhirEntry {
+    pub full_url: String,
+    pub resource: Value,
+}
+
+impl FhirEntry {
+    fn new(resource_type: &str, key: &str, mut resource: Value) -> Self {
+        let id = resource_uuid(resource_type, key);
+        if let Value::Object(map) = &mut resource {
+            map.insert("resourceType".to_string(), Value::from(resource_type));
+            map.insert("id".to_string(), Value::from(id.clone()));
+        }
+        FhirEntry {
+            full_url: format!("urn:uuid:{}", id),
+            resource,
+        }
+    }
+
+    pub fn reference(&self) -> Value {
+        json!({ "reference": self.full_url })
+    }
+}
+
+// ============================================================================
+// CONVERSIONI
+// ============================================================================
+
+fn non_empty(value: Option<&str>) -> Option<&str> {
+    value.map(str::trim).filter(|v| !v.is_empty())
+}
+
+/// Toglie chiavi nulle e array vuoti: in FHIR JSON non sono ammessi
+fn compact(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(k, v)| (k, compact(v)))
+                .filter(|(_, v)| !is_empty_value(v))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(
+            items.into_iter().map(compact).filter(|v| !is_empty_value(v)).collect(),
+        ),
+        other => other,
+    }
+}
+
+fn is_empty_value(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::String(s) => s.trim().is_empty(),
+        Value::Array(items) => items.is_empty(),
+        Value::Object(map) => map.is_empty(),
+        _ => false,
+    }
+}
+
+/// Data e ora locali nel formato `instant`/`dateTime` di FHIR (fuso orario obbligatorio)
+fn fhir_instant(value: NaiveDateTime) -> Option<String> {
+    Local
+        .from_local_datetime(&value)
+        .earliest()
+        .map(|dt| dt.format("%Y-%m-%dT%H:%M:%S%:z").to_string())
+}
+
+fn local_datetime(date: &str, time: &str) -> Option<NaiveDateTime> {
+    let date = NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d").ok()?;
+    let time = chrono::NaiveTime::parse_from_str(time.trim(), "%H:%M").ok()?;
+    Some(date.and_time(time))
+}
+
+fn display_reference(display: Option<&str>) -> Option<Value> {
+    non_empty(display).map(|d| json!({ "display": d }))
+}
+
+fn doctor_name(titolo: Option<&str>, nome: Option<&str>) -> Option<String> {
+    non_empty(nome).map(|nome| match non_empty(titolo) {
+        Some(titolo) => format!("{} {}", titolo, nome),
+        None => nome.to_string(),
+    })
+}
+
+// ============================================================================
+// RISORSE
+// ============================================================================
+
+/// Paziente; `key` distingue i pazienti del registro da quelli ricavati solo dalla procedura
+pub fn patient_entry(patient: &Patient, key: &str) -> FhirEntry {
+    let mut identifier = Vec::new();
+    if let Some(cf) = non_empty(patient.codice_fiscale.as_deref()) {
+        identifier.push(json!({
+            "use": "official",
+            "system": FHIR_SYSTEM_CODICE_FISCALE,
+            "value": cf.to_uppercase(),
+        }));
+    }
+    if let Some(id) = patient.id {
+        identifier.push(json!({ "use": "usual", "system": FHIR_SYSTEM_PATIENT_ID, "value": id.to_string() }));
+    }
+
+    let mut telecom = Vec::new();
+    if let Some(phone) = non_empty(patient.telefono.as_deref()) {
+        telecom.push(json!({ "system": "phone", "value": phone }));
+    }
+    if let Some(email) = non_empty(patient.email.as_deref()) {
+        telecom.push(json!({ "system": "email", "value": email }));
+    }
+
+    let gender = match non_empty(patient.sesso.as_deref()) {
+        Some("M" | "m") => Some("male"),
+        Some("F" | "f") => Some("female"),
+        Some(_) => Some("unknown"),
+        None => None,
+    };
+    let birth_place = non_empty(patient.luogo_nascita.as_deref()).map(|city| {
+        json!([{
+            "url": "http://hl7.org/fhir/StructureDefinition/patient-birthPlace",
+            "valueAddress": { "city": city },
+        }])
+    });
+
+    FhirEntry::new(
+        "Patient",
+        key,
+        compact(json!({
+            "extension": birth_place,
+            "identifier": identifier,
+            "active": true,
+            "name": [{
+                "use": "official",
+                "family": patient.cognome.trim(),
+                "given": [patient.nome.trim()],
+            }],
+            "telecom": telecom,
+            "gender": gender,
+            "birthDate": NaiveDate::parse_from_str(patient.data_nascita.trim(), "%Y-%m-%d")
+                .ok()
+                .map(|d| d.format("%Y-%m-%d").to_string()),
+        })),
+    )
+}
+
+/// Esami di laboratorio registrati nella scheda procedurale
+pub fn lab_observation_entries(patient: &Patient, key: &str, subject: &FhirEntry) -> Vec<FhirEntry> {
+    FHIR_LAB_OBSERVATIONS
+        .iter()
+        .filter_map(|(field, loinc, display, unit)| {
+            let raw = match *field {
+                "creatinina" => patient.procedurale_creatinina.as_deref(),
+                "egfr" => patient.procedurale_egfr.as_deref(),
+                _ => patient.procedurale_hb.as_deref(),
+            };
+            let raw = non_empty(raw)?;
+            let value = match parse_decimal_it(raw) {
+                Some(number) => json!({
+                    "valueQuantity": { "value": number, "unit": unit, "system": FHIR_SYSTEM_UCUM, "code": unit }
+                }),
+                None => json!({ "valueString": raw }),
+            };
+            let mut resource = json!({
+                "status": "final",
+                "category": [{
+                    "coding": [{ "system": FHIR_SYSTEM_OBSERVATION_CATEGORY, "code": "laboratory" }]
+                }],
+                "code": {
+                    "coding": [{ "system": FHIR_SYSTEM_LOINC, "code": loinc }],
+                    "text": display,
+                },
+                "subject": subject.reference(),
+            });
+            if let (Value::Object(map), Value::Object(value)) = (&mut resource, value) {
+                map.extend(value);
+            }
+            Some(FhirEntry::new("Observation", &format!("{}-{}", key, field), resource))
+        })
+        .collect()
+}
+
+/// Valvola impiantata durante la procedura
+pub fn device_entry(procedure: &Procedure, catalogue: &[ValveModel], subject: &FhirEntry) -> FhirEntry {
+    let model = catalogue.iter().find(|m| m.matches(&procedure.modello_valvola));
+    let size = procedure.dimensione_valvola.map(|mm| {
+        json!([{
+            "type": { "text": "Dimensione (mm)" },
+            "valueQuantity": [{ "value": mm, "unit": "mm", "system": FHIR_SYSTEM_UCUM, "code": "mm" }],
+        }])
+    });
+    FhirEntry::new(
+        "Device",
+        &procedure.id.unwrap_or_default().to_string(),
+        compact(json!({
+            "status": "active",
+            "manufacturer": model.map(|m| m.produttore.clone()),
+            "deviceName": [{
+                "name": model.map(|m| m.nome_commerciale.clone()).unwrap_or_else(|| procedure.modello_valvola.trim().to_string()),
+                "type": "model-name",
+            }],
+            "type": { "text": format!("Bioprotesi aortica transcatetere ({})", procedure.tipo_valvola) },
+            "property": size,
+            "patient": subject.reference(),
+        })),
+    )
+}
+
+pub fn procedure_entry(procedure: &Procedure, subject: &FhirEntry, device: &FhirEntry) -> FhirEntry {
+    let period = |time: &str| {
+        local_datetime(&procedure.data_procedura, time)
+            .and_then(fhir_instant)
+            .map(Value::from)
+            .unwrap_or_else(|| Value::from(procedure.data_procedura.trim()))
+    };
+    let mut notes = Vec::new();
+    if procedure.pre_dilatazione {
+        notes.push("Pre-dilatazione".to_string());
+    }
+    if procedure.post_dilatazione {
+        notes.push("Post-dilatazione".to_string());
+    }
+    if procedure.valvola_protesica {
+        let protesi = [procedure.protesica_modello.as_deref(), procedure.protesica_dimensione.as_deref()]
+            .into_iter()
+            .filter_map(non_empty)
+            .collect::<Vec<_>>()
+            .join(" ");
+        notes.push(format!("Valve-in-valve {}", protesi).trim().to_string());
+    }
+    let access = non_empty(procedure.accesso_principale.as_deref()).map(|code| {
+        crate::models::MAIN_ACCESS_OPTIONS
+            .iter()
+            .find(|(c, _)| *c == code)
+            .map(|(_, label)| label.to_string())
+            .unwrap_or_else(|| code.to_string())
+    });
+    if let Some(access) = access {
+        notes.push(format!("Accesso principale: {}", access));
+    }
+
+    FhirEntry::new(
+        "Procedure",
+        &procedure.id.unwrap_or_default().to_string(),
+        compact(json!({
+            "identifier": procedure.id.map(|id| json!([{ "system": FHIR_SYSTEM_PROCEDURE_ID, "value": id.to_string() }])),
+            "status": "completed",
+            "code": {
+                "coding": [{
+                    "system": FHIR_SYSTEM_SNOMED,
+                    "code": "725351001",
+                    "display": "Transcatheter aortic valve implantation",
+                }],
+                "text": "TAVI",
+            },
+            "subject": subject.reference(),
+            "performedPeriod": {
+                "start": period(&procedure.ora_inizio),
+                "end": period(&procedure.ora_fine),
+            },
+            "performer": display_reference(procedure.operatore.as_deref()).map(|actor| json!([{ "actor": actor }])),
+            "bodySite": [{
+                "coding": [{ "system": FHIR_SYSTEM_SNOMED, "code": "34202007", "display": "Aortic valve structure" }]
+            }],
+            "focalDevice": [{ "manipulated": device.reference() }],
+            "note": notes.into_iter().map(|text| json!({ "text": text })).collect::<Vec<_>>(),
+        })),
+    )
+}
+
+/// Stato FHIR dell'appuntamento a partire da APPOINTMENT_STATUSES
+fn appointment_status(stato: &str) -> &'static str {
+    match stato {
+        "eseguito" => "fulfilled",
+        "non_presentato" => "noshow",
+        "annullato" => "cancelled",
+        _ => "booked",
+    }
+}
+
+/// Appuntamento ambulatoriale; senza orario (visite storiche) non è rappresentabile come Appointment
+pub fn appointment_entry(appointment: &Appointment, minutes: i32, subject: &FhirEntry) -> Option<FhirEntry> {
+    let start = local_datetime(&appointment.data, appointment.orario.as_deref()?)?;
+    let end = start + chrono::Duration::minutes(minutes as i64);
+    let tipo = APPOINTMENT_TYPES
+        .iter()
+        .find(|(code, _)| *code == appointment.tipo)
+        .map(|(_, label)| *label)
+        .unwrap_or(appointment.tipo.as_str());
+
+    let mut participant = vec![json!({ "actor": subject.reference(), "status": "accepted" })];
+    if let Some(actor) = display_reference(
+        doctor_name(appointment.medico_titolo.as_deref(), appointment.medico_nome.as_deref()).as_deref(),
+    ) {
+        participant.push(json!({ "actor": actor, "status": "accepted" }));
+    }
+
+    Some(FhirEntry::new(
+        "Appointment",
+        &appointment.id.unwrap_or_default().to_string(),
+        compact(json!({
+            "identifier": appointment.id.map(|id| json!([{ "system": FHIR_SYSTEM_APPOINTMENT_ID, "value": id.to_string() }])),
+            "status": appointment_status(&appointment.stato),
+            "appointmentType": { "text": tipo },
+            "description": format!("Ambulatorio TAVI - {}", tipo),
+            "start": fhir_instant(start),
+            "end": fhir_instant(end),
+            "minutesDuration": minutes,
+            "comment": non_empty(appointment.note.as_deref()),
+            "participant": participant,
+        })),
+    ))
+}
+
+/// Visita ambulatoriale eseguita
+pub fn encounter_entry(
+    appointment: &Appointment,
+    minutes: i32,
+    subject: &FhirEntry,
+    booking: Option<&FhirEntry>,
+) -> FhirEntry {
+    let period = match appointment.orario.as_deref().and_then(|o| local_datetime(&appointment.data, o)) {
+        Some(start) => json!({
+            "start": fhir_instant(start),
+            "end": fhir_instant(start + chrono::Duration::minutes(minutes as i64)),
+        }),
+        None => json!({ "start": appointment.data.trim() }),
+    };
+    let tipo = APPOINTMENT_TYPES
+        .iter()
+        .find(|(code, _)| *code == appointment.tipo)
+        .map(|(_, label)| *label)
+        .unwrap_or(appointment.tipo.as_str());
+    let participant = display_reference(
+        doctor_name(appointment.medico_titolo.as_deref(), appointment.medico_nome.as_deref()).as_deref(),
+    )
+    .map(|individual| json!([{ "individual": individual }]));
+
+    FhirEntry::new(
+        "Encounter",
+        &appointment.id.unwrap_or_default().to_string(),
+        compact(json!({
+            "identifier": appointment.id.map(|id| json!([{ "system": FHIR_SYSTEM_APPOINTMENT_ID, "value": id.to_string() }])),
+            "status": "finished",
+            "class": { "system": FHIR_SYSTEM_ACT_CODE, "code": "AMB", "display": "ambulatory" },
+            "type": [{ "text": tipo }],
+            "subject": subject.reference(),
+            "participant": participant,
+            "appointment": booking.map(|b| json!([b.reference()])),
+            "period": period,
+        })),
+    )
+}
+
+/// Bundle di tipo `collection` con le risorse nell'ordine dato
+pub fn bundle(entries: &[FhirEntry]) -> Value {
+    let now = Local::now().format("%Y-%m-%dT%H:%M:%S%:z").to_string();
+    let key = entries.iter().map(|e| e.full_url.as_str()).collect::<Vec<_>>().join(",");
+    json!({
+        "resourceType": "Bundle",
+        "id": resource_uuid("Bundle", &format!("{}|{}", now, key)),
+        "meta": { "lastUpdated": now },
+        "type": "collection",
+        "timestamp": now,
+        "entry": entries
+            .iter()
+            .map(|e| json!({ "fullUrl": e.full_url, "resource": e.resource }))
+            .collect::<Vec<_>>(),
+    })
+}
+
+// ============================================================================
+// VALIDAZIONE
+// ============================================================================
+
+/// Elementi comuni a tutte le DomainResource
+const DOMAIN_RESOURCE_ELEMENTS: [&str; 9] = [
+    "resourceType", "id", "meta", "implicitRules", "language", "text", "contained", "extension",
+    "modifierExtension",
+];
+
+/// Elementi di primo livello ammessi dalla definizione base R4 per le risorse esportate
+fn resource_elements(resource_type: &str) -> Option<&'static [&'static str]> {
+    let elements: &[&str] = match resource_type {
+        "Patient" => &[
+            "identifier", "active", "name", "telecom", "gender", "birthDate", "deceasedBoolean",
+            "deceasedDateTime", "address", "maritalStatus", "multipleBirthBoolean", "multipleBirthInteger",
+            "photo", "contact", "communication", "generalPractitioner", "managingOrganization", "link",
+        ],
+        "Procedure" => &[
+            "identifier", "instantiatesCanonical", "instantiatesUri", "basedOn", "partOf", "status",
+            "statusReason", "category", "code", "subject", "encounter", "performedDateTime", "performedPeriod",
+            "performedString", "performedAge", "performedRange", "recorder", "asserter", "performer", "location",
+            "reasonCode", "reasonReference", "bodySite", "outcome", "report", "complication",
+            "complicationDetail", "followUp", "note", "focalDevice", "usedReference", "usedCode",
+        ],
+        "Device" => &[
+            "identifier", "definition", "udiCarrier", "status", "statusReason", "distinctIdentifier",
+            "manufacturer", "manufactureDate", "expirationDate", "lotNumber", "serialNumber", "deviceName",
+            "modelNumber", "partNumber", "type", "specialization", "version", "property", "patient", "owner",
+            "contact", "location", "url", "note", "safety", "parent",
+        ],
+        "Observation" => &[
+            "identifier", "basedOn", "partOf", "status", "category", "code", "subject", "focus", "encounter",
+            "effectiveDateTime", "effectivePeriod", "effectiveTiming", "effectiveInstant", "issued", "performer",
+            "valueQuantity", "valueCodeableConcept", "valueString", "valueBoolean", "valueInteger", "valueRange",
+            "valueRatio", "valueSampledData", "valueTime", "valueDateTime", "valuePeriod", "dataAbsentReason",
+            "interpretation", "note", "bodySite", "method", "specimen", "device", "referenceRange", "hasMember",
+            "derivedFrom", "component",
+        ],
+        "Appointment" => &[
+            "identifier", "status", "cancelationReason", "serviceCategory", "serviceType", "specialty",
+            "appointmentType", "reasonCode", "reasonReference", "priority", "description",
+            "supportingInformation", "start", "end", "minutesDuration", "slot", "created", "comment",
+            "patientInstruction", "basedOn", "participant", "requestedPeriod",
+        ],
+        "Encounter" => &[
+            "identifier", "status", "statusHistory", "class", "classHistory", "type", "serviceType", "priority",
+            "subject", "episodeOfCare", "basedOn", "participant", "appointment", "period", "length",
+            "reasonCode", "reasonReference", "diagnosis", "account", "hospitalization", "location",
+            "serviceProvider", "partOf",
+        ],
+        _ => return None,
+    };
+    Some(elements)
+}
+
+/// Elementi obbligatori (cardinalità minima 1) e valori ammessi dei binding `required`
+fn resource_rules(resource_type: &str) -> (&'static [&'static str], &'static [(&'static str, &'static [&'static str])]) {
+    match resource_type {
+        "Patient" => (&[], &[("gender", &["male", "female", "other", "unknown"])]),
+        "Procedure" => (
+            &["status", "subject"],
+            &[(
+                "status",
+                &["preparation", "in-progress", "not-done", "on-hold", "stopped", "completed", "entered-in-error", "unknown"],
+            )],
+        ),
+        "Device" => (&[], &[("status", &["active", "inactive", "entered-in-error", "unknown"])]),
+        "Observation" => (
+            &["status", "code"],
+            &[(
+                "status",
+                &["registered", "preliminary", "final", "amended", "corrected", "cancelled", "entered-in-error", "unknown"],
+            )],
+        ),
+        "Appointment" => (
+            &["status", "participant"],
+            &[(
+                "status",
+                &[
+                    "proposed", "pending", "booked", "arrived", "fulfilled", "cancelled", "noshow", "entered-in-error",
+                    "checked-in", "waitlist",
+                ],
+            )],
+        ),
+        "Encounter" => (
+            &["status", "class"],
+            &[(
+                "status",
+                &[
+                    "planned", "arrived", "triaged", "in-progress", "onleave", "finished", "cancelled",
+                    "entered-in-error", "unknown",
+                ],
+            )],
+        ),
+        _ => (&[], &[]),
+    }
+}
+
+fn is_fhir_date(value: &str) -> bool {
+    static DATE: OnceLock<Regex> = OnceLock::new();
+    DATE.get_or_init(|| Regex::new(r"^\d{4}(-\d{2}(-\d{2})?)?$").unwrap()).is_match(value)
+}
+
+fn is_fhir_datetime(value: &str) -> bool {
+    is_fhir_date(value) || is_fhir_instant(value)
+}
+
+fn is_fhir_instant(value: &str) -> bool {
+    static INSTANT: OnceLock<Regex> = OnceLock::new();
+    INSTANT
+        .get_or_init(|| Regex::new(r"^\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}(\.\d+)?(Z|[+-]\d{2}:\d{2})$").unwrap())
+        .is_match(value)
+}
+
+fn is_fhir_id(value: &str) -> bool {
+    static ID: OnceLock<Regex> = OnceLock::new();
+    ID.get_or_init(|| Regex::new(r"^[A-Za-z0-9\-.]{1,64}$").unwrap()).is_match(value)
+}
+
+/// Valori nulli, stringhe o array vuoti non sono ammessi in FHIR JSON
+fn check_no_empty(value: &Value, path: &str, issues: &mut Vec<String>) {
+    match value {
+        Value::Null => issues.push(format!("{}: valore nullo", path)),
+        Value::String(s) if s.trim().is_empty() => issues.push(format!("{}: stringa vuota", path)),
+        Value::Array(items) if items.is_empty() => issues.push(format!("{}: array vuoto", path)),
+        Value::Object(map) if map.is_empty() => issues.push(format!("{}: oggetto vuoto", path)),
+        Value::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                check_no_empty(item, &format!("{}[{}]", path, i), issues);
+            }
+        }
+        Value::Object(map) => {
+            for (key, item) in map {
+                check_no_empty(item, &format!("{}.{}", path, key), issues);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Raccoglie i riferimenti (`reference`) presenti nella risorsa
+fn collect_references(value: &Value, path: &str, out: &mut Vec<(String, String)>) {
+    match value {
+        Value::Object(map) => {
+            if let Some(Value::String(reference)) = map.get("reference") {
+                out.push((path.to_string(), reference.clone()));
+            }
+            for (key, item) in map {
+                collect_references(item, &format!("{}.{}", path, key), out);
+            }
+        }
+        Value::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                collect_references(item, &format!("{}[{}]", path, i), out);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Elemento temporale da verificare: (percorso, controllo del formato, tipo FHIR)
+type TemporalCheck = (&'static str, fn(&str) -> bool, &'static str);
+
+fn check_temporal(map: &Map<String, Value>, path: &str, fields: &[TemporalCheck], issues: &mut Vec<String>) {
+    for (field, check, kind) in fields {
+        let value = field.split('.').try_fold(map, |current, part| match current.get(part) {
+            Some(Value::Object(inner)) => Ok(inner),
+            Some(other) => Err(Some(other)),
+            None => Err(None),
+        });
+        if let Err(Some(value)) = value {
+            if !value.as_str().is_some_and(check) {
+                issues.push(format!("{}.{}: {} non valido ({})", path, field, kind, value));
+            }
+        }
+    }
+}
+
+/// Controlli specifici di una risorsa oltre alla struttura di base
+fn check_resource_invariants(resource_type: &str, map: &Map<String, Value>, path: &str, issues: &mut Vec<String>) {
+    match resource_type {
+        "Patient" => check_temporal(map, path, &[("birthDate", is_fhir_date, "date")], issues),
+        "Procedure" => check_temporal(
+            map,
+            path,
+            &[
+                ("performedDateTime", is_fhir_datetime, "dateTime"),
+                ("performedPeriod.start", is_fhir_datetime, "dateTime"),
+                ("performedPeriod.end", is_fhir_datetime, "dateTime"),
+            ],
+            issues,
+        ),
+        "Observation" => {
+            let values = map.keys().filter(|k| k.starts_with("value")).count();
+            if values > 1 {
+                issues.push(format!("{}: più di un valore value[x]", path));
+            }
+            if let Some(quantity) = map.get("valueQuantity") {
+                if !quantity.get("value").is_some_and(Value::is_number) {
+                    issues.push(format!("{}.valueQuantity.value: numero mancante", path));
+                }
+            }
+            check_temporal(map, path, &[("effectiveDateTime", is_fhir_datetime, "dateTime")], issues);
+        }
+        "Appointment" => {
+            check_temporal(
+                map,
+                path,
+                &[("start", is_fhir_instant, "instant"), ("end", is_fhir_instant, "instant")],
+                issues,
+            );
+            // app-2: inizio e fine insieme; app-3: solo proposed/cancelled/waitlist possono non averli
+            let timed = (map.contains_key("start"), map.contains_key("end"));
+            if timed.0 != timed.1 {
+                issues.push(format!("{}: start ed end devono essere presenti insieme", path));
+            }
+            let status = map.get("status").and_then(Value::as_str).unwrap_or("");
+            if !timed.0 && !["proposed", "cancelled", "waitlist"].contains(&status) {
+                issues.push(format!("{}: start ed end obbligatori con stato '{}'", path, status));
+            }
+            let participants = map.get("participant").and_then(Value::as_array).cloned().unwrap_or_default();
+            for (i, participant) in participants.iter().enumerate() {
+                let status = participant.get("status").and_then(Value::as_str);
+                if !status.is_some_and(|s| ["accepted", "declined", "tentative", "needs-action"].contains(&s)) {
+                    issues.push(format!("{}.participant[{}].status: valore non ammesso", path, i));
+                }
+                if participant.get("actor").is_none() && participant.get("type").is_none() {
+                    issues.push(format!("{}.participant[{}]: actor o type obbligatorio", path, i));
+                }
+            }
+        }
+        "Encounter" => {
+            if map.get("class").and_then(|c| c.get("code")).is_none() {
+                issues.push(format!("{}.class: codice mancante", path));
+            }
+            check_temporal(
+                map,
+                path,
+                &[("period.start", is_fhir_datetime, "dateTime"), ("period.end", is_fhir_datetime, "dateTime")],
+                issues,
+            );
+        }
+        "Device" => {
+            let names = map.get("deviceName").and_then(Value::as_array).cloned().unwrap_or_default();
+            for (i, name) in names.iter().enumerate() {
+                let kind = name.get("type").and_then(Value::as_str);
+                let allowed = [
+                    "udi-label-name", "user-friendly-name", "patient-reported-name", "manufacturer-name",
+                    "model-name", "other",
+                ];
+                if name.get("name").is_none() || !kind.is_some_and(|k| allowed.contains(&k)) {
+                    issues.push(format!("{}.deviceName[{}]: name e type validi obbligatori", path, i));
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Verifica il Bundle rispetto alla struttura di base delle risorse FHIR R4:
+/// elementi ammessi e obbligatori, binding obbligatori, formati di date e istanti,
+/// riferimenti interni risolvibili. Restituisce l'elenco dei problemi trovati.
+pub fn validate_bundle(bundle: &Value) -> Vec<String> {
+    let mut issues = Vec::new();
+    let Some(map) = bundle.as_object() else {
+        return vec!["Il Bundle non è un oggetto JSON".to_string()];
+    };
+    check_no_empty(bundle, "Bundle", &mut issues);
+    if map.get("resourceType").and_then(Value::as_str) != Some("Bundle") {
+        issues.push("Bundle.resourceType deve essere 'Bundle'".to_string());
+    }
+    let bundle_elements = [
+        "resourceType", "id", "meta", "implicitRules", "language", "identifier", "type", "timestamp", "total",
+        "link", "entry", "signature",
+    ];
+    for key in map.keys().filter(|k| !bundle_elements.contains(&k.as_str())) {
+        issues.push(format!("Bundle.{}: elemento non previsto", key));
+    }
+    let bundle_types = [
+        "document", "message", "transaction", "transaction-response", "batch", "batch-response", "history",
+        "searchset", "collection",
+    ];
+    if !map.get("type").and_then(Value::as_str).is_some_and(|t| bundle_types.contains(&t)) {
+        issues.push("Bundle.type mancante o non ammesso".to_string());
+    }
+    if let Some(timestamp) = map.get("timestamp") {
+        if !timestamp.as_str().is_some_and(is_fhir_instant) {
+            issues.push("Bundle.timestamp: instant non valido".to_string());
+        }
+    }
+
+    let entries = map.get("entry").and_then(Value::as_array).cloned().unwrap_or_default();
+    let mut full_urls = HashSet::new();
+    let mut references = Vec::new();
+
+    for (i, entry) in entries.iter().enumerate() {
+        let path = format!("Bundle.entry[{}]", i);
+        if let Some(full_url) = entry.get("fullUrl").and_then(Value::as_str) {
+            if !full_urls.insert(full_url.to_string()) {
+                issues.push(format!("{}.fullUrl duplicato: {}", path, full_url));
+            }
+        }
+        let Some(resource) = entry.get("resource").and_then(Value::as_object) else {
+            issues.push(format!("{}: risorsa mancante", path));
+            continue;
+        };
+        let resource_type = resource.get("resourceType").and_then(Value::as_str).unwrap_or("");
+        let path = format!("{}.resource({})", path, resource_type);
+        let Some(elements) = resource_elements(resource_type) else {
+            issues.push(format!("{}: tipo di risorsa non supportato", path));
+            continue;
+        };
+        for key in resource.keys() {
+            if !DOMAIN_RESOURCE_ELEMENTS.contains(&key.as_str()) && !elements.contains(&key.as_str()) {
+                issues.push(format!("{}.{}: elemento non previsto", path, key));
+            }
+        }
+        if let Some(id) = resource.get("id") {
+            if !id.as_str().is_some_and(is_fhir_id) {
+                issues.push(format!("{}.id: identificativo non valido", path));
+            }
+        }
+        let (required, bindings) = resource_rules(resource_type);
+        for field in required {
+            if !resource.contains_key(*field) {
+                issues.push(format!("{}.{}: elemento obbligatorio mancante", path, field));
+            }
+        }
+        for (field, allowed) in bindings {
+            if let Some(value) = resource.get(*field) {
+                if !value.as_str().is_some_and(|v| allowed.contains(&v)) {
+                    issues.push(format!("{}.{}: valore non ammesso ({})", path, field, value));
+                }
+            }
+        }
+        check_resource_invariants(resource_type, resource, &path, &mut issues);
+        collect_references(&Value::Object(resource.clone()), &path, &mut references);
+    }
+
+    for (path, reference) in references {
+        if reference.starts_with("urn:uuid:") && !full_urls.contains(&reference) {
+            issues.push(format!("{}: riferimento non risolto {}", path, reference));
+        }
+    }
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patient() -> FhirEntry {
+        FhirEntry::new(
+            "Patient",
+            "1",
+            json!({ "name": [{ "family": "Rossi", "given": ["Mario"] }], "gender": "male", "birthDate": "1948-03-07" }),
+        )
+    }
+
+    fn appointment(subject: &FhirEntry) -> FhirEntry {
+        FhirEntry::new(
+            "Appointment",
+            "2",
+            json!({
+                "status": "booked",
+                "start": "2030-05-07T09:00:00+02:00",
+                "end": "2030-05-07T09:30:00+02:00",
+                "participant": [{ "actor": subject.reference(), "status": "accepted" }],
+            }),
+        )
+    }
+
+    fn has_issue(issues: &[String], fragment: &str) -> bool {
+        issues.iter().any(|issue| issue.contains(fragment))
+    }
+
+    #[test]
+    fn resource_uuid_is_deterministic_version_8() {
+        let id = resource_uuid("Patient", "1");
+        assert_eq!(id, resource_uuid("Patient", "1"));
+        assert_ne!(id, resource_uuid("Patient", "2"));
+        assert_ne!(id, resource_uuid("Procedure", "1"));
+        assert_eq!(id.len(), 36);
+        assert_eq!(&id[14..15], "8");
+        assert!(matches!(&id[19..20], "8" | "9" | "a" | "b"));
+        assert!(is_fhir_id(&id));
+        // Primi 128 bit di SHA-256("Patient/1") con i bit di versione e variante
+        assert_eq!(id, "2b9a94ee-8825-869b-be8f-b7dabb6c8f7e");
+    }
+
+    #[test]
+    fn validate_bundle_accepts_exported_resources() {
+        let subject = patient();
+        let booking = appointment(&subject);
+        let issues = validate_bundle(&bundle(&[subject, booking]));
+        assert!(issues.is_empty(), "{:?}", issues);
+    }
+
+    #[test]
+    fn validate_bundle_rejects_non_bundle_roots() {
+        assert_eq!(validate_bundle(&json!([])), vec!["Il Bundle non è un oggetto JSON".to_string()]);
+        let issues = validate_bundle(&json!({ "resourceType": "Patient", "type": "raccolta", "extra": 1 }));
+        assert!(has_issue(&issues, "Bundle.resourceType deve essere 'Bundle'"));
+        assert!(has_issue(&issues, "Bundle.type mancante o non ammesso"));
+        assert!(has_issue(&issues, "Bundle.extra: elemento non previsto"));
+    }
+
+    #[test]
+    fn validate_bundle_reports_structural_problems() {
+        let subject = patient();
+        let mut broken = appointment(&subject);
+        if let Value::Object(map) = &mut broken.resource {
+            map.insert("status".to_string(), json!("prenotato"));
+            map.insert("colore".to_string(), json!("rosso"));
+            map.insert("comment".to_string(), json!(""));
+            map.remove("end");
+        }
+        let orphan = FhirEntry::new("Encounter", "3", json!({ "status": "finished", "subject": subject.reference() }));
+        let issues = validate_bundle(&bundle(&[broken, orphan]));
+
+        assert!(has_issue(&issues, "Appointment).status: valore non ammesso"));
+        assert!(has_issue(&issues, "Appointment).colore: elemento non previsto"));
+        assert!(has_issue(&issues, "entry[0].resource.comment: stringa vuota"));
+        assert!(has_issue(&issues, "start ed end devono essere presenti insieme"));
+        assert!(has_issue(&issues, "Encounter).class: elemento obbligatorio mancante"));
+        assert!(has_issue(&issues, &format!("riferimento non risolto {}", subject.full_url)));
+    }
+
+    #[test]
+    fn validate_bundle_checks_dates_and_duplicates() {
+        let mut subject = patient();
+        if let Value::Object(map) = &mut subject.resource {
+            map.insert("birthDate".to_string(), json!("07/03/1948"));
+        }
+        let device = FhirEntry::new(
+            "Device",
+            "4",
+            json!({ "status": "active", "deviceName": [{ "name": "Sapien 3", "type": "nome" }] }),
+        );
+        let issues = validate_bundle(&bundle(&[subject.clone(), subject, device]));
+
+        assert!(has_issue(&issues, "birthDate: date non valido"));
+        assert!(has_issue(&issues, "fullUrl duplicato"));
+        assert!(has_issue(&issues, "deviceName[0]: name e type validi obbligatori"));
+    }
+
+    #[test]
+    fn temporal_formats_follow_fhir_primitives() {
+        assert!(is_fhir_date("2030"));
+        assert!(is_fhir_date("2030-05"));
+        assert!(!is_fhir_date("2030-5-7"));
+        assert!(is_fhir_datetime("2030-05-07T09:00:00Z"));
+        assert!(!is_fhir_instant("2030-05-07"));
+        assert!(!is_fhir_instant("2030-05-07T09:00:00"));
+    }
+}
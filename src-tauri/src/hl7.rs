@@ -0,0 +1,580 @@
+use crate::models::Patient;
+use chrono::NaiveDate;
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Tipi di messaggio gestiti: (tipo^evento, descrizione)
+pub const HL7_SUPPORTED_MESSAGES: [(&str, &str); 3] = [
+    ("ADT^A04", "Registrazione paziente"),
+    ("ADT^A08", "Aggiornamento anagrafica"),
+    ("ORM^O01", "Richiesta (ordine)"),
+];
+
+/// OID del codice fiscale (Ministero dell'Economia e delle Finanze)
+const HL7_CF_OID: &str = "2.16.840.1.113883.2.9.4.3.2";
+/// Codici "identifier type" usati per il codice fiscale
+const HL7_CF_IDENTIFIER_TYPES: [&str; 3] = ["NNITA", "CF", "NN"];
+/// Controlli d'ordine ORC-1 che annullano la richiesta
+const HL7_CANCEL_ORDER_CONTROLS: [&str; 4] = ["CA", "OC", "DC", "CR"];
+
+/// Caratteri di codifica dichiarati in MSH-1/MSH-2
+#[derive(Debug, Clone, Copy)]
+struct Encoding {
+    field: char,
+    component: char,
+    repetition: char,
+    escape: char,
+    subcomponent: char,
+}
+
+/// Messaggio HL7 v2 (codifica ER7) diviso in segmenti e campi ancora codificati
+#[derive(Debug, Clone)]
+pub struct Hl7Message {
+    encoding: Encoding,
+    segments: Vec<(String, Vec<String>)>,
+}
+
+impl Hl7Message {
+    fn segments<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Vec<String>> + 'a {
+        self.segments.iter().filter(move |(n, _)| n == name).map(|(_, fields)| fields)
+    }
+
+    /// Campo grezzo (numerazione HL7: MSH-3 è il terzo campo)
+    fn raw_field(fields: &[String], index: usize) -> &str {
+        fields.get(index).map(String::as_str).unwrap_or("")
+    }
+
+    /// Componenti di ogni ripetizione del campo, con i caratteri di escape già risolti
+    fn repetitions(&self, fields: &[String], index: usize) -> Vec<Vec<String>> {
+        let raw = Self::raw_field(fields, index);
+        if raw.is_empty() {
+            return Vec::new();
+        }
+        raw.split(self.encoding.repetition)
+            .map(|rep| {
+                rep.split(self.encoding.component)
+                    .map(|c| {
+                        let first = c.split(self.encoding.subcomponent).next().unwrap_or("");
+                        self.unescape(first)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Componente (1-based) della prima ripetizione
+    fn component(&self, fields: &[String], index: usize, component: usize) -> String {
+        self.repetitions(fields, index)
+            .into_iter()
+            .next()
+            .and_then(|rep| rep.into_iter().nth(component.saturating_sub(1)))
+            .unwrap_or_default()
+            .trim()
+            .to_string()
+    }
+
+    fn unescape(&self, value: &str) -> String {
+        let esc = self.encoding.escape;
+        let mut out = String::new();
+        let mut rest = value;
+        while let Some(start) = rest.find(esc) {
+            out.push_str(&rest[..start]);
+            let after = &rest[start + esc.len_utf8()..];
+            let Some(end) = after.find(esc) else {
+                out.push_str(&rest[start..]);
+                return out;
+            };
+            match &after[..end] {
+                "F" => out.push(self.encoding.field),
+                "S" => out.push(self.encoding.component),
+                "T" => out.push(self.encoding.subcomponent),
+                "R" => out.push(self.encoding.repetition),
+                "E" => out.push(esc),
+                ".br" => out.push('\n'),
+                seq if seq.starts_with('X') => {
+                    let bytes: Vec<u8> = (1..seq.len())
+                        .step_by(2)
+                        .filter_map(|i| seq.get(i..i + 2).and_then(|h| u8::from_str_radix(h, 16).ok()))
+                        .collect();
+                    out.push_str(&String::from_utf8_lossy(&bytes));
+                }
+                _ => {}
+            }
+            rest = &after[end + esc.len_utf8()..];
+        }
+        out.push_str(rest);
+        out
+    }
+
+    /// Tipo del messaggio come "ADT^A04" (MSH-9.1 ^ MSH-9.2)
+    pub fn message_type(&self) -> String {
+        let Some(msh) = self.segments("MSH").next() else {
+            return String::new();
+        };
+        let kind = self.component(msh, 9, 1).to_uppercase();
+        let event = self.component(msh, 9, 2).to_uppercase();
+        if event.is_empty() {
+            kind
+        } else {
+            format!("{}^{}", kind, event)
+        }
+    }
+
+    /// Identificativo del messaggio (MSH-10)
+    pub fn control_id(&self) -> String {
+        self.segments("MSH").next().map(|msh| self.component(msh, 10, 1)).unwrap_or_default()
+    }
+
+    /// Struttura inviante (MSH-4): nome, altrimenti identificativo universale
+    pub fn sending_facility(&self) -> String {
+        self.segments("MSH")
+            .next()
+            .map(|msh| {
+                let name = self.component(msh, 4, 1);
+                if name.is_empty() {
+                    self.component(msh, 4, 2)
+                } else {
+                    name
+                }
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Divide il contenuto di un file in messaggi: uno per ogni segmento MSH.
+/// Toglie la cornice MLLP e i segmenti di batch (FHS/BHS/BTS/FTS).
+pub fn split_messages(content: &str) -> Vec<String> {
+    let cleaned: String = content.chars().filter(|c| !matches!(c, '\u{0b}' | '\u{1c}')).collect();
+    let mut messages: Vec<Vec<&str>> = Vec::new();
+    for segment in cleaned.split(['\r', '\n']).map(str::trim_end).filter(|s| !s.trim().is_empty()) {
+        let name = segment.get(..3).unwrap_or("");
+        if matches!(name, "FHS" | "BHS" | "BTS" | "FTS") {
+            continue;
+        }
+        if name == "MSH" || messages.is_empty() {
+            messages.push(Vec::new());
+        }
+        if let Some(current) = messages.last_mut() {
+            current.push(segment);
+        }
+    }
+    messages.into_iter().map(|segments| segments.join("\r")).collect()
+}
+
+/// Interpreta un messaggio ER7; deve iniziare con MSH e dichiarare i caratteri di codifica
+pub fn parse_message(text: &str) -> Result<Hl7Message, String> {
+    let text = text.trim_start_matches(['\u{feff}', '\u{0b}']).trim();
+    if !text.starts_with("MSH") {
+        return Err("Il messaggio non inizia con il segmento MSH".to_string());
+    }
+    let mut chars = text.chars().skip(3);
+    let field = chars.next().ok_or("Segmento MSH incompleto")?;
+    let declared: Vec<char> = chars.take_while(|c| *c != field).collect();
+    if field.is_alphanumeric() || declared.len() < 3 {
+        return Err("Caratteri di codifica MSH-2 non validi".to_string());
+    }
+    let encoding = Encoding {
+        field,
+        component: declared[0],
+        repetition: declared[1],
+        escape: declared[2],
+        subcomponent: declared.get(3).copied().unwrap_or('&'),
+    };
+
+    let mut segments = Vec::new();
+    for line in text.split(['\r', '\n']).map(str::trim_end).filter(|l| !l.trim().is_empty()) {
+        let name: String = line.chars().take(3).collect();
+        if name.len() != 3 || !name.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit()) {
+            return Err(format!("Segmento non valido: {}", line.chars().take(20).collect::<String>()));
+        }
+        let mut fields: Vec<String> = line.split(field).map(str::to_string).collect();
+        if name == "MSH" {
+            // In MSH il separatore stesso è il campo 1: i campi successivi scalano di uno
+            fields.insert(1, field.to_string());
+        }
+        segments.push((name, fields));
+    }
+
+    let message = Hl7Message { encoding, segments };
+    if message.segments("PID").next().is_none() {
+        return Err("Segmento PID mancante".to_string());
+    }
+    Ok(message)
+}
+
+/// Dati di invio estratti da ADT^A04/A08 o ORM^O01.
+/// Per i campi anagrafici `Some("")` corrisponde al valore nullo HL7 `""`: il dato va cancellato.
+#[derive(Debug, Clone, Default)]
+pub struct Hl7Referral {
+    pub message_type: String,
+    pub control_id: String,
+    pub sending_facility: String,
+    pub codice_fiscale: Option<String>,
+    pub cognome: Option<String>,
+    pub nome: Option<String>,
+    pub data_nascita: Option<String>,
+    pub sesso: Option<String>,
+    pub luogo_nascita: Option<String>,
+    pub telefono: Option<String>,
+    pub email: Option<String>,
+    pub richiesta: Option<String>,   // ORM: prestazione richiesta e note cliniche
+    pub annullata: bool,             // ORM con controllo d'ordine di annullamento
+}
+
+impl Hl7Referral {
+    pub fn is_update_only(&self) -> bool {
+        self.message_type == "ADT^A08"
+    }
+}
+
+fn is_codice_fiscale(value: &str) -> bool {
+    static CODICE_FISCALE: OnceLock<Regex> = OnceLock::new();
+    CODICE_FISCALE
+        .get_or_init(|| {
+            Regex::new(r"^[A-Z]{6}[0-9LMNPQRSTUV]{2}[A-Z][0-9LMNPQRSTUV]{2}[A-Z][0-9LMNPQRSTUV]{3}[A-Z]$").unwrap()
+        })
+        .is_match(value)
+}
+
+/// Valore di un campo anagrafico: `None` se assente, `Some("")` per il nullo esplicito `""`
+fn demographic(value: String) -> Option<String> {
+    match value.trim() {
+        "" => None,
+        "\"\"" => Some(String::new()),
+        v => Some(v.to_string()),
+    }
+}
+
+/// Data HL7 (AAAAMMGG[hhmm...]) in formato YYYY-MM-DD
+fn hl7_date(value: &str) -> Option<String> {
+    let digits: String = value.trim().chars().take(8).collect();
+    NaiveDate::parse_from_str(&digits, "%Y%m%d")
+        .ok()
+        .map(|d| d.format("%Y-%m-%d").to_string())
+}
+
+/// Estrae i dati del paziente e della richiesta da un messaggio gestito
+pub fn extract_referral(message: &Hl7Message) -> Result<Hl7Referral, String> {
+    let message_type = message.message_type();
+    // ORM^O01 può arrivare anche senza evento
+    let message_type = if message_type == "ORM" { "ORM^O01".to_string() } else { message_type };
+    if !HL7_SUPPORTED_MESSAGES.iter().any(|(code, _)| *code == message_type) {
+        return Err(format!("Tipo di messaggio non gestito: {}", message_type));
+    }
+    let pid = message.segments("PID").next().ok_or("Segmento PID mancante")?;
+
+    // Codice fiscale: identificativo PID-3 con tipo o autorità del CF, altrimenti un valore con il formato del CF
+    let identifiers = message.repetitions(pid, 3);
+    let normalized = |value: &str| value.trim().to_uppercase().replace(' ', "");
+    // L'autorità (CX.4) può riportare l'OID in un sottocomponente: si controlla il campo grezzo
+    let authorities: Vec<String> = Hl7Message::raw_field(pid, 3)
+        .split(message.encoding.repetition)
+        .map(|rep| rep.split(message.encoding.component).nth(3).unwrap_or("").to_uppercase())
+        .collect();
+    let typed = identifiers.iter().zip(&authorities).find(|(cx, authority)| {
+        let kind = cx.get(4).map(|s| s.trim().to_uppercase()).unwrap_or_default();
+        HL7_CF_IDENTIFIER_TYPES.contains(&kind.as_str())
+            || authority.contains(HL7_CF_OID)
+            || authority.split(message.encoding.subcomponent).next() == Some("MEF")
+    });
+    let codice_fiscale = typed
+        .and_then(|(cx, _)| cx.first().map(|id| normalized(id)))
+        .or_else(|| {
+            identifiers
+                .iter()
+                .filter_map(|cx| cx.first().map(|id| normalized(id)))
+                .chain(std::iter::once(normalized(&message.component(pid, 19, 1))))
+                .find(|id| is_codice_fiscale(id))
+        })
+        .filter(|cf| !cf.is_empty());
+
+    let mut telefono = None;
+    let mut email = None;
+    for xtn in message.repetitions(pid, 13) {
+        let get = |i: usize| xtn.get(i).map(|s| s.trim().to_string()).unwrap_or_default();
+        if get(1).eq_ignore_ascii_case("NET") || get(2).eq_ignore_ascii_case("Internet") {
+            email = email.or_else(|| demographic(get(3)));
+        } else {
+            let number = if get(0).is_empty() { get(11) } else { get(0) };
+            telefono = telefono.or_else(|| demographic(number));
+        }
+    }
+
+    let birth_place = demographic(message.component(pid, 23, 1)).or_else(|| {
+        message
+            .repetitions(pid, 11)
+            .into_iter()
+            .find(|xad| matches!(xad.get(6).map(|t| t.trim()), Some("BDL") | Some("N")))
+            .and_then(|xad| xad.get(2).cloned())
+            .and_then(demographic)
+    });
+
+    let sesso = demographic(message.component(pid, 8, 1)).map(|s| match s.to_uppercase().as_str() {
+        "M" => "M".to_string(),
+        "F" => "F".to_string(),
+        _ => String::new(),
+    });
+    let data_nascita = match demographic(message.component(pid, 7, 1)) {
+        Some(raw) if !raw.is_empty() => {
+            Some(hl7_date(&raw).ok_or_else(|| format!("Data di nascita PID-7 non valida: {}", raw))?)
+        }
+        other => other,
+    };
+
+    let mut referral = Hl7Referral {
+        message_type,
+        control_id: message.control_id(),
+        sending_facility: message.sending_facility(),
+        codice_fiscale,
+        cognome: demographic(message.component(pid, 5, 1)),
+        nome: demographic(message.component(pid, 5, 2)),
+        data_nascita,
+        sesso,
+        luogo_nascita: birth_place,
+        telefono,
+        email,
+        ..Default::default()
+    };
+
+    if referral.message_type.starts_with("ORM") {
+        let order_control = message
+            .segments("ORC")
+            .next()
+            .map(|orc| message.component(orc, 1, 1).to_uppercase())
+            .unwrap_or_default();
+        referral.annullata = HL7_CANCEL_ORDER_CONTROLS.contains(&order_control.as_str());
+
+        let mut parts = Vec::new();
+        for obr in message.segments("OBR") {
+            let service = message.component(obr, 4, 2);
+            let service = if service.is_empty() { message.component(obr, 4, 1) } else { service };
+            if !service.is_empty() {
+                parts.push(service);
+            }
+            let clinical = message.component(obr, 13, 1);
+            if !clinical.is_empty() {
+                parts.push(clinical);
+            }
+        }
+        for nte in message.segments("NTE") {
+            let comment = message.component(nte, 3, 1);
+            if !comment.is_empty() {
+                parts.push(comment);
+            }
+        }
+        if !parts.is_empty() {
+            referral.richiesta = Some(parts.join(" - "));
+        }
+    }
+
+    Ok(referral)
+}
+
+/// Paziente risultante dal messaggio: nuovo (provenienza = struttura inviante) o esistente aggiornato.
+/// Negli aggiornamenti ADT^A08 la provenienza viene impostata solo se mancante.
+pub fn referral_patient(referral: &Hl7Referral, existing: Option<Patient>) -> Result<Patient, String> {
+    let is_new = existing.is_none();
+    let mut patient = match existing {
+        Some(patient) => patient,
+        None => {
+            let required = |value: &Option<String>, label: &str| {
+                value
+                    .as_deref()
+                    .filter(|v| !v.trim().is_empty())
+                    .map(str::to_string)
+                    .ok_or_else(|| format!("{} mancante: impossibile creare il paziente", label))
+            };
+            serde_json::from_value(serde_json::json!({
+                "cognome": required(&referral.cognome, "Cognome (PID-5)")?,
+                "nome": required(&referral.nome, "Nome (PID-5)")?,
+                "data_nascita": required(&referral.data_nascita, "Data di nascita (PID-7)")?,
+            }))
+            .map_err(|e| e.to_string())?
+        }
+    };
+
+    let clear = |value: &str| (!value.is_empty()).then(|| value.to_string());
+    if let Some(v) = referral.cognome.as_deref().filter(|v| !v.is_empty()) {
+        patient.cognome = v.to_string();
+    }
+    if let Some(v) = referral.nome.as_deref().filter(|v| !v.is_empty()) {
+        patient.nome = v.to_string();
+    }
+    if let Some(v) = referral.data_nascita.as_deref().filter(|v| !v.is_empty()) {
+        patient.data_nascita = v.to_string();
+    }
+    for (target, value) in [
+        (&mut patient.codice_fiscale, &referral.codice_fiscale),
+        (&mut patient.sesso, &referral.sesso),
+        (&mut patient.luogo_nascita, &referral.luogo_nascita),
+        (&mut patient.telefono, &referral.telefono),
+        (&mut patient.email, &referral.email),
+    ] {
+        if let Some(value) = value {
+            *target = clear(value);
+        }
+    }
+
+    let facility = referral.sending_facility.trim();
+    let has_provenienza = patient.provenienza.as_deref().is_some_and(|p| !p.trim().is_empty());
+    if !facility.is_empty() && (is_new || !referral.is_update_only() || !has_provenienza) {
+        patient.provenienza = Some(facility.to_string());
+    }
+
+    // La richiesta viene annotata una sola volta anche se il messaggio è rielaborato
+    if let Some(richiesta) = referral.richiesta.as_deref() {
+        let line = format!("Richiesta {} ({}): {}", referral.control_id, facility, richiesta);
+        let note = patient.note.clone().unwrap_or_default();
+        if !note.contains(&line) {
+            patient.note = Some(if note.trim().is_empty() { line } else { format!("{}\n{}", note.trim_end(), line) });
+        }
+    }
+
+    Ok(patient)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADT_A04: &str = include_str!("../tests/fixtures/hl7/adt_a04.hl7");
+    const ADT_A08: &str = include_str!("../tests/fixtures/hl7/adt_a08.hl7");
+    const ORM_O01: &str = include_str!("../tests/fixtures/hl7/orm_o01.hl7");
+
+    fn referral(text: &str) -> Hl7Referral {
+        extract_referral(&parse_message(text).unwrap()).unwrap()
+    }
+
+    fn existing_patient() -> Patient {
+        serde_json::from_value(serde_json::json!({
+            "cognome": "Rossi",
+            "nome": "Mario",
+            "data_nascita": "1948-03-07",
+            "telefono": "061234567",
+            "provenienza": "Ambulatorio",
+            "note": "Paziente seguito dal 2029",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn split_messages_strips_mllp_and_batch_segments() {
+        let content = format!("FHS|^~\\&\rBHS|^~\\&\r\u{0b}{}\u{1c}\r\n{}BTS|2\rFTS|1\r", ADT_A04, ADT_A08);
+        let messages = split_messages(&content);
+        assert_eq!(messages.len(), 2);
+        assert!(messages.iter().all(|m| m.starts_with("MSH|")));
+        assert_eq!(messages[1].split('\r').count(), 4);
+    }
+
+    #[test]
+    fn parse_message_reads_header_fields() {
+        let message = parse_message(ADT_A04).unwrap();
+        assert_eq!(message.message_type(), "ADT^A04");
+        assert_eq!(message.control_id(), "MSG0001");
+        assert_eq!(message.sending_facility(), "OSPEDALE CIVILE");
+    }
+
+    #[test]
+    fn parse_message_rejects_malformed_input() {
+        assert!(parse_message("PID|1||X").is_err());
+        assert!(parse_message("MSH|^~").is_err());
+        assert_eq!(
+            parse_message("MSH|^~\\&|ADT|OSP|||20300101||ADT^A04|1|P|2.5").unwrap_err(),
+            "Segmento PID mancante"
+        );
+        assert!(parse_message("MSH|^~\\&|ADT\rpid|1").is_err());
+    }
+
+    #[test]
+    fn parse_message_honours_declared_encoding_characters() {
+        let message = parse_message("MSH#*@$%#ADT#OSP$F$ 1*X#####ORM*O01#42\rPID#1##ID1##VERDI*ANNA").unwrap();
+        assert_eq!(message.message_type(), "ORM^O01");
+        assert_eq!(message.sending_facility(), "OSP# 1");
+        assert_eq!(message.control_id(), "42");
+    }
+
+    #[test]
+    fn extract_referral_reads_adt_a04_demographics() {
+        let referral = referral(ADT_A04);
+        assert_eq!(referral.message_type, "ADT^A04");
+        assert_eq!(referral.codice_fiscale.as_deref(), Some("RSSMRA48C07H501X"));
+        assert_eq!(referral.cognome.as_deref(), Some("ROSSI"));
+        assert_eq!(referral.nome.as_deref(), Some("MARIO"));
+        assert_eq!(referral.data_nascita.as_deref(), Some("1948-03-07"));
+        assert_eq!(referral.sesso.as_deref(), Some("M"));
+        assert_eq!(referral.luogo_nascita.as_deref(), Some("NAPOLI"));
+        assert_eq!(referral.telefono.as_deref(), Some("3331234567"));
+        assert_eq!(referral.email.as_deref(), Some("mario.rossi@example.it"));
+        assert!(!referral.is_update_only());
+        assert!(referral.richiesta.is_none());
+    }
+
+    #[test]
+    fn extract_referral_matches_untyped_codice_fiscale_and_null_values() {
+        let referral = referral(ADT_A08);
+        assert!(referral.is_update_only());
+        // Nessun identificativo tipizzato: il CF viene riconosciuto dal formato in PID-19
+        assert_eq!(referral.codice_fiscale.as_deref(), Some("RSSMRA48C07H501X"));
+        assert_eq!(referral.nome.as_deref(), Some("MARIO ANTONIO"));
+        // `""` è il nullo HL7: il telefono va cancellato, i campi assenti restano invariati
+        assert_eq!(referral.telefono.as_deref(), Some(""));
+        assert_eq!(referral.email, None);
+        assert_eq!(referral.luogo_nascita, None);
+    }
+
+    #[test]
+    fn extract_referral_collects_orm_request_and_cancellation() {
+        let order = referral(ORM_O01);
+        assert_eq!(order.message_type, "ORM^O01");
+        assert_eq!(order.codice_fiscale.as_deref(), Some("BNCLRA52T45F839K"));
+        assert!(!order.annullata);
+        assert_eq!(
+            order.richiesta.as_deref(),
+            Some("Ecocardiogramma transtoracico - Stenosi aortica severa sintomatica - Valutazione per TAVI & consulenza")
+        );
+
+        let cancelled = referral(&ORM_O01.replace("ORC|NW|", "ORC|CA|"));
+        assert!(cancelled.annullata);
+    }
+
+    #[test]
+    fn extract_referral_rejects_unsupported_types_and_bad_dates() {
+        let unsupported = parse_message(&ADT_A04.replace("ADT^A04^ADT_A01", "ADT^A03")).unwrap();
+        assert_eq!(extract_referral(&unsupported).unwrap_err(), "Tipo di messaggio non gestito: ADT^A03");
+        let bad_date = parse_message(&ADT_A04.replace("19480307", "19481307")).unwrap();
+        assert!(extract_referral(&bad_date).unwrap_err().contains("PID-7"));
+    }
+
+    #[test]
+    fn referral_patient_creates_new_patient_from_registration() {
+        let patient = referral_patient(&referral(ADT_A04), None).unwrap();
+        assert_eq!(patient.cognome, "ROSSI");
+        assert_eq!(patient.data_nascita, "1948-03-07");
+        assert_eq!(patient.codice_fiscale.as_deref(), Some("RSSMRA48C07H501X"));
+        assert_eq!(patient.provenienza.as_deref(), Some("OSPEDALE CIVILE"));
+
+        let mut incomplete = referral(ADT_A04);
+        incomplete.data_nascita = None;
+        assert!(referral_patient(&incomplete, None).unwrap_err().contains("Data di nascita"));
+    }
+
+    #[test]
+    fn referral_patient_updates_existing_patient() {
+        let patient = referral_patient(&referral(ADT_A08), Some(existing_patient())).unwrap();
+        assert_eq!(patient.nome, "MARIO ANTONIO");
+        assert_eq!(patient.telefono, None);
+        // A08 non sovrascrive una provenienza già registrata
+        assert_eq!(patient.provenienza.as_deref(), Some("Ambulatorio"));
+        assert_eq!(patient.note.as_deref(), Some("Paziente seguito dal 2029"));
+    }
+
+    #[test]
+    fn referral_patient_notes_order_request_once() {
+        let order = referral(ORM_O01);
+        let first = referral_patient(&order, Some(existing_patient())).unwrap();
+        let again = referral_patient(&order, Some(first.clone())).unwrap();
+        assert_eq!(first.provenienza.as_deref(), Some("ASL ROMA 1"));
+        assert_eq!(first.note, again.note);
+        assert!(again.note.unwrap().ends_with("Richiesta MSG0003 (ASL ROMA 1): Ecocardiogramma transtoracico - Stenosi aortica severa sintomatica - Valutazione per TAVI & consulenza"));
+    }
+}
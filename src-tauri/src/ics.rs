@@ -0,0 +1,309 @@
+use chrono::{Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+
+const ICS_PRODUCT_ID: &str = "-//GMD Medical//Registro TAVI//IT";
+const ICS_UID_DOMAIN: &str = "registro-tavi";
+const ICS_LINE_LIMIT: usize = 75;
+/// Limite di sicurezza per gli eventi all-day su più giorni in importazione
+const ICS_MAX_EVENT_DAYS: i64 = 366;
+
+/// Evento da esportare. Gli orari sono locali e vengono scritti in UTC.
+#[derive(Debug, Clone)]
+pub struct IcsEvent {
+    pub uid: String,
+    pub summary: String,
+    pub description: Option<String>,
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    pub all_day: bool,
+}
+
+impl IcsEvent {
+    /// Evento di un'intera giornata
+    pub fn all_day(uid: String, date: NaiveDate, summary: String, description: Option<String>) -> Self {
+        let start = date.and_hms_opt(0, 0, 0).unwrap_or_default();
+        IcsEvent {
+            uid,
+            summary,
+            description,
+            start,
+            end: start + chrono::Duration::days(1),
+            all_day: true,
+        }
+    }
+
+    /// Evento con orario di inizio e durata
+    pub fn timed(uid: String, start: NaiveDateTime, minutes: i64, summary: String, description: Option<String>) -> Self {
+        IcsEvent {
+            uid,
+            summary,
+            description,
+            start,
+            end: start + chrono::Duration::minutes(minutes.max(1)),
+            all_day: false,
+        }
+    }
+}
+
+/// UID stabile: riesportando lo stesso elemento il calendario aggiorna l'evento invece di duplicarlo
+pub fn event_uid(kind: &str, key: &str) -> String {
+    format!("{}-{}@{}", kind, key, ICS_UID_DOMAIN)
+}
+
+fn escape_text(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+/// Spezza le righe oltre 75 ottetti (RFC 5545 §3.1) senza tagliare caratteri UTF-8
+fn fold_line(line: &str) -> String {
+    let mut folded = String::new();
+    let mut current = 0;
+    for ch in line.chars() {
+        let len = ch.len_utf8();
+        if current + len > ICS_LINE_LIMIT {
+            folded.push_str("\r\n ");
+            current = 1;
+        }
+        folded.push(ch);
+        current += len;
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+fn format_utc(local: &NaiveDateTime) -> String {
+    match Local.from_local_datetime(local).earliest() {
+        Some(dt) => dt.with_timezone(&Utc).format("%Y%m%dT%H%M%SZ").to_string(),
+        // Orario inesistente (cambio ora legale): resta come ora locale "floating"
+        None => local.format("%Y%m%dT%H%M%S").to_string(),
+    }
+}
+
+/// Calendario VCALENDAR completo con righe CRLF
+pub fn write_calendar(name: &str, events: &[IcsEvent]) -> String {
+    let stamp = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        format!("PRODID:{}", ICS_PRODUCT_ID),
+        "CALSCALE:GREGORIAN".to_string(),
+        "METHOD:PUBLISH".to_string(),
+        format!("X-WR-CALNAME:{}", escape_text(name)),
+    ];
+
+    for event in events {
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:{}", event.uid));
+        lines.push(format!("DTSTAMP:{}", stamp));
+        if event.all_day {
+            lines.push(format!("DTSTART;VALUE=DATE:{}", event.start.format("%Y%m%d")));
+            lines.push(format!("DTEND;VALUE=DATE:{}", event.end.format("%Y%m%d")));
+            lines.push("TRANSP:TRANSPARENT".to_string());
+        } else {
+            lines.push(format!("DTSTART:{}", format_utc(&event.start)));
+            lines.push(format!("DTEND:{}", format_utc(&event.end)));
+        }
+        lines.push(format!("SUMMARY:{}", escape_text(&event.summary)));
+        if let Some(description) = event.description.as_deref().filter(|d| !d.trim().is_empty()) {
+            lines.push(format!("DESCRIPTION:{}", escape_text(description)));
+        }
+        lines.push("END:VEVENT".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    lines.iter().map(|line| fold_line(line)).collect()
+}
+
+/// Riunisce le righe spezzate (CRLF o LF seguiti da spazio o tab)
+fn unfold(content: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for raw in content.split('\n') {
+        let raw = raw.strip_suffix('\r').unwrap_or(raw);
+        match raw.strip_prefix(' ').or_else(|| raw.strip_prefix('\t')) {
+            Some(rest) if !lines.is_empty() => {
+                if let Some(last) = lines.last_mut() {
+                    last.push_str(rest);
+                }
+            }
+            _ => lines.push(raw.to_string()),
+        }
+    }
+    lines
+}
+
+/// Separa "NOME;PARAM=...:valore" in (nome maiuscolo, valore); i due punti tra
+/// virgolette nei parametri non chiudono il nome.
+fn split_property(line: &str) -> Option<(String, String)> {
+    let mut in_quotes = false;
+    for (index, ch) in line.char_indices() {
+        match ch {
+            '"' => in_quotes = !in_quotes,
+            ':' if !in_quotes => {
+                let (head, value) = (&line[..index], &line[index + 1..]);
+                let name = head.split(';').next().unwrap_or(head);
+                return Some((name.trim().to_uppercase(), value.trim().to_string()));
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Data locale di un valore DTSTART/DTEND: giorno intero, data-ora locale o UTC ("Z")
+fn parse_ics_date(value: &str) -> Option<NaiveDate> {
+    if let Some(utc) = value.strip_suffix('Z') {
+        let dt = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").ok()?;
+        return Some(Utc.from_utc_datetime(&dt).with_timezone(&Local).date_naive());
+    }
+    NaiveDate::parse_from_str(value.get(..8)?, "%Y%m%d").ok()
+}
+
+/// Giorni coperti dagli eventi di un calendario (es. giorni di apertura dell'ambulatorio).
+/// Gli eventi annullati sono ignorati; gli eventi all-day su più giorni coprono ogni giorno
+/// fino a DTEND escluso. Le regole di ricorrenza (RRULE) non vengono espanse.
+pub fn parse_event_dates(content: &str) -> Result<Vec<NaiveDate>, String> {
+    let lines = unfold(content);
+    if !lines.iter().any(|l| l.trim().eq_ignore_ascii_case("BEGIN:VCALENDAR")) {
+        return Err("Il file non è un calendario iCalendar valido".to_string());
+    }
+
+    let mut dates = std::collections::BTreeSet::new();
+    let mut in_event = false;
+    let mut start: Option<(NaiveDate, bool)> = None;
+    let mut end: Option<NaiveDate> = None;
+    let mut cancelled = false;
+
+    for line in &lines {
+        let Some((name, value)) = split_property(line) else {
+            continue;
+        };
+        match (name.as_str(), value.to_uppercase().as_str()) {
+            ("BEGIN", "VEVENT") => {
+                in_event = true;
+                start = None;
+                end = None;
+                cancelled = false;
+            }
+            ("END", "VEVENT") if in_event => {
+                in_event = false;
+                if cancelled {
+                    continue;
+                }
+                if let Some((first, all_day)) = start {
+                    dates.insert(first);
+                    if let Some(last) = end.filter(|_| all_day) {
+                        let days = (last - first).num_days().min(ICS_MAX_EVENT_DAYS);
+                        for offset in 1..days {
+                            dates.insert(first + chrono::Duration::days(offset));
+                        }
+                    }
+                }
+            }
+            ("DTSTART", _) if in_event => {
+                // VALUE=DATE: giorno intero senza componente oraria
+                start = parse_ics_date(&value).map(|d| (d, !value.contains('T')));
+            }
+            ("DTEND", _) if in_event => end = parse_ics_date(&value),
+            ("STATUS", "CANCELLED") if in_event => cancelled = true,
+            _ => {}
+        }
+    }
+
+    Ok(dates.into_iter().collect())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn escape_text_escapes_separators_and_newlines() {
+        assert_eq!(escape_text("a;b,c\\d\r\ne\nf"), r"a\;b\,c\\d\ne\nf");
+    }
+
+    #[test]
+    fn fold_line_keeps_lines_within_limit_and_utf8_intact() {
+        let line = format!("SUMMARY:{}", "è".repeat(60));
+        let folded = fold_line(&line);
+        for part in folded.split("\r\n").filter(|p| !p.is_empty()) {
+            assert!(part.len() <= ICS_LINE_LIMIT, "{} ottetti", part.len());
+        }
+        assert_eq!(unfold(&folded), vec![line, String::new()]);
+    }
+
+    #[test]
+    fn write_calendar_writes_all_day_and_timed_events() {
+        let events = vec![
+            IcsEvent::all_day(event_uid("tavi", "1"), date("2030-05-06"), "TAVI, Rossi".to_string(), None),
+            IcsEvent::timed(
+                event_uid("visita", "2"),
+                date("2030-05-07").and_hms_opt(9, 0, 0).unwrap(),
+                30,
+                "Visita".to_string(),
+                Some("Prima visita".to_string()),
+            ),
+        ];
+        let calendar = write_calendar("Agenda", &events);
+
+        assert!(calendar.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(calendar.ends_with("END:VCALENDAR\r\n"));
+        assert!(calendar.contains("UID:tavi-1@registro-tavi\r\n"));
+        assert!(calendar.contains("DTSTART;VALUE=DATE:20300506\r\n"));
+        assert!(calendar.contains("DTEND;VALUE=DATE:20300507\r\n"));
+        assert!(calendar.contains("SUMMARY:TAVI\\, Rossi\r\n"));
+        assert!(calendar.contains("DESCRIPTION:Prima visita\r\n"));
+        assert_eq!(calendar.matches("BEGIN:VEVENT").count(), 2);
+    }
+
+    #[test]
+    fn exported_calendar_round_trips_through_the_parser() {
+        let events = vec![
+            IcsEvent::all_day(event_uid("tavi", "1"), date("2030-05-06"), "TAVI".to_string(), None),
+            IcsEvent::timed(
+                event_uid("visita", "2"),
+                date("2030-05-08").and_hms_opt(10, 30, 0).unwrap(),
+                45,
+                "Visita".to_string(),
+                None,
+            ),
+        ];
+        let dates = parse_event_dates(&write_calendar("Agenda", &events)).unwrap();
+        assert_eq!(dates, vec![date("2030-05-06"), date("2030-05-08")]);
+    }
+
+    #[test]
+    fn parse_event_dates_expands_multi_day_events_and_skips_cancelled() {
+        let content = "BEGIN:VCALENDAR\n\
+                       BEGIN:VEVENT\n\
+                       DTSTART;VALUE=DATE:20300101\n\
+                       DTEND;VALUE=DATE:20300104\n\
+                       END:VEVENT\n\
+                       BEGIN:VEVENT\n\
+                       DTSTART;VALUE=DATE:20300110\n\
+                       STATUS:CANCELLED\n\
+                       END:VEVENT\n\
+                       BEGIN:VEVENT\n\
+                       DTSTART;TZID=\"Europe/Rome: CET\":20300115T090000\n\
+                       END:VEVENT\n\
+                       END:VCALENDAR\n";
+        let dates = parse_event_dates(content).unwrap();
+        assert_eq!(
+            dates,
+            vec![date("2030-01-01"), date("2030-01-02"), date("2030-01-03"), date("2030-01-15")]
+        );
+    }
+
+    #[test]
+    fn parse_event_dates_rejects_non_calendar_content() {
+        assert!(parse_event_dates("nome;data\nRossi;2030-01-01\n").is_err());
+    }
+}
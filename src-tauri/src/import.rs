@@ -0,0 +1,555 @@
+use crate::models::{
+    import_fields, normalize_slot_time, ImportCandidate, ImportFieldDef, ImportFieldInfo, ImportPreview,
+    ImportRequest, Priority, MAIN_ACCESS_OPTIONS,
+};
+use crate::xlsx::{self, XlsxCell};
+use chrono::{Datelike, Local, NaiveDate};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// Righe mostrate nell'anteprima del file
+const IMPORT_PREVIEW_ROWS: usize = 10;
+
+/// Contenuto tabellare del file: intestazioni e righe con il numero di riga del file
+pub struct ImportTable {
+    pub sheets: Vec<String>,
+    pub sheet: Option<String>,
+    pub headers: Vec<String>,
+    pub rows: Vec<(usize, Vec<String>)>,
+}
+
+// ============================================================================
+// LETTURA FILE
+// ============================================================================
+
+fn cell_to_string(cell: &XlsxCell) -> String {
+    match cell {
+        XlsxCell::Empty => String::new(),
+        XlsxCell::Text(value) => value.trim().to_string(),
+        XlsxCell::Number(value) => {
+            if value.fract() == 0.0 && value.abs() < 1e15 {
+                format!("{}", *value as i64)
+            } else {
+                value.to_string()
+            }
+        }
+        XlsxCell::Date(date) => date.format("%Y-%m-%d").to_string(),
+        XlsxCell::DateTime(value) => {
+            // Le celle con solo orario hanno come data l'origine di Excel
+            if value.date() == NaiveDate::from_ymd_opt(1899, 12, 30).unwrap_or_default() {
+                value.format("%H:%M").to_string()
+            } else {
+                value.format("%Y-%m-%d %H:%M").to_string()
+            }
+        }
+        XlsxCell::Bool(value) => if *value { "si" } else { "no" }.to_string(),
+    }
+}
+
+/// Decodifica il testo CSV: UTF-8 (con o senza BOM), altrimenti Latin-1 come da export di Excel
+fn decode_csv(bytes: &[u8]) -> String {
+    let bytes = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(bytes);
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => bytes.iter().map(|&b| b as char).collect(),
+    }
+}
+
+/// Separatore più frequente nella prima riga, fuori dalle virgolette
+fn detect_delimiter(text: &str) -> char {
+    let first_line = text.lines().find(|line| !line.trim().is_empty()).unwrap_or("");
+    let mut counts = [(';', 0usize), (',', 0), ('\t', 0)];
+    let mut quoted = false;
+    for c in first_line.chars() {
+        if c == '"' {
+            quoted = !quoted;
+        } else if !quoted {
+            if let Some(entry) = counts.iter_mut().find(|(d, _)| *d == c) {
+                entry.1 += 1;
+            }
+        }
+    }
+    counts
+        .iter()
+        .max_by_key(|(_, n)| *n)
+        .filter(|(_, n)| *n > 0)
+        .map(|(d, _)| *d)
+        .unwrap_or(';')
+}
+
+/// Righe CSV con campi tra virgolette ("" come escape) anche su più righe
+fn parse_csv(text: &str) -> Vec<(usize, Vec<String>)> {
+    let delimiter = detect_delimiter(text);
+    let mut rows = Vec::new();
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut line = 1;
+    let mut row_start = 1;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if quoted {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => quoted = false,
+                '\n' => {
+                    line += 1;
+                    field.push(c);
+                }
+                _ => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' if field.trim().is_empty() => {
+                field.clear();
+                quoted = true;
+            }
+            '\r' => {}
+            '\n' => {
+                fields.push(std::mem::take(&mut field));
+                rows.push((row_start, std::mem::take(&mut fields)));
+                line += 1;
+                row_start = line;
+            }
+            c if c == delimiter => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    if !field.is_empty() || !fields.is_empty() {
+        fields.push(field);
+        rows.push((row_start, fields));
+    }
+
+    rows.into_iter()
+        .map(|(n, fields)| (n, fields.into_iter().map(|f| f.trim().to_string()).collect()))
+        .collect()
+}
+
+/// Legge un file CSV o XLSX; la prima riga non vuota è l'intestazione
+pub fn read_table(path: &str, sheet: Option<&str>) -> Result<ImportTable, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Impossibile leggere il file: {}", e))?;
+    let extension = std::path::Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_lowercase)
+        .unwrap_or_default();
+
+    let (sheets, selected, rows) = match extension.as_str() {
+        "xlsx" | "xlsm" => {
+            let workbook = xlsx::read_workbook(&bytes)?;
+            let names: Vec<String> = workbook.iter().map(|s| s.name.clone()).collect();
+            let selected = match sheet.map(str::trim).filter(|s| !s.is_empty()) {
+                Some(name) => workbook
+                    .into_iter()
+                    .find(|s| s.name == name)
+                    .ok_or_else(|| format!("Foglio non trovato: {}", name))?,
+                None => workbook.into_iter().next().ok_or("La cartella di lavoro non contiene fogli")?,
+            };
+            let rows = selected
+                .rows
+                .iter()
+                .map(|(n, cells)| (*n, cells.iter().map(cell_to_string).collect()))
+                .collect();
+            (names, Some(selected.name), rows)
+        }
+        "csv" | "txt" => (Vec::new(), None, parse_csv(&decode_csv(&bytes))),
+        _ => return Err("Formato non supportato: selezionare un file .csv o .xlsx".to_string()),
+    };
+
+    let mut rows = rows
+        .into_iter()
+        .filter(|(_, cells): &(usize, Vec<String>)| cells.iter().any(|c| !c.trim().is_empty()));
+    let (_, headers) = rows.next().ok_or("Il file non contiene dati")?;
+    let headers: Vec<String> = headers.into_iter().map(|h| h.trim().to_string()).collect();
+
+    Ok(ImportTable {
+        sheets,
+        sheet: selected,
+        headers,
+        rows: rows.collect(),
+    })
+}
+
+// ============================================================================
+// ABBINAMENTO COLONNE
+// ============================================================================
+
+/// Intestazione normalizzata: minuscole, senza accenti e punteggiatura
+fn normalize_header(value: &str) -> String {
+    value
+        .to_lowercase()
+        .chars()
+        .map(|c| match c {
+            'à' | 'á' => 'a',
+            'è' | 'é' => 'e',
+            'ì' | 'í' => 'i',
+            'ò' | 'ó' => 'o',
+            'ù' | 'ú' => 'u',
+            c if c.is_alphanumeric() => c,
+            _ => ' ',
+        })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Propone l'abbinamento campo -> intestazione confrontando codice, etichetta e alias
+pub fn suggest_mapping(fields: &[ImportFieldDef], headers: &[String]) -> HashMap<String, String> {
+    let mut mapping = HashMap::new();
+    let mut used = Vec::new();
+    for (codice, etichetta, _, _, aliases) in fields {
+        let candidates: Vec<String> = [codice.replace('_', " "), etichetta.to_string()]
+            .into_iter()
+            .chain(aliases.iter().map(|a| a.to_string()))
+            .map(|c| normalize_header(&c))
+            .collect();
+        let found = headers.iter().find(|header| {
+            let normalized = normalize_header(header);
+            !used.contains(*header) && candidates.contains(&normalized)
+        });
+        if let Some(header) = found {
+            used.push(header.clone());
+            mapping.insert(codice.to_string(), header.clone());
+        }
+    }
+    mapping
+}
+
+pub fn preview(path: &str, sheet: Option<&str>, target: &str) -> Result<ImportPreview, String> {
+    let fields = import_fields(target)?;
+    let table = read_table(path, sheet)?;
+    Ok(ImportPreview {
+        abbinamento_proposto: suggest_mapping(fields, &table.headers),
+        campi: fields
+            .iter()
+            .map(|(codice, etichetta, tipo, obbligatorio, _)| ImportFieldInfo {
+                codice: codice.to_string(),
+                etichetta: etichetta.to_string(),
+                tipo: tipo.to_string(),
+                obbligatorio: *obbligatorio,
+            })
+            .collect(),
+        totale_righe: table.rows.len(),
+        righe_esempio: table.rows.iter().take(IMPORT_PREVIEW_ROWS).map(|(_, r)| r.clone()).collect(),
+        fogli: table.sheets,
+        foglio: table.sheet,
+        intestazioni: table.headers,
+    })
+}
+
+// ============================================================================
+// CONVERSIONE VALORI
+// ============================================================================
+
+/// Date italiane (gg/mm/aaaa, gg-mm-aaaa, gg.mm.aaaa, anno a due cifre) o ISO; l'eventuale orario è ignorato
+pub fn parse_date_it(value: &str) -> Option<NaiveDate> {
+    let value = value.trim().split([' ', 'T']).next().unwrap_or("");
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Some(date);
+    }
+    let parts: Vec<&str> = value.split(['/', '-', '.']).collect();
+    if parts.len() != 3 {
+        return None;
+    }
+    let day: u32 = parts[0].parse().ok()?;
+    let month: u32 = parts[1].parse().ok()?;
+    let mut year: i32 = parts[2].parse().ok()?;
+    if parts[2].len() == 2 {
+        // Anni a due cifre: futuri rispetto a oggi vanno nel secolo precedente
+        let current = Local::now().year();
+        year += 2000;
+        if year > current {
+            year -= 100;
+        }
+    } else if parts[2].len() != 4 {
+        return None;
+    }
+    NaiveDate::from_ymd_opt(year, month, day)
+}
+
+/// Numeri con virgola decimale e separatore delle migliaia ("1.234,5"), unità di misura in coda ignorate
+pub fn parse_decimal_it(value: &str) -> Option<f64> {
+    let numeric: String = value
+        .trim()
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || matches!(c, ',' | '.' | '-' | '+' | ' '))
+        .filter(|c| *c != ' ')
+        .collect();
+    let normalized = if numeric.contains(',') {
+        numeric.replace('.', "").replace(',', ".")
+    } else if numeric.matches('.').count() > 1 {
+        numeric.replace('.', "")
+    } else {
+        numeric
+    };
+    normalized.parse::<f64>().ok().filter(|v| v.is_finite())
+}
+
+pub fn parse_yes_no(value: &str) -> Option<bool> {
+    match normalize_header(value).as_str() {
+        "si" | "s" | "yes" | "y" | "true" | "vero" | "1" | "x" => Some(true),
+        "no" | "n" | "false" | "falso" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+fn format_decimal(value: f64) -> String {
+    let text = format!("{:.2}", value);
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/// Converte il testo della cella nel valore JSON del campo; `Err` con il motivo se non interpretabile
+fn convert_value(tipo: &str, raw: &str) -> Result<Value, String> {
+    match tipo {
+        "data" => parse_date_it(raw)
+            .map(|d| Value::from(d.format("%Y-%m-%d").to_string()))
+            .ok_or_else(|| format!("data non valida '{}'", raw)),
+        "ora" => {
+            let time = raw.trim();
+            let time = match time.matches([':', '.']).count() {
+                2 => time.rsplit_once([':', '.']).map(|(hm, _)| hm).unwrap_or(time),
+                _ => time,
+            };
+            normalize_slot_time(time)
+                .map(Value::from)
+                .ok_or_else(|| format!("orario non valido '{}'", raw))
+        }
+        "decimale" => parse_decimal_it(raw)
+            .map(Value::from)
+            .ok_or_else(|| format!("numero non valido '{}'", raw)),
+        "decimale_testo" => parse_decimal_it(raw)
+            .map(|v| Value::from(format_decimal(v)))
+            .ok_or_else(|| format!("numero non valido '{}'", raw)),
+        "si_no" => parse_yes_no(raw)
+            .map(Value::from)
+            .ok_or_else(|| format!("valore sì/no non valido '{}'", raw)),
+        "si_no_codice" => parse_yes_no(raw)
+            .map(|v| Value::from(if v { "si" } else { "no" }))
+            .ok_or_else(|| format!("valore sì/no non valido '{}'", raw)),
+        "sesso" => match normalize_header(raw).as_str() {
+            "m" | "maschio" | "maschile" | "uomo" => Ok(Value::from("M")),
+            "f" | "femmina" | "femminile" | "donna" => Ok(Value::from("F")),
+            _ => Err(format!("sesso non valido '{}'", raw)),
+        },
+        "priorita" => Priority::parse(raw)
+            .map(|p| Value::from(p.as_str()))
+            .ok_or_else(|| format!("priorità non valida '{}'", raw)),
+        "tipo_valvola" => {
+            let normalized = normalize_header(raw);
+            if normalized.starts_with("balloon") || normalized.starts_with("pallone") || normalized == "be" {
+                Ok(Value::from("Balloon Expandable"))
+            } else if normalized.starts_with("self") || normalized.starts_with("auto") || normalized == "se" {
+                Ok(Value::from("Self Expandable"))
+            } else {
+                Err(format!("tipo valvola non valido '{}'", raw))
+            }
+        }
+        "accesso" => {
+            let normalized = normalize_header(raw);
+            MAIN_ACCESS_OPTIONS
+                .iter()
+                .find(|(code, label)| normalize_header(code) == normalized || normalize_header(label) == normalized)
+                .map(|(code, _)| Value::from(*code))
+                .ok_or_else(|| format!("accesso non valido '{}'", raw))
+        }
+        _ => Ok(Value::from(raw.trim())),
+    }
+}
+
+fn is_valid_codice_fiscale(value: &str) -> bool {
+    static CODICE_FISCALE: OnceLock<regex::Regex> = OnceLock::new();
+    CODICE_FISCALE
+        .get_or_init(|| {
+            regex::Regex::new(r"^[A-Z]{6}[0-9LMNPQRSTUV]{2}[A-Z][0-9LMNPQRSTUV]{2}[A-Z][0-9LMNPQRSTUV]{3}[A-Z]$")
+                .unwrap()
+        })
+        .is_match(value)
+}
+
+/// Legge il file e converte ogni riga nei valori dei campi abbinati, con errori e avvisi per riga
+pub fn build_candidates(request: &ImportRequest) -> Result<Vec<ImportCandidate>, String> {
+    let fields = import_fields(&request.target)?;
+    let table = read_table(&request.path, request.foglio.as_deref())?;
+
+    for (field, header) in &request.abbinamento {
+        if !fields.iter().any(|(codice, ..)| codice == field) {
+            return Err(format!("Campo di destinazione sconosciuto: {}", field));
+        }
+        if !table.headers.iter().any(|h| h == header) {
+            return Err(format!("Colonna non presente nel file: {}", header));
+        }
+    }
+    let missing: Vec<&str> = fields
+        .iter()
+        .filter(|(codice, _, _, obbligatorio, _)| *obbligatorio && !request.abbinamento.contains_key(*codice))
+        .map(|(_, etichetta, ..)| *etichetta)
+        .collect();
+    if !missing.is_empty() {
+        return Err(format!("Campi obbligatori non abbinati: {}", missing.join(", ")));
+    }
+
+    let columns: Vec<(&ImportFieldDef, usize)> = fields
+        .iter()
+        .filter_map(|field| {
+            let header = request.abbinamento.get(field.0)?;
+            Some((field, table.headers.iter().position(|h| h == header)?))
+        })
+        .collect();
+    let today = Local::now().date_naive();
+
+    Ok(table
+        .rows
+        .into_iter()
+        .map(|(riga, cells)| {
+            let mut candidate = ImportCandidate {
+                riga,
+                valori: serde_json::Map::new(),
+                errori: Vec::new(),
+                avvisi: Vec::new(),
+            };
+            for ((codice, etichetta, tipo, obbligatorio, _), index) in &columns {
+                let raw = cells.get(*index).map(|c| c.trim()).unwrap_or("");
+                if raw.is_empty() {
+                    if *obbligatorio {
+                        candidate.errori.push(format!("{}: valore mancante", etichetta));
+                    }
+                    continue;
+                }
+                match convert_value(tipo, raw) {
+                    Ok(value) => {
+                        candidate.valori.insert(codice.to_string(), value);
+                    }
+                    Err(reason) if *obbligatorio => candidate.errori.push(format!("{}: {}", etichetta, reason)),
+                    Err(reason) => candidate.avvisi.push(format!("{}: {}, valore ignorato", etichetta, reason)),
+                }
+            }
+
+            if let Some(Value::String(cf)) = candidate.valori.get_mut("codice_fiscale") {
+                *cf = cf.to_uppercase().replace(' ', "");
+                if !is_valid_codice_fiscale(cf) {
+                    candidate.avvisi.push(format!("Codice fiscale con formato non valido: {}", cf));
+                }
+            }
+            if let Some(Value::String(email)) = candidate.valori.get("email") {
+                if !email.contains('@') || email.contains(' ') {
+                    candidate.avvisi.push(format!("Email non valida: {}", email));
+                }
+            }
+            let nascita = candidate
+                .valori
+                .get("data_nascita")
+                .and_then(Value::as_str)
+                .and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok());
+            if nascita.is_some_and(|d| d > today) {
+                candidate.errori.push("Data di nascita nel futuro".to_string());
+            }
+            candidate
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::PATIENT_IMPORT_FIELDS;
+
+    #[test]
+    fn detect_delimiter_ignores_quoted_separators() {
+        assert_eq!(detect_delimiter("cognome;nome;note\n"), ';');
+        assert_eq!(detect_delimiter("\"Rossi; Mario\",nome,cf\n"), ',');
+        assert_eq!(detect_delimiter("a\tb\tc"), '\t');
+        assert_eq!(detect_delimiter("colonna"), ';');
+    }
+
+    #[test]
+    fn parse_csv_handles_quotes_escapes_and_multiline_fields() {
+        let text = "cognome;note\r\n\"Rossi\";\"riga \"\"uno\"\"\nriga due\"\r\n Bianchi ; \n";
+        assert_eq!(
+            parse_csv(text),
+            vec![
+                (1, vec!["cognome".to_string(), "note".to_string()]),
+                (2, vec!["Rossi".to_string(), "riga \"uno\"\nriga due".to_string()]),
+                (4, vec!["Bianchi".to_string(), String::new()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_csv_strips_bom_and_falls_back_to_latin1() {
+        assert_eq!(decode_csv(&[0xEF, 0xBB, 0xBF, b'a', b';']), "a;");
+        assert_eq!(decode_csv(&[b'c', b'a', b'f', 0xE8]), "cafè");
+    }
+
+    #[test]
+    fn parse_date_it_accepts_italian_and_iso_formats() {
+        let expected = NaiveDate::from_ymd_opt(1948, 3, 7);
+        assert_eq!(parse_date_it("07/03/1948"), expected);
+        assert_eq!(parse_date_it("7-3-1948"), expected);
+        assert_eq!(parse_date_it("07.03.1948 10:30"), expected);
+        assert_eq!(parse_date_it("1948-03-07T00:00:00"), expected);
+        assert_eq!(parse_date_it("07/03/48"), expected);
+        assert_eq!(parse_date_it("31/02/1948"), None);
+        assert_eq!(parse_date_it("07/03/948"), None);
+        assert_eq!(parse_date_it("marzo 1948"), None);
+    }
+
+    #[test]
+    fn parse_decimal_it_handles_separators_and_units() {
+        assert_eq!(parse_decimal_it("1.234,5"), Some(1234.5));
+        assert_eq!(parse_decimal_it("0,85 cm²"), Some(0.85));
+        assert_eq!(parse_decimal_it("72.5 kg"), Some(72.5));
+        assert_eq!(parse_decimal_it("1.234.567"), Some(1234567.0));
+        assert_eq!(parse_decimal_it("-3"), Some(-3.0));
+        assert_eq!(parse_decimal_it("n.d."), None);
+    }
+
+    #[test]
+    fn parse_yes_no_recognises_common_spellings() {
+        assert_eq!(parse_yes_no("Sì"), Some(true));
+        assert_eq!(parse_yes_no(" X "), Some(true));
+        assert_eq!(parse_yes_no("NO"), Some(false));
+        assert_eq!(parse_yes_no("0"), Some(false));
+        assert_eq!(parse_yes_no("forse"), None);
+    }
+
+    #[test]
+    fn convert_value_normalises_typed_fields() {
+        assert_eq!(convert_value("data", "07/03/1948"), Ok(Value::from("1948-03-07")));
+        assert_eq!(convert_value("decimale_testo", "23,50"), Ok(Value::from("23.5")));
+        assert_eq!(convert_value("si_no_codice", "si"), Ok(Value::from("si")));
+        assert_eq!(convert_value("sesso", "Femmina"), Ok(Value::from("F")));
+        assert_eq!(convert_value("tipo_valvola", "autoespandibile"), Ok(Value::from("Self Expandable")));
+        assert!(convert_value("sesso", "altro").is_err());
+        assert!(convert_value("data", "ieri").is_err());
+    }
+
+    #[test]
+    fn is_valid_codice_fiscale_checks_format_and_omocodia() {
+        assert!(is_valid_codice_fiscale("RSSMRA48C07H501X"));
+        assert!(is_valid_codice_fiscale("RSSMRA48C07H50MX"));
+        assert!(!is_valid_codice_fiscale("RSSMRA48C07H501"));
+        assert!(!is_valid_codice_fiscale("rssmra48c07h501x"));
+        assert!(!is_valid_codice_fiscale("1SSMRA48C07H501X"));
+    }
+
+    #[test]
+    fn suggest_mapping_matches_codes_labels_and_aliases_once() {
+        let headers: Vec<String> = ["COGNOME", "Nome", "Nato il", "C.F.", "Data di nascita", "Altro"]
+            .iter()
+            .map(|h| h.to_string())
+            .collect();
+        let mapping = suggest_mapping(&PATIENT_IMPORT_FIELDS, &headers);
+        assert_eq!(mapping.get("cognome").map(String::as_str), Some("COGNOME"));
+        assert_eq!(mapping.get("nome").map(String::as_str), Some("Nome"));
+        assert_eq!(mapping.get("data_nascita").map(String::as_str), Some("Nato il"));
+        assert_eq!(mapping.get("codice_fiscale").map(String::as_str), Some("C.F."));
+        assert!(!mapping.values().any(|h| h == "Altro"));
+    }
+}
@@ -3,8 +3,14 @@
 
 mod commands;
 mod database;
+mod fhir;
+mod hl7;
+mod ics;
+mod import;
 mod models;
+mod research;
 mod updater;
+mod xlsx;
 
 use commands::read_settings_from_disk;
 use database::Database;
@@ -17,8 +23,22 @@ fn main() {
     // Inizializza il database
     let db = Database::new(db_path).expect("Failed to initialize database");
 
+    // Importa nel calendario le date ambulatorio salvate nelle impostazioni
+    if let Ok(settings) = read_settings_from_disk() {
+        if let Some(dates) = settings.ambulatorio_open_dates {
+            let _ = db.sync_ambulatorio_sessions(&dates, false);
+        }
+    }
+
     tauri::Builder::default()
         .manage(db)
+        .setup(|app| {
+            // Avvisi di scadenza priorità all'avvio e periodici
+            commands::start_priority_alert_monitor(app.handle());
+            // Acquisizione dei messaggi HL7 dalla cartella di ingresso
+            commands::start_hl7_folder_watcher(app.handle());
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             commands::get_all_procedures,
             commands::get_procedure_by_id,
@@ -26,8 +46,64 @@ fn main() {
             commands::update_procedure,
             commands::delete_procedure,
             commands::calculate_statistics,
+            commands::get_procedure_aggregation,
             commands::get_procedure_count,
+            commands::get_procedure_outcome,
+            commands::save_procedure_outcome,
+            commands::delete_procedure_outcome,
             commands::get_all_patients,
+            commands::search_patients,
+            commands::search_clinical_notes,
+            commands::get_saved_searches,
+            commands::save_saved_search,
+            commands::delete_saved_search,
+            commands::run_saved_search,
+            commands::get_smart_list_counts,
+            commands::get_waiting_list_analytics,
+            commands::get_priority_alerts,
+            commands::acknowledge_priority_alert,
+            commands::snooze_priority_alert,
+            commands::get_ambulatorio_slot_templates,
+            commands::save_ambulatorio_slot_template,
+            commands::delete_ambulatorio_slot_template,
+            commands::get_ambulatorio_sessions,
+            commands::open_ambulatorio_session,
+            commands::close_ambulatorio_session,
+            commands::get_ambulatorio_blocked_slots,
+            commands::block_ambulatorio_slot,
+            commands::unblock_ambulatorio_slot,
+            commands::get_ambulatorio_free_slots,
+            commands::book_ambulatorio_slot,
+            commands::move_ambulatorio_booking,
+            commands::cancel_ambulatorio_booking,
+            commands::get_patient_appointments,
+            commands::get_appointments,
+            commands::save_appointment,
+            commands::set_appointment_status,
+            commands::delete_appointment,
+            commands::get_cathlab_sessions,
+            commands::save_cathlab_session,
+            commands::delete_cathlab_session,
+            commands::add_cathlab_case,
+            commands::move_cathlab_case,
+            commands::remove_cathlab_case,
+            commands::reorder_cathlab_cases,
+            commands::update_cathlab_case,
+            commands::get_cathlab_duration_estimates,
+            commands::suggest_cathlab_slots,
+            commands::export_schedule_ics,
+            commands::import_ambulatorio_open_dates_ics,
+            commands::generate_ambulatorio_worklist,
+            commands::generate_tavi_worklist,
+            commands::get_xlsx_export_columns,
+            commands::export_xlsx,
+            commands::preview_import_file,
+            commands::dry_run_import,
+            commands::commit_import,
+            commands::export_fhir_bundle,
+            commands::process_hl7_folder,
+            commands::get_hl7_ingestion_log,
+            commands::export_research_dataset,
             commands::get_patient_by_id,
             commands::create_patient,
             commands::update_patient,
@@ -35,6 +111,46 @@ fn main() {
             commands::change_patient_status,
             commands::get_patient_status_counts,
             commands::get_patients_by_status,
+            commands::get_follow_ups,
+            commands::update_follow_up,
+            commands::get_overdue_follow_ups,
+            commands::get_survival_data,
+            commands::export_survival_data,
+            commands::get_medications,
+            commands::save_medication,
+            commands::delete_medication,
+            commands::get_periprocedural_instructions,
+            commands::get_risk_factor_vocabulary,
+            commands::get_patient_risk_factors,
+            commands::save_patient_risk_factors,
+            commands::get_risk_factor_statistics,
+            commands::get_ecg_recordings,
+            commands::save_ecg_recording,
+            commands::delete_ecg_recording,
+            commands::get_pacemaker_risk,
+            commands::get_procedure_plans,
+            commands::get_procedure_plan,
+            commands::save_procedure_plan,
+            commands::delete_procedure_plan,
+            commands::compare_plan_with_procedure,
+            commands::get_ct_plannings,
+            commands::get_ct_planning_by_id,
+            commands::create_ct_planning,
+            commands::update_ct_planning,
+            commands::delete_ct_planning,
+            commands::get_valve_sizing_charts,
+            commands::save_valve_sizing_chart,
+            commands::delete_valve_sizing_chart,
+            commands::suggest_valve_sizes,
+            commands::get_valve_models,
+            commands::create_valve_model,
+            commands::update_valve_model,
+            commands::delete_valve_model,
+            commands::validate_procedure,
+            commands::get_prosthesis_models,
+            commands::save_prosthesis_model,
+            commands::delete_prosthesis_model,
+            commands::check_viv_compatibility,
             commands::generate_ambulatorio_referto,
             commands::generate_scheda_procedurale_referto,
             commands::generate_consenso_informato,
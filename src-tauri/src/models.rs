@@ -24,6 +24,7 @@ pub struct Procedure {
     pub valvola_protesica: bool,
     pub protesica_modello: Option<String>,
     pub protesica_dimensione: Option<String>,
+    pub protesica_catalogo_id: Option<i64>,  // protesi precedente nel catalogo (valve-in-valve)
 
     // DATI PROCEDURALI
     pub data_procedura: String,  // Format: YYYY-MM-DD
@@ -34,6 +35,9 @@ pub struct Procedure {
     pub dimensione_valvola: Option<f64>,  // mm
     pub pre_dilatazione: bool,
     pub post_dilatazione: bool,
+    pub accesso_principale: Option<String>,  // stessi valori del piano: 'percutaneo_dx', ...
+    pub plan_id: Option<i64>,                // piano procedurale eseguito
+    pub operatore: Option<String>,           // primo operatore
 }
 
 impl Procedure {
@@ -53,6 +57,8 @@ pub struct ProcedureFilters {
     pub search_query: Option<String>,
     pub tipo_valvola: Option<String>,  // 'all', 'Balloon Expandable', 'Self Expandable'
     pub period: Option<String>,        // 'all', '1m', '3m', '6m', '1y'
+    pub date_from: Option<String>,     // Format: YYYY-MM-DD (inclusivo)
+    pub date_to: Option<String>,
 }
 
 impl Default for ProcedureFilters {
@@ -61,6 +67,8 @@ impl Default for ProcedureFilters {
             search_query: None,
             tipo_valvola: Some("all".to_string()),
             period: Some("all".to_string()),
+            date_from: None,
+            date_to: None,
         }
     }
 }
@@ -79,6 +87,11 @@ pub struct Statistics {
     pub balloon_expandable_count: i32,
     pub self_expandable_count: i32,
     pub top_valve_models: Vec<(String, i32)>,  // (model_name, count)
+    pub native_procedures: i32,
+    pub viv_procedures: i32,
+    pub average_duration_native_minutes: Option<f64>,
+    pub average_duration_viv_minutes: Option<f64>,
+    pub outcome_rates: OutcomeRates,
 }
 
 // ============================================================================
@@ -99,7 +112,8 @@ pub struct Patient {
     pub email: Option<String>,
     pub provenienza: Option<String>,
     pub sesso: Option<String>,
-    pub priority: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_priority")]
+    pub priority: Option<Priority>,
     pub altezza: Option<f64>,  // cm
     pub peso: Option<f64>,     // kg
     pub note: Option<String>,
@@ -150,6 +164,72 @@ pub struct Patient {
 impl Patient {
 }
 
+/// Priorità clinica per l'accesso alla TAVI (vedi PRIORITY_OPTIONS nel frontend).
+/// Un paziente senza priorità esplicita è trattato come "media" in filtri, ordinamenti,
+/// scadenze e statistiche; i valori non riconosciuti sono azzerati dalla migrazione.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Alta,
+    Media,
+    Bassa,
+}
+
+impl Priority {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Priority::Alta => "alta",
+            Priority::Media => "media",
+            Priority::Bassa => "bassa",
+        }
+    }
+
+    /// Accetta anche le etichette estese ("Alta (entro 1 mese)") e maiuscole
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim().to_lowercase();
+        if value.starts_with("alta") {
+            Some(Priority::Alta)
+        } else if value.starts_with("media") {
+            Some(Priority::Media)
+        } else if value.starts_with("bassa") {
+            Some(Priority::Bassa)
+        } else {
+            None
+        }
+    }
+
+    /// Giorni massimi tra ingresso in lista d'attesa e TAVI ("bassa" non ha un target)
+    pub fn target_days(&self) -> Option<i64> {
+        match self {
+            Priority::Alta => Some(30),
+            Priority::Media => Some(90),
+            Priority::Bassa => None,
+        }
+    }
+
+    /// Ordine di programmazione: prima la priorità più alta
+    pub fn rank(&self) -> u8 {
+        match self {
+            Priority::Alta => 0,
+            Priority::Media => 1,
+            Priority::Bassa => 2,
+        }
+    }
+}
+
+fn deserialize_priority<'de, D>(deserializer: D) -> Result<Option<Priority>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value: Option<String> = Option::deserialize(deserializer)?;
+    match value.as_deref().map(str::trim) {
+        None | Some("") => Ok(None),
+        Some(raw) => Priority::parse(raw)
+            .map(Some)
+            .ok_or_else(|| serde::de::Error::custom(format!("Priorità non valida: {}", raw))),
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum PatientStatus {
     DaValutare,
@@ -189,10 +269,68 @@ pub struct PatientWithStatus {
     pub status_created_at: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct PatientFilters {
     pub search_query: Option<String>,
     pub status: Option<String>,
+    pub priority: Option<String>,            // 'alta' | 'media' | 'bassa'
+    pub provenienza: Option<String>,         // ricerca parziale
+    pub sesso: Option<String>,               // 'M' | 'F'
+    pub age_min: Option<i32>,
+    pub age_max: Option<i32>,
+    pub data_tavi_from: Option<String>,      // Format: YYYY-MM-DD (inclusivo)
+    pub data_tavi_to: Option<String>,
+    pub visit_date_from: Option<String>,     // data visita ambulatoriale
+    pub visit_date_to: Option<String>,
+    pub medico: Option<String>,              // medico o specializzando assegnato
+    pub egfr_below: Option<f64>,             // eGFR strettamente inferiore alla soglia
+    pub valve_model: Option<String>,         // modello valvola pianificato (codice o nome)
+    pub text_query: Option<String>,          // ricerca libera su note e conclusioni
+    pub min_days_in_status: Option<i32>,     // da almeno N giorni nello stato corrente
+    pub max_days_in_status: Option<i32>,
+    pub sort_by: Option<String>,             // vedi PATIENT_SORT_FIELDS
+    pub sort_dir: Option<String>,            // 'asc' | 'desc'
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// Ordinamenti ammessi per la lista pazienti
+pub const PATIENT_SORT_FIELDS: [&str; 8] = [
+    "status_created_at",
+    "cognome",
+    "data_nascita",
+    "data_tavi",
+    "ambulatorio_data_visita",
+    "priority",
+    "created_at",
+    "updated_at",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedSearch {
+    pub id: Option<i64>,
+    pub nome: String,
+    pub descrizione: Option<String>,
+    pub filters: PatientFilters,
+    pub mostra_in_dashboard: bool,
+    pub ordine: i32,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SmartListCount {
+    pub id: i64,
+    pub nome: String,
+    pub count: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatientSearchResult {
+    pub patients: Vec<PatientWithStatus>,
+    pub total: i64,
+    pub limit: Option<i64>,
+    pub offset: i64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -200,3 +338,2218 @@ pub struct PatientStatusCount {
     pub status: String,
     pub count: i32,
 }
+
+// ============================================================================
+// CT-TAVI PLANNING MODELS
+// ============================================================================
+
+/// Altezza minima degli osti coronarici (mm) sotto la quale si segnala il rischio di occlusione.
+pub const CT_CORONARY_HEIGHT_MIN_MM: f64 = 10.0;
+/// Soglia di cautela per gli osti coronarici in presenza di seni di Valsalva piccoli (mm).
+pub const CT_CORONARY_HEIGHT_CAUTION_MM: f64 = 12.0;
+/// Diametro dei seni di Valsalva (mm) considerato piccolo.
+pub const CT_SINUS_SMALL_MM: f64 = 30.0;
+/// Diametro luminale minimo iliofemorale (mm) sotto il quale l'accesso femorale è a rischio.
+pub const CT_ILIOFEMORAL_MLD_MIN_MM: f64 = 5.5;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CtPlanning {
+    pub id: Option<i64>,
+    pub patient_id: i64,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+    pub data_tc: Option<String>,  // Format: YYYY-MM-DD
+
+    // ANULUS
+    pub anulus_perimetro: Option<f64>,     // mm
+    pub anulus_area: Option<f64>,          // mm²
+    pub anulus_diametro_min: Option<f64>,  // mm
+    pub anulus_diametro_max: Option<f64>,  // mm
+
+    // RADICE AORTICA
+    pub altezza_coronaria_sx: Option<f64>,   // mm
+    pub altezza_coronaria_dx: Option<f64>,   // mm
+    pub seno_valsalva_diametro: Option<f64>, // mm
+    pub seno_valsalva_altezza: Option<f64>,  // mm
+    pub giunzione_st_diametro: Option<f64>,  // mm
+    pub giunzione_st_altezza: Option<f64>,   // mm
+    pub calcium_score: Option<f64>,          // Agatston AU
+
+    // ACCESSI ILIOFEMORALI (diametro luminale minimo)
+    pub iliaca_dx_mld: Option<f64>,    // mm
+    pub iliaca_sx_mld: Option<f64>,    // mm
+    pub femorale_dx_mld: Option<f64>,  // mm
+    pub femorale_sx_mld: Option<f64>,  // mm
+
+    pub note: Option<String>,
+
+    // DERIVATI (calcolati in lettura, ignorati in scrittura)
+    pub diametro_medio: Option<f64>,
+    pub diametro_da_perimetro: Option<f64>,
+    pub diametro_da_area: Option<f64>,
+    pub indice_eccentricita: Option<f64>,
+}
+
+impl CtPlanning {
+    /// Diametro medio dell'anulus (media di minimo e massimo)
+    pub fn calculate_mean_diameter(&self) -> Option<f64> {
+        match (self.anulus_diametro_min, self.anulus_diametro_max) {
+            (Some(min), Some(max)) => Some((min + max) / 2.0),
+            _ => None,
+        }
+    }
+
+    /// Diametro derivato dal perimetro (P / π)
+    pub fn calculate_perimeter_diameter(&self) -> Option<f64> {
+        self.anulus_perimetro
+            .filter(|p| *p > 0.0)
+            .map(|p| p / std::f64::consts::PI)
+    }
+
+    /// Diametro derivato dall'area (2 * √(A / π))
+    pub fn calculate_area_diameter(&self) -> Option<f64> {
+        self.anulus_area
+            .filter(|a| *a > 0.0)
+            .map(|a| 2.0 * (a / std::f64::consts::PI).sqrt())
+    }
+
+    /// Indice di eccentricità dell'anulus (1 - Dmin / Dmax)
+    pub fn calculate_eccentricity(&self) -> Option<f64> {
+        match (self.anulus_diametro_min, self.anulus_diametro_max) {
+            (Some(min), Some(max)) if max > 0.0 => Some(1.0 - min / max),
+            _ => None,
+        }
+    }
+
+    /// Popola i campi derivati a partire dalle misure inserite
+    pub fn with_derived(mut self) -> Self {
+        self.diametro_medio = self.calculate_mean_diameter();
+        self.diametro_da_perimetro = self.calculate_perimeter_diameter();
+        self.diametro_da_area = self.calculate_area_diameter();
+        self.indice_eccentricita = self.calculate_eccentricity();
+        self
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValveSizingChart {
+    pub id: Option<i64>,
+    pub modello_valvola: String,   // es. 'edwards_sapien_3'
+    pub dimensione: String,        // es. '23', 'S (23)'
+    pub diametro_nominale: f64,    // mm
+    pub perimetro_min: Option<f64>, // mm
+    pub perimetro_max: Option<f64>, // mm
+    pub area_min: Option<f64>,      // mm²
+    pub area_max: Option<f64>,      // mm²
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValveSizeSuggestion {
+    pub modello_valvola: String,
+    pub dimensione: String,
+    pub criterio: String,  // 'perimetro' or 'area'
+    pub oversizing_percentuale: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CtSizingResult {
+    pub ct_planning_id: i64,
+    pub suggestions: Vec<ValveSizeSuggestion>,
+    pub warnings: Vec<String>,
+    pub protezione_osti_consigliata: bool,
+}
+
+// ============================================================================
+// VALVE CATALOGUE MODELS
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValveModel {
+    pub id: Option<i64>,
+    pub codice: String,            // es. 'edwards_sapien_3'
+    pub produttore: String,
+    pub nome_commerciale: String,
+    pub tipo_valvola: String,      // 'Balloon Expandable' or 'Self Expandable'
+    pub dimensioni: Vec<String>,   // es. ['20', '23'] o ['S (23)', 'M (25)']
+    pub anulus_min: Option<f64>,   // mm
+    pub anulus_max: Option<f64>,   // mm
+    pub introduttore_min_fr: Option<f64>,  // French
+    pub alias: Vec<String>,        // varianti di scrittura accettate
+}
+
+impl ValveModel {
+    /// Nome completo da mostrare nei referti e nelle statistiche
+    pub fn display_name(&self) -> String {
+        format!("{} {}", self.produttore, self.nome_commerciale)
+    }
+
+    /// Verifica se una stringa libera identifica questo modello (codice, nome o alias)
+    pub fn matches(&self, name: &str) -> bool {
+        let key = normalize_valve_key(name);
+        if key.is_empty() {
+            return false;
+        }
+        std::iter::once(&self.codice)
+            .chain(std::iter::once(&self.nome_commerciale))
+            .chain(self.alias.iter())
+            .any(|candidate| normalize_valve_key(candidate) == key)
+            || normalize_valve_key(&self.display_name()) == key
+    }
+
+    /// Diametri nominali disponibili (mm), estratti dalle etichette delle misure
+    pub fn nominal_sizes(&self) -> Vec<f64> {
+        self.dimensioni
+            .iter()
+            .filter_map(|label| parse_valve_size(label))
+            .collect()
+    }
+}
+
+/// Normalizza il nome di un modello ignorando maiuscole, spazi e punteggiatura
+pub fn normalize_valve_key(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+/// Estrae il diametro nominale da un'etichetta di misura ('23', '21.5', 'S (23)')
+pub fn parse_valve_size(label: &str) -> Option<f64> {
+    let inner = match (label.find('('), label.find(')')) {
+        (Some(start), Some(end)) if end > start => &label[start + 1..end],
+        _ => label,
+    };
+    inner.trim().replace(',', ".").parse::<f64>().ok()
+}
+
+// ============================================================================
+// VALVE-IN-VALVE MODELS
+// ============================================================================
+
+/// Diametro interno reale (mm) al di sotto del quale il rischio di mismatch è elevato.
+pub const VIV_TID_HIGH_RISK_MM: f64 = 19.0;
+/// Diametro interno reale (mm) al di sotto del quale il rischio di mismatch è moderato.
+pub const VIV_TID_MODERATE_RISK_MM: f64 = 21.0;
+/// Superficie corporea (m²) oltre la quale un diametro interno piccolo aggrava il mismatch.
+pub const VIV_BSA_LARGE_M2: f64 = 1.9;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProsthesisModel {
+    pub id: Option<i64>,
+    pub codice: String,               // es. 'edwards_perimount'
+    pub produttore: String,
+    pub nome_commerciale: String,
+    pub tipologia: String,            // 'chirurgica' or 'transcatetere'
+    pub dimensione_etichetta: f64,    // mm (label size)
+    pub diametro_interno_reale: f64,  // mm (true internal diameter)
+}
+
+impl ProsthesisModel {
+    pub fn display_name(&self) -> String {
+        format!("{} {} {} mm", self.produttore, self.nome_commerciale, self.dimensione_etichetta)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VivSizeSuggestion {
+    pub codice_valvola: String,
+    pub nome_valvola: String,
+    pub tipo_valvola: String,
+    pub dimensione: String,
+    pub differenza_mm: f64,  // diametro nominale - diametro interno reale
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VivCompatibilityResult {
+    pub protesi: ProsthesisModel,
+    pub superficie_corporea: Option<f64>,  // m²
+    pub rischio_mismatch: String,          // 'basso', 'moderato', 'elevato'
+    pub suggestions: Vec<VivSizeSuggestion>,
+    pub warnings: Vec<String>,
+}
+
+/// Superficie corporea secondo Mosteller (m²)
+pub fn calculate_bsa(altezza_cm: Option<f64>, peso_kg: Option<f64>) -> Option<f64> {
+    match (altezza_cm, peso_kg) {
+        (Some(h), Some(w)) if h > 0.0 && w > 0.0 => Some((h * w / 3600.0).sqrt()),
+        _ => None,
+    }
+}
+
+// ============================================================================
+// VARC-3 OUTCOME MODELS
+// ============================================================================
+
+/// Gradiente medio massimo (mmHg) per la performance attesa della protesi (VARC-3).
+pub const VARC3_MAX_MEAN_GRADIENT: f64 = 20.0;
+/// Velocità di picco massima (m/s) per la performance attesa della protesi (VARC-3).
+pub const VARC3_MAX_PEAK_VELOCITY: f64 = 3.0;
+
+pub const VARC3_STROKE_VALUES: [&str; 4] = ["no", "tia", "non_disabilitante", "disabilitante"];
+pub const VARC3_VASCULAR_VALUES: [&str; 3] = ["no", "minore", "maggiore"];
+pub const VARC3_PVL_VALUES: [&str; 5] = ["nessuno", "traccia", "lieve", "moderato", "severo"];
+pub const VARC3_DEATH_CAUSES: [&str; 3] = ["cardiovascolare", "non_cardiovascolare", "indeterminata"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcedureOutcome {
+    pub id: Option<i64>,
+    pub procedure_id: i64,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+
+    pub decesso: Option<bool>,
+    pub decesso_data: Option<String>,          // Format: YYYY-MM-DD
+    pub decesso_causa: Option<String>,         // 'cardiovascolare', 'non_cardiovascolare', 'indeterminata'
+    pub ictus: Option<String>,                 // 'no', 'tia', 'non_disabilitante', 'disabilitante'
+    pub complicanza_vascolare: Option<String>, // 'no', 'minore', 'maggiore'
+    pub complicanza_strutturale: Option<bool>, // complicanza cardiaca strutturale maggiore
+    pub sanguinamento_tipo: Option<i32>,       // VARC-3: 0 nessuno, 1-4
+    pub aki_stadio: Option<i32>,               // VARC-3: 0 nessuno, 1-4
+    pub nuovo_pacemaker: Option<bool>,
+    pub leak_paravalvolare: Option<String>,    // 'nessuno', 'traccia', 'lieve', 'moderato', 'severo'
+    pub ostruzione_coronarica: Option<bool>,
+    pub conversione_chirurgica: Option<bool>,
+    pub reintervento_valvola: Option<bool>,    // chirurgia o intervento correlato al dispositivo
+    pub posizionamento_corretto: Option<bool>, // singola protesi in posizione corretta
+    pub gradiente_medio_post: Option<f64>,     // mmHg
+    pub vmax_post: Option<f64>,                // m/s
+    pub note: Option<String>,
+
+    // COMPOSITI VARC-3 (calcolati in lettura, ignorati in scrittura)
+    pub device_success: Option<bool>,
+    pub early_safety: Option<bool>,
+}
+
+impl ProcedureOutcome {
+    /// Controlla che i valori rispettino le categorie VARC-3
+    pub fn validate(&self) -> Result<(), String> {
+        fn check(field: &str, value: &Option<String>, allowed: &[&str]) -> Result<(), String> {
+            match value.as_deref() {
+                Some(v) if !allowed.contains(&v) => Err(format!("Valore non valido per {}: {}", field, v)),
+                _ => Ok(()),
+            }
+        }
+        check("ictus", &self.ictus, &VARC3_STROKE_VALUES)?;
+        check("complicanza_vascolare", &self.complicanza_vascolare, &VARC3_VASCULAR_VALUES)?;
+        check("leak_paravalvolare", &self.leak_paravalvolare, &VARC3_PVL_VALUES)?;
+        check("decesso_causa", &self.decesso_causa, &VARC3_DEATH_CAUSES)?;
+        if let Some(tipo) = self.sanguinamento_tipo {
+            if !(0..=4).contains(&tipo) {
+                return Err(format!("Tipo di sanguinamento VARC-3 non valido: {}", tipo));
+            }
+        }
+        if let Some(stadio) = self.aki_stadio {
+            if !(0..=4).contains(&stadio) {
+                return Err(format!("Stadio AKI VARC-3 non valido: {}", stadio));
+            }
+        }
+        Ok(())
+    }
+
+    fn has_significant_pvl(&self) -> bool {
+        matches!(self.leak_paravalvolare.as_deref(), Some("moderato") | Some("severo"))
+    }
+
+    /// Device success VARC-3: successo tecnico, assenza di mortalità e reinterventi sul
+    /// dispositivo, performance attesa (gradiente < 20 mmHg, Vmax < 3 m/s, leak < moderato).
+    /// Restituisce None se mancano i dati ecocardiografici post-procedurali.
+    pub fn calculate_device_success(&self) -> Option<bool> {
+        if self.gradiente_medio_post.is_none() && self.vmax_post.is_none() {
+            return None;
+        }
+        let performance = self
+            .gradiente_medio_post
+            .map(|g| g < VARC3_MAX_MEAN_GRADIENT)
+            .unwrap_or(true)
+            && self.vmax_post.map(|v| v < VARC3_MAX_PEAK_VELOCITY).unwrap_or(true)
+            && !self.has_significant_pvl();
+
+        Some(
+            performance
+                && self.posizionamento_corretto.unwrap_or(true)
+                && !self.decesso.unwrap_or(false)
+                && !self.conversione_chirurgica.unwrap_or(false)
+                && !self.reintervento_valvola.unwrap_or(false)
+                && self.complicanza_vascolare.as_deref() != Some("maggiore")
+                && !self.complicanza_strutturale.unwrap_or(false),
+        )
+    }
+
+    /// Early safety VARC-3 a 30 giorni: assenza di mortalità, ictus, sanguinamento tipo 2-4,
+    /// complicanze vascolari/strutturali maggiori, AKI stadio 3-4, leak moderato-severo,
+    /// nuovo pacemaker e reinterventi sul dispositivo.
+    /// Un evento registrato basta per l'esito negativo; altrimenti restituisce None finché
+    /// non sono registrate tutte le componenti.
+    pub fn calculate_early_safety(&self) -> Option<bool> {
+        // Per ogni componente: None se non registrata, Some(true) se l'evento si è verificato
+        let events = [
+            self.decesso,
+            self.ictus.as_deref().map(|v| !matches!(v, "no" | "tia")),
+            self.sanguinamento_tipo.map(|v| v >= 2),
+            self.complicanza_vascolare.as_deref().map(|v| v == "maggiore"),
+            self.complicanza_strutturale,
+            self.aki_stadio.map(|v| v >= 3),
+            self.leak_paravalvolare.as_ref().map(|_| self.has_significant_pvl()),
+            self.nuovo_pacemaker,
+            self.conversione_chirurgica,
+            self.reintervento_valvola,
+        ];
+        if events.contains(&Some(true)) {
+            return Some(false);
+        }
+        if events.contains(&None) {
+            return None;
+        }
+        Some(true)
+    }
+
+    /// Popola i compositi VARC-3
+    pub fn with_derived(mut self) -> Self {
+        self.device_success = self.calculate_device_success();
+        self.early_safety = self.calculate_early_safety();
+        self
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OutcomeRates {
+    pub outcomes_recorded: i32,
+    pub mortality_percentage: Option<f64>,
+    pub stroke_percentage: Option<f64>,
+    pub major_vascular_percentage: Option<f64>,
+    pub major_bleeding_percentage: Option<f64>,  // VARC-3 tipo 2-4
+    pub aki_percentage: Option<f64>,             // qualsiasi stadio
+    pub new_pacemaker_percentage: Option<f64>,
+    pub moderate_severe_pvl_percentage: Option<f64>,
+    pub coronary_obstruction_percentage: Option<f64>,
+    pub conversion_to_surgery_percentage: Option<f64>,
+    pub device_success_percentage: Option<f64>,
+    pub early_safety_percentage: Option<f64>,
+}
+
+impl OutcomeRates {
+    /// Calcola le percentuali sugli esiti registrati
+    pub fn from_outcomes(outcomes: &[ProcedureOutcome]) -> Self {
+        let total = outcomes.len();
+        if total == 0 {
+            return OutcomeRates::default();
+        }
+        let rate = |predicate: &dyn Fn(&ProcedureOutcome) -> bool| -> Option<f64> {
+            let count = outcomes.iter().filter(|o| predicate(o)).count();
+            Some(count as f64 / total as f64 * 100.0)
+        };
+
+        // I compositi si calcolano solo sugli esiti valutabili
+        let evaluable_rate = |composite: &dyn Fn(&ProcedureOutcome) -> Option<bool>| -> Option<f64> {
+            let evaluable: Vec<bool> = outcomes.iter().filter_map(composite).collect();
+            if evaluable.is_empty() {
+                return None;
+            }
+            let successes = evaluable.iter().filter(|v| **v).count();
+            Some(successes as f64 / evaluable.len() as f64 * 100.0)
+        };
+
+        OutcomeRates {
+            outcomes_recorded: total as i32,
+            mortality_percentage: rate(&|o| o.decesso.unwrap_or(false)),
+            stroke_percentage: rate(&|o| {
+                matches!(o.ictus.as_deref(), Some("non_disabilitante") | Some("disabilitante"))
+            }),
+            major_vascular_percentage: rate(&|o| o.complicanza_vascolare.as_deref() == Some("maggiore")),
+            major_bleeding_percentage: rate(&|o| o.sanguinamento_tipo.unwrap_or(0) >= 2),
+            aki_percentage: rate(&|o| o.aki_stadio.unwrap_or(0) >= 1),
+            new_pacemaker_percentage: rate(&|o| o.nuovo_pacemaker.unwrap_or(false)),
+            moderate_severe_pvl_percentage: rate(&|o| o.has_significant_pvl()),
+            coronary_obstruction_percentage: rate(&|o| o.ostruzione_coronarica.unwrap_or(false)),
+            conversion_to_surgery_percentage: rate(&|o| o.conversione_chirurgica.unwrap_or(false)),
+            device_success_percentage: evaluable_rate(&|o| o.calculate_device_success()),
+            early_safety_percentage: evaluable_rate(&|o| o.calculate_early_safety()),
+        }
+    }
+}
+
+// ============================================================================
+// FOLLOW-UP MODELS
+// ============================================================================
+
+/// Numero di controlli annuali pianificati dopo la TAVI (oltre al controllo a 30 giorni).
+pub const FOLLOW_UP_YEARS: i32 = 5;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FollowUp {
+    pub id: Option<i64>,
+    pub patient_id: i64,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+    pub tipo: String,                 // '30_giorni', '1_anno', 'annuale'
+    pub anno: i32,                    // 0 per il controllo a 30 giorni
+    pub data_prevista: String,        // Format: YYYY-MM-DD
+    pub data_eseguita: Option<String>,  // Format: YYYY-MM-DD
+    pub nyha_classe: Option<i32>,     // 1-4
+    pub gradiente_medio: Option<f64>, // mmHg
+    pub gradiente_massimo: Option<f64>, // mmHg
+    pub vmax: Option<f64>,            // m/s
+    pub fe: Option<f64>,              // %
+    pub leak_paravalvolare: Option<String>,  // 'nessuno', 'traccia', 'lieve', 'moderato', 'severo'
+    pub riospedalizzazione: Option<bool>,
+    pub riospedalizzazione_causa: Option<String>,
+    pub decesso: Option<bool>,
+    pub decesso_data: Option<String>,   // Format: YYYY-MM-DD
+    pub decesso_causa: Option<String>,  // 'cardiovascolare', 'non_cardiovascolare', 'indeterminata'
+    pub note: Option<String>,
+}
+
+impl FollowUp {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(nyha) = self.nyha_classe {
+            if !(1..=4).contains(&nyha) {
+                return Err(format!("Classe NYHA non valida: {}", nyha));
+            }
+        }
+        if let Some(pvl) = self.leak_paravalvolare.as_deref() {
+            if !VARC3_PVL_VALUES.contains(&pvl) {
+                return Err(format!("Valore non valido per leak_paravalvolare: {}", pvl));
+            }
+        }
+        if let Some(causa) = self.decesso_causa.as_deref() {
+            if !VARC3_DEATH_CAUSES.contains(&causa) {
+                return Err(format!("Valore non valido per decesso_causa: {}", causa));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OverdueFollowUp {
+    pub follow_up: FollowUp,
+    pub nome: String,
+    pub cognome: String,
+    pub telefono: Option<String>,
+    pub data_tavi: String,
+    pub giorni_ritardo: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SurvivalRecord {
+    pub patient_id: i64,
+    pub data_tavi: String,
+    pub data_ultimo_contatto: String,
+    pub giorni: i64,
+    pub decesso: bool,
+    pub decesso_cardiovascolare: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KaplanMeierPoint {
+    pub giorni: i64,
+    pub a_rischio: i32,
+    pub eventi: i32,
+    pub censurati: i32,
+    pub sopravvivenza: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SurvivalData {
+    pub records: Vec<SurvivalRecord>,
+    pub curva: Vec<KaplanMeierPoint>,
+}
+
+impl SurvivalData {
+    /// Stima di Kaplan-Meier della sopravvivenza globale
+    pub fn from_records(records: Vec<SurvivalRecord>) -> Self {
+        let mut times: Vec<i64> = records.iter().map(|r| r.giorni).collect();
+        times.sort_unstable();
+        times.dedup();
+
+        let mut survival = 1.0;
+        let mut curva = Vec::new();
+        for t in times {
+            let a_rischio = records.iter().filter(|r| r.giorni >= t).count() as i32;
+            let eventi = records.iter().filter(|r| r.giorni == t && r.decesso).count() as i32;
+            let censurati = records.iter().filter(|r| r.giorni == t && !r.decesso).count() as i32;
+            if a_rischio > 0 && eventi > 0 {
+                survival *= 1.0 - eventi as f64 / a_rischio as f64;
+            }
+            curva.push(KaplanMeierPoint {
+                giorni: t,
+                a_rischio,
+                eventi,
+                censurati,
+                sopravvivenza: survival,
+            });
+        }
+
+        SurvivalData { records, curva }
+    }
+}
+
+// ============================================================================
+// MEDICATION MODELS
+// ============================================================================
+
+/// Principi attivi e nomi commerciali riconosciuti come anticoagulanti.
+const ANTICOAGULANT_KEYWORDS: [&str; 18] = [
+    "warfarin", "coumadin", "acenocumarolo", "sintrom",
+    "apixaban", "eliquis", "rivaroxaban", "xarelto",
+    "dabigatran", "pradaxa", "edoxaban", "lixiana",
+    "enoxaparina", "clexane", "fondaparinux", "arixtra",
+    "eparina", "nadroparina",
+];
+
+/// Principi attivi e nomi commerciali riconosciuti come antiaggreganti.
+const ANTIPLATELET_KEYWORDS: [&str; 14] = [
+    "acido acetilsalicilico", "aspirin", "cardioaspirin", "asa",
+    "clopidogrel", "plavix", "prasugrel", "efient",
+    "ticagrelor", "brilique", "ticlopidina", "indobufene",
+    "ibustrin", "cangrelor",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Medication {
+    pub id: Option<i64>,
+    pub patient_id: i64,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+    pub farmaco: String,
+    pub dose: Option<String>,
+    pub frequenza: Option<String>,
+    pub via: Option<String>,            // es. 'orale', 'sottocutanea', 'endovenosa'
+    pub data_inizio: Option<String>,    // Format: YYYY-MM-DD
+    pub data_fine: Option<String>,      // Format: YYYY-MM-DD
+    pub indicazione: Option<String>,
+    pub anticoagulante: bool,           // calcolato al salvataggio
+    pub antiaggregante: bool,           // calcolato al salvataggio
+}
+
+impl Medication {
+    fn matches_keyword(&self, keywords: &[&str]) -> bool {
+        let name = self.farmaco.to_lowercase();
+        name.split(|c: char| !c.is_alphanumeric())
+            .any(|word| keywords.contains(&word))
+            || keywords
+                .iter()
+                .filter(|k| k.contains(' '))
+                .any(|k| name.contains(k))
+    }
+
+    pub fn is_anticoagulant(&self) -> bool {
+        self.matches_keyword(&ANTICOAGULANT_KEYWORDS)
+    }
+
+    pub fn is_antiplatelet(&self) -> bool {
+        self.matches_keyword(&ANTIPLATELET_KEYWORDS)
+    }
+
+    /// Aggiorna i flag anticoagulante/antiaggregante a partire dal nome del farmaco
+    pub fn classified(mut self) -> Self {
+        self.anticoagulante = self.is_anticoagulant();
+        self.antiaggregante = self.is_antiplatelet();
+        self
+    }
+
+    /// Terapia in corso alla data indicata (YYYY-MM-DD)
+    pub fn is_active_on(&self, date_iso: &str) -> bool {
+        match self.data_fine.as_deref().map(str::trim) {
+            Some(fine) if !fine.is_empty() => fine >= date_iso,
+            _ => true,
+        }
+    }
+}
+
+/// Indicazioni peri-procedurali sulla terapia antitrombotica derivate dalla lista farmaci.
+/// Il valore di eGFR (ml/min/1.73m²) modula la sospensione del dabigatran.
+pub fn periprocedural_instructions(medications: &[Medication], egfr: Option<f64>) -> Vec<String> {
+    let mut instructions = Vec::new();
+
+    for med in medications.iter().filter(|m| m.anticoagulante) {
+        let name = med.farmaco.to_lowercase();
+        let label = med.farmaco.trim();
+        if ["warfarin", "coumadin", "acenocumarolo", "sintrom"].iter().any(|k| name.contains(k)) {
+            instructions.push(format!(
+                "{}: sospendere 5 giorni prima della procedura con controllo INR il giorno precedente; valutare bridging con eparina a basso peso molecolare in base al rischio trombotico.",
+                label
+            ));
+        } else if ["dabigatran", "pradaxa"].iter().any(|k| name.contains(k)) {
+            let hours = match egfr {
+                Some(value) if value < 50.0 => "72-96",
+                _ => "48",
+            };
+            instructions.push(format!(
+                "{}: sospendere {} ore prima della procedura (in base alla funzione renale).",
+                label, hours
+            ));
+        } else if ["apixaban", "eliquis", "rivaroxaban", "xarelto", "edoxaban", "lixiana"]
+            .iter()
+            .any(|k| name.contains(k))
+        {
+            let hours = match egfr {
+                Some(value) if value < 30.0 => "72",
+                _ => "48",
+            };
+            instructions.push(format!(
+                "{}: sospendere {} ore prima della procedura.",
+                label, hours
+            ));
+        } else {
+            instructions.push(format!(
+                "{}: ultima somministrazione almeno 24 ore prima della procedura.",
+                label
+            ));
+        }
+    }
+
+    for med in medications.iter().filter(|m| m.antiaggregante) {
+        let name = med.farmaco.to_lowercase();
+        let label = med.farmaco.trim();
+        if ["prasugrel", "efient", "ticagrelor", "brilique"].iter().any(|k| name.contains(k)) {
+            instructions.push(format!(
+                "{}: valutare con il cardiologo interventista la prosecuzione o lo switch a clopidogrel.",
+                label
+            ));
+        } else {
+            instructions.push(format!("{}: proseguire senza sospensione.", label));
+        }
+    }
+
+    let has_anticoagulant = medications.iter().any(|m| m.anticoagulante);
+    let has_antiplatelet = medications.iter().any(|m| m.antiaggregante);
+    if has_anticoagulant && has_antiplatelet {
+        instructions.push(
+            "Associazione anticoagulante e antiaggregante in corso: rivalutare l'indicazione alla terapia combinata dopo la TAVI.".to_string(),
+        );
+    } else if !has_anticoagulant && !has_antiplatelet {
+        instructions.push(
+            "Nessuna terapia antitrombotica in corso: dopo la TAVI è indicata di norma la terapia antiaggregante singola.".to_string(),
+        );
+    }
+
+    instructions
+}
+
+// ============================================================================
+// CARDIOVASCULAR RISK FACTOR MODELS
+// ============================================================================
+
+/// Vocabolario controllato dei fattori di rischio CV: (codice stabile, etichetta referto).
+pub const RISK_FACTOR_VOCABULARY: [(&str, &str); 11] = [
+    ("ipertensione", "Ipertensione arteriosa"),
+    ("diabete", "Diabete mellito"),
+    ("dislipidemia", "Dislipidemia"),
+    ("fumo", "Fumo di sigaretta"),
+    ("obesita", "Obesità"),
+    ("familiarita", "Familiarità per cardiopatia"),
+    ("irc", "Insufficienza renale cronica"),
+    ("bpco", "BPCO"),
+    ("arteriopatia_periferica", "Arteriopatia periferica"),
+    ("vasculopatia_cerebrale", "Pregresso ictus/TIA"),
+    ("osas", "OSAS"),
+];
+
+/// Valori ammessi per lo stato di fumatore
+pub const SMOKING_STATUS_VALUES: [&str; 2] = ["attuale", "ex"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RiskFactorDefinition {
+    pub codice: String,
+    pub etichetta: String,
+}
+
+pub fn risk_factor_vocabulary() -> Vec<RiskFactorDefinition> {
+    RISK_FACTOR_VOCABULARY
+        .iter()
+        .map(|(codice, etichetta)| RiskFactorDefinition {
+            codice: codice.to_string(),
+            etichetta: etichetta.to_string(),
+        })
+        .collect()
+}
+
+pub fn risk_factor_label(codice: &str) -> Option<&'static str> {
+    RISK_FACTOR_VOCABULARY
+        .iter()
+        .find(|(c, _)| *c == codice)
+        .map(|(_, label)| *label)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatientRiskFactor {
+    pub id: Option<i64>,
+    pub patient_id: i64,
+    pub codice: String,
+    pub insulino_trattato: Option<bool>,  // solo per 'diabete'
+    pub stato_fumo: Option<String>,       // solo per 'fumo': 'attuale' | 'ex'
+    pub pack_years: Option<f64>,          // solo per 'fumo'
+    pub note: Option<String>,
+}
+
+impl PatientRiskFactor {
+    pub fn new(patient_id: i64, codice: &str) -> Self {
+        PatientRiskFactor {
+            id: None,
+            patient_id,
+            codice: codice.to_string(),
+            insulino_trattato: None,
+            stato_fumo: None,
+            pack_years: None,
+            note: None,
+        }
+    }
+
+    /// Mappa una stringa libera (dal vecchio campo `ambulatorio_fattori`) sul vocabolario
+    pub fn from_free_text(patient_id: i64, text: &str) -> Option<Self> {
+        let lower = text.trim().to_lowercase();
+        if lower.is_empty() {
+            return None;
+        }
+        let has = |keys: &[&str]| keys.iter().any(|k| lower.contains(k));
+        // Le sigle brevi vanno confrontate a parola intera (es. "tia" in "cardiopatia")
+        let words: Vec<&str> = lower.split(|c: char| !c.is_alphanumeric()).collect();
+        let has_word = |keys: &[&str]| keys.iter().any(|k| words.contains(k));
+
+        let codice = if has(&["ipertens", "iperteso"]) || has_word(&["hta", "ipa"]) {
+            "ipertensione"
+        } else if has(&["diabet"]) || has_word(&["dm", "dm2", "dmt2"]) {
+            "diabete"
+        } else if has(&["dislipid", "ipercolest", "iperlipid", "ipertrigl"]) {
+            "dislipidemia"
+        } else if has(&["fumo", "fumat", "tabag"]) {
+            "fumo"
+        } else if has(&["obes"]) {
+            "obesita"
+        } else if has(&["familiar", "eredo"]) {
+            "familiarita"
+        } else if has(&["renale", "nefropat"]) || has_word(&["irc", "ckd"]) {
+            "irc"
+        } else if has(&["broncopneumopatia"]) || has_word(&["bpco", "copd"]) {
+            "bpco"
+        } else if has(&["arteriopat", "vasculopatia periferica"]) || has_word(&["aocp", "pad"]) {
+            "arteriopatia_periferica"
+        } else if has(&["ictus", "stroke", "cerebrovascolare", "vasculopatia cerebrale"])
+            || has_word(&["tia"]) {
+            "vasculopatia_cerebrale"
+        } else if has(&["apnee"]) || has_word(&["osas"]) {
+            "osas"
+        } else {
+            return None;
+        };
+
+        let mut factor = PatientRiskFactor::new(patient_id, codice);
+        if codice == "diabete" && has(&["insulin"]) {
+            factor.insulino_trattato = Some(true);
+        }
+        if codice == "fumo" {
+            factor.stato_fumo = Some(if has(&["(ex)", "ex fumat", "ex-fumat", "pregresso"]) {
+                "ex".to_string()
+            } else {
+                "attuale".to_string()
+            });
+        }
+        Some(factor)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if risk_factor_label(&self.codice).is_none() {
+            return Err(format!("Fattore di rischio non riconosciuto: {}", self.codice));
+        }
+        if let Some(stato) = &self.stato_fumo {
+            if !SMOKING_STATUS_VALUES.contains(&stato.as_str()) {
+                return Err(format!("Stato fumo non valido: {}", stato));
+            }
+        }
+        if let Some(pack_years) = self.pack_years {
+            if !(0.0..=300.0).contains(&pack_years) {
+                return Err("Pack-years fuori range (0-300)".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Etichetta per referto, con i dettagli clinici del fattore
+    pub fn describe(&self) -> String {
+        let label = risk_factor_label(&self.codice).unwrap_or(&self.codice).to_string();
+        match self.codice.as_str() {
+            "diabete" if self.insulino_trattato == Some(true) => format!("{} insulino-trattato", label),
+            "fumo" => {
+                let mut details = Vec::new();
+                if let Some(stato) = &self.stato_fumo {
+                    details.push(stato.clone());
+                }
+                if let Some(pack_years) = self.pack_years {
+                    details.push(format!("{} pack-years", pack_years));
+                }
+                if details.is_empty() {
+                    label
+                } else {
+                    format!("{} ({})", label, details.join(", "))
+                }
+            }
+            _ => label,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RiskFactorPrevalence {
+    pub codice: String,
+    pub etichetta: String,
+    pub lista_attesa: i32,
+    pub lista_attesa_percentuale: f64,
+    pub trattati: i32,
+    pub trattati_percentuale: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RiskFactorStatistics {
+    pub totale_lista_attesa: i32,   // pazienti "In attesa di TAVI"
+    pub totale_trattati: i32,       // pazienti "TAVI eseguita"
+    pub fattori: Vec<RiskFactorPrevalence>,
+}
+
+// ============================================================================
+// ECG MODELS
+// ============================================================================
+
+/// Valori ammessi per il ritmo di base
+pub const ECG_RHYTHM_VALUES: [&str; 5] = ["sinusale", "fa", "flutter", "stimolato", "altro"];
+
+/// Soglie di durata (ms) per BAV I grado e QRS largo
+pub const ECG_PR_PROLONGED_MS: i32 = 200;
+pub const ECG_QRS_WIDE_MS: i32 = 120;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EcgRecording {
+    pub id: Option<i64>,
+    pub patient_id: i64,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+    pub data_registrazione: String,       // Format: YYYY-MM-DD
+    pub ritmo: Option<String>,            // vedi ECG_RHYTHM_VALUES
+    pub frequenza_cardiaca: Option<i32>,  // bpm
+    pub pr_ms: Option<i32>,
+    pub qrs_ms: Option<i32>,
+    pub bbs: bool,                        // blocco di branca sinistra
+    pub bbd: bool,                        // blocco di branca destra
+    pub eas: bool,                        // emiblocco anteriore sinistro
+    pub bav_grado: Option<i32>,           // 1, 2 o 3
+    pub note: Option<String>,
+}
+
+impl EcgRecording {
+    pub fn validate(&self) -> Result<(), String> {
+        if chrono::NaiveDate::parse_from_str(&self.data_registrazione, "%Y-%m-%d").is_err() {
+            return Err("Data ECG non valida (formato YYYY-MM-DD)".to_string());
+        }
+        if let Some(ritmo) = &self.ritmo {
+            if !ECG_RHYTHM_VALUES.contains(&ritmo.as_str()) {
+                return Err(format!("Ritmo non valido: {}", ritmo));
+            }
+        }
+        if let Some(fc) = self.frequenza_cardiaca {
+            if !(20..=250).contains(&fc) {
+                return Err("Frequenza cardiaca fuori range (20-250 bpm)".to_string());
+            }
+        }
+        if let Some(pr) = self.pr_ms {
+            if !(60..=600).contains(&pr) {
+                return Err("PR fuori range (60-600 ms)".to_string());
+            }
+        }
+        if let Some(qrs) = self.qrs_ms {
+            if !(40..=300).contains(&qrs) {
+                return Err("QRS fuori range (40-300 ms)".to_string());
+            }
+        }
+        if let Some(grado) = self.bav_grado {
+            if !(1..=3).contains(&grado) {
+                return Err("Grado BAV non valido (1-3)".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// BAV di I grado dichiarato o desunto dal PR
+    pub fn has_first_degree_av_block(&self) -> bool {
+        self.bav_grado == Some(1) || self.pr_ms.is_some_and(|pr| pr > ECG_PR_PROLONGED_MS)
+    }
+
+    /// Sintesi testuale per la scheda procedurale
+    pub fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(ritmo) = &self.ritmo {
+            parts.push(format!("ritmo {}", ritmo));
+        }
+        if let Some(fc) = self.frequenza_cardiaca {
+            parts.push(format!("FC {} bpm", fc));
+        }
+        if let Some(pr) = self.pr_ms {
+            parts.push(format!("PR {} ms", pr));
+        }
+        if let Some(qrs) = self.qrs_ms {
+            parts.push(format!("QRS {} ms", qrs));
+        }
+        if self.bbs {
+            parts.push("BBS".to_string());
+        }
+        if self.bbd {
+            parts.push("BBD".to_string());
+        }
+        if self.eas {
+            parts.push("EAS".to_string());
+        }
+        if let Some(grado) = self.bav_grado {
+            parts.push(format!("BAV {}° grado", grado));
+        }
+        parts.join(", ")
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PacemakerRisk {
+    pub livello: String,            // 'basso' | 'intermedio' | 'alto' | 'non_applicabile'
+    pub punteggio: i32,
+    pub fattori: Vec<String>,
+    pub ecg_data: Option<String>,   // data dell'ECG valutato
+}
+
+impl PacemakerRisk {
+    /// Stima il rischio di impianto di pacemaker post-TAVI dall'ECG più recente e dal
+    /// tipo di valvola pianificata. Il BBD preesistente è il predittore più forte,
+    /// con effetto amplificato dalle valvole autoespandibili.
+    pub fn assess(ecg: Option<&EcgRecording>, self_expandable: Option<bool>) -> Self {
+        let Some(ecg) = ecg else {
+            return PacemakerRisk {
+                livello: "non_applicabile".to_string(),
+                punteggio: 0,
+                fattori: vec!["Nessun ECG registrato".to_string()],
+                ecg_data: None,
+            };
+        };
+
+        if ecg.ritmo.as_deref() == Some("stimolato") {
+            return PacemakerRisk {
+                livello: "non_applicabile".to_string(),
+                punteggio: 0,
+                fattori: vec!["Ritmo stimolato: paziente già portatore di pacemaker".to_string()],
+                ecg_data: Some(ecg.data_registrazione.clone()),
+            };
+        }
+
+        let mut punteggio = 0;
+        let mut fattori = Vec::new();
+        let self_expandable = self_expandable == Some(true);
+
+        if ecg.bbd {
+            punteggio += 3;
+            fattori.push("Blocco di branca destra".to_string());
+            if self_expandable {
+                punteggio += 2;
+                fattori.push("BBD con valvola autoespandibile".to_string());
+            }
+        }
+        if ecg.bav_grado.is_some_and(|g| g >= 2) {
+            punteggio += 3;
+            fattori.push("BAV di grado avanzato".to_string());
+        } else if ecg.has_first_degree_av_block() {
+            punteggio += 1;
+            fattori.push("BAV I grado / PR prolungato".to_string());
+        }
+        if ecg.eas {
+            punteggio += 1;
+            fattori.push("Emiblocco anteriore sinistro".to_string());
+        }
+        if ecg.bbs {
+            punteggio += 1;
+            fattori.push("Blocco di branca sinistra".to_string());
+        } else if !ecg.bbd && ecg.qrs_ms.is_some_and(|qrs| qrs >= ECG_QRS_WIDE_MS) {
+            punteggio += 1;
+            fattori.push("QRS largo".to_string());
+        }
+        if ecg.frequenza_cardiaca.is_some_and(|fc| fc < 50) {
+            punteggio += 1;
+            fattori.push("Bradicardia".to_string());
+        }
+        if self_expandable {
+            punteggio += 1;
+            fattori.push("Valvola autoespandibile".to_string());
+        }
+
+        let livello = match punteggio {
+            p if p >= 4 => "alto",
+            p if p >= 2 => "intermedio",
+            _ => "basso",
+        };
+
+        PacemakerRisk {
+            livello: livello.to_string(),
+            punteggio,
+            fattori,
+            ecg_data: Some(ecg.data_registrazione.clone()),
+        }
+    }
+}
+
+// ============================================================================
+// PROCEDURE PLAN MODELS
+// ============================================================================
+
+/// Campi del piano procedurale, nello stesso ordine delle colonne `procedurale_*` del paziente
+pub const PROCEDURE_PLAN_FIELDS: [&str; 23] = [
+    "allergia_mdc",
+    "preparazione_mdc",
+    "creatinina",
+    "egfr",
+    "hb",
+    "altro",
+    "anestesia",
+    "coronarografia",
+    "coronarografia_note",
+    "pacemaker",
+    "pacemaker_note",
+    "accesso_principale_fem",
+    "accesso_principale_altro",
+    "accesso_protezione",
+    "accesso_protezione_note",
+    "altri_accessi",
+    "diametro_pallone_femorale",
+    "guida_safari",
+    "protezione_osti",
+    "valvuloplastica",
+    "valvuloplastica_note",
+    "bioprotesi_modello",
+    "bioprotesi_dimensione",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcedurePlan {
+    pub id: Option<i64>,
+    pub patient_id: i64,
+    pub versione: Option<i32>,          // assegnata al salvataggio
+    pub created_at: Option<String>,
+    pub motivo_revisione: Option<String>,
+    pub allergia_mdc: Option<String>,
+    pub preparazione_mdc: Option<String>,
+    pub creatinina: Option<String>,
+    pub egfr: Option<String>,
+    pub hb: Option<String>,
+    pub altro: Option<String>,
+    pub anestesia: Option<String>,
+    pub coronarografia: Option<String>,
+    pub coronarografia_note: Option<String>,
+    pub pacemaker: Option<String>,
+    pub pacemaker_note: Option<String>,
+    pub accesso_principale_fem: Option<String>,
+    pub accesso_principale_altro: Option<String>,
+    pub accesso_protezione: Option<String>,
+    pub accesso_protezione_note: Option<String>,
+    pub altri_accessi: Option<String>,
+    pub diametro_pallone_femorale: Option<String>,
+    pub guida_safari: Option<String>,
+    pub protezione_osti: Option<String>,
+    pub valvuloplastica: Option<String>,
+    pub valvuloplastica_note: Option<String>,
+    pub bioprotesi_modello: Option<String>,
+    pub bioprotesi_dimensione: Option<String>,
+}
+
+impl ProcedurePlan {
+    /// Piano ricavato dai campi procedurali registrati sul paziente
+    pub fn from_patient(patient: &Patient) -> Self {
+        ProcedurePlan {
+            id: None,
+            patient_id: patient.id.unwrap_or_default(),
+            versione: None,
+            created_at: None,
+            motivo_revisione: None,
+            allergia_mdc: patient.procedurale_allergia_mdc.clone(),
+            preparazione_mdc: patient.procedurale_preparazione_mdc.clone(),
+            creatinina: patient.procedurale_creatinina.clone(),
+            egfr: patient.procedurale_egfr.clone(),
+            hb: patient.procedurale_hb.clone(),
+            altro: patient.procedurale_altro.clone(),
+            anestesia: patient.procedurale_anestesia.clone(),
+            coronarografia: patient.procedurale_coronarografia.clone(),
+            coronarografia_note: patient.procedurale_coronarografia_note.clone(),
+            pacemaker: patient.procedurale_pacemaker.clone(),
+            pacemaker_note: patient.procedurale_pacemaker_note.clone(),
+            accesso_principale_fem: patient.procedurale_accesso_principale_fem.clone(),
+            accesso_principale_altro: patient.procedurale_accesso_principale_altro.clone(),
+            accesso_protezione: patient.procedurale_accesso_protezione.clone(),
+            accesso_protezione_note: patient.procedurale_accesso_protezione_note.clone(),
+            altri_accessi: patient.procedurale_altri_accessi.clone(),
+            diametro_pallone_femorale: patient.procedurale_diametro_pallone_femorale.clone(),
+            guida_safari: patient.procedurale_guida_safari.clone(),
+            protezione_osti: patient.procedurale_protezione_osti.clone(),
+            valvuloplastica: patient.procedurale_valvuloplastica.clone(),
+            valvuloplastica_note: patient.procedurale_valvuloplastica_note.clone(),
+            bioprotesi_modello: patient.procedurale_bioprotesi_modello.clone(),
+            bioprotesi_dimensione: patient.procedurale_bioprotesi_dimensione.clone(),
+        }
+    }
+
+    /// Valori dei campi nell'ordine di `PROCEDURE_PLAN_FIELDS`
+    pub fn field_values(&self) -> [&Option<String>; 23] {
+        [
+            &self.allergia_mdc,
+            &self.preparazione_mdc,
+            &self.creatinina,
+            &self.egfr,
+            &self.hb,
+            &self.altro,
+            &self.anestesia,
+            &self.coronarografia,
+            &self.coronarografia_note,
+            &self.pacemaker,
+            &self.pacemaker_note,
+            &self.accesso_principale_fem,
+            &self.accesso_principale_altro,
+            &self.accesso_protezione,
+            &self.accesso_protezione_note,
+            &self.altri_accessi,
+            &self.diametro_pallone_femorale,
+            &self.guida_safari,
+            &self.protezione_osti,
+            &self.valvuloplastica,
+            &self.valvuloplastica_note,
+            &self.bioprotesi_modello,
+            &self.bioprotesi_dimensione,
+        ]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.field_values()
+            .iter()
+            .all(|v| v.as_deref().map(str::trim).unwrap_or_default().is_empty())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanComparisonItem {
+    pub campo: String,
+    pub pianificato: Option<String>,
+    pub eseguito: Option<String>,
+    pub concordante: Option<bool>,   // None se il dato non era pianificato
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanComparison {
+    pub procedure_id: i64,
+    pub plan_id: i64,
+    pub versione: i32,
+    pub voci: Vec<PlanComparisonItem>,
+}
+
+// ============================================================================
+// CLINICAL NOTES SEARCH MODELS
+// ============================================================================
+
+/// Colonne dell'indice full-text delle note cliniche: (colonna, etichetta)
+pub const CLINICAL_NOTE_FIELDS: [(&str, &str); 6] = [
+    ("note", "Note"),
+    ("anamnesi_cardiologica", "Anamnesi patologica remota"),
+    ("apr", "Terapia domiciliare"),
+    ("visita_odierna", "Valutazione odierna"),
+    ("conclusioni", "Conclusioni"),
+    ("note_procedurali", "Note procedurali"),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClinicalNoteSnippet {
+    pub campo: String,
+    pub etichetta: String,
+    pub snippet: String,   // HTML già escapato, termini trovati racchiusi in <mark>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClinicalNoteHit {
+    pub patient_id: i64,
+    pub nome: String,
+    pub cognome: String,
+    pub score: f64,        // bm25: valori più bassi indicano maggiore pertinenza
+    pub snippets: Vec<ClinicalNoteSnippet>,
+}
+
+/// Converte il testo libero dell'utente in una query FTS5 sicura: le frasi tra
+/// virgolette restano frasi, le altre parole diventano prefissi in AND.
+pub fn build_fts_query(input: &str) -> Option<String> {
+    let mut terms = Vec::new();
+    for (i, chunk) in input.split('"').enumerate() {
+        let words: Vec<&str> = chunk
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .collect();
+        if words.is_empty() {
+            continue;
+        }
+        if i % 2 == 1 {
+            terms.push(format!("\"{}\"", words.join(" ")));
+        } else {
+            terms.extend(words.iter().map(|w| format!("\"{}\"*", w)));
+        }
+    }
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
+// ============================================================================
+// WAITING LIST ANALYTICS MODELS
+// ============================================================================
+
+/// Intervalli del percorso analizzati: (codice, etichetta)
+pub const WAITING_INTERVALS: [(&str, &str); 3] = [
+    ("invio_visita", "Invio → visita ambulatoriale"),
+    ("visita_decisione", "Visita → decisione Heart Team"),
+    ("decisione_tavi", "Decisione → TAVI"),
+];
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IntervalStatistics {
+    pub n: i32,
+    pub mediana: Option<f64>,
+    pub q1: Option<f64>,
+    pub q3: Option<f64>,
+    pub min: Option<i64>,
+    pub max: Option<i64>,
+}
+
+impl IntervalStatistics {
+    /// Mediana e quartili con interpolazione lineare (metodo R-7)
+    pub fn from_days(days: &[i64]) -> Self {
+        let mut sorted = days.to_vec();
+        sorted.sort_unstable();
+        let quantile = |q: f64| -> Option<f64> {
+            if sorted.is_empty() {
+                return None;
+            }
+            let pos = q * (sorted.len() - 1) as f64;
+            let lower = pos.floor() as usize;
+            let upper = pos.ceil() as usize;
+            let weight = pos - lower as f64;
+            Some(sorted[lower] as f64 + (sorted[upper] - sorted[lower]) as f64 * weight)
+        };
+
+        IntervalStatistics {
+            n: sorted.len() as i32,
+            mediana: quantile(0.5),
+            q1: quantile(0.25),
+            q3: quantile(0.75),
+            min: sorted.first().copied(),
+            max: sorted.last().copied(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IntervalGroup {
+    pub gruppo: String,
+    pub statistiche: IntervalStatistics,
+    pub target_giorni: Option<i64>,
+    pub entro_target: Option<i32>,
+    pub percentuale_entro_target: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WaitingIntervalAnalytics {
+    pub intervallo: String,
+    pub etichetta: String,
+    pub complessivo: IntervalStatistics,
+    pub per_priorita: Vec<IntervalGroup>,
+    pub per_provenienza: Vec<IntervalGroup>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OverdueWaitingPatient {
+    pub patient_id: i64,
+    pub nome: String,
+    pub cognome: String,
+    pub priority: Priority,
+    pub provenienza: Option<String>,
+    pub data_decisione: String,
+    pub giorni_attesa: i64,
+    pub target_giorni: i64,
+    pub giorni_oltre_target: i64,
+    pub data_tavi_programmata: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WaitingListAnalytics {
+    pub intervalli: Vec<WaitingIntervalAnalytics>,
+    pub pazienti_oltre_target: Vec<OverdueWaitingPatient>,
+}
+
+// ============================================================================
+// PRIORITY ALERT MODELS
+// ============================================================================
+
+/// Paziente in lista d'attesa TAVI con la scadenza calcolata dalla priorità
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriorityDueItem {
+    pub patient_id: i64,
+    pub nome: String,
+    pub cognome: String,
+    pub priority: Priority,
+    pub provenienza: Option<String>,
+    pub data_ingresso_lista: String,      // Format: YYYY-MM-DD
+    pub data_scadenza: Option<String>,    // None per priorità "bassa"
+    pub giorni_attesa: i64,
+    pub data_tavi: Option<String>,
+}
+
+impl PriorityDueItem {
+    /// Giorni oltre la scadenza alla data indicata (0 se non scaduto o senza target)
+    pub fn days_overdue(&self, today: chrono::NaiveDate) -> i64 {
+        self.data_scadenza
+            .as_deref()
+            .and_then(|d| chrono::NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+            .map(|due| (today - due).num_days().max(0))
+            .unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriorityAlert {
+    pub patient_id: i64,
+    pub nome: String,
+    pub cognome: String,
+    pub priority: Priority,
+    pub data_ingresso_lista: String,
+    pub data_scadenza: String,
+    pub giorni_ritardo: i64,
+    pub data_tavi: Option<String>,
+    pub acknowledged: bool,               // presa visione per questa scadenza
+    pub snoozed_until: Option<String>,    // Format: YYYY-MM-DD
+}
+
+impl PriorityAlert {
+    pub fn is_suppressed(&self, today: chrono::NaiveDate) -> bool {
+        let snoozed = self
+            .snoozed_until
+            .as_deref()
+            .and_then(|d| chrono::NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+            .is_some_and(|until| until >= today);
+        self.acknowledged || snoozed
+    }
+}
+
+// ============================================================================
+// PROCEDURE AGGREGATION MODELS
+// ============================================================================
+
+/// Raggruppamenti ammessi per le statistiche aggregate delle procedure
+pub const PROCEDURE_GROUP_BY: [&str; 8] = [
+    "totale",
+    "mese",
+    "trimestre",
+    "anno",
+    "tipo_valvola",
+    "modello",
+    "operatore",
+    "accesso",
+];
+
+/// Campi numerici riassunti per gruppo: (codice, etichetta)
+pub const PROCEDURE_METRICS: [(&str, &str); 8] = [
+    ("durata", "Durata (min)"),
+    ("fe", "FE (%)"),
+    ("vmax", "Vmax (m/s)"),
+    ("gmax", "Gradiente max (mmHg)"),
+    ("gmed", "Gradiente medio (mmHg)"),
+    ("ava", "AVA (cm²)"),
+    ("anulus_aortico", "Anulus aortico (mm)"),
+    ("dimensione_valvola", "Dimensione valvola (mm)"),
+];
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MetricSummary {
+    pub campo: String,
+    pub etichetta: String,
+    pub n: i32,
+    pub mancanti: i32,
+    pub media: Option<f64>,
+    pub mediana: Option<f64>,
+    pub q1: Option<f64>,
+    pub q3: Option<f64>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProcedureGroupStatistics {
+    pub gruppo: String,
+    pub totale: i32,
+    pub balloon_expandable: i32,
+    pub self_expandable: i32,
+    pub valve_in_valve: i32,
+    pub pre_dilatazione: i32,
+    pub post_dilatazione: i32,
+    pub metriche: Vec<MetricSummary>,
+    /// Solo per i raggruppamenti temporali: variazione % del numero di procedure sul periodo precedente
+    pub variazione_percentuale: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcedureAggregation {
+    pub group_by: String,
+    pub complessivo: ProcedureGroupStatistics,
+    pub gruppi: Vec<ProcedureGroupStatistics>,
+}
+
+// ============================================================================
+// AMBULATORIO CALENDAR MODELS
+// ============================================================================
+
+/// Tipi di slot ambulatoriali
+pub const AMBULATORIO_SLOT_TYPES: [&str; 2] = ["standard", "tc"];
+
+/// Modello iniziale per i giorni feriali (orario, tipo)
+pub const AMBULATORIO_DEFAULT_SLOTS: [(&str, &str); 5] = [
+    ("08:30", "standard"),
+    ("09:00", "standard"),
+    ("09:45", "standard"),
+    ("10:30", "standard"),
+    ("11:15", "standard"),
+];
+
+/// Durata di una visita quando lo slot del modello non la specifica (minuti)
+pub const AMBULATORIO_DEFAULT_VISIT_MINUTES: i32 = 30;
+
+/// Normalizza un orario "8.30" / "8:30" in "08:30"
+pub fn normalize_slot_time(value: &str) -> Option<String> {
+    let compact = value.trim().replace('.', ":");
+    NaiveTime::parse_from_str(&compact, "%H:%M")
+        .ok()
+        .map(|t| t.format("%H:%M").to_string())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AmbulatorioSlotTemplate {
+    pub id: Option<i64>,
+    pub giorno_settimana: u32,        // 1 = lunedì … 7 = domenica
+    pub orario: String,               // Format: HH:MM
+    pub tipo: String,                 // vedi AMBULATORIO_SLOT_TYPES
+    pub durata_minuti: Option<i32>,
+    pub attivo: bool,
+}
+
+impl AmbulatorioSlotTemplate {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=7).contains(&self.giorno_settimana) {
+            return Err("Giorno della settimana non valido (1 = lunedì … 7 = domenica)".to_string());
+        }
+        if normalize_slot_time(&self.orario).is_none() {
+            return Err(format!("Orario non valido: {}", self.orario));
+        }
+        if !AMBULATORIO_SLOT_TYPES.contains(&self.tipo.as_str()) {
+            return Err(format!("Tipo di slot non valido: {}", self.tipo));
+        }
+        if self.durata_minuti.is_some_and(|d| d <= 0) {
+            return Err("La durata dello slot deve essere positiva".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AmbulatorioSession {
+    pub id: Option<i64>,
+    pub data: String,                 // Format: YYYY-MM-DD
+    pub note: Option<String>,
+    pub prenotazioni: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AmbulatorioBlockedSlot {
+    pub id: Option<i64>,
+    pub data: String,
+    pub orario: String,
+    pub motivo: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AmbulatorioSlot {
+    pub data: String,
+    pub orario: String,
+    pub tipo: String,
+    pub durata_minuti: Option<i32>,
+}
+
+// ============================================================================
+// APPOINTMENT MODELS
+// ============================================================================
+
+/// Tipi di appuntamento ambulatoriale: (codice, etichetta)
+pub const APPOINTMENT_TYPES: [(&str, &str); 4] = [
+    ("prima_visita", "Prima visita"),
+    ("rivalutazione", "Rivalutazione"),
+    ("follow_up_post_tavi", "Follow-up post-TAVI"),
+    ("tc", "TC"),
+];
+
+/// Stati dell'appuntamento: (codice, etichetta)
+pub const APPOINTMENT_STATUSES: [(&str, &str); 5] = [
+    ("prenotato", "Prenotato"),
+    ("eseguito", "Eseguito"),
+    ("pregresso", "Pregresso (esito non registrato)"),
+    ("non_presentato", "Non presentato"),
+    ("annullato", "Annullato"),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Appointment {
+    pub id: Option<i64>,
+    pub patient_id: i64,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+    pub data: String,                     // Format: YYYY-MM-DD
+    pub orario: Option<String>,           // Format: HH:MM (assente solo per visite storiche)
+    pub tipo: String,                     // vedi APPOINTMENT_TYPES
+    pub stato: String,                    // vedi APPOINTMENT_STATUSES
+    pub medico_titolo: Option<String>,
+    pub medico_nome: Option<String>,
+    pub note: Option<String>,
+    pub referto_path: Option<String>,     // ultimo referto ambulatoriale generato
+}
+
+impl Appointment {
+    pub fn validate(&self) -> Result<(), String> {
+        if chrono::NaiveDate::parse_from_str(self.data.trim(), "%Y-%m-%d").is_err() {
+            return Err(format!("Data appuntamento non valida: {}", self.data));
+        }
+        if let Some(orario) = self.orario.as_deref().filter(|o| !o.trim().is_empty()) {
+            if normalize_slot_time(orario).is_none() {
+                return Err(format!("Orario non valido: {}", orario));
+            }
+        }
+        if !APPOINTMENT_TYPES.iter().any(|(code, _)| *code == self.tipo) {
+            return Err(format!("Tipo di appuntamento non valido: {}", self.tipo));
+        }
+        if !APPOINTMENT_STATUSES.iter().any(|(code, _)| *code == self.stato) {
+            return Err(format!("Stato dell'appuntamento non valido: {}", self.stato));
+        }
+        if self.stato == "prenotato" && self.orario.as_deref().map(str::trim).unwrap_or_default().is_empty() {
+            return Err("Un appuntamento prenotato richiede l'orario".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppointmentWithPatient {
+    pub appointment: Appointment,
+    pub nome: String,
+    pub cognome: String,
+}
+
+// ============================================================================
+// CATH-LAB PLANNER MODELS
+// ============================================================================
+
+/// Casi per seduta quando per la data non è stata aperta una seduta nel planner
+pub const CATHLAB_DEFAULT_CAPACITY: i32 = 3;
+/// Durata stimata di un caso quando mancano dati storici sufficienti (minuti)
+pub const CATHLAB_DEFAULT_CASE_MINUTES: i32 = 90;
+/// Numero minimo di procedure con durata registrata per usare la mediana storica
+pub const CATHLAB_MIN_DURATION_SAMPLES: usize = 5;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CathLabSession {
+    pub id: Option<i64>,
+    pub data: String,                 // Format: YYYY-MM-DD
+    pub capacita: i32,                // numero massimo di casi
+    pub ora_inizio: String,           // Format: HH:MM
+    pub anestesista: bool,            // anestesista disponibile per anestesia generale
+    pub chirurgo_vascolare: bool,     // chirurgo disponibile per accessi chirurgici
+    pub note: Option<String>,
+    #[serde(default)]
+    pub casi: Vec<CathLabCase>,
+    #[serde(default)]
+    pub minuti_pianificati: i32,
+}
+
+impl CathLabSession {
+    pub fn validate(&self) -> Result<(), String> {
+        if chrono::NaiveDate::parse_from_str(self.data.trim(), "%Y-%m-%d").is_err() {
+            return Err(format!("Data della seduta non valida: {}", self.data));
+        }
+        if self.capacita < 1 {
+            return Err("La capacità della seduta deve essere almeno 1".to_string());
+        }
+        if normalize_slot_time(&self.ora_inizio).is_none() {
+            return Err(format!("Ora di inizio non valida: {}", self.ora_inizio));
+        }
+        Ok(())
+    }
+
+    /// Risorse richieste dal caso ma non disponibili nella seduta
+    pub fn missing_resources(&self, anestesia_generale: bool, accesso_chirurgico: bool) -> Vec<String> {
+        let mut missing = Vec::new();
+        if anestesia_generale && !self.anestesista {
+            missing.push("Anestesia generale pianificata ma anestesista non disponibile".to_string());
+        }
+        if accesso_chirurgico && !self.chirurgo_vascolare {
+            missing.push("Accesso chirurgico pianificato ma chirurgo vascolare non disponibile".to_string());
+        }
+        missing
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CathLabCase {
+    pub id: Option<i64>,
+    pub session_id: i64,
+    pub patient_id: i64,
+    pub nome: String,
+    pub cognome: String,
+    pub priority: Option<Priority>,
+    pub ordine: i32,
+    pub tipo_valvola: Option<String>,         // dal modello pianificato nel catalogo
+    pub durata_manuale_minuti: Option<i32>,   // sovrascrive la stima storica
+    pub durata_stimata_minuti: i32,
+    pub orario_stimato: String,               // Format: HH:MM
+    pub anestesia_generale: bool,
+    pub accesso_chirurgico: bool,
+    pub avvisi: Vec<String>,
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CathLabDurationEstimate {
+    pub tipo_valvola: String,                 // 'Tutte' per la stima complessiva
+    pub procedure_count: i32,
+    pub media_minuti: Option<f64>,
+    pub mediana_minuti: Option<f64>,
+    pub stima_minuti: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CathLabSlotSuggestion {
+    pub patient_id: i64,
+    pub nome: String,
+    pub cognome: String,
+    pub priority: Priority,
+    pub data_scadenza: Option<String>,
+    pub session_id: Option<i64>,
+    pub data: Option<String>,
+    pub ordine: Option<i32>,
+    pub orario_stimato: Option<String>,
+    pub durata_stimata_minuti: i32,
+    pub entro_scadenza: bool,
+    pub motivo: Option<String>,               // perché il paziente non è stato collocato
+}
+
+// ============================================================================
+// WORKLIST MODELS
+// ============================================================================
+
+/// Accesso principale pianificato (`procedurale_accesso_principale_fem`): (codice, etichetta)
+pub const MAIN_ACCESS_OPTIONS: [(&str, &str); 5] = [
+    ("percutaneo_dx", "Percutaneo femorale destro"),
+    ("percutaneo_sn", "Percutaneo femorale sinistro"),
+    ("chirurgico_dx", "Chirurgico femorale destro"),
+    ("chirurgico_sn", "Chirurgico femorale sinistro"),
+    ("altro", "Altro"),
+];
+
+/// Età in anni compiuti alla data indicata
+pub fn age_at(data_nascita: &str, date: chrono::NaiveDate) -> Option<i32> {
+    use chrono::Datelike;
+    let birth = chrono::NaiveDate::parse_from_str(data_nascita.trim(), "%Y-%m-%d").ok()?;
+    let mut age = date.year() - birth.year();
+    if (date.month(), date.day()) < (birth.month(), birth.day()) {
+        age -= 1;
+    }
+    (age >= 0).then_some(age)
+}
+
+/// Riga della lista di lavoro giornaliera (ambulatorio o sala TAVI)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorklistEntry {
+    pub patient_id: i64,
+    pub orario: Option<String>,               // Format: HH:MM (stimato per la sala)
+    pub attivita: Option<String>,             // tipo di visita o posizione del caso in seduta
+    pub nome: String,
+    pub cognome: String,
+    pub data_nascita: String,
+    pub codice_fiscale: Option<String>,
+    pub eta: Option<i32>,
+    pub priority: Option<Priority>,
+    pub creatinina: Option<String>,
+    pub egfr: Option<String>,
+    pub hb: Option<String>,
+    pub allergia_mdc: Option<String>,
+    pub valvola_pianificata: Option<String>,
+    pub accesso: Option<String>,
+    pub avvisi: Vec<String>,
+    pub note: Option<String>,
+}
+
+impl WorklistEntry {
+    /// Dati anagrafici e procedurali del paziente; l'età è calcolata alla data della lista
+    pub fn from_patient(patient: &Patient, date: chrono::NaiveDate, catalogue: &[ValveModel]) -> Self {
+        let filled = |value: &Option<String>| value.clone().filter(|v| !v.trim().is_empty());
+
+        let modello = filled(&patient.procedurale_bioprotesi_modello).map(|code| {
+            catalogue
+                .iter()
+                .find(|m| m.matches(&code))
+                .map(|m| m.display_name())
+                .unwrap_or(code)
+        });
+        let valvola_pianificata = match (modello, filled(&patient.procedurale_bioprotesi_dimensione)) {
+            (Some(modello), Some(misura)) => Some(format!("{} {} mm", modello, misura)),
+            (Some(modello), None) => Some(modello),
+            (None, Some(misura)) => Some(format!("{} mm", misura)),
+            (None, None) => None,
+        };
+        let accesso = filled(&patient.procedurale_accesso_principale_fem).map(|code| {
+            if code == "altro" {
+                if let Some(altro) = filled(&patient.procedurale_accesso_principale_altro) {
+                    return altro;
+                }
+            }
+            MAIN_ACCESS_OPTIONS
+                .iter()
+                .find(|(c, _)| *c == code)
+                .map(|(_, label)| label.to_string())
+                .unwrap_or(code)
+        });
+
+        WorklistEntry {
+            patient_id: patient.id.unwrap_or_default(),
+            orario: None,
+            attivita: None,
+            nome: patient.nome.clone(),
+            cognome: patient.cognome.clone(),
+            data_nascita: patient.data_nascita.clone(),
+            codice_fiscale: filled(&patient.codice_fiscale),
+            eta: age_at(&patient.data_nascita, date),
+            priority: patient.priority,
+            creatinina: filled(&patient.procedurale_creatinina),
+            egfr: filled(&patient.procedurale_egfr),
+            hb: filled(&patient.procedurale_hb),
+            allergia_mdc: filled(&patient.procedurale_allergia_mdc),
+            valvola_pianificata,
+            accesso,
+            avvisi: Vec::new(),
+            note: None,
+        }
+    }
+}
+
+// ============================================================================
+// EXCEL EXPORT MODELS
+// ============================================================================
+
+/// Fogli esportabili: (codice, nome del foglio)
+pub const XLSX_EXPORT_SHEETS: [(&str, &str); 4] = [
+    ("pazienti", "Pazienti"),
+    ("procedure", "Procedure"),
+    ("storico_stati", "Storico stati"),
+    ("statistiche", "Statistiche"),
+];
+
+/// Colonne del foglio pazienti: (codice, intestazione), nell'ordine di default
+pub const PATIENT_EXPORT_COLUMNS: [(&str, &str); 30] = [
+    ("id", "ID"),
+    ("cognome", "Cognome"),
+    ("nome", "Nome"),
+    ("data_nascita", "Data di nascita"),
+    ("eta", "Età"),
+    ("sesso", "Sesso"),
+    ("luogo_nascita", "Luogo di nascita"),
+    ("codice_fiscale", "Codice fiscale"),
+    ("telefono", "Telefono"),
+    ("email", "Email"),
+    ("provenienza", "Provenienza"),
+    ("priority", "Priorità"),
+    ("stato", "Stato"),
+    ("stato_dal", "Nello stato dal"),
+    ("altezza", "Altezza (cm)"),
+    ("peso", "Peso (kg)"),
+    ("medico", "Medico"),
+    ("ambulatorio_data_visita", "Data visita"),
+    ("ambulatorio_orario_visita", "Orario visita"),
+    ("data_tavi", "Data TAVI"),
+    ("creatinina", "Creatinina"),
+    ("egfr", "eGFR"),
+    ("hb", "Hb"),
+    ("allergia_mdc", "Allergia MDC"),
+    ("anestesia", "Anestesia"),
+    ("accesso_principale", "Accesso principale"),
+    ("bioprotesi_modello", "Valvola pianificata"),
+    ("bioprotesi_dimensione", "Misura pianificata (mm)"),
+    ("note", "Note"),
+    ("conclusioni", "Conclusioni"),
+];
+
+/// Colonne del foglio procedure: (codice, intestazione), nell'ordine di default
+pub const PROCEDURE_EXPORT_COLUMNS: [(&str, &str); 29] = [
+    ("id", "ID"),
+    ("nome", "Nome"),
+    ("cognome", "Cognome"),
+    ("data_nascita", "Data di nascita"),
+    ("eta", "Età alla procedura"),
+    ("altezza", "Altezza (cm)"),
+    ("peso", "Peso (kg)"),
+    ("bmi", "BMI"),
+    ("fe", "FE (%)"),
+    ("vmax", "Vmax (m/s)"),
+    ("gmax", "Gmax (mmHg)"),
+    ("gmed", "Gmed (mmHg)"),
+    ("ava", "AVA (cm²)"),
+    ("anulus_aortico", "Anulus aortico (mm)"),
+    ("valvola_protesica", "Valvola protesica"),
+    ("protesica_modello", "Protesica modello"),
+    ("protesica_dimensione", "Protesica dimensione"),
+    ("data_procedura", "Data procedura"),
+    ("ora_inizio", "Ora inizio"),
+    ("ora_fine", "Ora fine"),
+    ("durata_minuti", "Durata (min)"),
+    ("tipo_valvola", "Tipo valvola"),
+    ("modello_valvola", "Modello valvola"),
+    ("dimensione_valvola", "Dimensione valvola (mm)"),
+    ("pre_dilatazione", "Pre-dilatazione"),
+    ("post_dilatazione", "Post-dilatazione"),
+    ("accesso_principale", "Accesso principale"),
+    ("operatore", "Operatore"),
+    ("plan_id", "Piano procedurale"),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportColumn {
+    pub codice: String,
+    pub etichetta: String,
+}
+
+pub fn export_columns(columns: &[(&str, &str)]) -> Vec<ExportColumn> {
+    columns
+        .iter()
+        .map(|(codice, etichetta)| ExportColumn {
+            codice: codice.to_string(),
+            etichetta: etichetta.to_string(),
+        })
+        .collect()
+}
+
+/// Colonne disponibili per foglio, da proporre nella selezione dell'utente
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct XlsxExportColumns {
+    pub pazienti: Vec<ExportColumn>,
+    pub procedure: Vec<ExportColumn>,
+}
+
+/// Opzioni dell'esportazione Excel. I filtri pazienti sono gli stessi della lista
+/// (`get_all_patients_with_status`), senza paginazione: si esporta l'intero risultato.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct XlsxExportOptions {
+    pub patient_filters: Option<PatientFilters>,
+    pub procedure_filters: Option<ProcedureFilters>,
+    pub sheets: Option<Vec<String>>,            // vedi XLSX_EXPORT_SHEETS; default tutti
+    pub patient_columns: Option<Vec<String>>,   // vedi PATIENT_EXPORT_COLUMNS; default tutte
+    pub procedure_columns: Option<Vec<String>>, // vedi PROCEDURE_EXPORT_COLUMNS; default tutte
+}
+
+impl XlsxExportOptions {
+    pub fn includes_sheet(&self, code: &str) -> bool {
+        self.sheets
+            .as_ref()
+            .map(|sheets| sheets.iter().any(|s| s == code))
+            .unwrap_or(true)
+    }
+}
+
+/// Colonne scelte nell'ordine indicato dall'utente; codici sconosciuti sono un errore
+pub fn select_export_columns(
+    available: &[(&'static str, &'static str)],
+    selected: Option<&[String]>,
+) -> Result<Vec<(&'static str, &'static str)>, String> {
+    let Some(selected) = selected.filter(|s| !s.is_empty()) else {
+        return Ok(available.to_vec());
+    };
+    selected
+        .iter()
+        .map(|code| {
+            available
+                .iter()
+                .find(|(c, _)| c == code)
+                .copied()
+                .ok_or_else(|| format!("Colonna di esportazione non valida: {}", code))
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusHistoryEntry {
+    pub patient_id: i64,
+    pub nome: String,
+    pub cognome: String,
+    pub stato: String,
+    pub changed_at: String,
+}
+
+// ============================================================================
+// IMPORT MODELS
+// ============================================================================
+
+/// Campo importabile: (codice, etichetta, tipo, obbligatorio, intestazioni alternative).
+/// Il codice coincide con il nome del campo in `Patient`/`Procedure`.
+pub type ImportFieldDef = (&'static str, &'static str, &'static str, bool, &'static [&'static str]);
+
+pub const PATIENT_IMPORT_FIELDS: [ImportFieldDef; 24] = [
+    ("cognome", "Cognome", "testo", true, &[]),
+    ("nome", "Nome", "testo", true, &[]),
+    ("data_nascita", "Data di nascita", "data", true, &["nato il", "data nascita", "dn"]),
+    ("sesso", "Sesso", "sesso", false, &["genere"]),
+    ("luogo_nascita", "Luogo di nascita", "testo", false, &["nato a", "luogo nascita"]),
+    ("codice_fiscale", "Codice fiscale", "testo", false, &["cf", "c.f."]),
+    ("telefono", "Telefono", "testo", false, &["cellulare", "tel"]),
+    ("email", "Email", "testo", false, &["e-mail", "mail"]),
+    ("provenienza", "Provenienza", "testo", false, &["ospedale", "centro inviante", "inviante"]),
+    ("priority", "Priorità", "priorita", false, &["priorita", "urgenza"]),
+    ("altezza", "Altezza (cm)", "decimale", false, &["altezza"]),
+    ("peso", "Peso (kg)", "decimale", false, &["peso"]),
+    ("note", "Note", "testo", false, &[]),
+    ("anamnesi_cardiologica", "Anamnesi patologica remota", "testo", false, &["anamnesi"]),
+    ("apr", "Terapia domiciliare", "testo", false, &["terapia"]),
+    ("medico_nome", "Medico", "testo", false, &["medico referente", "cardiologo"]),
+    ("ambulatorio_data_visita", "Data visita", "data", false, &["data visita ambulatoriale"]),
+    ("data_tavi", "Data TAVI", "data", false, &["data intervento", "data procedura"]),
+    ("procedurale_creatinina", "Creatinina", "decimale_testo", false, &["creatinina"]),
+    ("procedurale_egfr", "eGFR", "decimale_testo", false, &["egfr", "gfr"]),
+    ("procedurale_hb", "Hb", "decimale_testo", false, &["hb", "emoglobina"]),
+    ("procedurale_allergia_mdc", "Allergia MDC", "si_no_codice", false, &["allergia mdc", "allergia mezzo di contrasto"]),
+    ("procedurale_bioprotesi_modello", "Valvola pianificata", "testo", false, &["modello valvola"]),
+    ("procedurale_bioprotesi_dimensione", "Misura pianificata (mm)", "decimale_testo", false, &["misura valvola"]),
+];
+
+pub const PROCEDURE_IMPORT_FIELDS: [ImportFieldDef; 24] = [
+    ("cognome", "Cognome", "testo", true, &[]),
+    ("nome", "Nome", "testo", true, &[]),
+    ("data_nascita", "Data di nascita", "data", true, &["nato il", "data nascita"]),
+    ("altezza", "Altezza (cm)", "decimale", false, &["altezza"]),
+    ("peso", "Peso (kg)", "decimale", false, &["peso"]),
+    ("fe", "FE (%)", "decimale", false, &["fe", "frazione di eiezione"]),
+    ("vmax", "Vmax (m/s)", "decimale", false, &["vmax"]),
+    ("gmax", "Gmax (mmHg)", "decimale", false, &["gmax"]),
+    ("gmed", "Gmed (mmHg)", "decimale", false, &["gmed"]),
+    ("ava", "AVA (cm²)", "decimale", false, &["ava"]),
+    ("anulus_aortico", "Anulus aortico (mm)", "decimale", false, &["anulus"]),
+    ("valvola_protesica", "Valvola protesica", "si_no", false, &["valve-in-valve", "viv"]),
+    ("protesica_modello", "Protesica modello", "testo", false, &[]),
+    ("protesica_dimensione", "Protesica dimensione", "testo", false, &[]),
+    ("data_procedura", "Data procedura", "data", true, &["data tavi", "data intervento"]),
+    ("ora_inizio", "Ora inizio", "ora", true, &["inizio"]),
+    ("ora_fine", "Ora fine", "ora", true, &["fine"]),
+    ("tipo_valvola", "Tipo valvola", "tipo_valvola", true, &[]),
+    ("modello_valvola", "Modello valvola", "testo", true, &["valvola", "modello"]),
+    ("dimensione_valvola", "Dimensione valvola (mm)", "decimale", false, &["misura valvola", "dimensione"]),
+    ("pre_dilatazione", "Pre-dilatazione", "si_no", false, &["predilatazione"]),
+    ("post_dilatazione", "Post-dilatazione", "si_no", false, &["postdilatazione"]),
+    ("accesso_principale", "Accesso principale", "accesso", false, &["accesso"]),
+    ("operatore", "Operatore", "testo", false, &["primo operatore"]),
+];
+
+pub fn import_fields(target: &str) -> Result<&'static [ImportFieldDef], String> {
+    match target {
+        "patients" => Ok(&PATIENT_IMPORT_FIELDS),
+        "procedures" => Ok(&PROCEDURE_IMPORT_FIELDS),
+        other => Err(format!("Destinazione di importazione non valida: {}", other)),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportFieldInfo {
+    pub codice: String,
+    pub etichetta: String,
+    pub tipo: String,
+    pub obbligatorio: bool,
+}
+
+/// Anteprima del file: intestazioni, prime righe e abbinamento colonne proposto
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportPreview {
+    pub fogli: Vec<String>,
+    pub foglio: Option<String>,
+    pub intestazioni: Vec<String>,
+    pub righe_esempio: Vec<Vec<String>>,
+    pub totale_righe: usize,
+    pub campi: Vec<ImportFieldInfo>,
+    pub abbinamento_proposto: std::collections::HashMap<String, String>,  // campo -> intestazione
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportRequest {
+    pub path: String,
+    pub target: String,                                     // 'patients' | 'procedures'
+    pub foglio: Option<String>,                             // XLSX: default il primo foglio
+    pub abbinamento: std::collections::HashMap<String, String>,  // campo -> intestazione di colonna
+    pub aggiorna_esistenti: Option<bool>,                   // default true
+}
+
+/// Riga del file con i valori già convertiti (chiavi = campi di `Patient`/`Procedure`)
+#[derive(Debug, Clone)]
+pub struct ImportCandidate {
+    pub riga: usize,
+    pub valori: serde_json::Map<String, serde_json::Value>,
+    pub errori: Vec<String>,
+    pub avvisi: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportRowReport {
+    pub riga: usize,
+    pub azione: String,              // 'inserisci' | 'aggiorna' | 'scarta'
+    pub record_id: Option<i64>,      // record esistente aggiornato o nuovo id dopo il commit
+    pub descrizione: String,
+    pub errori: Vec<String>,
+    pub avvisi: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub target: String,
+    pub simulazione: bool,           // true: nessuna modifica salvata
+    pub totale: usize,
+    pub da_inserire: usize,
+    pub da_aggiornare: usize,
+    pub scartate: usize,
+    pub righe: Vec<ImportRowReport>,
+}
+
+// ============================================================================
+// HL7 INGESTION MODELS
+// ============================================================================
+
+pub const HL7_OUTCOME_CREATED: &str = "creato";
+pub const HL7_OUTCOME_UPDATED: &str = "aggiornato";
+pub const HL7_OUTCOME_IGNORED: &str = "ignorato";
+pub const HL7_OUTCOME_ERROR: &str = "errore";
+
+/// Esiti dell'elaborazione di un messaggio HL7: (codice, etichetta)
+pub const HL7_INGESTION_OUTCOMES: [(&str, &str); 4] = [
+    (HL7_OUTCOME_CREATED, "Paziente creato"),
+    (HL7_OUTCOME_UPDATED, "Paziente aggiornato"),
+    (HL7_OUTCOME_IGNORED, "Ignorato"),
+    (HL7_OUTCOME_ERROR, "Errore"),
+];
+
+/// Riga del registro di acquisizione: un messaggio elaborato dalla cartella di ingresso
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hl7IngestionLogEntry {
+    pub id: Option<i64>,
+    pub received_at: Option<String>,
+    pub file_name: String,
+    pub message_type: Option<String>,      // es. 'ADT^A04'
+    pub control_id: Option<String>,        // MSH-10
+    pub sending_facility: Option<String>,  // MSH-4
+    pub esito: String,                     // vedi HL7_INGESTION_OUTCOMES
+    pub patient_id: Option<i64>,
+    pub dettaglio: Option<String>,
+}
+
+/// Riepilogo di un passaggio sulla cartella di ingresso
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Hl7IngestionSummary {
+    pub file_elaborati: usize,
+    pub file_in_errore: usize,
+    pub creati: usize,
+    pub aggiornati: usize,
+    pub ignorati: usize,
+    pub errori: usize,
+}
+
+// ============================================================================
+// DATASET DI RICERCA ANONIMIZZATO
+// ============================================================================
+
+pub const RESEARCH_EXPORT_FORMATS: [&str; 2] = ["csv", "json"];
+
+/// Trasformazioni applicate alle colonne del dataset di ricerca: (codice, descrizione)
+pub const RESEARCH_TRANSFORMATIONS: [(&str, &str); 5] = [
+    ("nessuna", "Valore riportato senza modifiche"),
+    ("pseudonimo", "Identificativo stabile: HMAC-SHA256 della chiave interna con il segreto di sito, troncato a 64 bit"),
+    ("data_spostata", "Data spostata dell'offset del paziente; delle date-ora resta solo il giorno"),
+    ("eta", "Età in anni compiuti alla data della procedura, al posto della data di nascita"),
+    ("generalizzata", "Il testo libero dell'opzione \"altro\" è sostituito da \"Altro\""),
+];
+
+/// Colonne del dataset pazienti: (codice, descrizione, trasformazione)
+pub const RESEARCH_PATIENT_COLUMNS: [(&str, &str, &str); 20] = [
+    ("id_paziente", "Pseudonimo del paziente", "pseudonimo"),
+    ("sesso", "Sesso", "nessuna"),
+    ("eta_procedura", "Età alla prima procedura (o alla data TAVI)", "eta"),
+    ("provenienza", "Provenienza", "nessuna"),
+    ("priority", "Priorità", "nessuna"),
+    ("stato", "Stato", "nessuna"),
+    ("stato_dal", "Nello stato dal", "data_spostata"),
+    ("data_inserimento", "Data di inserimento", "data_spostata"),
+    ("altezza", "Altezza (cm)", "nessuna"),
+    ("peso", "Peso (kg)", "nessuna"),
+    ("ambulatorio_data_visita", "Data visita", "data_spostata"),
+    ("data_tavi", "Data TAVI", "data_spostata"),
+    ("creatinina", "Creatinina", "nessuna"),
+    ("egfr", "eGFR", "nessuna"),
+    ("hb", "Hb", "nessuna"),
+    ("allergia_mdc", "Allergia MDC", "nessuna"),
+    ("anestesia", "Anestesia", "nessuna"),
+    ("accesso_principale", "Accesso principale", "generalizzata"),
+    ("bioprotesi_modello", "Valvola pianificata", "nessuna"),
+    ("bioprotesi_dimensione", "Misura pianificata (mm)", "nessuna"),
+];
+
+/// Colonne del dataset procedure: (codice, descrizione, trasformazione)
+pub const RESEARCH_PROCEDURE_COLUMNS: [(&str, &str, &str); 23] = [
+    ("id_procedura", "Pseudonimo della procedura", "pseudonimo"),
+    ("id_paziente", "Pseudonimo del paziente", "pseudonimo"),
+    ("eta_procedura", "Età alla procedura", "eta"),
+    ("altezza", "Altezza (cm)", "nessuna"),
+    ("peso", "Peso (kg)", "nessuna"),
+    ("bmi", "BMI", "nessuna"),
+    ("fe", "FE (%)", "nessuna"),
+    ("vmax", "Vmax (m/s)", "nessuna"),
+    ("gmax", "Gmax (mmHg)", "nessuna"),
+    ("gmed", "Gmed (mmHg)", "nessuna"),
+    ("ava", "AVA (cm²)", "nessuna"),
+    ("anulus_aortico", "Anulus aortico (mm)", "nessuna"),
+    ("valvola_protesica", "Valvola protesica", "nessuna"),
+    ("protesica_modello", "Protesica modello", "nessuna"),
+    ("protesica_dimensione", "Protesica dimensione", "nessuna"),
+    ("data_procedura", "Data procedura", "data_spostata"),
+    ("durata_minuti", "Durata (min)", "nessuna"),
+    ("tipo_valvola", "Tipo valvola", "nessuna"),
+    ("modello_valvola", "Modello valvola", "nessuna"),
+    ("dimensione_valvola", "Dimensione valvola (mm)", "nessuna"),
+    ("pre_dilatazione", "Pre-dilatazione", "nessuna"),
+    ("post_dilatazione", "Post-dilatazione", "nessuna"),
+    ("accesso_principale", "Accesso principale", "nessuna"),
+];
+
+/// Dati esclusi dal dataset di ricerca: (campo, motivo)
+pub const RESEARCH_REMOVED_FIELDS: [(&str, &str); 13] = [
+    ("cognome, nome", "identificativo diretto"),
+    ("data_nascita", "sostituita dall'età alla procedura"),
+    ("luogo_nascita", "identificativo indiretto"),
+    ("codice_fiscale", "identificativo diretto"),
+    ("telefono, email", "identificativo diretto"),
+    ("id", "sostituito dal pseudonimo"),
+    ("note, conclusioni", "testo libero"),
+    ("anamnesi_cardiologica, apr, visita_odierna, ambulatorio_fattori", "testo libero"),
+    ("procedurale_*_note, procedurale_altro", "testo libero"),
+    ("medico, operatore", "identifica il personale"),
+    ("ora_inizio, ora_fine, orario visita", "non necessari, resta la durata"),
+    ("plan_id", "riferimento interno"),
+    ("storico stati, appuntamenti, follow-up, documenti", "non inclusi nel dataset"),
+];
+
+/// Esito dell'esportazione del dataset di ricerca
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResearchExportResult {
+    pub cartella: String,
+    pub file: Vec<String>,
+    pub pazienti: usize,
+    pub procedure: usize,
+}
@@ -0,0 +1,257 @@
+// Pseudonimizzazione per i dataset di ricerca: identificativi stabili (HMAC-SHA256 con un
+// segreto di sito conservato localmente) e spostamento delle date con un offset per paziente.
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use chrono::Duration;
+use hmac::{Hmac, Mac};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::xlsx::XlsxCell;
+
+/// Spostamento massimo delle date, in giorni, in entrambe le direzioni
+pub const DATE_SHIFT_MAX_DAYS: i64 = 180;
+const SITE_SECRET_BYTES: usize = 32;
+
+/// SHA-256 (FIPS 180-4)
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
+
+/// HMAC-SHA256 (RFC 2104)
+pub fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accetta chiavi di qualsiasi lunghezza");
+    mac.update(message);
+    mac.finalize().into_bytes().into()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(value: &str) -> Option<Vec<u8>> {
+    // Con lunghezza dispari l'ultima coppia manca e il risultato è None
+    let value = value.trim();
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(value.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Byte casuali dal generatore del sistema operativo
+fn random_bytes(len: usize) -> Result<Vec<u8>, String> {
+    let mut bytes = vec![0u8; len];
+    getrandom::getrandom(&mut bytes).map_err(|e| format!("Generatore casuale di sistema non disponibile: {}", e))?;
+    Ok(bytes)
+}
+
+/// Legge il segreto di sito (esadecimale); alla prima esportazione lo genera e lo salva.
+/// Perdere il file significa ottenere pseudonimi diversi nelle esportazioni successive.
+pub fn load_or_create_site_secret(path: &Path) -> Result<Vec<u8>, String> {
+    if path.exists() {
+        let mut content = String::new();
+        File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut content))
+            .map_err(|e| format!("Impossibile leggere il segreto di sito: {}", e))?;
+        return from_hex(&content)
+            .filter(|secret| secret.len() >= 16)
+            .ok_or_else(|| format!("Segreto di sito non valido: {}", path.display()));
+    }
+    let secret = random_bytes(SITE_SECRET_BYTES)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("Impossibile salvare il segreto di sito: {}", e))?;
+    }
+    File::create(path)
+        .and_then(|mut f| f.write_all(to_hex(&secret).as_bytes()))
+        .map_err(|e| format!("Impossibile salvare il segreto di sito: {}", e))?;
+    Ok(secret)
+}
+
+/// Pseudonimi e offset delle date derivati dal segreto di sito
+pub struct Pseudonymizer {
+    secret: Vec<u8>,
+}
+
+impl Pseudonymizer {
+    pub fn new(secret: Vec<u8>) -> Self {
+        Self { secret }
+    }
+
+    /// Impronta del segreto, per verificare che due esportazioni usino la stessa chiave
+    pub fn fingerprint(&self) -> String {
+        to_hex(&sha256(&self.secret)[..6])
+    }
+
+    /// Identificativo stabile: prefisso + primi 64 bit dell'HMAC della chiave
+    pub fn pseudonym(&self, prefix: &str, key: &str) -> String {
+        let digest = hmac_sha256(&self.secret, format!("id|{}", key).as_bytes());
+        format!("{}-{}", prefix, to_hex(&digest[..8]).to_uppercase())
+    }
+
+    /// Offset in giorni, mai nullo, tra -DATE_SHIFT_MAX_DAYS e +DATE_SHIFT_MAX_DAYS
+    pub fn date_offset_days(&self, key: &str) -> i64 {
+        let digest = hmac_sha256(&self.secret, format!("shift|{}", key).as_bytes());
+        let mut value = [0u8; 8];
+        value.copy_from_slice(&digest[..8]);
+        let magnitude = (u64::from_be_bytes(value) % DATE_SHIFT_MAX_DAYS as u64) as i64 + 1;
+        if digest[8] & 1 == 0 {
+            magnitude
+        } else {
+            -magnitude
+        }
+    }
+}
+
+/// Sposta una data dell'offset indicato; delle date-ora resta solo il giorno.
+/// I valori non riconosciuti come date vengono rimossi, non potendo essere spostati.
+pub fn shift_cell(cell: XlsxCell, days: i64) -> XlsxCell {
+    match cell {
+        XlsxCell::Date(date) => XlsxCell::Date(date + Duration::days(days)),
+        XlsxCell::DateTime(datetime) => XlsxCell::Date(datetime.date() + Duration::days(days)),
+        XlsxCell::Text(_) => XlsxCell::Empty,
+        other => other,
+    }
+}
+
+/// Valore CSV (separatore ';', decimali con la virgola come negli altri export)
+pub fn csv_value(cell: &XlsxCell) -> String {
+    let value = match cell {
+        XlsxCell::Empty => String::new(),
+        XlsxCell::Text(text) => text.clone(),
+        XlsxCell::Number(number) => number.to_string().replace('.', ","),
+        XlsxCell::Date(date) => date.format("%Y-%m-%d").to_string(),
+        XlsxCell::DateTime(datetime) => datetime.format("%Y-%m-%d %H:%M:%S").to_string(),
+        XlsxCell::Bool(flag) => (*flag as i32).to_string(),
+    };
+    if value.contains([';', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+pub fn json_value(cell: &XlsxCell) -> Value {
+    match cell {
+        XlsxCell::Empty => Value::Null,
+        XlsxCell::Text(text) => Value::from(text.as_str()),
+        XlsxCell::Number(number) if number.fract() == 0.0 && number.abs() < 1e15 => Value::from(*number as i64),
+        XlsxCell::Number(number) => Value::from(*number),
+        XlsxCell::Date(date) => Value::from(date.format("%Y-%m-%d").to_string()),
+        XlsxCell::DateTime(datetime) => Value::from(datetime.format("%Y-%m-%dT%H:%M:%S").to_string()),
+        XlsxCell::Bool(flag) => Value::from(*flag),
+    }
+}
+
+/// Tabella CSV con intestazione
+pub fn csv_table(headers: &[&str], rows: &[Vec<XlsxCell>]) -> String {
+    let mut csv = headers.join(";");
+    csv.push('\n');
+    for row in rows {
+        csv.push_str(&row.iter().map(csv_value).collect::<Vec<_>>().join(";"));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Righe come array di oggetti JSON
+pub fn json_table(headers: &[&str], rows: &[Vec<XlsxCell>]) -> Value {
+    Value::Array(
+        rows.iter()
+            .map(|row| {
+                Value::Object(
+                    headers
+                        .iter()
+                        .zip(row)
+                        .map(|(header, cell)| (header.to_string(), json_value(cell)))
+                        .collect(),
+                )
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn pseudonymizer() -> Pseudonymizer {
+        Pseudonymizer::new(b"segreto di sito per i test".to_vec())
+    }
+
+    #[test]
+    fn hex_round_trip_rejects_malformed_values() {
+        assert_eq!(from_hex(&to_hex(&[0x00, 0x7f, 0xff])), Some(vec![0x00, 0x7f, 0xff]));
+        assert_eq!(from_hex(" 0a0B\n"), Some(vec![0x0a, 0x0b]));
+        assert_eq!(from_hex("abc"), None);
+        assert_eq!(from_hex("zz"), None);
+    }
+
+    #[test]
+    fn pseudonyms_are_stable_and_depend_on_the_secret() {
+        let pseudonymizer = pseudonymizer();
+        let id = pseudonymizer.pseudonym("PZ", "42");
+        assert_eq!(id, pseudonymizer.pseudonym("PZ", "42"));
+        assert_ne!(id, pseudonymizer.pseudonym("PZ", "43"));
+        assert_ne!(id, Pseudonymizer::new(b"altro segreto".to_vec()).pseudonym("PZ", "42"));
+        assert!(id.starts_with("PZ-"));
+        assert_eq!(id.len(), 3 + 16);
+        assert_eq!(pseudonymizer.fingerprint().len(), 12);
+    }
+
+    #[test]
+    fn date_offsets_are_non_zero_and_bounded() {
+        let pseudonymizer = pseudonymizer();
+        for key in 0..500 {
+            let offset = pseudonymizer.date_offset_days(&key.to_string());
+            assert!(offset != 0 && offset.abs() <= DATE_SHIFT_MAX_DAYS, "{}", offset);
+            assert_eq!(offset, pseudonymizer.date_offset_days(&key.to_string()));
+        }
+    }
+
+    #[test]
+    fn shift_cell_moves_dates_and_drops_unparsed_text() {
+        let date = NaiveDate::from_ymd_opt(2030, 1, 10).unwrap();
+        assert_eq!(
+            shift_cell(XlsxCell::Date(date), -15),
+            XlsxCell::Date(NaiveDate::from_ymd_opt(2029, 12, 26).unwrap())
+        );
+        assert_eq!(
+            shift_cell(XlsxCell::DateTime(date.and_hms_opt(8, 30, 0).unwrap()), 1),
+            XlsxCell::Date(NaiveDate::from_ymd_opt(2030, 1, 11).unwrap())
+        );
+        assert_eq!(shift_cell(XlsxCell::Text("gennaio 2030".to_string()), 5), XlsxCell::Empty);
+        assert_eq!(shift_cell(XlsxCell::Number(3.0), 5), XlsxCell::Number(3.0));
+    }
+
+    #[test]
+    fn csv_and_json_tables_format_cells() {
+        let rows = vec![vec![
+            XlsxCell::Text("a;\"b\"".to_string()),
+            XlsxCell::Number(2.5),
+            XlsxCell::Bool(true),
+            XlsxCell::Empty,
+        ]];
+        assert_eq!(csv_table(&["t", "n", "b", "e"], &rows), "t;n;b;e\n\"a;\"\"b\"\"\";2,5;1;\n");
+        assert_eq!(
+            json_table(&["t", "n", "b", "e"], &rows),
+            serde_json::json!([{ "t": "a;\"b\"", "n": 2.5, "b": true, "e": null }])
+        );
+    }
+
+    #[test]
+    fn site_secret_is_created_once_and_reused() {
+        let path = std::env::temp_dir().join(format!("tavi-segreto-{}", std::process::id())).join("site.key");
+        let _ = std::fs::remove_file(&path);
+        let secret = load_or_create_site_secret(&path).unwrap();
+        assert_eq!(secret.len(), SITE_SECRET_BYTES);
+        assert_eq!(load_or_create_site_secret(&path).unwrap(), secret);
+
+        std::fs::write(&path, "0a0b").unwrap();
+        assert!(load_or_create_site_secret(&path).is_err());
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+}
@@ -0,0 +1,655 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use regex::Regex;
+use std::io::Write;
+use std::sync::OnceLock;
+use zip::write::FileOptions;
+
+/// Limite di Excel per il nome di un foglio
+const XLSX_SHEET_NAME_LIMIT: usize = 31;
+/// Limite di Excel per il testo di una cella
+const XLSX_CELL_TEXT_LIMIT: usize = 32767;
+
+// Indici degli stili definiti in `STYLES_XML`
+const STYLE_HEADER: u32 = 1;
+const STYLE_DATE: u32 = 2;
+const STYLE_DATETIME: u32 = 3;
+
+/// Valore tipizzato di una cella: numeri, date e booleani restano tali in Excel
+#[derive(Debug, Clone, PartialEq)]
+pub enum XlsxCell {
+    Empty,
+    Text(String),
+    Number(f64),
+    Date(NaiveDate),
+    DateTime(NaiveDateTime),
+    Bool(bool),
+}
+
+impl XlsxCell {
+    pub fn text(value: Option<&str>) -> Self {
+        match value.map(str::trim) {
+            Some(v) if !v.is_empty() => XlsxCell::Text(v.to_string()),
+            _ => XlsxCell::Empty,
+        }
+    }
+
+    pub fn number(value: Option<f64>) -> Self {
+        match value {
+            Some(v) if v.is_finite() => XlsxCell::Number(v),
+            _ => XlsxCell::Empty,
+        }
+    }
+
+    /// Data ISO (YYYY-MM-DD) o data-ora SQLite; i valori non riconosciuti restano testo
+    pub fn date(value: Option<&str>) -> Self {
+        let Some(value) = value.map(str::trim).filter(|v| !v.is_empty()) else {
+            return XlsxCell::Empty;
+        };
+        if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+            return XlsxCell::Date(date);
+        }
+        ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"]
+            .iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+            .map(XlsxCell::DateTime)
+            .unwrap_or_else(|| XlsxCell::Text(value.to_string()))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct XlsxSheet {
+    pub name: String,
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<XlsxCell>>,
+}
+
+/// Lettera di colonna Excel (0 → A, 26 → AA)
+fn column_name(mut index: usize) -> String {
+    let mut name = Vec::new();
+    loop {
+        name.push(b'A' + (index % 26) as u8);
+        if index < 26 {
+            break;
+        }
+        index = index / 26 - 1;
+    }
+    name.reverse();
+    String::from_utf8(name).unwrap_or_default()
+}
+
+/// Escape XML che scarta i caratteri di controllo non ammessi in SpreadsheetML
+fn escape_xml(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for ch in value.chars().take(XLSX_CELL_TEXT_LIMIT) {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\t' | '\n' | '\r' => out.push(ch),
+            c if (c as u32) < 0x20 => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Numero seriale Excel (giorni dal 30/12/1899, frazione per l'orario)
+fn serial_date(value: &NaiveDateTime) -> f64 {
+    let epoch = NaiveDate::from_ymd_opt(1899, 12, 30)
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .unwrap_or_default();
+    (*value - epoch).num_seconds() as f64 / 86_400.0
+}
+
+fn cell_xml(reference: &str, cell: &XlsxCell) -> Option<String> {
+    let xml = match cell {
+        XlsxCell::Empty => return None,
+        XlsxCell::Text(text) => format!(
+            r#"<c r="{}" t="inlineStr"><is><t xml:space="preserve">{}</t></is></c>"#,
+            reference,
+            escape_xml(text)
+        ),
+        XlsxCell::Number(value) => format!(r#"<c r="{}"><v>{}</v></c>"#, reference, value),
+        XlsxCell::Date(date) => format!(
+            r#"<c r="{}" s="{}"><v>{}</v></c>"#,
+            reference,
+            STYLE_DATE,
+            serial_date(&date.and_hms_opt(0, 0, 0).unwrap_or_default())
+        ),
+        XlsxCell::DateTime(value) => format!(
+            r#"<c r="{}" s="{}"><v>{}</v></c>"#,
+            reference,
+            STYLE_DATETIME,
+            serial_date(value)
+        ),
+        XlsxCell::Bool(value) => format!(r#"<c r="{}" t="b"><v>{}</v></c>"#, reference, u8::from(*value)),
+    };
+    Some(xml)
+}
+
+/// Larghezza indicativa della colonna in caratteri, dall'intestazione e dai testi presenti
+fn column_width(sheet: &XlsxSheet, index: usize) -> usize {
+    let header = sheet.headers.get(index).map(|h| h.chars().count()).unwrap_or(0);
+    let content = sheet
+        .rows
+        .iter()
+        .filter_map(|row| match row.get(index) {
+            Some(XlsxCell::Text(text)) => Some(text.chars().count()),
+            Some(XlsxCell::Date(_)) => Some(10),
+            Some(XlsxCell::DateTime(_)) => Some(16),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0);
+    (header.max(content) + 2).clamp(8, 60)
+}
+
+fn sheet_xml(sheet: &XlsxSheet) -> String {
+    let columns = sheet.headers.len().max(1);
+    let last_row = sheet.rows.len() + 1;
+
+    let mut xml = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships">"#,
+    );
+    // Intestazione bloccata durante lo scorrimento
+    xml.push_str(r#"<sheetViews><sheetView workbookViewId="0"><pane ySplit="1" topLeftCell="A2" activePane="bottomLeft" state="frozen"/></sheetView></sheetViews>"#);
+    xml.push_str("<cols>");
+    for index in 0..columns {
+        xml.push_str(&format!(
+            r#"<col min="{n}" max="{n}" width="{w}" customWidth="1"/>"#,
+            n = index + 1,
+            w = column_width(sheet, index)
+        ));
+    }
+    xml.push_str("</cols><sheetData>");
+
+    xml.push_str(r#"<row r="1">"#);
+    for (index, header) in sheet.headers.iter().enumerate() {
+        xml.push_str(&format!(
+            r#"<c r="{}1" t="inlineStr" s="{}"><is><t xml:space="preserve">{}</t></is></c>"#,
+            column_name(index),
+            STYLE_HEADER,
+            escape_xml(header)
+        ));
+    }
+    xml.push_str("</row>");
+
+    for (row_index, row) in sheet.rows.iter().enumerate() {
+        let row_number = row_index + 2;
+        xml.push_str(&format!(r#"<row r="{}">"#, row_number));
+        for (index, cell) in row.iter().enumerate() {
+            let reference = format!("{}{}", column_name(index), row_number);
+            if let Some(cell) = cell_xml(&reference, cell) {
+                xml.push_str(&cell);
+            }
+        }
+        xml.push_str("</row>");
+    }
+    xml.push_str("</sheetData>");
+    xml.push_str(&format!(r#"<autoFilter ref="A1:{}{}"/>"#, column_name(columns - 1), last_row));
+    xml.push_str("</worksheet>");
+    xml
+}
+
+/// Nomi dei fogli validi per Excel: senza caratteri riservati, entro 31 caratteri e univoci
+fn sheet_names(sheets: &[XlsxSheet]) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for (index, sheet) in sheets.iter().enumerate() {
+        let cleaned: String = sheet
+            .name
+            .chars()
+            .filter(|c| !['[', ']', ':', '*', '?', '/', '\\'].contains(c))
+            .collect();
+        let cleaned = cleaned.trim().trim_matches('\'').to_string();
+        let base: String = if cleaned.is_empty() {
+            format!("Foglio{}", index + 1)
+        } else {
+            cleaned.chars().take(XLSX_SHEET_NAME_LIMIT).collect()
+        };
+        let mut name = base.clone();
+        let mut counter = 2;
+        while names.iter().any(|n| n.eq_ignore_ascii_case(&name)) {
+            let suffix = format!(" ({})", counter);
+            let keep = XLSX_SHEET_NAME_LIMIT - suffix.chars().count();
+            name = format!("{}{}", base.chars().take(keep).collect::<String>(), suffix);
+            counter += 1;
+        }
+        names.push(name);
+    }
+    names
+}
+
+const STYLES_XML: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><styleSheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><numFmts count="2"><numFmt numFmtId="164" formatCode="dd/mm/yyyy"/><numFmt numFmtId="165" formatCode="dd/mm/yyyy hh:mm"/></numFmts><fonts count="2"><font><sz val="11"/><name val="Calibri"/><family val="2"/></font><font><b/><sz val="11"/><color rgb="FFFFFFFF"/><name val="Calibri"/><family val="2"/></font></fonts><fills count="3"><fill><patternFill patternType="none"/></fill><fill><patternFill patternType="gray125"/></fill><fill><patternFill patternType="solid"><fgColor rgb="FF2196F3"/><bgColor indexed="64"/></patternFill></fill></fills><borders count="1"><border><left/><right/><top/><bottom/><diagonal/></border></borders><cellStyleXfs count="1"><xf numFmtId="0" fontId="0" fillId="0" borderId="0"/></cellStyleXfs><cellXfs count="4"><xf numFmtId="0" fontId="0" fillId="0" borderId="0" xfId="0"/><xf numFmtId="0" fontId="1" fillId="2" borderId="0" xfId="0" applyFont="1" applyFill="1"/><xf numFmtId="164" fontId="0" fillId="0" borderId="0" xfId="0" applyNumberFormat="1"/><xf numFmtId="165" fontId="0" fillId="0" borderId="0" xfId="0" applyNumberFormat="1"/></cellXfs><cellStyles count="1"><cellStyle name="Normal" xfId="0" builtinId="0"/></cellStyles></styleSheet>"#;
+
+/// Cartella di lavoro XLSX (SpreadsheetML) con un foglio per ogni elemento di `sheets`
+pub fn write_workbook(sheets: &[XlsxSheet]) -> Result<Vec<u8>, String> {
+    if sheets.is_empty() {
+        return Err("Nessun foglio da esportare".to_string());
+    }
+    let names = sheet_names(sheets);
+
+    let mut content_types = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/><Default Extension="xml" ContentType="application/xml"/><Override PartName="/xl/workbook.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml"/><Override PartName="/xl/styles.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.styles+xml"/>"#,
+    );
+    let mut workbook = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><sheets>"#,
+    );
+    let mut workbook_rels = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">"#,
+    );
+    let mut defined_names = String::new();
+
+    for (index, (sheet, name)) in sheets.iter().zip(&names).enumerate() {
+        let number = index + 1;
+        content_types.push_str(&format!(
+            r#"<Override PartName="/xl/worksheets/sheet{}.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml"/>"#,
+            number
+        ));
+        workbook.push_str(&format!(
+            r#"<sheet name="{}" sheetId="{}" r:id="rId{}"/>"#,
+            escape_xml(name),
+            number,
+            number
+        ));
+        workbook_rels.push_str(&format!(
+            r#"<Relationship Id="rId{}" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet{}.xml"/>"#,
+            number, number
+        ));
+        defined_names.push_str(&format!(
+            r#"<definedName name="_xlnm._FilterDatabase" localSheetId="{}" hidden="1">'{}'!$A$1:${}${}</definedName>"#,
+            index,
+            escape_xml(&name.replace('\'', "''")),
+            column_name(sheet.headers.len().max(1) - 1),
+            sheet.rows.len() + 1
+        ));
+    }
+    content_types.push_str("</Types>");
+    workbook.push_str(&format!("</sheets><definedNames>{}</definedNames></workbook>", defined_names));
+    workbook_rels.push_str(&format!(
+        r#"<Relationship Id="rId{}" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles" Target="styles.xml"/></Relationships>"#,
+        sheets.len() + 1
+    ));
+
+    let mut parts: Vec<(String, String)> = vec![
+        ("[Content_Types].xml".to_string(), content_types),
+        (
+            "_rels/.rels".to_string(),
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="xl/workbook.xml"/></Relationships>"#.to_string(),
+        ),
+        ("xl/workbook.xml".to_string(), workbook),
+        ("xl/_rels/workbook.xml.rels".to_string(), workbook_rels),
+        ("xl/styles.xml".to_string(), STYLES_XML.to_string()),
+    ];
+    for (index, sheet) in sheets.iter().enumerate() {
+        parts.push((format!("xl/worksheets/sheet{}.xml", index + 1), sheet_xml(sheet)));
+    }
+
+    let mut output_bytes: Vec<u8> = Vec::new();
+    {
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(&mut output_bytes));
+        let options = FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        for (name, content) in parts {
+            writer
+                .start_file(name, options)
+                .map_err(|_| "Errore scrittura file Excel".to_string())?;
+            writer
+                .write_all(content.as_bytes())
+                .map_err(|_| "Errore scrittura file Excel".to_string())?;
+        }
+        writer.finish().map_err(|_| "Errore finale file Excel".to_string())?;
+    }
+    Ok(output_bytes)
+}
+
+// ============================================================================
+// LETTURA
+// ============================================================================
+
+/// Foglio letto da un file XLSX: righe con numero di riga (1 = prima riga) e celle tipizzate
+#[derive(Debug, Clone)]
+pub struct XlsxReadSheet {
+    pub name: String,
+    pub rows: Vec<(usize, Vec<XlsxCell>)>,
+}
+
+fn read_part(archive: &mut zip::ZipArchive<std::io::Cursor<&[u8]>>, name: &str) -> Option<String> {
+    use std::io::Read;
+    let mut file = archive.by_name(name).ok()?;
+    let mut content = String::new();
+    file.read_to_string(&mut content).ok()?;
+    Some(content)
+}
+
+/// Espressione regolare compilata una sola volta e riusata per tutti i fogli
+fn cached_regex(cell: &'static OnceLock<Regex>, pattern: &str) -> &'static Regex {
+    cell.get_or_init(|| Regex::new(pattern).unwrap())
+}
+
+fn decode_entities(value: &str) -> String {
+    static NUMERIC: OnceLock<Regex> = OnceLock::new();
+    let numeric = cached_regex(&NUMERIC, r"&#(x?)([0-9a-fA-F]+);");
+    let decoded = numeric.replace_all(value, |caps: &regex::Captures| {
+        let radix = if caps[1].is_empty() { 10 } else { 16 };
+        u32::from_str_radix(&caps[2], radix)
+            .ok()
+            .and_then(char::from_u32)
+            .map(String::from)
+            .unwrap_or_default()
+    });
+    decoded
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Testo di un elemento `<si>`/`<is>`: concatena i run ignorando le letture fonetiche
+fn rich_text(xml: &str) -> String {
+    static PHONETIC: OnceLock<Regex> = OnceLock::new();
+    static TEXT: OnceLock<Regex> = OnceLock::new();
+    let phonetic = cached_regex(&PHONETIC, r"(?s)<rPh\b.*?</rPh>");
+    let text = cached_regex(&TEXT, r"(?s)<t(?:\s[^>]*)?>(.*?)</t>");
+    let cleaned = phonetic.replace_all(xml, "");
+    text.captures_iter(&cleaned)
+        .map(|c| decode_entities(&c[1]))
+        .collect()
+}
+
+fn attribute(attrs: &str, name: &str) -> Option<String> {
+    static ATTRIBUTE: OnceLock<Regex> = OnceLock::new();
+    cached_regex(&ATTRIBUTE, r#"(?:^|\s)([\w:]+)="([^"]*)""#)
+        .captures_iter(attrs)
+        .find(|c| &c[1] == name)
+        .map(|c| decode_entities(&c[2]))
+}
+
+/// Indice di colonna da un riferimento di cella ("B7" → 1)
+fn column_index(reference: &str) -> Option<usize> {
+    let letters: String = reference.chars().take_while(|c| c.is_ascii_alphabetic()).collect();
+    if letters.is_empty() {
+        return None;
+    }
+    let mut index = 0usize;
+    for ch in letters.to_ascii_uppercase().chars() {
+        index = index * 26 + (ch as usize - 'A' as usize + 1);
+    }
+    Some(index - 1)
+}
+
+/// Stili di cella che rappresentano date (formati predefiniti o personalizzati con giorno/anno/ora)
+fn date_styles(styles_xml: &str) -> Vec<bool> {
+    static NUM_FMT: OnceLock<Regex> = OnceLock::new();
+    static QUOTED: OnceLock<Regex> = OnceLock::new();
+    let num_fmt = cached_regex(&NUM_FMT, r#"<numFmt\s[^>]*numFmtId="(\d+)"[^>]*formatCode="([^"]*)""#);
+    let quoted = cached_regex(&QUOTED, r#""[^"]*"|\[[^\]]*\]|\\."#);
+    let custom: std::collections::HashMap<u32, bool> = num_fmt
+        .captures_iter(styles_xml)
+        .filter_map(|c| {
+            let id = c[1].parse::<u32>().ok()?;
+            let code = quoted.replace_all(&decode_entities(&c[2]), "").to_lowercase();
+            Some((id, code.contains('d') || code.contains('y') || code.contains('h')))
+        })
+        .collect();
+
+    static CELL_XFS: OnceLock<Regex> = OnceLock::new();
+    static XF: OnceLock<Regex> = OnceLock::new();
+    let cell_xfs = cached_regex(&CELL_XFS, r"(?s)<cellXfs[^>]*>(.*?)</cellXfs>");
+    let xf = cached_regex(&XF, r"<xf\s([^>]*?)/?>");
+    let Some(block) = cell_xfs.captures(styles_xml) else {
+        return Vec::new();
+    };
+    xf.captures_iter(&block[1])
+        .map(|c| {
+            let id = attribute(&c[1], "numFmtId").and_then(|v| v.parse::<u32>().ok()).unwrap_or(0);
+            matches!(id, 14..=22 | 45..=47) || custom.get(&id).copied().unwrap_or(false)
+        })
+        .collect()
+}
+
+fn from_serial(serial: f64) -> Option<NaiveDateTime> {
+    let epoch = NaiveDate::from_ymd_opt(1899, 12, 30)?.and_hms_opt(0, 0, 0)?;
+    let seconds = (serial * 86_400.0).round() as i64;
+    epoch.checked_add_signed(chrono::Duration::seconds(seconds))
+}
+
+fn parse_sheet(xml: &str, shared: &[String], dates: &[bool]) -> Vec<(usize, Vec<XlsxCell>)> {
+    static ROW: OnceLock<Regex> = OnceLock::new();
+    static CELL: OnceLock<Regex> = OnceLock::new();
+    static VALUE: OnceLock<Regex> = OnceLock::new();
+    static INLINE: OnceLock<Regex> = OnceLock::new();
+    let row_re = cached_regex(&ROW, r"(?s)<row\b([^>]*?)(?:/>|>(.*?)</row>)");
+    let cell_re = cached_regex(&CELL, r"(?s)<c\b([^>]*?)(?:/>|>(.*?)</c>)");
+    let value_re = cached_regex(&VALUE, r"(?s)<v>(.*?)</v>");
+    let inline_re = cached_regex(&INLINE, r"(?s)<is>(.*?)</is>");
+
+    let mut rows = Vec::new();
+    let mut next_row = 1usize;
+    for row_cap in row_re.captures_iter(xml) {
+        let row_number = attribute(&row_cap[1], "r")
+            .and_then(|r| r.parse::<usize>().ok())
+            .unwrap_or(next_row);
+        next_row = row_number + 1;
+        let Some(content) = row_cap.get(2) else {
+            continue;
+        };
+
+        let mut cells: Vec<XlsxCell> = Vec::new();
+        for cell_cap in cell_re.captures_iter(content.as_str()) {
+            let attrs = &cell_cap[1];
+            let body = cell_cap.get(2).map(|m| m.as_str()).unwrap_or("");
+            let index = attribute(attrs, "r")
+                .and_then(|r| column_index(&r))
+                .unwrap_or(cells.len());
+            let kind = attribute(attrs, "t").unwrap_or_default();
+            let style = attribute(attrs, "s").and_then(|s| s.parse::<usize>().ok()).unwrap_or(0);
+            let raw = value_re.captures(body).map(|c| decode_entities(&c[1]));
+
+            let cell = match kind.as_str() {
+                "s" => raw
+                    .and_then(|v| v.trim().parse::<usize>().ok())
+                    .and_then(|i| shared.get(i))
+                    .map(|t| XlsxCell::text(Some(t)))
+                    .unwrap_or(XlsxCell::Empty),
+                "inlineStr" => inline_re
+                    .captures(body)
+                    .map(|c| XlsxCell::text(Some(&rich_text(&c[1]))))
+                    .unwrap_or(XlsxCell::Empty),
+                "str" => XlsxCell::text(raw.as_deref()),
+                "b" => XlsxCell::Bool(raw.as_deref().map(str::trim) == Some("1")),
+                "e" => XlsxCell::Empty,
+                _ => match raw.and_then(|v| v.trim().parse::<f64>().ok()) {
+                    Some(serial) if dates.get(style).copied().unwrap_or(false) => match from_serial(serial) {
+                        Some(dt) if dt.time() == chrono::NaiveTime::MIN => XlsxCell::Date(dt.date()),
+                        Some(dt) => XlsxCell::DateTime(dt),
+                        None => XlsxCell::Number(serial),
+                    },
+                    Some(number) => XlsxCell::Number(number),
+                    None => XlsxCell::Empty,
+                },
+            };
+            if cells.len() <= index {
+                cells.resize(index + 1, XlsxCell::Empty);
+            }
+            cells[index] = cell;
+        }
+        rows.push((row_number, cells));
+    }
+    rows
+}
+
+/// Legge i fogli di una cartella di lavoro XLSX nell'ordine del file
+pub fn read_workbook(bytes: &[u8]) -> Result<Vec<XlsxReadSheet>, String> {
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes))
+        .map_err(|_| "Il file non è una cartella di lavoro Excel (.xlsx) valida".to_string())?;
+
+    let workbook = read_part(&mut archive, "xl/workbook.xml")
+        .ok_or_else(|| "Il file non è una cartella di lavoro Excel (.xlsx) valida".to_string())?;
+    let rels = read_part(&mut archive, "xl/_rels/workbook.xml.rels").unwrap_or_default();
+    static SHARED_STRING: OnceLock<Regex> = OnceLock::new();
+    let shared: Vec<String> = read_part(&mut archive, "xl/sharedStrings.xml")
+        .map(|xml| {
+            cached_regex(&SHARED_STRING, r"(?s)<si>(.*?)</si>")
+                .captures_iter(&xml)
+                .map(|c| rich_text(&c[1]))
+                .collect()
+        })
+        .unwrap_or_default();
+    let dates = read_part(&mut archive, "xl/styles.xml")
+        .map(|xml| date_styles(&xml))
+        .unwrap_or_default();
+
+    static RELATIONSHIP: OnceLock<Regex> = OnceLock::new();
+    let rel_re = cached_regex(&RELATIONSHIP, r"<Relationship\s([^>]*?)/?>");
+    let targets: std::collections::HashMap<String, String> = rel_re
+        .captures_iter(&rels)
+        .filter_map(|c| Some((attribute(&c[1], "Id")?, attribute(&c[1], "Target")?)))
+        .collect();
+
+    static SHEET: OnceLock<Regex> = OnceLock::new();
+    let sheet_re = cached_regex(&SHEET, r"<sheet\s([^>]*?)/?>");
+    let mut sheets = Vec::new();
+    for cap in sheet_re.captures_iter(&workbook) {
+        let Some(name) = attribute(&cap[1], "name") else {
+            continue;
+        };
+        let Some(target) = attribute(&cap[1], "r:id").and_then(|id| targets.get(&id).cloned()) else {
+            continue;
+        };
+        let path = match target.strip_prefix('/') {
+            Some(absolute) => absolute.to_string(),
+            None => format!("xl/{}", target),
+        };
+        let xml = read_part(&mut archive, &path)
+            .ok_or_else(|| format!("Foglio '{}' non leggibile", name))?;
+        sheets.push(XlsxReadSheet {
+            name,
+            rows: parse_sheet(&xml, &shared, &dates),
+        });
+    }
+    if sheets.is_empty() {
+        return Err("La cartella di lavoro non contiene fogli".to_string());
+    }
+    Ok(sheets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn column_index_reads_cell_references() {
+        assert_eq!(column_index("A1"), Some(0));
+        assert_eq!(column_index("B7"), Some(1));
+        assert_eq!(column_index("AA12"), Some(26));
+        assert_eq!(column_name(26), "AA");
+        assert_eq!(column_index("12"), None);
+    }
+
+    #[test]
+    fn attribute_matches_whole_names_and_decodes_entities() {
+        let attrs = r#" r="C3" s="2" t="s" xml:space="preserve" title="a &amp; b""#;
+        assert_eq!(attribute(attrs, "r").as_deref(), Some("C3"));
+        assert_eq!(attribute(attrs, "s").as_deref(), Some("2"));
+        assert_eq!(attribute(attrs, "space"), None);
+        assert_eq!(attribute(attrs, "xml:space").as_deref(), Some("preserve"));
+        assert_eq!(attribute(attrs, "title").as_deref(), Some("a & b"));
+        assert_eq!(attribute(attrs, "x"), None);
+    }
+
+    #[test]
+    fn decode_entities_handles_named_and_numeric_references() {
+        assert_eq!(decode_entities("&lt;b&gt; &quot;x&quot; &apos;y&apos;"), "<b> \"x\" 'y'");
+        assert_eq!(decode_entities("&#232;&#xE0;"), "èà");
+        assert_eq!(decode_entities("&amp;lt;"), "&lt;");
+    }
+
+    #[test]
+    fn parse_sheet_reads_shared_inline_boolean_and_date_cells() {
+        let xml = r#"<worksheet><sheetData>
+            <row r="1"><c r="A1" t="s"><v>1</v></c><c r="C1" t="inlineStr"><is><r><t>Ros</t></r><r><t>si</t></r><rPh><t>x</t></rPh></is></c></row>
+            <row r="3"/>
+            <row r="4"><c r="B4" t="b"><v>1</v></c><c r="C4" s="1"><v>45000</v></c><c r="D4"><v>3.5</v></c><c r="E4" t="e"><v>#N/A</v></c></row>
+        </sheetData></worksheet>"#;
+        let shared = vec!["zero".to_string(), "uno".to_string()];
+        let rows = parse_sheet(xml, &shared, &[false, true]);
+
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].0, 1);
+        assert_eq!(
+            rows[0].1,
+            vec![XlsxCell::Text("uno".to_string()), XlsxCell::Empty, XlsxCell::Text("Rossi".to_string())]
+        );
+        assert_eq!(rows[1].0, 4);
+        assert_eq!(
+            rows[1].1,
+            vec![
+                XlsxCell::Empty,
+                XlsxCell::Bool(true),
+                XlsxCell::Date(date("2023-03-15")),
+                XlsxCell::Number(3.5),
+                XlsxCell::Empty,
+            ]
+        );
+    }
+
+    #[test]
+    fn write_and_read_workbook_round_trip() {
+        let sheets = vec![
+            XlsxSheet {
+                name: "Pazienti".to_string(),
+                headers: vec!["Cognome".to_string(), "Nascita".to_string(), "Peso".to_string()],
+                rows: vec![
+                    vec![
+                        XlsxCell::text(Some("D'Angelo & <Figli>")),
+                        XlsxCell::date(Some("1950-04-02")),
+                        XlsxCell::number(Some(72.5)),
+                    ],
+                    vec![
+                        XlsxCell::text(Some("Bianchi")),
+                        XlsxCell::date(Some("2030-01-02 08:30:00")),
+                        XlsxCell::Bool(false),
+                    ],
+                ],
+            },
+            XlsxSheet {
+                name: "Vuoto/1".to_string(),
+                headers: vec!["Note".to_string()],
+                rows: Vec::new(),
+            },
+        ];
+        let bytes = write_workbook(&sheets).unwrap();
+        let read = read_workbook(&bytes).unwrap();
+
+        assert_eq!(read.len(), 2);
+        assert_eq!(read[0].name, "Pazienti");
+        assert_eq!(read[1].name, "Vuoto1");
+        assert_eq!(
+            read[0].rows,
+            vec![
+                (
+                    1,
+                    vec![
+                        XlsxCell::Text("Cognome".to_string()),
+                        XlsxCell::Text("Nascita".to_string()),
+                        XlsxCell::Text("Peso".to_string()),
+                    ]
+                ),
+                (2, sheets[0].rows[0].clone()),
+                (3, sheets[0].rows[1].clone()),
+            ]
+        );
+        assert_eq!(read[0].rows[2].1[1], XlsxCell::DateTime(date("2030-01-02").and_hms_opt(8, 30, 0).unwrap()));
+        assert_eq!(read[1].rows.len(), 1);
+    }
+
+    #[test]
+    fn read_workbook_rejects_non_zip_content() {
+        assert!(read_workbook(b"non un file xlsx").is_err());
+    }
+}
@@ -4,7 +4,6 @@ export const BALLOON_EXPANDABLE_MODELS = [
   'Edwards SAPIEN 3',
   'Edwards SAPIEN 3 Ultra',
   'Myval',
-  'Allegra',
 ];
 
 export const SELF_EXPANDABLE_MODELS = [
@@ -13,6 +12,7 @@ export const SELF_EXPANDABLE_MODELS = [
   'Medtronic CoreValve Evolut PRO+',
   'Boston Scientific ACURATE neo',
   'Portico',
+  'Allegra',
 ];
 
 export const VALVE_TYPES = {
@@ -28,6 +28,11 @@ export const CV_RISK_FACTORS = [
   'Fumo di sigaretta',
   'Obesità',
   'Familiarità per cardiopatia',
+  'Insufficienza renale cronica',
+  'BPCO',
+  'Arteriopatia periferica',
+  'Pregresso ictus/TIA',
+  'OSAS',
 ];
 
 // Range di validazione per parametri medici
@@ -10,3 +10,6 @@ export const notifyError = (err, fallback = 'Errore imprevisto', duration) => {
       : err?.message || fallback;
   showToast(msg, 'error', duration);
 };
+
+export const notifyWarning = (message, duration) =>
+  showToast(message, 'warning', duration);